@@ -0,0 +1,181 @@
+//! Throughput of the sampling loops in proposals per second on a trivial model, whose
+//! own cost is negligible, such that the results measure the overhead of the
+//! framework. The `parts` group measures the building blocks of this overhead
+//! separately, i.e., the bare model, the random numbers, the binning and the
+//! formatting of the records.
+
+use std::fmt::Write as _;
+use std::io;
+use std::ops::ControlFlow;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use largedev::rand::Rng;
+use largedev::{DirectSamplable, Histogram, MarkovChain, Metropolis, Model, Seed, Simple, WangLandau};
+
+/// number of proposals per iteration of the benchmarks
+const PROPOSALS: u64 = 100_000;
+
+/// number of proposals per sweep
+const SWEEP: usize = 100;
+
+/// A model with 16 states, whose change flips one of four bits.
+#[derive(Clone, Debug, Default)]
+struct Trivial {
+    state: u8,
+    last: u8,
+}
+
+impl Model for Trivial {
+    fn value(&self) -> f64 {
+        self.state as f64
+    }
+}
+
+impl MarkovChain for Trivial {
+    fn change(&mut self, rng: &mut impl Rng) {
+        self.last = self.state;
+        self.state ^= 1 << rng.gen_range(0..4);
+    }
+
+    fn undo(&mut self) {
+        self.state = self.last;
+    }
+}
+
+impl DirectSamplable for Trivial {
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        self.state = rng.gen_range(0..16);
+    }
+}
+
+fn samplers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("samplers");
+    group.throughput(Throughput::Elements(PROPOSALS));
+
+    group.bench_function("metropolis", |b| {
+        let mut rng = Seed::new(1).rng();
+        b.iter(|| {
+            Metropolis::new(Trivial::default())
+                .temperature(4.)
+                .sweep(SWEEP)
+                .iterations(PROPOSALS as usize / SWEEP)
+                .build()
+                .unwrap()
+                .run(&mut rng, &mut io::sink())
+                .unwrap()
+        })
+    });
+
+    group.bench_function("metropolis_delta_table", |b| {
+        let mut rng = Seed::new(1).rng();
+        b.iter(|| {
+            Metropolis::new(Trivial::default())
+                .temperature(4.)
+                .sweep(SWEEP)
+                .iterations(PROPOSALS as usize / SWEEP)
+                .delta_table(8)
+                .build()
+                .unwrap()
+                .run(&mut rng, &mut io::sink())
+                .unwrap()
+        })
+    });
+
+    group.bench_function("wang_landau", |b| {
+        let mut rng = Seed::new(1).rng();
+        b.iter(|| {
+            // the hook stops after a fixed number of sweeps, since the length of a
+            // complete run depends on the convergence
+            WangLandau::new(Trivial::default())
+                .range(0., 16.)
+                .bins(16)
+                .sweep(SWEEP)
+                .on_sweep(|_, state| if state.sweep * SWEEP >= PROPOSALS as usize {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                })
+                .build()
+                .unwrap()
+                .run(&mut rng, &mut io::sink())
+                .unwrap()
+        })
+    });
+
+    group.bench_function("simple", |b| {
+        let mut rng = Seed::new(1).rng();
+        b.iter(|| {
+            Simple::new(Trivial::default())
+                .iterations(PROPOSALS as usize)
+                .build()
+                .unwrap()
+                .run(&mut rng, &mut io::sink())
+                .unwrap()
+        })
+    });
+
+    group.finish();
+}
+
+fn parts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parts");
+    group.throughput(Throughput::Elements(PROPOSALS));
+
+    group.bench_function("model", |b| {
+        let mut rng = Seed::new(1).rng();
+        let mut model = Trivial::default();
+        b.iter(|| {
+            for _ in 0..PROPOSALS {
+                model.change(&mut rng);
+                if black_box(model.value()) > 8. {
+                    model.undo();
+                }
+            }
+        })
+    });
+
+    group.bench_function("rng", |b| {
+        let mut rng = Seed::new(1).rng();
+        b.iter(|| {
+            for _ in 0..PROPOSALS {
+                black_box(rng.gen::<f64>());
+            }
+        })
+    });
+
+    group.bench_function("fast_rng", |b| {
+        let mut rng = Seed::new(1).fast_rng();
+        b.iter(|| {
+            for _ in 0..PROPOSALS {
+                black_box(rng.gen::<f64>());
+            }
+        })
+    });
+
+    group.bench_function("binning", |b| {
+        let mut histogram = Histogram::new(0., 16., 16).unwrap();
+        b.iter(|| {
+            for i in 0..PROPOSALS {
+                histogram.count(black_box((i % 16) as f64));
+            }
+        })
+    });
+
+    group.bench_function("formatting", |b| {
+        let model = Trivial { state: 7, last: 0 };
+        let mut record = String::new();
+        b.iter(|| {
+            for _ in 0..PROPOSALS {
+                record.clear();
+                model.write_record(&mut record).unwrap();
+                writeln!(record).unwrap();
+                black_box(&record);
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, samplers, parts);
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+//! The acceptance of proposals in Markov chain Monte Carlo, which is shared by all
+//! samplers, see `AcceptanceRule`.
+
+/// A rule for the probability to accept a proposal from the logarithm `ln_weight` of
+/// the ratio of the weights of the new and the old state, e.g., `-beta dE` for a
+/// Boltzmann distribution, plus the logarithmic proposal ratio for non-symmetric
+/// proposals. A `NaN`, e.g., from `inf - inf`, is always rejected.
+pub trait AcceptanceRule {
+    /// the probability to accept the proposal, in `[0, 1]`
+    fn probability(&self, ln_weight: f64) -> f64;
+
+    /// whether to accept the proposal with the uniform random number `u` in `[0, 1)`,
+    /// such that a probability of `0` is never and one of `1` always accepted
+    fn accept(&self, ln_weight: f64, u: f64) -> bool {
+        u < self.probability(ln_weight)
+    }
+}
+
+/// The Metropolis-Hastings rule `min(1, exp(ln_weight))`, which is used by all samplers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MetropolisRule;
+
+impl AcceptanceRule for MetropolisRule {
+    fn probability(&self, ln_weight: f64) -> f64 {
+        if ln_weight >= 0. {
+            1.
+        } else if ln_weight.is_nan() {
+            0.
+        } else {
+            ln_weight.exp()
+        }
+    }
+}
+
+/// The heat bath or Glauber rule `1 / (1 + exp(-ln_weight))`, which satisfies detailed
+/// balance as well, but accepts less often than `MetropolisRule`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GlauberRule;
+
+impl AcceptanceRule for GlauberRule {
+    fn probability(&self, ln_weight: f64) -> f64 {
+        if ln_weight.is_nan() {
+            0.
+        } else {
+            1. / (1. + (-ln_weight).exp())
+        }
+    }
+}
+
+/// The logarithm `-beta dE` of the ratio of the Boltzmann weights after and before a
+/// change of the energy by `delta`, with the exact limits for `beta = 0`, where every
+/// change is accepted, and `beta = inf`, where every increase of the energy is rejected,
+/// instead of the undefined products `0 inf` and `inf 0`. Huge products are infinite,
+/// which `AcceptanceRule` handles without overflow.
+pub fn ln_boltzmann(beta: f64, delta: f64) -> f64 {
+    if beta == 0. || delta == 0. {
+        0.
+    } else if beta.is_infinite() {
+        -(beta.signum() * delta.signum()) * f64::INFINITY
+    } else {
+        -beta * delta
+    }
+}
@@ -0,0 +1,311 @@
+use std::io::{BufWriter, Write};
+
+use rand::Rng;
+
+use crate::markovchain::{MarkovChain, Proposal, Restorable};
+use crate::simple::DirectSamplable;
+use crate::error::{LargedevError, Result};
+use crate::report::AmsReport;
+use crate::seed::Seed;
+use crate::hooks::CancelToken;
+
+/// the quantile of the standard normal distribution for the 95% confidence interval
+const Z_95: f64 = 1.959964;
+
+/// Parameters of adaptive multilevel splitting, see `Ams` for the builder.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AmsConfig {
+    /// the value `a`, whose tail probability `P(value > a)` is estimated
+    pub threshold: f64,
+    /// number of particles, i.e., configurations
+    pub particles: usize,
+    /// how many particles with the lowest values are replaced per iteration
+    pub kill: usize,
+    /// how many change moves are applied to every copy
+    pub sweep: usize,
+    /// the largest number of iterations before the simulation is stopped
+    pub max_iterations: usize,
+    /// seed for `run_seeded`
+    pub seed: Option<Seed>,
+}
+
+impl Default for AmsConfig {
+    fn default() -> Self {
+        AmsConfig {
+            threshold: 0.,
+            particles: 100,
+            kill: 1,
+            sweep: 100,
+            max_iterations: usize::MAX,
+            seed: None,
+        }
+    }
+}
+
+impl AmsConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.threshold.is_nan() {
+            return Err(LargedevError::InvalidParameter("threshold must not be NaN".into()))
+        }
+        if self.particles < 2 {
+            return Err(LargedevError::InvalidParameter("at least two particles are needed".into()))
+        }
+        if self.kill == 0 || self.kill >= self.particles {
+            return Err(LargedevError::InvalidParameter(format!("kill must be between 1 and {}, not {}", self.particles - 1, self.kill)))
+        }
+        if self.sweep == 0 {
+            return Err(LargedevError::InvalidParameter("sweep must be positive".into()))
+        }
+        Ok(())
+    }
+}
+
+/// A builder used to set up adaptive multilevel splitting (AMS) to estimate the
+/// probability `P(value > a)` of a rare event far in the tail of the distribution of
+/// simple sampling. The model needs to be `DirectSamplable` for the initial particles,
+/// `Restorable` to copy them and a `MarkovChain`, whose changes leave the distribution
+/// of simple sampling invariant, like the unbiased Markov chain of the other samplers.
+///
+/// In every iteration the level is set to the `kill`-th lowest value of the particles,
+/// all particles at or below the level are replaced by copies of random particles above
+/// the level, and each copy is moved by `sweep` changes, which are rejected if they end
+/// at or below the level. The estimate is the product of the fractions of the survivors
+/// of all iterations and the fraction of the final particles above `a`, which is
+/// unbiased, see Bréhier et al., Ann. Appl. Probab. 26, 3559 (2016), e.g.:
+///
+/// ```ignore
+/// let report = Ams::new(model)
+///    .threshold(100.)
+///    .particles(1000)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// println!("P = {} in [{}, {}]", report.probability, report.lower, report.upper);
+/// ```
+///
+/// The output has a line with every level and the logarithm of the estimate of the
+/// probability to exceed it, i.e., the tail of the distribution up to `a`.
+pub struct Ams<MC> {
+    model: MC,
+    config: AmsConfig,
+    cancel: Option<CancelToken>,
+}
+
+impl<MC: MarkovChain + Restorable + DirectSamplable> Ams<MC> {
+    pub fn new(model: MC) -> Self {
+        Ams::with_config(model, AmsConfig::default())
+    }
+
+    pub fn with_config(model: MC, config: AmsConfig) -> Self {
+        Ams::<MC> {
+            model,
+            config,
+            cancel: None,
+        }
+    }
+
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.config.threshold = threshold;
+        self
+    }
+
+    pub fn particles(mut self, particles: usize) -> Self {
+        self.config.particles = particles;
+        self
+    }
+
+    pub fn kill(mut self, kill: usize) -> Self {
+        self.config.kill = kill;
+        self
+    }
+
+    pub fn sweep(mut self, sweep: usize) -> Self {
+        self.config.sweep = sweep;
+        self
+    }
+
+    pub fn max_iterations(mut self, max_iterations: usize) -> Self {
+        self.config.max_iterations = max_iterations;
+        self
+    }
+
+    /// the seed used by `run_seeded`, if not set, a seed is drawn from entropy
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Self {
+        self.config.seed = Some(seed.into());
+        self
+    }
+
+    /// a token to cancel the simulation, see `CancelToken`
+    pub fn cancel_token(mut self, token: impl Into<CancelToken>) -> Self {
+        self.cancel = Some(token.into());
+        self
+    }
+
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// validate the parameters and return the configured sampler
+    pub fn build(self) -> Result<AmsRun<MC>> {
+        self.config.validate()?;
+        Ok(AmsRun {
+            model: self.model,
+            config: self.config,
+            cancel: self.cancel,
+        })
+    }
+}
+
+/// A configured adaptive multilevel splitting, see `Ams` for the builder.
+pub struct AmsRun<MC> {
+    model: MC,
+    config: AmsConfig,
+    cancel: Option<CancelToken>,
+}
+
+impl<MC: MarkovChain + Restorable + DirectSamplable> AmsRun<MC> {
+    pub fn config(&self) -> &AmsConfig {
+        &self.config
+    }
+
+    /// the current state of the model
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    /// consume the sampler and return the model in its current state
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    pub fn run(&mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<AmsReport> {
+        self.simulate(&mut rng, file, None)
+    }
+
+    /// like `run`, but with a random number generator initialized from the configured
+    /// `seed` (or from entropy), which is written to the output and the report
+    pub fn run_seeded(&mut self, file: &mut impl Write) -> Result<AmsReport> {
+        let seed = self.config.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate(&mut seed.rng(), file, Some(seed))
+    }
+
+    /// Move the model by `sweep` changes, which leave the distribution of simple
+    /// sampling restricted to values above `level` invariant. Returns the new value.
+    fn mutate(&mut self, level: f64, mut value: f64, mut rng: &mut impl Rng) -> f64 {
+        for _ in 0..self.config.sweep {
+            if self.model.try_change(&mut rng) != Proposal::Valid {
+                continue;
+            }
+            let new_value = self.model.value();
+            let ln_ratio = self.model.ln_proposal_ratio();
+            if new_value <= level || (ln_ratio < 0. && rng.gen::<f64>() >= ln_ratio.exp()) {
+                self.model.undo();
+            } else {
+                value = new_value;
+            }
+        }
+        value
+    }
+
+    fn simulate(&mut self, mut rng: &mut impl Rng, file: &mut impl Write, seed: Option<Seed>) -> Result<AmsReport> {
+        // buffer the output, since it is written level by level
+        let mut file = BufWriter::new(file);
+
+        if let Some(seed) = seed {
+            writeln!(file, "# seed = {}", seed)?;
+        }
+        writeln!(file, "# level, ln P(value > level)")?;
+
+        let n = self.config.particles;
+        let threshold = self.config.threshold;
+        let mut particles = Vec::with_capacity(n);
+        let mut values = Vec::with_capacity(n);
+        for _ in 0..n {
+            self.model.reconstruct(&mut rng);
+            values.push(self.model.value());
+            particles.push(self.model.snapshot());
+        }
+
+        let mut ln_p = 0.;
+        let mut iterations = 0;
+        let mut stopped = false;
+        // buffers reused to avoid allocations
+        let mut sorted = values.clone();
+        let mut survivors = Vec::with_capacity(n);
+
+        trace_span!(_span = info_span!("ams", threshold, particles = n, kill = self.config.kill));
+        loop {
+            if values.iter().any(|v| v.is_nan()) {
+                return Err(LargedevError::NanValue)
+            }
+            sorted.clone_from(&values);
+            sorted.sort_by(f64::total_cmp);
+            let level = sorted[self.config.kill - 1];
+            if level >= threshold {
+                break;
+            }
+            if iterations >= self.config.max_iterations || self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                stopped = true;
+                break;
+            }
+            iterations += 1;
+
+            survivors.clear();
+            survivors.extend((0..n).filter(|&i| values[i] > level));
+            ln_p += (survivors.len() as f64 / n as f64).ln();
+            writeln!(file, "{} {}", level, ln_p)?;
+            trace_event!(debug!(iterations, level, ln_p, "level"));
+            if survivors.is_empty() {
+                // all particles share the same value, which can not be resolved
+                break;
+            }
+
+            for i in 0..n {
+                if values[i] > level {
+                    continue;
+                }
+                let parent = survivors[rng.gen_range(0..survivors.len())];
+                self.model.restore(&particles[parent]);
+                values[i] = self.mutate(level, values[parent], &mut rng);
+                particles[i] = self.model.snapshot();
+            }
+        }
+
+        let above = values.iter().filter(|&&v| v > threshold).count();
+        let ln_probability = ln_p + (above as f64 / n as f64).ln();
+        let probability = ln_probability.exp();
+        // asymptotic relative error of the idealized algorithm, see Cérou and Guyader,
+        // Stoch. Anal. Appl. 25, 417 (2007)
+        let error = Z_95 * (-ln_probability / n as f64).sqrt();
+        writeln!(file, "{} {}", threshold, ln_probability)?;
+        file.flush()?;
+        trace_event!(info!(iterations, probability, stopped, "finished"));
+
+        Ok(AmsReport {
+            probability,
+            ln_probability,
+            lower: probability * (-error).exp(),
+            upper: probability * error.exp(),
+            iterations,
+            seed,
+            stopped,
+        })
+    }
+
+    pub fn exec(mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<MC> {
+        self.run(&mut rng, file)?;
+        Ok(self.model)
+    }
+}
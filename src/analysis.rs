@@ -0,0 +1,393 @@
+//! Analyses of the time series written by the samplers, e.g., the reweighting of a
+//! `Metropolis` simulation to nearby temperatures or its autocorrelation.
+
+use std::collections::VecDeque;
+
+use crate::error::{LargedevError, Result};
+
+/// minimal fraction of the samples, which the effective sample size of the weights of
+/// a reliable reweighting needs, see `Reweighted::reliable`
+const MIN_OVERLAP: f64 = 0.1;
+
+/// minimal number of blocks of a level of `blocking`, which enters the detection of the
+/// plateau
+const MIN_BLOCKS: usize = 16;
+
+/// number of successive levels of `blocking`, which need to agree within their
+/// uncertainties to form a plateau
+const PLATEAU_LEVELS: usize = 3;
+
+/// The mean of an observable at the inverse temperature `beta`, estimated by `reweight`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Reweighted {
+    pub beta: f64,
+    /// the reweighted mean of the observable
+    pub mean: f64,
+    /// the jackknife error of `mean`
+    pub error: f64,
+    /// the Kish effective sample size of the weights
+    pub ess: f64,
+    /// `ess` over the number of samples, i.e., the overlap of the sampled and the
+    /// reweighted distribution of the energy, `1` for the sampled temperature
+    pub overlap: f64,
+    /// Whether the overlap is at least `0.1`. Far from the sampled temperature, a few
+    /// samples from the tail of the sampled distribution dominate the reweighted mean,
+    /// which is biased, although its jackknife error can be small.
+    pub reliable: bool,
+}
+
+/// Reweight the time series of the `energies` and the `observable` of a simulation at
+/// the inverse temperature `beta` to each of the inverse temperatures `betas`, i.e.,
+/// `<O>(beta') = sum O_i w_i / sum w_i` with the weights `w_i = exp(-(beta' - beta) E_i)`.
+/// The errors are estimated by a jackknife over `blocks` consecutive blocks of the
+/// series, which should be longer than the autocorrelation time, e.g.:
+///
+/// ```ignore
+/// let points = reweight(&energies, &magnetizations, 1. / 2.3, &[1. / 2.25, 1. / 2.35], 20)?;
+/// for p in points.iter().filter(|p| p.reliable) {
+///     println!("{} {} {}", 1. / p.beta, p.mean, p.error);
+/// }
+/// ```
+///
+/// A reweighting without enough overlap is flagged as not `reliable` and traced as a
+/// warning.
+pub fn reweight(energies: &[f64], observable: &[f64], beta: f64, betas: &[f64], blocks: usize) -> Result<Vec<Reweighted>> {
+    let n = energies.len();
+    if observable.len() != n {
+        return Err(LargedevError::InvalidParameter(format!("{} energies, but {} values of the observable", n, observable.len())))
+    }
+    if blocks < 2 {
+        return Err(LargedevError::InvalidParameter("the jackknife needs at least two blocks".into()))
+    }
+    if n < blocks {
+        return Err(LargedevError::TooFewSamples)
+    }
+    if !beta.is_finite() || betas.iter().any(|b| !b.is_finite()) {
+        return Err(LargedevError::InvalidParameter("the inverse temperatures must be finite".into()))
+    }
+
+    let mut points = Vec::with_capacity(betas.len());
+    for &target in betas {
+        let delta = target - beta;
+        // the weights relative to the largest one, to avoid overflows
+        let shift = energies.iter().map(|e| -delta * e).fold(f64::NEG_INFINITY, f64::max);
+
+        // the sums of the weights, of their squares and of the weighted observable in
+        // every block
+        let mut sums = vec![(0., 0., 0.); blocks];
+        for (i, (e, o)) in energies.iter().zip(observable).enumerate() {
+            let w = (-delta * e - shift).exp();
+            let block = &mut sums[i * blocks / n];
+            block.0 += w;
+            block.1 += w * w;
+            block.2 += w * o;
+        }
+        let (sum, sum2, sum_o) = sums.iter()
+            .fold((0., 0., 0.), |acc, b| (acc.0 + b.0, acc.1 + b.1, acc.2 + b.2));
+        let mean = sum_o / sum;
+
+        // the estimates without one of the blocks
+        let partial: Vec<f64> = sums.iter()
+            .map(|b| (sum_o - b.2) / (sum - b.0))
+            .collect();
+        let m = blocks as f64;
+        let average = partial.iter().sum::<f64>() / m;
+        let error = ((m - 1.) / m * partial.iter().map(|p| (p - average).powi(2)).sum::<f64>()).sqrt();
+
+        let ess = sum * sum / sum2;
+        let overlap = ess / n as f64;
+        let reliable = overlap >= MIN_OVERLAP;
+        if !reliable {
+            trace_event!(warn!(beta = target, overlap, "reweighting beyond the overlap of the distributions"));
+        }
+        points.push(Reweighted { beta: target, mean, error, ess, overlap, reliable });
+    }
+    Ok(points)
+}
+
+/// series with at least this many products `n * max_lag` use the FFT, if the `fft`
+/// feature is enabled
+#[cfg(feature = "fft")]
+const FFT_THRESHOLD: usize = 1 << 16;
+
+/// The autocorrelation function `C(t) = <O(0) O(t)> - <O>^2` of the `series` for the
+/// time displacements `t = 0, ..., max_lag`, where the average over the `n - t` pairs
+/// of the series at the distance `t` is taken relative to the mean of the whole series.
+/// `C(0)` is the variance, `C(t) / C(0)` the normalized autocorrelation, whose
+/// integrated autocorrelation time `integrated_time` estimates. With the `fft` feature,
+/// long series are correlated with a fast Fourier transform in `O(n log n)` instead of
+/// `O(n max_lag)`. For series, which are too long to be stored, see
+/// `StreamingAutocorrelation`.
+pub fn autocorrelation(series: &[f64], max_lag: usize) -> Result<Vec<f64>> {
+    let n = series.len();
+    if max_lag >= n {
+        return Err(LargedevError::TooFewSamples)
+    }
+    let mean = series.iter().sum::<f64>() / n as f64;
+    let x: Vec<f64> = series.iter().map(|v| v - mean).collect();
+    let sums = lagged_sums(&x, &x, max_lag);
+    Ok(sums.iter().enumerate().map(|(t, s)| s / (n - t) as f64).collect())
+}
+
+/// The time-displaced cross-correlation `C_xy(t) = <x(0) y(t)> - <x><y>` of two series
+/// recorded at the same times, e.g., the energy and the magnetization of a `Metropolis`
+/// simulation, for `t = -max_lag, ..., max_lag` at the index `max_lag + t`. A positive
+/// `t` correlates `y` with earlier values of `x`, such that an asymmetry shows which of
+/// the observables relaxes first. Like `autocorrelation`, the averages are over the
+/// `n - |t|` pairs relative to the means of the whole series, and `C_xy(0)` is the
+/// covariance, which `sqrt(C_xx(0) C_yy(0))` normalizes to the correlation coefficient.
+pub fn cross_correlation(x: &[f64], y: &[f64], max_lag: usize) -> Result<Vec<f64>> {
+    let n = x.len();
+    if y.len() != n {
+        return Err(LargedevError::InvalidParameter(format!("series of different lengths {} and {}", n, y.len())))
+    }
+    if max_lag >= n {
+        return Err(LargedevError::TooFewSamples)
+    }
+    let center = |series: &[f64]| {
+        let mean = series.iter().sum::<f64>() / n as f64;
+        series.iter().map(|v| v - mean).collect::<Vec<f64>>()
+    };
+    let x = center(x);
+    let y = center(y);
+    let forward = lagged_sums(&x, &y, max_lag);
+    let backward = lagged_sums(&y, &x, max_lag);
+    let correlation = backward.iter().enumerate().skip(1).rev()
+        .chain(forward.iter().enumerate())
+        .map(|(t, s)| s / (n - t) as f64)
+        .collect();
+    Ok(correlation)
+}
+
+/// the sums `sum_i x_i y_{i+t}` for `t = 0, ..., max_lag`
+fn lagged_sums(x: &[f64], y: &[f64], max_lag: usize) -> Vec<f64> {
+    #[cfg(feature = "fft")]
+    if x.len().saturating_mul(max_lag) >= FFT_THRESHOLD {
+        return lagged_sums_fft(x, y, max_lag)
+    }
+    (0..=max_lag)
+        .map(|t| x.iter().zip(&y[t..]).map(|(a, b)| a * b).sum())
+        .collect()
+}
+
+/// `lagged_sums` by the cross-correlation theorem, with zero padding to avoid the
+/// periodic wrap-around
+#[cfg(feature = "fft")]
+fn lagged_sums_fft(x: &[f64], y: &[f64], max_lag: usize) -> Vec<f64> {
+    use rustfft::FftPlanner;
+    use rustfft::num_complex::Complex;
+
+    let size = (x.len() + max_lag + 1).next_power_of_two();
+    let mut planner = FftPlanner::new();
+    let forward = planner.plan_fft_forward(size);
+    let inverse = planner.plan_fft_inverse(size);
+    let pad = |v: &[f64]| {
+        let mut padded: Vec<Complex<f64>> = v.iter().map(|&re| Complex::new(re, 0.)).collect();
+        padded.resize(size, Complex::new(0., 0.));
+        padded
+    };
+    let mut a = pad(x);
+    let mut b = pad(y);
+    forward.process(&mut a);
+    forward.process(&mut b);
+    let mut product: Vec<Complex<f64>> = a.iter().zip(&b).map(|(a, b)| a.conj() * b).collect();
+    inverse.process(&mut product);
+    product[..=max_lag].iter().map(|c| c.re / size as f64).collect()
+}
+
+/// The integrated autocorrelation time `tau = 1/2 + sum_t C(t) / C(0)` of the
+/// `correlation` from `autocorrelation`, where the sum is truncated at the first
+/// window `W >= 5 tau(W)` to limit the noise of the tail (Sokal). The statistical error
+/// of the mean of `n` correlated samples is `sqrt(2 tau C(0) / n)`. Without such a
+/// window within `max_lag`, e.g., for a series shorter than a few autocorrelation
+/// times, the estimate is `None`.
+pub fn integrated_time(correlation: &[f64]) -> Option<f64> {
+    let variance = *correlation.first()?;
+    if variance.is_nan() || variance <= 0. {
+        return None
+    }
+    let mut tau = 0.5;
+    for (w, c) in correlation.iter().enumerate().skip(1) {
+        tau += c / variance;
+        if w as f64 >= 5. * tau {
+            return Some(tau)
+        }
+    }
+    None
+}
+
+/// The autocorrelation function of a series, which is streamed value by value, e.g.,
+/// from a sweep hook, with the same result as `autocorrelation` of the whole series,
+/// but only `O(max_lag)` memory and time per value, e.g.:
+///
+/// ```ignore
+/// let mut correlation = StreamingAutocorrelation::new(1000);
+/// let run = Metropolis::new(model)
+///    .on_sweep(move |model, _| { correlation.push(model.magnetization() as f64); ControlFlow::Continue(()) })
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StreamingAutocorrelation {
+    max_lag: usize,
+    count: usize,
+    sum: f64,
+    /// the first `max_lag` values
+    head: Vec<f64>,
+    /// the last `max_lag` values
+    tail: VecDeque<f64>,
+    /// the sums `sum_i x_i x_{i+t}`
+    products: Vec<f64>,
+}
+
+impl StreamingAutocorrelation {
+    pub fn new(max_lag: usize) -> Self {
+        StreamingAutocorrelation {
+            max_lag,
+            count: 0,
+            sum: 0.,
+            head: Vec::with_capacity(max_lag),
+            tail: VecDeque::with_capacity(max_lag),
+            products: vec![0.; max_lag + 1],
+        }
+    }
+
+    /// number of values so far
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn push(&mut self, value: f64) {
+        self.products[0] += value * value;
+        // the last values in `tail` are the most recent ones
+        for (t, x) in self.tail.iter().rev().enumerate() {
+            self.products[t + 1] += x * value;
+        }
+        if self.max_lag > 0 {
+            if self.tail.len() == self.max_lag {
+                self.tail.pop_front();
+            }
+            self.tail.push_back(value);
+        }
+        if self.head.len() < self.max_lag {
+            self.head.push(value);
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    /// the autocorrelation function of the values so far, see `autocorrelation`
+    pub fn correlation(&self) -> Result<Vec<f64>> {
+        let n = self.count;
+        if self.max_lag >= n {
+            return Err(LargedevError::TooFewSamples)
+        }
+        let mean = self.sum / n as f64;
+        // the sums of the first and the last `n - t` values
+        let mut first = self.sum;
+        let mut last = self.sum;
+        let mut correlation = Vec::with_capacity(self.max_lag + 1);
+        for t in 0..=self.max_lag {
+            if t > 0 {
+                first -= self.tail[self.max_lag - t];
+                last -= self.head[t - 1];
+            }
+            let pairs = (n - t) as f64;
+            // `sum (x_i - m)(x_{i+t} - m)` expanded into the stored sums
+            let centered = self.products[t] - mean * (first + last) + pairs * mean * mean;
+            correlation.push(centered / pairs);
+        }
+        Ok(correlation)
+    }
+}
+
+/// A level of `blocking`, i.e., the series averaged over blocks of `size` values.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockingLevel {
+    /// the number of values per block, `2^level`
+    pub size: usize,
+    /// the number of blocks
+    pub blocks: usize,
+    /// the error of the mean, assuming uncorrelated blocks
+    pub error: f64,
+    /// the statistical uncertainty of `error`, `error / sqrt(2 (blocks - 1))`
+    pub uncertainty: f64,
+}
+
+/// The error of the mean of a correlated series estimated by `blocking`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Blocking {
+    pub mean: f64,
+    /// the recommended error of `mean`, from the first level of the plateau, or the
+    /// largest error of the levels with enough blocks, if there is no plateau
+    pub error: f64,
+    /// the index into `levels` of the first level of the plateau
+    pub plateau: Option<usize>,
+    /// Whether a plateau is detected. Otherwise, the blocks are not longer than the
+    /// autocorrelation time, and `error` is only a lower bound, which needs a longer
+    /// series.
+    pub reliable: bool,
+    pub levels: Vec<BlockingLevel>,
+}
+
+/// The blocking (or binning) analysis of the error of the mean of a correlated series
+/// (Flyvbjerg and Petersen): the series is repeatedly averaged over pairs of values,
+/// such that the naive error of the mean grows with the block size, until the blocks
+/// are longer than the autocorrelation time and the errors form a plateau. The plateau
+/// starts at the first of three successive levels with at least 16 blocks, whose
+/// errors differ by less than their combined uncertainties, e.g., to analyze many files
+/// unattended:
+///
+/// ```ignore
+/// let blocking = blocking(&magnetizations)?;
+/// if blocking.reliable {
+///     println!("{} {}", blocking.mean, blocking.error);
+/// }
+/// ```
+///
+/// A series without a plateau is flagged as not `reliable` and traced as a warning.
+pub fn blocking(series: &[f64]) -> Result<Blocking> {
+    let n = series.len();
+    if n < MIN_BLOCKS {
+        return Err(LargedevError::TooFewSamples)
+    }
+    let mean = series.iter().sum::<f64>() / n as f64;
+
+    let mut levels = Vec::new();
+    let mut blocks = series.to_vec();
+    let mut size = 1;
+    while blocks.len() >= 2 {
+        let m = blocks.len() as f64;
+        let variance = blocks.iter().map(|b| (b - mean).powi(2)).sum::<f64>() / m;
+        let error = (variance / (m - 1.)).sqrt();
+        let uncertainty = error / (2. * (m - 1.)).sqrt();
+        levels.push(BlockingLevel { size, blocks: blocks.len(), error, uncertainty });
+
+        // an odd last value is dropped
+        blocks = blocks.chunks_exact(2).map(|pair| (pair[0] + pair[1]) / 2.).collect();
+        size *= 2;
+    }
+
+    let usable = levels.iter().take_while(|l| l.blocks >= MIN_BLOCKS).count();
+    let agree = |a: &BlockingLevel, b: &BlockingLevel| {
+        (a.error - b.error).abs() <= a.uncertainty.hypot(b.uncertainty)
+    };
+    let plateau = levels[..usable]
+        .windows(PLATEAU_LEVELS)
+        .position(|w| w.windows(2).all(|pair| agree(&pair[0], &pair[1])));
+    let reliable = plateau.is_some();
+    let error = match plateau {
+        Some(level) => levels[level].error,
+        None => {
+            trace_event!(warn!(samples = n, "no plateau of the blocking analysis"));
+            levels[..usable].iter().map(|l| l.error).fold(0., f64::max)
+        }
+    };
+    Ok(Blocking { mean, error, plateau, reliable, levels })
+}
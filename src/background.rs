@@ -0,0 +1,181 @@
+//! Running a sampler on a blocking task of `tokio`, such that a simulation can be
+//! embedded in an async service or a GUI, which receives snapshots of its progress
+//! and can cancel it. It is only compiled with the `async` feature.
+
+use std::io;
+use std::ops::ControlFlow;
+use std::panic;
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::error::{LargedevError, Result};
+use crate::hooks::{CancelToken, SweepState};
+
+/// number of snapshots, which are buffered for a slow receiver, further ones are dropped
+const CAPACITY: usize = 16;
+
+/// A snapshot of the progress of a simulation, see `Background`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Progress {
+    /// number of completed sweeps, or samples for `Simple`
+    pub sweep: usize,
+    /// current value of the model
+    pub energy: f64,
+    /// number of proposed changes so far, `0` for `Simple`
+    pub tries: usize,
+    /// number of rejected changes so far
+    pub rejects: usize,
+    /// number of invalid proposals so far
+    pub invalid: usize,
+    /// current logarithmic refinement parameter of Wang-Landau sampling
+    pub lnf: Option<f64>,
+    /// time since the start of the simulation
+    pub elapsed: Duration,
+}
+
+/// A simulation running on a blocking task of the current `tokio` runtime. The closure
+/// passed to `spawn` builds and runs the sampler and gets a `Reporter`, whose hooks
+/// send snapshots of the progress at most once per `interval` and whose `CancelToken`
+/// stops the sampler, when `cancel` is called, e.g.:
+///
+/// ```ignore
+/// let mut task = Background::spawn(Duration::from_secs(1), move |reporter| {
+///     WangLandau::new(model)
+///         .on_sweep(reporter.hook())
+///         .cancel_token(reporter.cancel_token())
+///         .build()?
+///         .run_seeded(&mut outfile)
+/// });
+/// while let Some(progress) = task.progress().await {
+///     println!("t = {}, ln f = {:?}", progress.sweep, progress.lnf);
+/// }
+/// let report = task.join().await?;
+/// ```
+///
+/// The snapshots end, when the simulation is finished. If the receiver is too slow,
+/// snapshots are dropped instead of blocking the simulation.
+pub struct Background<T> {
+    handle: JoinHandle<Result<T>>,
+    progress: mpsc::Receiver<Progress>,
+    cancel: CancelToken,
+}
+
+impl<T: Send + 'static> Background<T> {
+    /// Start `job` on a blocking task, it must be called within a `tokio` runtime.
+    pub fn spawn<F>(interval: Duration, job: F) -> Self
+    where
+        F: FnOnce(Reporter) -> Result<T> + Send + 'static,
+    {
+        let (sender, progress) = mpsc::channel(CAPACITY);
+        let cancel = CancelToken::new();
+        let reporter = Reporter {
+            sender,
+            cancel: cancel.clone(),
+            interval,
+        };
+        let handle = tokio::task::spawn_blocking(move || job(reporter));
+        Background { handle, progress, cancel }
+    }
+
+    /// the next snapshot of the progress, `None` after the simulation is finished
+    pub async fn progress(&mut self) -> Option<Progress> {
+        self.progress.recv().await
+    }
+
+    /// Request the simulation to stop, it returns its partial results as usual.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// the token, which cancels the simulation, e.g., for a callback of a GUI
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// whether the simulation is finished
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Wait for the end of the simulation and return its result. A panic of the
+    /// simulation is resumed here.
+    pub async fn join(self) -> Result<T> {
+        match self.handle.await {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+            Err(e) => Err(LargedevError::Io(io::Error::other(e))),
+        }
+    }
+}
+
+/// The connection of a simulation in a `Background` task to its owner, see
+/// `Background::spawn`.
+#[derive(Clone, Debug)]
+pub struct Reporter {
+    sender: mpsc::Sender<Progress>,
+    cancel: CancelToken,
+    interval: Duration,
+}
+
+impl Reporter {
+    /// the token, with which `Background::cancel` stops the sampler
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// a sweep hook, which sends a snapshot of the state after the first sweep and
+    /// then at most once per interval
+    pub fn hook<M>(&self) -> impl FnMut(&M, &SweepState) -> ControlFlow<()> + 'static {
+        let mut throttle = self.throttle();
+        move |_, state| {
+            throttle(&|elapsed| Progress {
+                sweep: state.sweep,
+                energy: state.energy,
+                tries: state.tries,
+                rejects: state.rejects,
+                invalid: state.invalid,
+                lnf: state.lnf,
+                elapsed,
+            });
+            ControlFlow::Continue(())
+        }
+    }
+
+    /// a sample hook for `Simple`, which sends snapshots like `hook`
+    pub fn sample_hook<M>(&self) -> impl FnMut(&M, f64) -> ControlFlow<()> + 'static {
+        let mut throttle = self.throttle();
+        let mut samples = 0;
+        move |_, value| {
+            samples += 1;
+            throttle(&|elapsed| Progress {
+                sweep: samples,
+                energy: value,
+                tries: 0,
+                rejects: 0,
+                invalid: 0,
+                lnf: None,
+                elapsed,
+            });
+            ControlFlow::Continue(())
+        }
+    }
+
+    /// a function, which sends the snapshot created by its argument from the elapsed
+    /// time, if the interval since the last one has passed
+    fn throttle(&self) -> impl FnMut(&dyn Fn(Duration) -> Progress) + 'static {
+        let sender = self.sender.clone();
+        let interval = self.interval;
+        let start = Instant::now();
+        let mut last: Option<Instant> = None;
+        move |progress| {
+            if last.is_none_or(|time| time.elapsed() >= interval) {
+                last = Some(Instant::now());
+                // a full channel or a dropped receiver must not disturb the simulation
+                let _ = sender.try_send(progress(start.elapsed()));
+            }
+        }
+    }
+}
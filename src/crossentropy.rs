@@ -0,0 +1,356 @@
+use std::io::{BufWriter, Write};
+
+use rand::Rng;
+
+use crate::simple::DirectSamplable;
+use crate::error::{LargedevError, Result};
+use crate::report::CrossEntropyReport;
+use crate::seed::Seed;
+use crate::hooks::CancelToken;
+
+/// An extension of `DirectSamplable` for models, whose samples are drawn from a
+/// parametric family of distributions `f(x; v)`, e.g., independent exponentials with
+/// tunable means, where the initial parameters of the model are the nominal ones. It
+/// is needed by `CrossEntropy`.
+pub trait Parametric: DirectSamplable {
+    /// the current parameters, with which `reconstruct` samples
+    fn parameters(&self) -> Vec<f64>;
+
+    fn set_parameters(&mut self, parameters: &[f64]);
+
+    /// the logarithm of the density `f(x; parameters)` of the current sample `x`, up to
+    /// a constant, which does not depend on the parameters
+    fn ln_density(&self, parameters: &[f64]) -> f64;
+
+    /// the statistics of the current sample, which determine the maximum likelihood
+    /// estimate of the parameters, e.g., the sample itself for exponentials
+    fn statistics(&self) -> Vec<f64>;
+
+    /// The maximum likelihood estimate of the parameters for samples, whose weighted
+    /// mean of the `statistics` is `mean`. For natural exponential families, the
+    /// statistics are the sufficient ones and this is moment matching, e.g., the means
+    /// of exponentials are just `mean`.
+    fn fit(&self, mean: &[f64]) -> Vec<f64>;
+}
+
+/// Parameters of the cross-entropy method, see `CrossEntropy` for the builder.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CrossEntropyConfig {
+    /// the value `a`, whose tail probability `P(value >= a)` is estimated
+    pub threshold: f64,
+    /// number of samples per iteration
+    pub samples: usize,
+    /// fraction of elite samples, which determine the next parameters
+    pub rho: f64,
+    /// weight of the new parameters, the rest is kept from the old ones
+    pub smoothing: f64,
+    /// number of samples of the final importance sampling
+    pub final_samples: usize,
+    /// the largest number of iterations before the simulation is stopped
+    pub max_iterations: usize,
+    /// seed for `run_seeded`
+    pub seed: Option<Seed>,
+}
+
+impl Default for CrossEntropyConfig {
+    fn default() -> Self {
+        CrossEntropyConfig {
+            threshold: 0.,
+            samples: 1000,
+            rho: 0.1,
+            smoothing: 1.,
+            final_samples: 10000,
+            max_iterations: 100,
+            seed: None,
+        }
+    }
+}
+
+impl CrossEntropyConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.threshold.is_nan() {
+            return Err(LargedevError::InvalidParameter("threshold must not be NaN".into()))
+        }
+        if !(self.rho > 0. && self.rho < 1.) {
+            return Err(LargedevError::InvalidParameter(format!("rho must be in (0, 1), not {}", self.rho)))
+        }
+        if (self.rho * self.samples as f64) < 1. {
+            return Err(LargedevError::InvalidParameter("at least one elite sample per iteration is needed".into()))
+        }
+        if !(self.smoothing > 0. && self.smoothing <= 1.) {
+            return Err(LargedevError::InvalidParameter(format!("smoothing must be in (0, 1], not {}", self.smoothing)))
+        }
+        if self.final_samples < 2 {
+            return Err(LargedevError::InvalidParameter("final_samples must be at least 2 to estimate the error".into()))
+        }
+        Ok(())
+    }
+}
+
+/// A builder used to set up the cross-entropy method to estimate the probability
+/// `P(value >= a)` of a rare event under the nominal parameters of a `Parametric`
+/// model. Starting from the nominal parameters, every iteration draws `samples`
+/// samples, sets the level to the `1 - rho` quantile of their values, but at most `a`,
+/// and fits the parameters to the samples above the level, weighted with their
+/// likelihood ratio of the nominal and the current parameters. This tilts the
+/// distribution towards the rare event, until the level reaches `a`. The final
+/// estimate is importance sampling with the tilted parameters, see Rubinstein and
+/// Kroese, The Cross-Entropy Method (2004), e.g.:
+///
+/// ```ignore
+/// let report = CrossEntropy::new(model)
+///    .threshold(100.)
+///    .samples(1000)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// println!("P = {} +- {}", report.probability, report.error);
+/// ```
+///
+/// The output has a line with every level and the parameters fitted to it. The model
+/// is left with the final parameters.
+pub struct CrossEntropy<P> {
+    model: P,
+    config: CrossEntropyConfig,
+    cancel: Option<CancelToken>,
+}
+
+impl<P: Parametric> CrossEntropy<P> {
+    pub fn new(model: P) -> Self {
+        CrossEntropy::with_config(model, CrossEntropyConfig::default())
+    }
+
+    pub fn with_config(model: P, config: CrossEntropyConfig) -> Self {
+        CrossEntropy::<P> {
+            model,
+            config,
+            cancel: None,
+        }
+    }
+
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.config.threshold = threshold;
+        self
+    }
+
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.config.samples = samples;
+        self
+    }
+
+    pub fn rho(mut self, rho: f64) -> Self {
+        self.config.rho = rho;
+        self
+    }
+
+    pub fn smoothing(mut self, smoothing: f64) -> Self {
+        self.config.smoothing = smoothing;
+        self
+    }
+
+    pub fn final_samples(mut self, final_samples: usize) -> Self {
+        self.config.final_samples = final_samples;
+        self
+    }
+
+    pub fn max_iterations(mut self, max_iterations: usize) -> Self {
+        self.config.max_iterations = max_iterations;
+        self
+    }
+
+    /// the seed used by `run_seeded`, if not set, a seed is drawn from entropy
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Self {
+        self.config.seed = Some(seed.into());
+        self
+    }
+
+    /// a token to cancel the simulation, see `CancelToken`
+    pub fn cancel_token(mut self, token: impl Into<CancelToken>) -> Self {
+        self.cancel = Some(token.into());
+        self
+    }
+
+    pub fn model(&self) -> &P {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut P {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> P {
+        self.model
+    }
+
+    /// validate the parameters and return the configured sampler
+    pub fn build(self) -> Result<CrossEntropyRun<P>> {
+        self.config.validate()?;
+        Ok(CrossEntropyRun {
+            model: self.model,
+            config: self.config,
+            cancel: self.cancel,
+        })
+    }
+}
+
+/// A configured cross-entropy method, see `CrossEntropy` for the builder.
+pub struct CrossEntropyRun<P> {
+    model: P,
+    config: CrossEntropyConfig,
+    cancel: Option<CancelToken>,
+}
+
+impl<P: Parametric> CrossEntropyRun<P> {
+    pub fn config(&self) -> &CrossEntropyConfig {
+        &self.config
+    }
+
+    /// the current state of the model
+    pub fn model(&self) -> &P {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut P {
+        &mut self.model
+    }
+
+    /// consume the sampler and return the model in its current state
+    pub fn into_model(self) -> P {
+        self.model
+    }
+
+    pub fn run(&mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<CrossEntropyReport> {
+        self.simulate(&mut rng, file, None)
+    }
+
+    /// like `run`, but with a random number generator initialized from the configured
+    /// `seed` (or from entropy), which is written to the output and the report
+    pub fn run_seeded(&mut self, file: &mut impl Write) -> Result<CrossEntropyReport> {
+        let seed = self.config.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate(&mut seed.rng(), file, Some(seed))
+    }
+
+    /// draw a sample with the current parameters and return its value and the
+    /// logarithm of its likelihood ratio of the `nominal` and the current parameters
+    fn sample(&mut self, nominal: &[f64], current: &[f64], rng: &mut impl Rng) -> (f64, f64) {
+        self.model.reconstruct(rng);
+        let ln_w = self.model.ln_density(nominal) - self.model.ln_density(current);
+        (self.model.value(), ln_w)
+    }
+
+    fn simulate(&mut self, mut rng: &mut impl Rng, file: &mut impl Write, seed: Option<Seed>) -> Result<CrossEntropyReport> {
+        // buffer the output, since it is written iteration by iteration
+        let mut file = BufWriter::new(file);
+
+        if let Some(seed) = seed {
+            writeln!(file, "# seed = {}", seed)?;
+        }
+        writeln!(file, "# level, parameters")?;
+
+        let threshold = self.config.threshold;
+        let n = self.config.samples;
+        let nominal = self.model.parameters();
+        let mut parameters = nominal.clone();
+        let mut iterations = 0;
+        let mut stopped = false;
+        // buffers reused to avoid allocations
+        let mut values = Vec::with_capacity(n);
+        let mut ln_weights = Vec::with_capacity(n);
+        let mut statistics = Vec::with_capacity(n);
+        let mut sorted = Vec::with_capacity(n);
+
+        trace_span!(_span = info_span!("cross_entropy", threshold, samples = n, rho = self.config.rho));
+        loop {
+            if iterations >= self.config.max_iterations || self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                stopped = true;
+                break;
+            }
+            iterations += 1;
+
+            values.clear();
+            ln_weights.clear();
+            statistics.clear();
+            for _ in 0..n {
+                let (value, ln_w) = self.sample(&nominal, &parameters, &mut rng);
+                values.push(value);
+                ln_weights.push(ln_w);
+                statistics.push(self.model.statistics());
+            }
+            if values.iter().any(|v| v.is_nan()) {
+                return Err(LargedevError::NanValue)
+            }
+            sorted.clone_from(&values);
+            sorted.sort_by(f64::total_cmp);
+            let quantile = sorted[((1. - self.config.rho) * n as f64) as usize];
+            let level = quantile.min(threshold);
+
+            // weighted mean of the statistics of the elite samples, relative to the
+            // largest weight to avoid overflows
+            let elite: Vec<usize> = (0..n).filter(|&i| values[i] >= level).collect();
+            let max = elite.iter().map(|&i| ln_weights[i]).fold(f64::NEG_INFINITY, f64::max);
+            let mut sum = 0.;
+            let mut mean = vec![0.; statistics[0].len()];
+            for &i in &elite {
+                let w = (ln_weights[i] - max).exp();
+                sum += w;
+                for (m, s) in mean.iter_mut().zip(&statistics[i]) {
+                    *m += w * s;
+                }
+            }
+            for m in &mut mean {
+                *m /= sum;
+            }
+            let fitted = self.model.fit(&mean);
+            for (p, f) in parameters.iter_mut().zip(&fitted) {
+                *p = self.config.smoothing * f + (1. - self.config.smoothing) * *p;
+            }
+            self.model.set_parameters(&parameters);
+
+            let formatted: Vec<String> = parameters.iter().map(|p| p.to_string()).collect();
+            writeln!(file, "{} {}", level, formatted.join(" "))?;
+            trace_event!(debug!(iterations, level, "level"));
+            if level >= threshold {
+                break;
+            }
+        }
+
+        // importance sampling with the tilted parameters
+        ln_weights.clear();
+        for _ in 0..self.config.final_samples {
+            let (value, ln_w) = self.sample(&nominal, &parameters, &mut rng);
+            if value >= threshold {
+                ln_weights.push(ln_w);
+            }
+        }
+        let n = self.config.final_samples as f64;
+        let (ln_probability, error) = if ln_weights.is_empty() {
+            (f64::NEG_INFINITY, 0.)
+        } else {
+            let max = ln_weights.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            let sum: f64 = ln_weights.iter().map(|w| (w - max).exp()).sum();
+            let sum2: f64 = ln_weights.iter().map(|w| (2. * (w - max)).exp()).sum();
+            let mean = sum / n;
+            // standard error of the mean of the weighted indicators
+            let variance = (sum2 / n - mean * mean) / (n - 1.);
+            (max + mean.ln(), max.exp() * variance.max(0.).sqrt())
+        };
+        let probability = ln_probability.exp();
+        file.flush()?;
+        trace_event!(info!(iterations, probability, stopped, "finished"));
+
+        Ok(CrossEntropyReport {
+            probability,
+            ln_probability,
+            error,
+            parameters,
+            iterations,
+            seed,
+            stopped,
+        })
+    }
+
+    pub fn exec(mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<P> {
+        self.run(&mut rng, file)?;
+        Ok(self.model)
+    }
+}
@@ -0,0 +1,155 @@
+use std::io::{self, BufWriter, Write};
+
+use ::mpi::traits::*;
+
+use crate::histogram::Histogram;
+use crate::markovchain::MarkovChain;
+use crate::error::{LargedevError, Result};
+use crate::report::Report;
+use crate::seed::Seed;
+use crate::wanglandau;
+use crate::windows::WindowedWangLandauRun;
+
+/// the rank, which merges the windows and writes the output
+const ROOT: i32 = 0;
+
+impl<MC: MarkovChain + Clone + Send> WindowedWangLandauRun<MC> {
+    /// Like `run_seeded`, but the windows are distributed over all processes of the
+    /// MPI communicator `world`, such that the window `k` is simulated by the rank
+    /// `k % size`, which uses its own thread pool for its windows. All ranks need to
+    /// call this method with the same configuration. The master seed of the root rank
+    /// is used by all ranks. The root rank collects the densities of states, merges
+    /// them and writes them to `file`, which is ignored on the other ranks, e.g.:
+    ///
+    /// ```ignore
+    /// let universe = mpi::initialize().unwrap();
+    /// let world = universe.world();
+    /// let mut file = if world.rank() == 0 { Some(File::create("dos.dat")?) } else { None };
+    /// let report = WindowedWangLandau::new(model)
+    ///    .range(low, high)
+    ///    .windows(64)
+    ///    .build()?
+    ///    .run_mpi(&world, file.as_mut())?;
+    /// ```
+    ///
+    /// The report of the root rank accumulates the reports of all windows, the other
+    /// ranks report only their own windows. If any window fails, the root rank reports
+    /// the error after it received the messages of all ranks. Only the windows of
+    /// Wang-Landau sampling are distributed, the replicas of `ParallelTempering` are
+    /// always simulated by the threads of a single process. The pre-runs of
+    /// `WindowedWangLandau::adapt` are not distributed, so a configuration with
+    /// `adapt_rounds > 0` is rejected by all ranks, before any of them communicates.
+    pub fn run_mpi(&mut self, world: &impl Communicator, file: Option<&mut impl Write>) -> Result<Report> {
+        if self.config().adapt_rounds > 0 {
+            return Err(LargedevError::InvalidParameter("the windows can not be adapted by run_mpi, use fixed boundaries".into()))
+        }
+        let rank = world.rank();
+        let size = world.size() as usize;
+        let root = world.process_at_rank(ROOT);
+
+        let mut seed = self.config().wang_landau.seed.unwrap_or_else(Seed::from_entropy).value();
+        root.broadcast_into(&mut seed);
+        let seed = Seed::new(seed);
+
+        let own = self.simulate_windows(seed, |k| k % size == rank as usize);
+
+        if rank != ROOT {
+            // an empty message signals the failure of this rank to the root
+            let message = match &own {
+                Ok(windows) => windows.iter().map(|(g, r)| encode(g, r)).collect(),
+                Err(_) => vec![Vec::new(); count(self.config().windows, size, rank as usize)],
+            };
+            for m in message {
+                root.send(&m[..]);
+            }
+            let mut report = Report::default();
+            for (_, r) in own? {
+                report.tries += r.tries;
+                report.rejects += r.rejects;
+                report.invalid += r.invalid;
+                report.stopped |= r.stopped;
+            }
+            report.seed = Some(seed);
+            return Ok(report)
+        }
+
+        // receive the messages of all ranks before any error is reported, otherwise
+        // the other ranks would block forever in their sends
+        let received: Vec<Option<Vec<f64>>> = (0..self.config().windows)
+            .map(|k| {
+                let source = (k % size) as i32;
+                if source == ROOT {
+                    None
+                } else {
+                    Some(world.process_at_rank(source).receive_vec::<f64>().0)
+                }
+            })
+            .collect();
+
+        let mut own = own?.into_iter();
+        let mut windows = Vec::with_capacity(self.config().windows);
+        for (k, message) in received.into_iter().enumerate() {
+            match message {
+                None => windows.push(own.next().unwrap()),
+                Some(message) if message.is_empty() => {
+                    let msg = format!("window {} failed on rank {}", k, k % size);
+                    return Err(LargedevError::Io(io::Error::other(msg)))
+                }
+                Some(message) => windows.push(decode(&message)?),
+            }
+        }
+
+        let (g, mut report) = self.merge_windows(windows)?;
+        report.seed = Some(seed);
+        report.streams = (0..self.config().windows).map(|k| seed.stream(k as u64)).collect();
+
+        let file = file.ok_or_else(|| LargedevError::InvalidParameter("the root rank needs an output file".into()))?;
+        let mut file = BufWriter::new(file);
+        wanglandau::write_dos(&mut file, &g, Some(seed), &report.streams)?;
+        file.flush()?;
+
+        Ok(report)
+    }
+}
+
+/// number of windows of `rank`, if `windows` windows are distributed over `size` ranks
+fn count(windows: usize, size: usize, rank: usize) -> usize {
+    (0..windows).filter(|k| k % size == rank).count()
+}
+
+/// pack a density of states and its report into one message,
+/// `[low, high, bins, tries, rejects, invalid, stopped, data...]`
+fn encode(g: &Histogram, report: &Report) -> Vec<f64> {
+    let (low, high) = g.bounds();
+    let mut message = vec![
+        low,
+        high,
+        g.bins() as f64,
+        report.tries as f64,
+        report.rejects as f64,
+        report.invalid as f64,
+        if report.stopped { 1. } else { 0. },
+    ];
+    message.extend_from_slice(g.data());
+    message
+}
+
+/// unpack a message of `encode`
+fn decode(message: &[f64]) -> Result<(Histogram, Report)> {
+    let mut g = Histogram::new(message[0], message[1], message[2] as usize)?;
+    for (n, &d) in message[7..].iter().enumerate() {
+        *g.idx(n) = d;
+    }
+    let report = Report {
+        tries: message[3] as usize,
+        rejects: message[4] as usize,
+        invalid: message[5] as usize,
+        stopped: message[6] != 0.,
+        seed: None,
+        streams: Vec::new(),
+        phases: Vec::new(),
+        observables: Vec::new(),
+        ladder: Vec::new(),
+    };
+    Ok((g, report))
+}
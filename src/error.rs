@@ -0,0 +1,57 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// The error type of all fallible operations of this crate.
+#[derive(Debug)]
+pub enum LargedevError {
+    /// a parameter of a sampler is outside of its valid range
+    InvalidParameter(String),
+    /// a model can not be constructed with the requested configuration, e.g., a
+    /// lattice without spins
+    InvalidConfig(String),
+    /// a histogram can not be constructed or modified as requested, e.g., `low >= high`
+    HistogramRange(String),
+    /// not enough samples were gathered to calculate the requested statistics
+    TooFewSamples,
+    /// the value of a sample is NaN, which can not be ordered
+    NanValue,
+    /// writing the output failed
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, LargedevError>;
+
+impl fmt::Display for LargedevError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LargedevError::InvalidParameter(msg) => write!(f, "invalid parameter: {}", msg),
+            LargedevError::InvalidConfig(msg) => write!(f, "invalid configuration: {}", msg),
+            LargedevError::HistogramRange(msg) => write!(f, "histogram range error: {}", msg),
+            LargedevError::TooFewSamples => write!(f, "too few samples"),
+            LargedevError::NanValue => write!(f, "the value of a sample is NaN"),
+            LargedevError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl error::Error for LargedevError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            LargedevError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LargedevError {
+    fn from(e: io::Error) -> Self {
+        LargedevError::Io(e)
+    }
+}
+
+impl From<fmt::Error> for LargedevError {
+    fn from(e: fmt::Error) -> Self {
+        LargedevError::Io(io::Error::other(e))
+    }
+}
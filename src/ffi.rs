@@ -0,0 +1,134 @@
+//! An adapter for models, which are implemented in C or any other language with a C
+//! interface, e.g., Fortran with `iso_c_binding`. It is only compiled with the `ffi`
+//! feature.
+
+use std::os::raw::c_void;
+
+use rand::Rng;
+
+use crate::Model;
+use crate::markovchain::MarkovChain;
+
+/// A function, which returns a uniform random number in `[0, 1)` from the random number
+/// generator `rng` of the sampler, which is passed to `change`.
+pub type UniformFn = unsafe extern "C" fn(rng: *mut c_void) -> f64;
+
+/// The functions of a model implemented in C, which all receive the opaque pointer to
+/// the state of the model, e.g., in C:
+///
+/// ```c
+/// typedef struct { double x, old; } walker;
+///
+/// double value(const void *state) { return ((const walker *) state)->x; }
+///
+/// void change(void *state, double (*uniform)(void *), void *rng) {
+///     walker *w = state;
+///     w->old = w->x;
+///     w->x += uniform(rng) - 0.5;
+/// }
+///
+/// void undo(void *state) { walker *w = state; w->x = w->old; }
+/// ```
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CModelFunctions {
+    /// the value of the current state, see `Model::value`
+    pub value: unsafe extern "C" fn(state: *const c_void) -> f64,
+    /// propose a change, random numbers are drawn by calling `uniform(rng)`,
+    /// see `MarkovChain::change`
+    pub change: unsafe extern "C" fn(state: *mut c_void, uniform: UniformFn, rng: *mut c_void),
+    /// undo the last change, see `MarkovChain::undo`
+    pub undo: unsafe extern "C" fn(state: *mut c_void),
+    /// free the state, when the `CModel` is dropped, may be `NULL`
+    pub drop: Option<unsafe extern "C" fn(state: *mut c_void)>,
+}
+
+/// A model defined by C functions over an opaque state, which implements
+/// `MarkovChain`, such that it can be used with all samplers, which do not need
+/// to send the model to other threads, e.g.:
+///
+/// ```ignore
+/// extern "C" {
+///     fn walker_new() -> *mut c_void;
+///     fn walker_free(state: *mut c_void);
+///     fn value(state: *const c_void) -> f64;
+///     fn change(state: *mut c_void, uniform: UniformFn, rng: *mut c_void);
+///     fn undo(state: *mut c_void);
+/// }
+///
+/// let functions = CModelFunctions { value, change, undo, drop: Some(walker_free) };
+/// let model = unsafe { CModel::new(walker_new(), functions) };
+/// let report = Metropolis::new(model)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+#[derive(Debug)]
+pub struct CModel {
+    state: *mut c_void,
+    functions: CModelFunctions,
+}
+
+impl CModel {
+    /// Wrap the `state` of a C model, which takes ownership of it, if `functions.drop`
+    /// is given.
+    ///
+    /// # Safety
+    ///
+    /// The `state` has to be valid for the functions as long as the `CModel` lives,
+    /// and the functions must not unwind.
+    pub unsafe fn new(state: *mut c_void, functions: CModelFunctions) -> Self {
+        CModel {
+            state,
+            functions,
+        }
+    }
+
+    /// the opaque pointer to the state of the model
+    pub fn state(&self) -> *mut c_void {
+        self.state
+    }
+
+    pub fn functions(&self) -> &CModelFunctions {
+        &self.functions
+    }
+}
+
+impl Drop for CModel {
+    fn drop(&mut self) {
+        if let Some(drop) = self.functions.drop {
+            // safety: the state is valid until now by the contract of `new`
+            unsafe { drop(self.state) }
+        }
+    }
+}
+
+/// draw a uniform random number from the generator of the type `R` behind `rng`
+unsafe extern "C" fn uniform<R: Rng>(rng: *mut c_void) -> f64 {
+    unsafe { (*(rng as *mut R)).gen() }
+}
+
+/// the `uniform` function for the generator `rng` and the pointer to pass to it
+fn trampoline<R: Rng>(rng: &mut R) -> (UniformFn, *mut c_void) {
+    (uniform::<R>, rng as *mut R as *mut c_void)
+}
+
+impl Model for CModel {
+    fn value(&self) -> f64 {
+        // safety: the state is valid by the contract of `new`
+        unsafe { (self.functions.value)(self.state) }
+    }
+}
+
+impl MarkovChain for CModel {
+    fn change(&mut self, rng: &mut impl Rng) {
+        let (uniform, rng) = trampoline(rng);
+        // safety: the state is valid by the contract of `new`, the pointer to the
+        // generator is only used by `uniform` during this call
+        unsafe { (self.functions.change)(self.state, uniform, rng) }
+    }
+
+    fn undo(&mut self) {
+        // safety: the state is valid by the contract of `new`
+        unsafe { (self.functions.undo)(self.state) }
+    }
+}
@@ -1,57 +1,128 @@
-use ordered_float::NotNan;
+use std::fmt;
+use std::ops::AddAssign;
 
+
+use crate::error::{LargedevError, Result};
+use crate::special::{gamma_q, kolmogorov_q};
+
+/// size of the charts of `to_ascii`, which the samplers emit in their verbose mode
+#[cfg(feature = "tracing")]
+pub(crate) const ASCII_WIDTH: usize = 72;
+#[cfg(feature = "tracing")]
+pub(crate) const ASCII_HEIGHT: usize = 12;
+
+/// The floating point type of the bins of a `Histogram`, i.e., `f64` or `f32`.
+pub trait Float: Copy + Default + PartialOrd + AddAssign + fmt::Debug + fmt::Display + Send + Sync + 'static {
+    fn from_f64(x: f64) -> Self;
+    fn to_f64(self) -> f64;
+}
+
+impl Float for f64 {
+    #[inline]
+    fn from_f64(x: f64) -> Self {
+        x
+    }
+
+    #[inline]
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+impl Float for f32 {
+    #[inline]
+    fn from_f64(x: f64) -> Self {
+        x as f32
+    }
+
+    #[inline]
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+/// A histogram of equally wide bins of the type `T`, which is `f64` by default. A
+/// `Histogram<f32>` halves the memory of large histograms, e.g., of a density of states
+/// for storage or postprocessing. Its precision of about seven digits suffices for
+/// counts up to `2^24` per bin, but not to accumulate the small increments of `ln f`
+/// of Wang-Landau sampling, which therefore uses `f64` internally. `convert` changes
+/// the type of the bins.
 #[derive(Clone, Debug)]
-pub struct Histogram {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Histogram<T = f64> {
     low: f64,
     high: f64,
     bins: usize,
-    histogram: Vec<f64>,
+    /// `bins / (high - low)`, to map values to bins with a multiplication
+    scale: f64,
+    histogram: Vec<T>,
 }
 
-/// Als always, low is included, high is excluded
 impl Histogram {
-    pub fn new(low: f64, high: f64, bins: usize) -> Histogram {
-        assert!(low < high);
-        Histogram {
+    pub fn new(low: f64, high: f64, bins: usize) -> Result<Histogram> {
+        Histogram::with_precision(low, high, bins)
+    }
+}
+
+/// Als always, low is included, high is excluded
+impl<T: Float> Histogram<T> {
+    /// like `new`, but with bins of the type `T`, e.g.,
+    /// `Histogram::<f32>::with_precision(0., 1., 1 << 24)`
+    pub fn with_precision(low: f64, high: f64, bins: usize) -> Result<Histogram<T>> {
+        if low.is_nan() || high.is_nan() || low >= high {
+            return Err(LargedevError::HistogramRange(format!("low ({}) must be smaller than high ({})", low, high)))
+        }
+        if bins == 0 {
+            return Err(LargedevError::HistogramRange("a histogram needs at least one bin".into()))
+        }
+        Ok(Histogram {
             low,
             high,
             bins,
-            histogram: vec![0.; bins],
+            scale: bins as f64 / (high - low),
+            histogram: vec![T::default(); bins],
+        })
+    }
+
+    /// the index of the bin containing `value`, or `None` if it is outside of the range
+    /// (or NaN)
+    #[inline]
+    pub fn index_of(&self, value: f64) -> Option<usize> {
+        let x = (value - self.low) * self.scale;
+        // a single range check, which also catches rounding to `bins` at `high`
+        if x >= 0. && x < self.bins as f64 {
+            Some(x as usize)
+        } else {
+            None
         }
     }
 
     pub fn add(&mut self, value: f64, amount: f64) {
-        if value >= self.low && value < self.high {
-            self.histogram[((value-self.low)/(self.high - self.low) * self.bins as f64) as usize] += amount;
+        if let Some(idx) = self.index_of(value) {
+            self.histogram[idx] += T::from_f64(amount);
         }
     }
 
     pub fn count(&mut self, value: f64) {
-        if value >= self.low && value < self.high {
-            self.histogram[((value-self.low)/(self.high - self.low) * self.bins as f64) as usize] += 1.;
-        }
+        self.add(value, 1.)
     }
 
+    /// the smallest entry, `NaN` entries are ignored and it is infinite if all are `NaN`
     pub fn min(&self) -> f64 {
-        self.histogram.iter().map(|x| NotNan::new(*x).unwrap()).min().unwrap().into_inner()
+        self.histogram.iter().map(|x| x.to_f64()).fold(f64::INFINITY, f64::min)
     }
 
     pub fn at(&self, value: f64) -> Option<f64> {
-        if value >= self.low && value < self.high {
-            let idx = ((value-self.low)/(self.high - self.low) * self.bins as f64) as usize;
-            Some(self.histogram[idx])
-        } else {
-            None
-        }
+        self.index_of(value).map(|idx| self.histogram[idx].to_f64())
     }
 
-    pub fn idx(&mut self, idx: usize) -> &mut f64 {
+    pub fn idx(&mut self, idx: usize) -> &mut T {
         &mut self.histogram[idx]
     }
 
     pub fn reset(&mut self) {
         for i in &mut self.histogram {
-            *i = 0.;
+            *i = T::default();
         }
     }
 
@@ -60,7 +131,36 @@ impl Histogram {
     }
 
     pub fn mean(&self) -> f64 {
-        self.histogram.iter().sum::<f64>() / self.bins as f64
+        self.histogram.iter().map(|x| x.to_f64()).sum::<f64>() / self.bins as f64
+    }
+
+    /// The mean of the binned values, i.e., of the bin centers weighted by the counts,
+    /// unlike `mean`, which is the mean height of the bins. It is `NaN` for a histogram
+    /// without entries.
+    pub fn mean_value(&self) -> f64 {
+        let (total, sum) = self.centers().iter()
+            .zip(&self.histogram)
+            .fold((0., 0.), |(total, sum), (c, x)| (total + x.to_f64(), sum + c * x.to_f64()));
+        sum / total
+    }
+
+    /// The variance of the binned values, see `central_moment`. The binning adds about
+    /// `w^2 / 12` for bins of the width `w` (Sheppard's correction), which is not
+    /// subtracted.
+    pub fn variance_value(&self) -> f64 {
+        self.central_moment(2)
+    }
+
+    /// The central moment `<(x - <x>)^order>` of the binned values, with every value
+    /// at the center of its bin, such that the skewness is `central_moment(3) /
+    /// variance_value().powf(1.5)`, without keeping the samples. It is `NaN` for a
+    /// histogram without entries.
+    pub fn central_moment(&self, order: i32) -> f64 {
+        let mean = self.mean_value();
+        let (total, sum) = self.centers().iter()
+            .zip(&self.histogram)
+            .fold((0., 0.), |(total, sum), (c, x)| (total + x.to_f64(), sum + (c - mean).powi(order) * x.to_f64()));
+        sum / total
     }
 
     pub fn hist(&self) -> Vec<(f64, f64)> {
@@ -68,7 +168,7 @@ impl Histogram {
             .enumerate()
             .map(|(n, &x)| (
                     (n as f64 / self.bins as f64) * (self.high - self.low) + self.low,
-                    x
+                    x.to_f64()
                 )
             )
             .collect()
@@ -78,44 +178,35 @@ impl Histogram {
         (self.low, self.high)
     }
 
-    /// shrink the range such that there are no zero bins
-    /// panics if the zero bins are not at the border
-    pub fn trim(&mut self) {
-        // trim left
-        let mut i = 0;
-        let lb = loop {
-            if *self.idx(i) > 0. {
-                break i
-            }
-            i += 1;
-        };
-        if lb > 0 {
-            let lower = self.low + lb as f64 * (self.high - self.low) / self.bins as f64;
-            let num_bins = self.bins - lb;
-            let mut hist = vec![0.; num_bins];
-            for i in 0..num_bins {
-                hist[i] = self.histogram[i+lb]
-            }
-            self.low = lower;
-            self.bins = num_bins;
-            self.histogram = hist;
-        }
+    /// shrink the range such that there are no zero bins at the borders
+    /// fails if all bins are zero
+    pub fn trim(&mut self) -> Result<()> {
+        let (first, end) = self.occupied()
+            .ok_or_else(|| LargedevError::HistogramRange("can not trim a histogram without entries".into()))?;
+        self.crop(first, end);
+        Ok(())
+    }
 
-        // trim right
-        let mut i = 0;
-        let rb = loop {
-            if *self.idx(self.bins - 1 - i) > 0. {
-                break i
-            }
-            i += 1;
-        };
-        if rb > 0 {
-            let higher = self.low + (self.bins - rb) as f64 * (self.high - self.low) / self.bins as f64;
-            let num_bins = self.bins - rb;
-            self.histogram.resize_with(num_bins, || panic!());
-            self.high = higher;
-            self.bins = num_bins;
-        }
+    /// the first and one after the last nonzero bin, `None` if all bins are zero
+    pub(crate) fn occupied(&self) -> Option<(usize, usize)> {
+        let first = self.histogram.iter().position(|&x| x > T::default())?;
+        // there is at least one nonzero entry, so `rposition` always succeeds
+        let last = self.histogram.iter().rposition(|&x| x > T::default()).unwrap();
+        Some((first, last + 1))
+    }
+
+    /// shrink the range to the bins `first..end`, e.g., to trim a histogram like
+    /// another one on the same bins
+    pub(crate) fn crop(&mut self, first: usize, end: usize) {
+        debug_assert!(first < end && end <= self.bins);
+        let lower = self.left_border(first);
+        let higher = self.left_border(end);
+        self.histogram.truncate(end);
+        self.histogram.drain(..first);
+        self.low = lower;
+        self.high = higher;
+        self.bins = end - first;
+        self.scale = self.bins as f64 / (self.high - self.low);
     }
 
     fn left_border(&self, n: usize) -> f64 {
@@ -127,14 +218,545 @@ impl Histogram {
     }
 
     pub fn borders(&self) -> Vec<f64> {
-        (0..self.bins+1).map(|i| (self.left_border(i))).collect()
+        (0..self.bins+1).map(|i| self.left_border(i)).collect()
     }
 
     pub fn centers(&self) -> Vec<f64> {
         (0..self.bins).map(|i| (self.left_border(i) + self.right_border(i))/2.).collect()
     }
 
-    pub fn data(&self) -> &[f64] {
+    pub fn data(&self) -> &[T] {
         &self.histogram
     }
+
+    /// a copy with bins of the type `U`, e.g., `g.convert::<f32>()` to store a density
+    /// of states with half the memory
+    pub fn convert<U: Float>(&self) -> Histogram<U> {
+        Histogram {
+            low: self.low,
+            high: self.high,
+            bins: self.bins,
+            scale: self.scale,
+            histogram: self.histogram.iter().map(|x| U::from_f64(x.to_f64())).collect(),
+        }
+    }
+
+    /// A bar chart of the bins with `width` columns and `height` rows of text, for a
+    /// quick look at a histogram in a terminal, e.g., of a remote run. A column shows
+    /// the mean of its bins, if there are more bins than columns. The bars start at `0`,
+    /// or at the smallest value, if it is negative, and bins with values, which are not
+    /// finite, stay empty. The largest and the smallest value and the range of the
+    /// histogram are printed on the axes.
+    pub fn to_ascii(&self, width: usize, height: usize) -> String {
+        let width = width.max(1);
+        let height = height.max(1);
+        let columns: Vec<Option<f64>> = (0..width)
+            .map(|c| {
+                let first = c * self.bins / width;
+                let last = ((c + 1) * self.bins / width).max(first + 1);
+                let finite: Vec<f64> = self.histogram[first..last].iter()
+                    .map(|x| x.to_f64())
+                    .filter(|x| x.is_finite())
+                    .collect();
+                if finite.is_empty() {
+                    None
+                } else {
+                    Some(finite.iter().sum::<f64>() / finite.len() as f64)
+                }
+            })
+            .collect();
+        let lowest = columns.iter().flatten().cloned().fold(0., f64::min);
+        let mut highest = columns.iter().flatten().cloned().fold(0., f64::max);
+        if highest <= lowest {
+            highest = lowest + 1.;
+        }
+        // the number of filled rows of every column
+        let filled: Vec<usize> = columns.iter()
+            .map(|v| match v {
+                Some(v) => ((v - lowest) / (highest - lowest) * height as f64).round() as usize,
+                None => 0,
+            })
+            .collect();
+
+        let top = format!("{:.3e}", highest);
+        let bottom = format!("{:.3e}", lowest);
+        let label = top.len().max(bottom.len());
+        let mut chart = String::new();
+        for row in 0..height {
+            let text = match row {
+                0 => &top,
+                r if r == height - 1 => &bottom,
+                _ => "",
+            };
+            chart.push_str(&format!("{:>label$} |", text, label = label));
+            chart.extend(filled.iter().map(|&f| if f >= height - row { '#' } else { ' ' }));
+            chart.push('\n');
+        }
+        chart.push_str(&format!("{:>label$} +{}\n", "", "-".repeat(width), label = label));
+        let low = self.low.to_string();
+        let high = self.high.to_string();
+        let gap = width.saturating_sub(low.len() + high.len()).max(1);
+        chart.push_str(&format!("{:>label$}  {}{}{}\n", "", low, " ".repeat(gap), high, label = label));
+        chart
+    }
+
+    /// The ratio of the counts of this histogram and of the `denominator` in every bin
+    /// with its error, which assumes independent Poisson errors of the counts, e.g., of
+    /// accepted and proposed moves for an estimate of the acceptance function or of
+    /// the visits of two overlapping windows, see `WeightedHistogram::ratio` for
+    /// weighted counts. Both need the same bins, the ratio of a bin with no counts in
+    /// the `denominator` is `NaN`.
+    pub fn ratio<U: Float>(&self, denominator: &Histogram<U>) -> Result<Vec<(f64, f64)>> {
+        self.check_bins(denominator)?;
+        Ok(self.histogram.iter()
+            .zip(&denominator.histogram)
+            .map(|(a, b)| {
+                let (a, b) = (a.to_f64(), b.to_f64());
+                ratio_with_error(a, a, b, b)
+            })
+            .collect())
+    }
+
+    /// The chi-square test, whether the counts of this and the `other` histogram are
+    /// samples of the same distribution, e.g., of independent runs or of different
+    /// algorithms, with the statistic of two histograms with different totals `R` and
+    /// `S`, `sum_i (sqrt(S / R) r_i - sqrt(R / S) s_i)^2 / (r_i + s_i)`, over the bins
+    /// with any counts. Both need the same bins and unweighted counts, whose errors are
+    /// Poisson, and the approximation of the p-value needs about five counts per bin.
+    pub fn chi_square<U: Float>(&self, other: &Histogram<U>) -> Result<ChiSquare> {
+        self.check_bins(other)?;
+        let r: f64 = self.histogram.iter().map(|x| x.to_f64()).sum();
+        let s: f64 = other.histogram.iter().map(|x| x.to_f64()).sum();
+        if !(r > 0. && s > 0.) {
+            return Err(LargedevError::TooFewSamples)
+        }
+        let (a, b) = ((s / r).sqrt(), (r / s).sqrt());
+        let mut statistic = 0.;
+        let mut occupied = 0;
+        for (x, y) in self.histogram.iter().zip(&other.histogram) {
+            let (x, y) = (x.to_f64(), y.to_f64());
+            if x + y > 0. {
+                statistic += (a * x - b * y).powi(2) / (x + y);
+                occupied += 1;
+            }
+        }
+        // the totals are independent, such that only one bin is constrained
+        let dof = occupied - 1;
+        let p_value = if dof == 0 { 1. } else { gamma_q(dof as f64 / 2., statistic / 2.) };
+        Ok(ChiSquare { statistic, dof, p_value })
+    }
+
+    /// The two-sample Kolmogorov-Smirnov test, whether the counts of this and the
+    /// `other` histogram are samples of the same distribution, with the maximal
+    /// distance of their cumulative distributions at the borders of the bins. It is
+    /// insensitive to the number of counts per bin, but conservative, since the
+    /// distance within the bins is unknown. Both need the same bins and unweighted
+    /// counts.
+    pub fn kolmogorov_smirnov<U: Float>(&self, other: &Histogram<U>) -> Result<KolmogorovSmirnov> {
+        self.check_bins(other)?;
+        let r: f64 = self.histogram.iter().map(|x| x.to_f64()).sum();
+        let s: f64 = other.histogram.iter().map(|x| x.to_f64()).sum();
+        if !(r > 0. && s > 0.) {
+            return Err(LargedevError::TooFewSamples)
+        }
+        let (mut cdf_r, mut cdf_s) = (0., 0.);
+        let mut statistic: f64 = 0.;
+        for (x, y) in self.histogram.iter().zip(&other.histogram) {
+            cdf_r += x.to_f64() / r;
+            cdf_s += y.to_f64() / s;
+            statistic = statistic.max((cdf_r - cdf_s).abs());
+        }
+        let samples = r * s / (r + s);
+        let sqrt = samples.sqrt();
+        let p_value = kolmogorov_q((sqrt + 0.12 + 0.11 / sqrt) * statistic);
+        Ok(KolmogorovSmirnov { statistic, samples, p_value })
+    }
+
+    /// fail, if `other` has different bins
+    fn check_bins<U>(&self, other: &Histogram<U>) -> Result<()> {
+        if self.low != other.low || self.high != other.high || self.bins != other.bins {
+            let msg = format!(
+                "the bins of [{}, {}) with {} bins differ from [{}, {}) with {} bins",
+                self.low, self.high, self.bins, other.low, other.high, other.bins
+            );
+            return Err(LargedevError::HistogramRange(msg))
+        }
+        Ok(())
+    }
+
+    /// the bins as an `ndarray` view without copying, e.g., for `ndarray-stats`
+    #[cfg(feature = "ndarray")]
+    pub fn as_array(&self) -> ndarray::ArrayView1<'_, T> {
+        ndarray::ArrayView1::from(&self.histogram[..])
+    }
+
+    /// the bins as a mutable `ndarray` view without copying
+    #[cfg(feature = "ndarray")]
+    pub fn as_array_mut(&mut self) -> ndarray::ArrayViewMut1<'_, T> {
+        ndarray::ArrayViewMut1::from(&mut self.histogram[..])
+    }
+
+    /// the bin borders as an `ndarray`, which has one element more than the bins
+    #[cfg(feature = "ndarray")]
+    pub fn borders_array(&self) -> ndarray::Array1<f64> {
+        ndarray::Array1::from(self.borders())
+    }
+}
+
+/// The result of `Histogram::chi_square`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChiSquare {
+    pub statistic: f64,
+    /// the degrees of freedom, the number of bins with counts minus one
+    pub dof: usize,
+    /// the probability of a statistic at least as large for histograms of the same
+    /// distribution, a small p-value, e.g., below `0.01`, indicates inconsistent ones
+    pub p_value: f64,
+}
+
+/// The result of `Histogram::kolmogorov_smirnov`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KolmogorovSmirnov {
+    /// the maximal distance of the cumulative distributions
+    pub statistic: f64,
+    /// the effective number of samples `R S / (R + S)` of the totals `R` and `S`
+    pub samples: f64,
+    /// the asymptotic probability of a distance at least as large for histograms of
+    /// the same distribution
+    pub p_value: f64,
+}
+
+/// The ratio `a / b` of two independent sums with the variances `var_a` and `var_b` and
+/// its error from Gaussian error propagation, `NaN` for `b = 0`.
+fn ratio_with_error(a: f64, var_a: f64, b: f64, var_b: f64) -> (f64, f64) {
+    if b == 0. {
+        return (f64::NAN, f64::NAN)
+    }
+    let r = a / b;
+    (r, (var_a + r * r * var_b).sqrt() / b.abs())
+}
+
+/// A histogram of weighted values, which accumulates the sums of the weights and of
+/// their squares in every bin. The sum of the squares estimates the variance of the
+/// sum of the weights, such that the errors of weighted counts, e.g., of reweighted
+/// samples, are known.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WeightedHistogram {
+    sums: Histogram,
+    squares: Histogram,
+}
+
+impl WeightedHistogram {
+    pub fn new(low: f64, high: f64, bins: usize) -> Result<WeightedHistogram> {
+        let sums = Histogram::new(low, high, bins)?;
+        Ok(WeightedHistogram { squares: sums.clone(), sums })
+    }
+
+    /// add `value` with the weight `weight`
+    pub fn add(&mut self, value: f64, weight: f64) {
+        if let Some(idx) = self.sums.index_of(value) {
+            *self.sums.idx(idx) += weight;
+            *self.squares.idx(idx) += weight * weight;
+        }
+    }
+
+    /// the sums of the weights in every bin
+    pub fn sums(&self) -> &Histogram {
+        &self.sums
+    }
+
+    /// the sums of the squared weights in every bin
+    pub fn squares(&self) -> &Histogram {
+        &self.squares
+    }
+
+    /// the errors of the sums of the weights in every bin
+    pub fn errors(&self) -> Vec<f64> {
+        self.squares.data().iter().map(|s| s.sqrt()).collect()
+    }
+
+    /// The Kish effective sample size `(sum w)^2 / sum w^2` of every bin, `0` for empty
+    /// bins. It is the number of samples with equal weights, which would have the same
+    /// relative error, such that a bin dominated by a few large weights, e.g., after a
+    /// reweighting far from the sampled distribution, has a small effective sample size
+    /// and an unreliable count, even if it contains many samples.
+    pub fn ess(&self) -> Vec<f64> {
+        self.sums.data().iter()
+            .zip(self.squares.data())
+            .map(|(&s, &s2)| if s2 > 0. { s * s / s2 } else { 0. })
+            .collect()
+    }
+
+    /// the Kish effective sample size of all values in the range of the histogram
+    pub fn total_ess(&self) -> f64 {
+        let sum: f64 = self.sums.data().iter().sum();
+        let sum2: f64 = self.squares.data().iter().sum();
+        if sum2 > 0. {
+            sum * sum / sum2
+        } else {
+            0.
+        }
+    }
+
+    /// multiply all weights by `factor`
+    pub(crate) fn scale(&mut self, factor: f64) {
+        for i in 0..self.sums.bins() {
+            *self.sums.idx(i) *= factor;
+            *self.squares.idx(i) *= factor * factor;
+        }
+    }
+
+    /// the ratio of the sums of the weights of this histogram and of the `denominator`
+    /// in every bin with its error, like `Histogram::ratio`
+    pub fn ratio(&self, denominator: &WeightedHistogram) -> Result<Vec<(f64, f64)>> {
+        self.sums.check_bins(&denominator.sums)?;
+        Ok((0..self.sums.bins())
+            .map(|i| ratio_with_error(
+                self.sums.data()[i],
+                self.squares.data()[i],
+                denominator.sums.data()[i],
+                denominator.squares.data()[i],
+            ))
+            .collect())
+    }
+}
+
+/// The equally wide bins of one axis of a `HistogramNd` in `[low, high)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Axis {
+    low: f64,
+    high: f64,
+    bins: usize,
+}
+
+impl Axis {
+    pub fn new(low: f64, high: f64, bins: usize) -> Result<Axis> {
+        // the same requirements as for a one-dimensional histogram
+        Histogram::new(low, high, bins)?;
+        Ok(Axis { low, high, bins })
+    }
+
+    pub fn bounds(&self) -> (f64, f64) {
+        (self.low, self.high)
+    }
+
+    pub fn bins(&self) -> usize {
+        self.bins
+    }
+
+    /// the index of the bin containing `value`, or `None` if it is outside of the range
+    #[inline]
+    pub fn index_of(&self, value: f64) -> Option<usize> {
+        // the same arithmetic as `Histogram::index_of`, such that values are mapped to
+        // the same bins as in the marginals
+        let x = (value - self.low) * (self.bins as f64 / (self.high - self.low));
+        if x >= 0. && x < self.bins as f64 {
+            Some(x as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn borders(&self) -> Vec<f64> {
+        (0..self.bins+1).map(|i| self.left_border(i)).collect()
+    }
+
+    pub fn centers(&self) -> Vec<f64> {
+        (0..self.bins).map(|i| self.center(i)).collect()
+    }
+
+    fn left_border(&self, n: usize) -> f64 {
+        (n as f64 / self.bins as f64) * (self.high - self.low) + self.low
+    }
+
+    fn center(&self, n: usize) -> f64 {
+        (self.left_border(n) + self.left_border(n + 1)) / 2.
+    }
+}
+
+/// A histogram over `N` dimensions, whose bins are the products of the bins of its axes,
+/// e.g., for the joint distribution of multiple observables or the density of states
+/// of a generalized ensemble with multiple parameters. The bins are stored in row-major
+/// order, i.e., the index of the last axis changes fastest, which is the order of
+/// `data` and of the flat indices used by `idx`, e.g.:
+///
+/// ```ignore
+/// let mut h = HistogramNd::new(vec![Axis::new(-32., 33., 65)?, Axis::new(-16., 17., 33)?])?;
+/// h.count(&[model.energy() as f64, model.magnetization() as f64]);
+/// let energies = h.marginal(0);
+/// ```
+///
+/// A `HistogramNd<f32>` halves the memory of large, e.g., two-dimensional, densities
+/// of states. It can count visits exactly up to `2^24` per bin, but a density of
+/// states, which is built from small increments like `ln f` of Wang-Landau sampling,
+/// has to be accumulated in `f64` and can be converted afterwards, see `convert`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HistogramNd<T = f64> {
+    axes: Vec<Axis>,
+    /// the distance of neighboring bins along every axis in the flat storage
+    strides: Vec<usize>,
+    histogram: Vec<T>,
+}
+
+impl<T: Float> HistogramNd<T> {
+    /// a histogram with the bins of `axes`, which needs at least one axis
+    pub fn new(axes: Vec<Axis>) -> Result<HistogramNd<T>> {
+        if axes.is_empty() {
+            return Err(LargedevError::HistogramRange("a histogram needs at least one axis".into()))
+        }
+        let mut strides = vec![1; axes.len()];
+        let mut total: usize = 1;
+        for (k, axis) in axes.iter().enumerate().rev() {
+            strides[k] = total;
+            total = total.checked_mul(axis.bins)
+                .ok_or_else(|| LargedevError::HistogramRange("the number of bins overflows".into()))?;
+        }
+        Ok(HistogramNd {
+            axes,
+            strides,
+            histogram: vec![T::default(); total],
+        })
+    }
+
+    /// number of dimensions
+    pub fn dimensions(&self) -> usize {
+        self.axes.len()
+    }
+
+    pub fn axes(&self) -> &[Axis] {
+        &self.axes
+    }
+
+    /// number of bins along every axis
+    pub fn shape(&self) -> Vec<usize> {
+        self.axes.iter().map(Axis::bins).collect()
+    }
+
+    /// total number of bins
+    pub fn bins(&self) -> usize {
+        self.histogram.len()
+    }
+
+    /// the flat index of the bin containing the point `values`, which has one
+    /// coordinate per axis, or `None` if it is outside of the range (or has the wrong
+    /// number of coordinates)
+    pub fn index_of(&self, values: &[f64]) -> Option<usize> {
+        if values.len() != self.axes.len() {
+            return None
+        }
+        let mut index = 0;
+        for ((axis, &stride), &value) in self.axes.iter().zip(&self.strides).zip(values) {
+            index += axis.index_of(value)? * stride;
+        }
+        Some(index)
+    }
+
+    /// the flat index of the bin with the index `indices[k]` along the `k`-th axis
+    pub fn flat_index(&self, indices: &[usize]) -> Option<usize> {
+        if indices.len() != self.axes.len() {
+            return None
+        }
+        let mut index = 0;
+        for ((axis, &stride), &i) in self.axes.iter().zip(&self.strides).zip(indices) {
+            if i >= axis.bins {
+                return None
+            }
+            index += i * stride;
+        }
+        Some(index)
+    }
+
+    /// the indices along every axis of the bin with the flat index `index`
+    pub fn indices(&self, index: usize) -> Vec<usize> {
+        self.axes.iter()
+            .zip(&self.strides)
+            .map(|(axis, &stride)| index / stride % axis.bins)
+            .collect()
+    }
+
+    /// the center of the bin with the flat index `index`
+    pub fn center(&self, index: usize) -> Vec<f64> {
+        self.axes.iter()
+            .zip(self.indices(index))
+            .map(|(axis, i)| axis.center(i))
+            .collect()
+    }
+
+    pub fn add(&mut self, values: &[f64], amount: f64) {
+        if let Some(idx) = self.index_of(values) {
+            self.histogram[idx] += T::from_f64(amount);
+        }
+    }
+
+    pub fn count(&mut self, values: &[f64]) {
+        self.add(values, 1.)
+    }
+
+    pub fn at(&self, values: &[f64]) -> Option<f64> {
+        self.index_of(values).map(|idx| self.histogram[idx].to_f64())
+    }
+
+    /// the bin with the flat index `idx`
+    pub fn idx(&mut self, idx: usize) -> &mut T {
+        &mut self.histogram[idx]
+    }
+
+    pub fn reset(&mut self) {
+        for i in &mut self.histogram {
+            *i = T::default();
+        }
+    }
+
+    /// the smallest entry, `NaN` entries are ignored and it is infinite if all are `NaN`
+    pub fn min(&self) -> f64 {
+        self.histogram.iter().map(|x| x.to_f64()).fold(f64::INFINITY, f64::min)
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.histogram.iter().map(|x| x.to_f64()).sum::<f64>() / self.bins() as f64
+    }
+
+    pub fn data(&self) -> &[T] {
+        &self.histogram
+    }
+
+    /// a copy with bins of the type `U`, e.g., `g.convert::<f32>()` to store an
+    /// accumulated density of states with half the memory
+    pub fn convert<U: Float>(&self) -> HistogramNd<U> {
+        HistogramNd {
+            axes: self.axes.clone(),
+            strides: self.strides.clone(),
+            histogram: self.histogram.iter().map(|x| U::from_f64(x.to_f64())).collect(),
+        }
+    }
+
+    /// the sums of the bins over all axes but `axis`, e.g., the distribution of one of
+    /// the observables of a joint distribution
+    pub fn marginal(&self, axis: usize) -> Histogram<T> {
+        let a = self.axes[axis];
+        let mut marginal = Histogram::with_precision(a.low, a.high, a.bins).unwrap();
+        for (index, &x) in self.histogram.iter().enumerate() {
+            *marginal.idx(index / self.strides[axis] % a.bins) += x;
+        }
+        marginal
+    }
+
+    /// the bins as an `ndarray` view of the shape `shape` without copying
+    #[cfg(feature = "ndarray")]
+    pub fn as_array(&self) -> ndarray::ArrayViewD<'_, T> {
+        // the shape matches the number of bins by construction
+        ndarray::ArrayViewD::from_shape(self.shape(), &self.histogram[..]).unwrap()
+    }
+
+    /// the bins as a mutable `ndarray` view without copying
+    #[cfg(feature = "ndarray")]
+    pub fn as_array_mut(&mut self) -> ndarray::ArrayViewMutD<'_, T> {
+        let shape = self.shape();
+        ndarray::ArrayViewMutD::from_shape(shape, &mut self.histogram[..]).unwrap()
+    }
 }
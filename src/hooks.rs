@@ -0,0 +1,164 @@
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::histogram::Histogram;
+
+/// The state of a sampler after a sweep, which is passed to the sweep hook.
+/// Fields which do not apply to the sampler, e.g., `lnf` for `Metropolis`, are `None`.
+#[derive(Clone, Debug)]
+pub struct SweepState<'a> {
+    /// number of completed sweeps
+    pub sweep: usize,
+    /// current value of the model, e.g., the energy, cached by the sampler, such that
+    /// hooks do not need to call `value`
+    pub energy: f64,
+    /// number of proposed changes so far
+    pub tries: usize,
+    /// number of rejected changes so far
+    pub rejects: usize,
+    /// number of invalid proposals so far
+    pub invalid: usize,
+    /// current logarithmic refinement parameter of Wang-Landau sampling
+    pub lnf: Option<f64>,
+    /// current estimate of the logarithm of the density of states
+    pub g: Option<&'a Histogram>,
+    /// current auxiliary histogram of visits
+    pub h: Option<&'a Histogram>,
+}
+
+/// A hook which is called after every sweep with read access to the model and the
+/// state of the sampler. It can be used for custom measurements, live plots, or
+/// to stop the simulation early by returning `ControlFlow::Break(())`, e.g.:
+///
+/// ```ignore
+/// let report = Metropolis::new(model)
+///    .on_sweep(|model, state| {
+///        if state.energy < target {
+///            ControlFlow::Break(())
+///        } else {
+///            ControlFlow::Continue(())
+///        }
+///    })
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+pub type SweepHook<M> = Box<dyn FnMut(&M, &SweepState) -> ControlFlow<()>>;
+
+/// A hook which is called by `Simple` after every sample with read access to the model
+/// and its value. It can be used to accumulate custom observables, e.g., full
+/// correlation functions, without formatting them into the records, or to stop the
+/// sampling early by returning `ControlFlow::Break(())`, e.g.:
+///
+/// ```ignore
+/// let correlation = Rc::new(RefCell::new(vec![0.; l]));
+/// let sum = correlation.clone();
+/// let report = Simple::new(model)
+///    .iterations(1000000)
+///    .records(false)
+///    .on_sample(move |model, _value| {
+///        model.add_correlation(&mut sum.borrow_mut());
+///        ControlFlow::Continue(())
+///    })
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+pub type SampleHook<M> = Box<dyn FnMut(&M, f64) -> ControlFlow<()>>;
+
+/// The occasion of a call of the `WangLandauMonitor`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WangLandauEvent {
+    /// every `WangLandau::monitor` interval of sweeps
+    Periodic,
+    /// a stage of the first phase is complete, before `ln f` is halved
+    Stage,
+    /// the start of the phase `WangLandauState::phase`
+    Phase,
+    /// Too much time was spent in the first stage at `ln f = 1`, the histograms were
+    /// trimmed to the visited range and reset. The results may be inaccurate, a
+    /// simulation with a different range or a smaller `ln f` is advisable.
+    Trimmed,
+    /// The first phase took so long, that `ln f` reached `lnf_final`, so the second
+    /// phase is skipped. The results may be inaccurate, a simulation with a different
+    /// range, smaller windows or a smaller `ln f` is advisable.
+    Skipped,
+    /// the end of the simulation, with the final estimate in `g`
+    Finished,
+}
+
+/// The state of the convergence of Wang-Landau sampling, which is passed to the
+/// `WangLandauMonitor`.
+#[derive(Clone, Debug)]
+pub struct WangLandauState<'a> {
+    pub event: WangLandauEvent,
+    /// the phase, `1` for the halving of `ln f`, `2` for its power-law decrease and
+    /// `3` for the entropic sampling
+    pub phase: usize,
+    /// number of completed sweeps, `t`
+    pub sweep: usize,
+    /// current logarithmic refinement parameter
+    pub lnf: f64,
+    /// the flatness `min(H) / mean(H)` of the histogram of the visits, which is not
+    /// accumulated in the second phase
+    pub flatness: Option<f64>,
+    /// number of round trips of the walker from the lowest to the highest bin and back
+    pub round_trips: usize,
+    /// the acceptance rate so far
+    pub acceptance: f64,
+    /// current estimate of the logarithm of the density of states
+    pub g: &'a Histogram,
+    /// current histogram of the visits
+    pub h: &'a Histogram,
+}
+
+/// A callback of `WangLandau`, which is called periodically and at the milestones of
+/// the simulation with its `WangLandauState`, e.g., for live plots of the convergence
+/// or logs of long simulations:
+///
+/// ```ignore
+/// let report = WangLandau::new(model)
+///    .range(low, high)
+///    .monitor(10000, |state| {
+///        eprintln!("{:?} t = {} ln f = {} round trips = {}", state.event, state.sweep, state.lnf, state.round_trips);
+///    })
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+pub type WangLandauMonitor = Box<dyn FnMut(&WangLandauState)>;
+
+/// A token to cancel a running simulation cleanly from another thread. The sampler
+/// checks the token after every sweep and returns its partial results, with the
+/// `stopped` flag of the report set, e.g.:
+///
+/// ```ignore
+/// let token = CancelToken::new();
+/// let mut run = Metropolis::new(model)
+///    .cancel_token(token.clone())
+///    .build()?;
+/// // e.g., in a GUI callback
+/// token.cancel();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    /// request the cancellation of all simulations holding a clone of this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl From<Arc<AtomicBool>> for CancelToken {
+    fn from(flag: Arc<AtomicBool>) -> Self {
+        CancelToken(flag)
+    }
+}
@@ -0,0 +1,460 @@
+use std::io::{BufWriter, Write};
+use std::ops::ControlFlow;
+
+use rand::Rng;
+
+use crate::histogram::Histogram;
+use crate::acceptance::{AcceptanceRule, MetropolisRule};
+use crate::markovchain::{MarkovChain, Proposal};
+use crate::error::{LargedevError, Result};
+use crate::report::Report;
+use crate::seed::Seed;
+use crate::hooks::{CancelToken, SweepHook, SweepState};
+use crate::uniforms::Uniforms;
+use crate::wanglandau::{self, HistogramCriterion, PhaseClock, WangLandau, WangLandauConfig};
+
+/// kernels are truncated at this multiple of the bandwidth
+const CUTOFF: f64 = 3.;
+
+/// Parameters of a kernel-update Wang-Landau simulation, see `KernelWangLandau` for
+/// the builder.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KernelWangLandauConfig {
+    /// parameters of the Wang-Landau simulation, its `bins` are only used for the
+    /// auxiliary histogram of the criterion of the first phase
+    pub wang_landau: WangLandauConfig,
+    /// number of equidistant nodes, at which the smooth estimate is stored
+    pub nodes: usize,
+    /// standard deviation of the Gaussian kernels, the width of a bin of the
+    /// auxiliary histogram, if not set
+    pub bandwidth: Option<f64>,
+}
+
+impl Default for KernelWangLandauConfig {
+    fn default() -> Self {
+        KernelWangLandauConfig {
+            wang_landau: WangLandauConfig::default(),
+            nodes: 1000,
+            bandwidth: None,
+        }
+    }
+}
+
+impl KernelWangLandauConfig {
+    pub fn validate(&self) -> Result<()> {
+        self.wang_landau.validate()?;
+        if !self.wang_landau.masked.is_empty() {
+            return Err(LargedevError::InvalidParameter("masked bins are not supported by KernelWangLandau".into()))
+        }
+        if self.nodes < 2 {
+            return Err(LargedevError::InvalidParameter("at least two nodes are needed".into()))
+        }
+        if let Some(h) = self.bandwidth {
+            if !h.is_finite() || h <= 0. {
+                return Err(LargedevError::InvalidParameter(format!("the bandwidth must be positive, not {}", h)))
+            }
+        }
+        Ok(())
+    }
+
+    /// the configured bandwidth or the width of a bin of the auxiliary histogram
+    fn bandwidth(&self) -> f64 {
+        let (low, high) = self.wang_landau.range.unwrap();
+        self.bandwidth.unwrap_or((high - low) / self.wang_landau.bins as f64)
+    }
+}
+
+/// A builder used to set up Wang-Landau sampling of continuous observables, which
+/// updates a smooth estimate of the logarithm of the density of states by adding
+/// Gaussian kernels centered at the visited values instead of incrementing hard bins.
+/// The estimate is stored at equidistant nodes and linearly interpolated between them,
+/// such that the acceptance does not jump at the borders of bins. Kernels close to the
+/// ends of the range are reflected at the ends, such that the estimate does not drop
+/// off there, where the truncated kernels would otherwise bias it, e.g.:
+///
+/// ```ignore
+/// let report = WangLandau::new(model)
+///    .range(low, high)
+///    .bins(50)
+///    .kernel()
+///    .nodes(1000)
+///    .bandwidth(0.1)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+///
+/// The phases follow `WangLandau`, the entropic sampling of the last phase counts the
+/// visits of the bins around the nodes. The output has the format of
+/// `WangLandauRun::run`, with one bin per node, whose center is the node.
+pub struct KernelWangLandau<MC> {
+    model: MC,
+    config: KernelWangLandauConfig,
+    hook: Option<SweepHook<MC>>,
+    cancel: Option<CancelToken>,
+}
+
+impl<MC: MarkovChain> WangLandau<MC> {
+    /// use the kernel-update variant, see `KernelWangLandau`
+    pub fn kernel(self) -> KernelWangLandau<MC> {
+        let (model, config, hook, cancel) = self.into_parts();
+        KernelWangLandau {
+            model,
+            config: KernelWangLandauConfig { wang_landau: config, ..KernelWangLandauConfig::default() },
+            hook,
+            cancel,
+        }
+    }
+}
+
+impl<MC: MarkovChain> KernelWangLandau<MC> {
+    pub fn new(model: MC) -> Self {
+        KernelWangLandau::with_config(model, KernelWangLandauConfig::default())
+    }
+
+    pub fn with_config(model: MC, config: KernelWangLandauConfig) -> Self {
+        KernelWangLandau::<MC> {
+            model,
+            config,
+            hook: None,
+            cancel: None,
+        }
+    }
+
+    /// the energy window to sample, `low` is included, `high` is excluded
+    pub fn range(mut self, low: f64, high: f64) -> Self {
+        self.config.wang_landau.range = Some((low, high));
+        self
+    }
+
+    /// the number of bins of the auxiliary histogram
+    pub fn bins(mut self, bins: usize) -> Self {
+        self.config.wang_landau.bins = bins;
+        self
+    }
+
+    pub fn sweep(mut self, sweep: usize) -> Self {
+        self.config.wang_landau.sweep = sweep;
+        self
+    }
+
+    pub fn lnf_final(mut self, lnf_final: f64) -> Self {
+        self.config.wang_landau.lnf_final = lnf_final;
+        self
+    }
+
+    /// the exponent of the schedule `t^-alpha` in the second phase, see `WangLandau::alpha`
+    pub fn alpha(mut self, alpha: f64) -> Self {
+        self.config.wang_landau.alpha = alpha;
+        self
+    }
+
+    /// when a stage of the first phase is complete, see `HistogramCriterion`
+    pub fn criterion(mut self, criterion: HistogramCriterion) -> Self {
+        self.config.wang_landau.criterion = criterion;
+        self
+    }
+
+    /// the seed used by `run_seeded`, if not set, a seed is drawn from entropy
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Self {
+        self.config.wang_landau.seed = Some(seed.into());
+        self
+    }
+
+    pub fn nodes(mut self, nodes: usize) -> Self {
+        self.config.nodes = nodes;
+        self
+    }
+
+    /// The standard deviation of the kernels. It should be larger than the distance of
+    /// the nodes, but smaller than the scale, on which the density of states changes
+    /// its curvature, since the kernels smooth it out.
+    pub fn bandwidth(mut self, bandwidth: f64) -> Self {
+        self.config.bandwidth = Some(bandwidth);
+        self
+    }
+
+    /// a hook called after every sweep, see `SweepHook`
+    pub fn on_sweep(mut self, hook: impl FnMut(&MC, &SweepState) -> ControlFlow<()> + 'static) -> Self {
+        self.hook = Some(Box::new(hook));
+        self
+    }
+
+    /// a token to cancel the simulation, see `CancelToken`
+    pub fn cancel_token(mut self, token: impl Into<CancelToken>) -> Self {
+        self.cancel = Some(token.into());
+        self
+    }
+
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// validate the parameters and return the configured sampler
+    pub fn build(self) -> Result<KernelWangLandauRun<MC>> {
+        self.config.validate()?;
+        Ok(KernelWangLandauRun {
+            model: self.model,
+            uniforms: Uniforms::new(self.config.wang_landau.sweep),
+            config: self.config,
+            hook: self.hook,
+            cancel: self.cancel,
+        })
+    }
+}
+
+/// A configured kernel-update Wang-Landau sampler, see `KernelWangLandau` for the
+/// builder.
+pub struct KernelWangLandauRun<MC> {
+    model: MC,
+    /// random numbers for the acceptance tests of one sweep
+    uniforms: Uniforms,
+    config: KernelWangLandauConfig,
+    hook: Option<SweepHook<MC>>,
+    cancel: Option<CancelToken>,
+}
+
+impl<MC: MarkovChain> KernelWangLandauRun<MC> {
+    pub fn config(&self) -> &KernelWangLandauConfig {
+        &self.config
+    }
+
+    /// the current state of the model
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    /// consume the sampler and return the model in its current state
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// call the hook, if any, and return whether the simulation should stop,
+    /// either because the hook requests it, or because the run was cancelled
+    fn call_hook(&mut self, state: &SweepState) -> bool {
+        if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return true
+        }
+        match &mut self.hook {
+            Some(hook) => hook(&self.model, state).is_break(),
+            None => false,
+        }
+    }
+
+    /// Propose a change and accept it according to the current estimate `g`. The
+    /// proposal is counted in `report`, returns the new energy.
+    fn step(&mut self, g: &Histogram, old_e: f64, mut rng: &mut impl Rng, report: &mut Report) -> f64 {
+        report.tries += 1;
+        match self.model.try_change(&mut rng) {
+            Proposal::Valid => (),
+            Proposal::Invalid => {
+                report.invalid += 1;
+                return old_e
+            }
+            Proposal::Unchanged => return old_e,
+        }
+        let new_e = self.model.value();
+
+        let ln_weight = match (interpolate(g, old_e), interpolate(g, new_e)) {
+            (Some(old), Some(new)) => old - new + self.model.ln_proposal_ratio(),
+            _ => f64::NEG_INFINITY,
+        };
+
+        if !MetropolisRule.accept(ln_weight, self.uniforms.next(rng)) {
+            self.model.undo();
+            report.rejects += 1;
+            old_e
+        } else {
+            new_e
+        }
+    }
+
+    /// The estimate of the logarithm of the density of states is written like the one
+    /// of `WangLandauRun::run`, with the nodes at the centers of the bins.
+    pub fn run(&mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<Report> {
+        self.simulate(&mut rng, file, None)
+    }
+
+    /// like `run`, but with a random number generator initialized from the configured
+    /// `seed` (or from entropy), which is written to the output and the report
+    pub fn run_seeded(&mut self, file: &mut impl Write) -> Result<Report> {
+        let seed = self.config.wang_landau.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate(&mut seed.rng(), file, Some(seed))
+    }
+
+    fn simulate(&mut self, rng: &mut impl Rng, file: &mut impl Write, seed: Option<Seed>) -> Result<Report> {
+        let mut file = BufWriter::new(file);
+
+        let mut report = Report {
+            seed,
+            ..Report::default()
+        };
+        let g = self.estimate(rng, &mut report)?;
+
+        wanglandau::write_dos(&mut file, &g, seed, &report.streams)?;
+        file.flush()?;
+
+        Ok(report)
+    }
+
+    /// Perform the simulation and return the estimate of the logarithm of the density
+    /// of states at the nodes. The proposals are counted in `report`.
+    fn estimate(&mut self, mut rng: &mut impl Rng, report: &mut Report) -> Result<Histogram> {
+        let config = self.config.wang_landau.clone();
+        let (low, high) = config.range.unwrap();
+        let bandwidth = self.config.bandwidth();
+        let mut g = Histogram::new(low, high, self.config.nodes)?;
+        // auxiliary histogram for the criterion
+        let mut h = Histogram::new(low, high, config.bins)?;
+        // the numbers left over from the last run stem from another generator
+        self.uniforms.reset();
+
+        trace_span!(_span = info_span!("kernel_wang_landau", low, high, nodes = self.config.nodes, bandwidth));
+
+        wanglandau::find_start(&mut self.model, low, high, &mut rng);
+
+        let mut t = 0;
+        let mut lnf = 1.;
+        let mut energy = self.model.value();
+
+        trace_span!(phase = info_span!("phase", number = 1));
+        let clock = PhaseClock::start(1, report);
+        'phase1: while t < 10 || lnf > config.schedule(t) {
+            trace_event!(debug!(lnf, t, acceptance = report.acceptance(), "ln f"));
+            // like `WangLandau`, check the criterion only every 1000 sweeps
+            while !config.criterion.is_met(h.min(), h.mean()) {
+                for _ in 0..1000 {
+                    for _ in 0..config.sweep {
+                        let failed = report.rejects + report.invalid;
+                        energy = self.step(&g, energy, &mut rng, report);
+
+                        add_kernel(&mut g, energy, lnf, bandwidth);
+                        if config.criterion.counts_rejections() || report.rejects + report.invalid == failed {
+                            h.count(energy);
+                        }
+                    }
+                    t += 1;
+
+                    let state = SweepState { sweep: t, energy, tries: report.tries, rejects: report.rejects, invalid: report.invalid, lnf: Some(lnf), g: Some(&g), h: Some(&h) };
+                    if self.call_hook(&state) {
+                        report.stopped = true;
+                        break 'phase1;
+                    }
+                }
+            }
+            h.reset();
+            lnf /= 2.;
+        }
+        report.phases.push(clock.stop(config.sweep, report));
+        trace_exit!(phase);
+
+        if !report.stopped {
+            trace_event!(info!(t, acceptance = report.acceptance(), "begin phase 2"));
+        }
+        trace_span!(phase = info_span!("phase", number = 2));
+        let clock = if report.stopped { None } else { Some(PhaseClock::start(2, report)) };
+        while !report.stopped && lnf > config.lnf_final {
+            lnf = config.schedule(t);
+
+            for _ in 0..config.sweep {
+                energy = self.step(&g, energy, &mut rng, report);
+                add_kernel(&mut g, energy, lnf, bandwidth);
+            }
+            t += 1;
+
+            let state = SweepState { sweep: t, energy, tries: report.tries, rejects: report.rejects, invalid: report.invalid, lnf: Some(lnf), g: Some(&g), h: None };
+            report.stopped = self.call_hook(&state);
+        }
+        if let Some(clock) = clock {
+            report.phases.push(clock.stop(config.sweep, report));
+        }
+        trace_exit!(phase);
+
+        // entropic sampling with the bias g, twice as long as the previous phases, the
+        // visits are counted per node without kernels, since the kernels would smooth
+        // out the fluctuations of g on the scale of the bandwidth instead of correcting
+        // them
+        if !report.stopped {
+            trace_event!(info!(t, acceptance = report.acceptance(), "begin phase 3"));
+            trace_span!(_phase = info_span!("phase", number = 3));
+            let clock = PhaseClock::start(3, report);
+            let mut visits = g.clone();
+            visits.reset();
+            for i in 0..2 * t {
+                for _ in 0..config.sweep {
+                    energy = self.step(&g, energy, &mut rng, report);
+                    visits.count(energy);
+                }
+
+                let state = SweepState { sweep: t + i + 1, energy, tries: report.tries, rejects: report.rejects, invalid: report.invalid, lnf: Some(lnf), g: Some(&g), h: Some(&visits) };
+                if self.call_hook(&state) {
+                    report.stopped = true;
+                    break;
+                }
+            }
+            report.phases.push(clock.stop(config.sweep, report));
+
+            // remove the bias, the visits are proportional to g_true / g
+            let mean = visits.mean();
+            for (j, &v) in visits.data().iter().enumerate() {
+                if v > 0. {
+                    *g.idx(j) += (v / mean).ln();
+                }
+            }
+        }
+        trace_event!(info!(lnf, t, acceptance = report.acceptance(), stopped = report.stopped, "finished"));
+
+        Ok(g)
+    }
+
+    pub fn exec(mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<MC> {
+        self.run(&mut rng, file)?;
+        Ok(self.model)
+    }
+}
+
+/// The value of the smooth function stored at the centers of the bins of `g` at `x`,
+/// linearly interpolated between the centers and constant beyond the outermost
+/// centers, or `None`, if `x` is outside of the range.
+fn interpolate(g: &Histogram, x: f64) -> Option<f64> {
+    g.index_of(x)?;
+    let (low, high) = g.bounds();
+    let n = g.bins();
+    let position = ((x - low) / (high - low) * n as f64 - 0.5).clamp(0., (n - 1) as f64);
+    let i = (position as usize).min(n - 2);
+    let fraction = position - i as f64;
+    let data = g.data();
+    Some(data[i] * (1. - fraction) + data[i + 1] * fraction)
+}
+
+/// Add a Gaussian kernel with the height `amount` and the standard deviation
+/// `bandwidth` centered at `x` to the nodes at the centers of the bins of `g`. The
+/// parts of the kernel beyond the ends of the range are reflected back into it.
+fn add_kernel(g: &mut Histogram, x: f64, amount: f64, bandwidth: f64) {
+    let (low, high) = g.bounds();
+    let n = g.bins();
+    let width = (high - low) / n as f64;
+    for center in [x, 2. * low - x, 2. * high - x] {
+        let first = ((center - CUTOFF * bandwidth - low) / width - 0.5).ceil().max(0.);
+        let last = ((center + CUTOFF * bandwidth - low) / width - 0.5).floor().min((n - 1) as f64);
+        if first > last {
+            continue
+        }
+        for j in first as usize..=last as usize {
+            let d = (low + (j as f64 + 0.5) * width - center) / bandwidth;
+            *g.idx(j) += amount * (-0.5 * d * d).exp();
+        }
+    }
+}
@@ -1,17 +1,122 @@
+use std::fmt;
+
+/// the version of `rand`, whose traits the models and samplers use
+pub use rand;
+
+#[macro_use]
+mod trace;
+
+mod error;
+pub use error::{LargedevError, Result};
+
+mod seed;
+pub use seed::{DefaultRng, FastRng, Seed};
+
+mod quasi;
+pub use quasi::QuasiRng;
+
+mod replay;
+pub use replay::{MoveLog, Outcome};
+
+mod uniforms;
+
+mod special;
+
+mod acceptance;
+pub use acceptance::{ln_boltzmann, AcceptanceRule, GlauberRule, MetropolisRule};
+
+mod report;
+pub use report::{AmsReport, AntitheticReport, ControlReport, CrossEntropyReport, ObservableReport, PermReport, PhaseReport, ReplayReport, Report, SelfTestReport, SimpleReport, TailReport, WeightedReport};
+
+mod hooks;
+pub use hooks::{CancelToken, SampleHook, SweepHook, SweepState, WangLandauEvent, WangLandauMonitor, WangLandauState};
+
 mod simple;
-pub use simple::{DirectSamplable, Simple};
+pub use simple::{Antithetic, DirectSamplable, ImportanceWeighted, Simple, SimpleConfig, SimpleRun, SimpleSteps, UnitCube};
+
+mod perm;
+pub use perm::{Growable, Perm, PermConfig, PermRun};
 
 mod markovchain;
-pub use markovchain::MarkovChain;
+pub use markovchain::{DynMarkovChain, MarkovChain, Proposal, Restorable};
 
 mod histogram;
-pub use histogram::Histogram;
+pub use histogram::{Axis, ChiSquare, Float, Histogram, HistogramNd, KolmogorovSmirnov, WeightedHistogram};
 
 mod metropolis;
-pub use metropolis::Metropolis;
+pub use metropolis::{Metropolis, MetropolisConfig, MetropolisRun, MetropolisSteps, Sample, Samples};
 
 mod wanglandau;
-pub use wanglandau::WangLandau;
+pub use wanglandau::{HistogramCriterion, WangLandau, WangLandauConfig, WangLandauRun};
+
+mod selftest;
+
+mod shared;
+pub use shared::{SharedWangLandau, SharedWangLandauConfig, SharedWangLandauRun};
+
+mod kernel;
+pub use kernel::{KernelWangLandau, KernelWangLandauConfig, KernelWangLandauRun};
+
+mod production;
+pub use production::{WangLandauProduction, WangLandauProductionConfig, WangLandauProductionRun};
+
+mod tail;
+pub use tail::{TailPipeline, TailPipelineConfig, TailPipelineRun};
+
+mod windows;
+pub use windows::{WindowedWangLandau, WindowedWangLandauConfig, WindowedWangLandauRun};
+
+mod multicanonical;
+pub use multicanonical::{Multicanonical, MulticanonicalConfig, MulticanonicalRun, WeightUpdate};
+
+mod ams;
+pub use ams::{Ams, AmsConfig, AmsRun};
+
+mod crossentropy;
+pub use crossentropy::{CrossEntropy, CrossEntropyConfig, CrossEntropyRun, Parametric};
+
+mod sensemble;
+pub use sensemble::{SEnsemble, SEnsembleConfig, SEnsembleRun, TimeAdditive, Trajectory};
+
+#[cfg(feature = "mpi")]
+mod distributed;
+
+mod tempering;
+pub use tempering::{LadderChange, ParallelTempering, ParallelTemperingConfig, ParallelTemperingRun, SwapScheme};
+
+mod moves;
+pub use moves::{Move, MoveSet, MoveStats, NativeMove, WithMoves};
+
+mod sampler;
+pub use sampler::{DynSampler, Sampler, SamplerReport};
+
+#[cfg(feature = "models")]
+pub mod models;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+pub mod validate;
+
+pub mod analysis;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "plot")]
+pub mod plot;
+
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+#[cfg(feature = "status")]
+pub use metrics::Status;
+
+#[cfg(feature = "async")]
+mod background;
+#[cfg(feature = "async")]
+pub use background::{Background, Progress, Reporter};
 
 /// The fundamental trait of any model, which defines at least one observable to measure
 pub trait Model {
@@ -32,4 +137,12 @@ pub trait Model {
     fn save(&self) -> String {
         self.value().to_string()
     }
+
+    /// write the record of one sample to `w`, which is used by the samplers instead of
+    /// `save` to avoid the allocation of a `String` per sample
+    /// by default it writes the output of `save`, so models with expensive records
+    /// should overwrite this, e.g., `write!(w, "{} {}", self.energy, self.magnetization)`
+    fn write_record(&self, w: &mut dyn fmt::Write) -> fmt::Result {
+        w.write_str(&self.save())
+    }
 }
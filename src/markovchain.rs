@@ -1,7 +1,23 @@
-use rand::Rng;
+use std::fmt;
+
+use rand::{Rng, RngCore};
 
 use crate::Model;
 
+/// The outcome of proposing a change, see `MarkovChain::try_change`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Proposal {
+    /// the model was changed, the sampler decides whether to accept the change
+    Valid,
+    /// the change would violate a hard constraint, e.g., self-avoidance, and was not
+    /// applied, i.e., the model is unchanged and the sampler will not call `undo`
+    Invalid,
+    /// the change did not alter the state, e.g., a move of a particle to its own
+    /// position, the sampler accepts it without calling `value` or `undo`
+    Unchanged,
+}
+
 /// An abstract Markov Chain trait, which defines a `change` move to propose a new state
 /// and a method to `undo` the last change. Also needs a primary observable defined the
 /// super trait `Model`.
@@ -13,4 +29,128 @@ pub trait MarkovChain: Model {
 
     /// undo the previous `change`
     fn undo(&mut self);
+
+    /// Propose a change like `change`, but signal whether the proposal is valid.
+    /// Models with hard constraints should overwrite this to reject invalid proposals
+    /// cleanly, the samplers count them separately from energy rejections.
+    /// This is used by all samplers, by default it calls `change` and is always valid.
+    /// The samplers cache the value of the current state and call `value` once per
+    /// valid proposal, models which can detect trivial proposals cheaply should return
+    /// `Proposal::Unchanged` for them to avoid this call as well.
+    fn try_change(&mut self, rng: &mut impl Rng) -> Proposal {
+        self.change(rng);
+        Proposal::Valid
+    }
+
+    /// logarithm of the ratio of the proposal probabilities `q(new -> old) / q(old -> new)`
+    /// of the previous `change`, which is needed for the Metropolis-Hastings acceptance
+    /// of non-symmetric proposals. For symmetric proposals it is `0`, which is the default.
+    fn ln_proposal_ratio(&self) -> f64 {
+        0.
+    }
+}
+
+/// An optional extension for models, which can save their complete configuration and
+/// later return to it. In contrast to the single-step `MarkovChain::undo` this allows to
+/// go back arbitrarily far, which is needed, e.g., for replica exchange, population
+/// methods or checkpointing. For models which implement `Clone`, it is simply:
+///
+/// ```ignore
+/// impl Restorable for MyModel {
+///     type Snapshot = MyModel;
+///
+///     fn snapshot(&self) -> MyModel {
+///         self.clone()
+///     }
+///
+///     fn restore(&mut self, snapshot: &MyModel) {
+///         self.clone_from(snapshot)
+///     }
+/// }
+/// ```
+pub trait Restorable: Model {
+    /// the saved configuration, which can be a more compact representation than the model
+    type Snapshot: Clone;
+
+    /// save the current configuration
+    fn snapshot(&self) -> Self::Snapshot;
+
+    /// return to a previously saved configuration
+    fn restore(&mut self, snapshot: &Self::Snapshot);
+}
+
+/// An object-safe variant of `MarkovChain`, which is implemented for every `MarkovChain`.
+/// In turn, every `Box<dyn DynMarkovChain>` is a `MarkovChain`, such that runtime-selected
+/// or heterogeneous collections of models can be driven by the samplers, e.g.:
+///
+/// ```ignore
+/// let model: Box<dyn DynMarkovChain> = if ising { Box::new(ising) } else { Box::new(potts) };
+/// Metropolis::new(model).build()?.run(&mut rng, outfile)?;
+/// ```
+pub trait DynMarkovChain: Model {
+    /// see `MarkovChain::change`
+    fn dyn_change(&mut self, rng: &mut dyn RngCore);
+
+    /// see `MarkovChain::undo`
+    fn dyn_undo(&mut self);
+
+    /// see `MarkovChain::try_change`
+    fn dyn_try_change(&mut self, rng: &mut dyn RngCore) -> Proposal;
+
+    /// see `MarkovChain::ln_proposal_ratio`
+    fn dyn_ln_proposal_ratio(&self) -> f64;
+}
+
+impl<MC: MarkovChain> DynMarkovChain for MC {
+    fn dyn_change(&mut self, mut rng: &mut dyn RngCore) {
+        self.change(&mut rng)
+    }
+
+    fn dyn_undo(&mut self) {
+        self.undo()
+    }
+
+    fn dyn_try_change(&mut self, mut rng: &mut dyn RngCore) -> Proposal {
+        self.try_change(&mut rng)
+    }
+
+    fn dyn_ln_proposal_ratio(&self) -> f64 {
+        self.ln_proposal_ratio()
+    }
+}
+
+impl<T: DynMarkovChain + ?Sized> Model for Box<T> {
+    fn value(&self) -> f64 {
+        (**self).value()
+    }
+
+    fn header(&self) -> String {
+        (**self).header()
+    }
+
+    fn save(&self) -> String {
+        (**self).save()
+    }
+
+    fn write_record(&self, w: &mut dyn fmt::Write) -> fmt::Result {
+        (**self).write_record(w)
+    }
+}
+
+impl<T: DynMarkovChain + ?Sized> MarkovChain for Box<T> {
+    fn change(&mut self, rng: &mut impl Rng) {
+        (**self).dyn_change(rng)
+    }
+
+    fn undo(&mut self) {
+        (**self).dyn_undo()
+    }
+
+    fn try_change(&mut self, rng: &mut impl Rng) -> Proposal {
+        (**self).dyn_try_change(rng)
+    }
+
+    fn ln_proposal_ratio(&self) -> f64 {
+        (**self).dyn_ln_proposal_ratio()
+    }
 }
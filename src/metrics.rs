@@ -0,0 +1,266 @@
+//! Live metrics of a running simulation in the text format of Prometheus, e.g., to
+//! monitor long jobs on a cluster with existing dashboards instead of tailing their
+//! output. It is only compiled with the `metrics` feature.
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::hooks::SweepState;
+#[cfg(feature = "status")]
+use crate::histogram::Histogram;
+
+/// time to wait for the request of a connection to the exporter
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// minimal time between two copies of the histograms for the status, in seconds
+#[cfg(feature = "status")]
+const SNAPSHOT_INTERVAL: f64 = 1.;
+
+#[derive(Debug, Default)]
+struct Inner {
+    phase: AtomicUsize,
+    /// the expected total number of sweeps plus one, `0` if it is unknown
+    total: AtomicUsize,
+    /// the bits of the current `lnf`, `NaN` for samplers without it
+    lnf: AtomicU64,
+    sweeps: AtomicUsize,
+    tries: AtomicUsize,
+    rejects: AtomicUsize,
+    invalid: AtomicUsize,
+    /// the time of the first update, from which the rate of sweeps is measured
+    start: Mutex<Option<Instant>>,
+    /// copies of the current histograms `g` and `h` and the time they were taken
+    #[cfg(feature = "status")]
+    histograms: Mutex<(Option<Instant>, Option<Histogram>, Option<Histogram>)>,
+}
+
+/// Metrics of a simulation, which are updated by the sampler after every sweep and
+/// can be read from other threads, e.g., by the built-in exporter:
+///
+/// ```ignore
+/// let metrics = Metrics::new();
+/// metrics.serve("0.0.0.0:9184")?;
+/// let report = WangLandau::new(model)
+///    .metrics(metrics.clone())
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+///
+/// The exported gauges are `largedev_phase`, `largedev_lnf`, `largedev_t` (the number
+/// of completed sweeps), `largedev_acceptance` and `largedev_samples_per_second`,
+/// i.e., sweeps per second, since every sweep yields one sample. Clones share the
+/// same values, such that one instance should be used per simulation.
+#[derive(Clone, Debug)]
+pub struct Metrics(Arc<Inner>);
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let inner = Inner::default();
+        inner.lnf.store(f64::NAN.to_bits(), Ordering::Relaxed);
+        Metrics(Arc::new(inner))
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    /// the current phase, e.g., `1`, `2` or `3` for Wang-Landau sampling, `0` for
+    /// samplers without phases
+    pub fn phase(&self) -> usize {
+        self.0.phase.load(Ordering::Relaxed)
+    }
+
+    /// set the current `phase` and the expected `total` number of sweeps, if known
+    pub(crate) fn set_phase(&self, phase: usize, total: Option<usize>) {
+        self.0.phase.store(phase, Ordering::Relaxed);
+        self.0.total.store(total.map_or(0, |t| t + 1), Ordering::Relaxed);
+    }
+
+    /// the current refinement parameter `ln f`, `NaN` for samplers without it
+    pub fn lnf(&self) -> f64 {
+        f64::from_bits(self.0.lnf.load(Ordering::Relaxed))
+    }
+
+    /// the number of completed sweeps
+    pub fn t(&self) -> usize {
+        self.0.sweeps.load(Ordering::Relaxed)
+    }
+
+    /// fraction of accepted changes so far
+    pub fn acceptance(&self) -> f64 {
+        let tries = self.0.tries.load(Ordering::Relaxed);
+        let rejects = self.0.rejects.load(Ordering::Relaxed);
+        let invalid = self.0.invalid.load(Ordering::Relaxed);
+        // the counters are updated one after another, while they are read
+        tries.saturating_sub(rejects + invalid) as f64 / tries as f64
+    }
+
+    /// the number of sweeps per second since the first update
+    pub fn samples_per_second(&self) -> f64 {
+        let start = *self.0.start.lock().expect("a thread panicked while updating the metrics");
+        match start {
+            Some(start) => self.t() as f64 / start.elapsed().as_secs_f64(),
+            None => 0.,
+        }
+    }
+
+    /// Estimated remaining time in seconds at the current rate of sweeps, if the total
+    /// number of sweeps is known, which is the case for `Metropolis` and from the
+    /// second phase on for `WangLandau`.
+    pub fn eta(&self) -> Option<f64> {
+        let total = self.0.total.load(Ordering::Relaxed).checked_sub(1)?;
+        let rate = self.samples_per_second();
+        if rate > 0. {
+            Some(total.saturating_sub(self.t()) as f64 / rate)
+        } else {
+            None
+        }
+    }
+
+    /// update the metrics with the state of the sampler after a sweep
+    pub(crate) fn update(&self, state: &SweepState) {
+        self.0.start.lock()
+            .expect("a thread panicked while updating the metrics")
+            .get_or_insert_with(Instant::now);
+        self.0.lnf.store(state.lnf.unwrap_or(f64::NAN).to_bits(), Ordering::Relaxed);
+        self.0.sweeps.store(state.sweep, Ordering::Relaxed);
+        self.0.tries.store(state.tries, Ordering::Relaxed);
+        self.0.rejects.store(state.rejects, Ordering::Relaxed);
+        self.0.invalid.store(state.invalid, Ordering::Relaxed);
+
+        #[cfg(feature = "status")]
+        {
+            let mut histograms = self.0.histograms.lock().expect("a thread panicked while updating the metrics");
+            if histograms.0.is_none_or(|time| time.elapsed().as_secs_f64() >= SNAPSHOT_INTERVAL) {
+                *histograms = (Some(Instant::now()), state.g.cloned(), state.h.cloned());
+            }
+        }
+    }
+
+    /// A snapshot of the live state of the run, including copies of the histograms,
+    /// which are taken at most once per second.
+    #[cfg(feature = "status")]
+    pub fn status(&self) -> Status {
+        let (_, g, h) = self.0.histograms.lock().expect("a thread panicked while updating the metrics").clone();
+        Status {
+            phase: self.phase(),
+            lnf: self.lnf(),
+            t: self.t(),
+            tries: self.0.tries.load(Ordering::Relaxed),
+            acceptance: self.acceptance(),
+            samples_per_second: self.samples_per_second(),
+            eta: self.eta(),
+            g,
+            h,
+        }
+    }
+
+    /// the `status` as JSON, e.g., to be polled by a dashboard
+    #[cfg(feature = "status")]
+    pub fn status_json(&self) -> String {
+        // the status consists only of numbers, which always serialize
+        serde_json::to_string(&self.status()).unwrap()
+    }
+
+    /// the current metrics in the text exposition format of Prometheus
+    pub fn render(&self) -> String {
+        let gauges = [
+            ("largedev_phase", "current phase of the sampler", self.phase() as f64),
+            ("largedev_lnf", "current refinement parameter ln f of Wang-Landau sampling", self.lnf()),
+            ("largedev_t", "number of completed sweeps", self.t() as f64),
+            ("largedev_acceptance", "fraction of accepted changes", self.acceptance()),
+            ("largedev_samples_per_second", "sweeps per second since the start", self.samples_per_second()),
+        ];
+        let mut out = String::new();
+        for (name, help, value) in &gauges {
+            // writing to a `String` does not fail
+            writeln!(out, "# HELP {} {}", name, help).unwrap();
+            writeln!(out, "# TYPE {} gauge", name).unwrap();
+            writeln!(out, "{} {}", name, value).unwrap();
+        }
+        out
+    }
+
+    /// Serve the metrics over HTTP at `addr` from a background thread, which answers
+    /// every request with the output of `render` and lives as long as the process.
+    /// With the `status` feature, requests for `/status` are answered with the output
+    /// of `status_json` instead. Every connection is answered on its own thread and
+    /// dropped after `READ_TIMEOUT` without a complete request, such that a slow or
+    /// idle client can not block later scrapes.
+    pub fn serve(&self, addr: impl ToSocketAddrs) -> io::Result<thread::JoinHandle<()>> {
+        let listener = TcpListener::bind(addr)?;
+        let metrics = self.clone();
+        Ok(thread::spawn(move || {
+            for stream in listener.incoming() {
+                // a failed connection must not stop the exporter
+                let Ok(stream) = stream else { continue };
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    let _ = metrics.answer(stream);
+                });
+            }
+        }))
+    }
+
+    /// read the request from `stream` up to the empty line ending its header and answer it
+    fn answer(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(&stream);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 2 {
+            line.clear();
+        }
+        let (content_type, body) = self.respond(&request);
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            content_type,
+            body.len(),
+            body
+        )
+    }
+
+    /// the content type and the body of the answer to the HTTP `request` line
+    fn respond(&self, request: &str) -> (&'static str, String) {
+        #[cfg(feature = "status")]
+        if request.split_whitespace().nth(1) == Some("/status") {
+            return ("application/json", self.status_json())
+        }
+        #[cfg(not(feature = "status"))]
+        let _ = request;
+        ("text/plain; version=0.0.4", self.render())
+    }
+}
+
+/// The live state of a run, see `Metrics::status`.
+#[cfg(feature = "status")]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Status {
+    /// see `Metrics::phase`
+    pub phase: usize,
+    /// see `Metrics::lnf`, `null` in JSON for samplers without it
+    pub lnf: f64,
+    /// number of completed sweeps
+    pub t: usize,
+    /// number of proposed changes so far
+    pub tries: usize,
+    /// fraction of accepted changes so far
+    pub acceptance: f64,
+    /// sweeps per second since the start
+    pub samples_per_second: f64,
+    /// estimated remaining time in seconds, see `Metrics::eta`
+    pub eta: Option<f64>,
+    /// the latest copy of the estimate of the logarithm of the density of states
+    pub g: Option<Histogram>,
+    /// the latest copy of the auxiliary histogram of visits
+    pub h: Option<Histogram>,
+}
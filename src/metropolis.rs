@@ -1,117 +1,613 @@
-use std::io::{self, Write};
-use std::fs::File;
+use std::fmt::Write as _;
+use std::io::{BufWriter, Write};
+use std::ops::ControlFlow;
 
-use crate::markovchain::MarkovChain;
+use crate::acceptance::{AcceptanceRule, MetropolisRule, ln_boltzmann};
+use crate::markovchain::{MarkovChain, Proposal};
+use crate::error::{LargedevError, Result};
+use crate::report::{ObservableReport, ReplayReport, Report};
+use crate::seed::Seed;
+use crate::hooks::{CancelToken, SweepHook, SweepState};
+use crate::simple::Moments;
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::uniforms::Uniforms;
+use crate::replay::{MoveLog, Outcome, Recording, Replaying};
 
 use rand::Rng;
 
-/// A struct used to perform Metropolis sampling on some model, which implements the
-/// `MarkovChain` trait. This follows the builder pattern to specify all parameters.
-/// The `run` method executes the sampling, e.g.:
+/// the names and functions of the observables of `Metropolis::observable`
+type Observables<MC> = Vec<(String, Box<dyn Fn(&MC) -> f64>)>;
+
+/// Parameters of a Metropolis simulation, see `Metropolis` for the builder.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MetropolisConfig {
+    /// inverse temperature at which to simulate, `0` is a random walk, which accepts
+    /// every change, and `f64::INFINITY` a greedy quench, which accepts only changes
+    /// which do not increase the energy
+    pub beta: f64,
+    /// how many change moves does one sweep have
+    pub sweep: usize,
+    /// equilibration time in sweeps
+    pub t_eq: usize,
+    /// how many values to sample (total number of change moves is (`iterations` + `t_eq`) * `sweep`)
+    pub iterations: usize,
+    /// largest integer increase of the energy, whose acceptance probability is
+    /// precomputed in a lookup table, `0` disables the table
+    pub delta_table: usize,
+    /// seed for `run_seeded`
+    pub seed: Option<Seed>,
+    /// inverse temperatures, which are traversed in order instead of simulating at
+    /// `beta`, see `Metropolis::anneal`
+    pub chain: Vec<f64>,
+    /// sweeps at each temperature of the `chain` before the measured ones
+    pub chain_sweeps: usize,
+    /// number of the last temperatures of the `chain` with a full measurement
+    pub chain_measured: usize,
+}
+
+impl Default for MetropolisConfig {
+    fn default() -> Self {
+        MetropolisConfig {
+            beta: 0.,
+            t_eq: 0,
+            sweep: 1,
+            iterations: 1,
+            delta_table: 0,
+            seed: None,
+            chain: Vec::new(),
+            chain_sweeps: 0,
+            chain_measured: 1,
+        }
+    }
+}
+
+impl MetropolisConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.sweep == 0 {
+            return Err(LargedevError::InvalidParameter("sweep must be positive".into()))
+        }
+        if self.iterations == 0 {
+            return Err(LargedevError::InvalidParameter("iterations must be positive".into()))
+        }
+        for &beta in std::iter::once(&self.beta).chain(&self.chain) {
+            if beta.is_nan() || beta < 0. {
+                return Err(LargedevError::InvalidParameter(format!("beta must be non-negative, not {}", beta)))
+            }
+        }
+        if !self.chain.is_empty() && (self.chain_measured == 0 || self.chain_measured > self.chain.len()) {
+            let msg = format!("between 1 and {} temperatures of the chain can be measured, not {}", self.chain.len(), self.chain_measured);
+            return Err(LargedevError::InvalidParameter(msg))
+        }
+        Ok(())
+    }
+}
+
+/// A builder used to set up Metropolis sampling on some model, which implements the
+/// `MarkovChain` trait. After all parameters are specified, `build` validates them
+/// and returns a `MetropolisRun`, whose `run` method executes the sampling, e.g.:
 ///
-/// ```
-/// let (tries, rejects) = Metropolis::new(model)
+/// ```ignore
+/// let report = Metropolis::new(model)
 ///    .temperature(2.269)
 ///    .sweep(100)
 ///    .iterations(1000)
+///    .build()?
 ///    .run(&mut rng, outfile)?;
 /// ```
 pub struct Metropolis<MC> {
-    /// file handle of the output file
     model: MC,
-    /// temperature at which to simulate
-    temperature: f64,
-    /// how many change moves does one sweep have
-    sweep: usize,
-    /// equilibration time in sweeps
-    t_eq: usize,
-    /// how many values to sample (total number of change moves is (`iterations` + `t_eq`) * `sweep`)
-    iterations: usize,
+    config: MetropolisConfig,
+    observables: Observables<MC>,
+    hook: Option<SweepHook<MC>>,
+    cancel: Option<CancelToken>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
 }
 
 impl<MC: MarkovChain> Metropolis<MC> {
     pub fn new(model: MC) -> Self {
+        Metropolis::with_config(model, MetropolisConfig::default())
+    }
+
+    pub fn with_config(model: MC, config: MetropolisConfig) -> Self {
         Metropolis::<MC> {
             model,
-            temperature: 1e10,
-            t_eq: 0,
-            sweep: 1,
-            iterations: 1,
+            config,
+            observables: Vec::new(),
+            hook: None,
+            cancel: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
         }
     }
 
-    pub fn temperature(&mut self, t: f64) -> &mut Self {
-        self.temperature = t;
+    /// the inverse temperature, see `MetropolisConfig::beta`, the default is `0`
+    pub fn beta(mut self, beta: f64) -> Self {
+        self.config.beta = beta;
         self
     }
 
-    pub fn t_eq(&mut self, t_eq: usize) -> &mut Self {
-        self.t_eq = t_eq;
+    /// set the inverse temperature to `1 / t`, i.e., `0` is a greedy quench
+    pub fn temperature(mut self, t: f64) -> Self {
+        self.config.beta = 1. / t;
         self
     }
 
-    pub fn sweep(&mut self, sweep: usize) -> &mut Self {
-        assert!(sweep > 0);
-        self.sweep = sweep;
+    pub fn t_eq(mut self, t_eq: usize) -> Self {
+        self.config.t_eq = t_eq;
         self
     }
 
-    pub fn iterations(&mut self, iterations: usize) -> &mut Self {
-        assert!(iterations > 0);
-        self.iterations = iterations;
+    pub fn sweep(mut self, sweep: usize) -> Self {
+        self.config.sweep = sweep;
         self
     }
 
-    pub fn run(&mut self, mut rng: &mut impl Rng, file: &mut File) -> io::Result<(usize, usize)> {
-        let mut tries = 0;
-        let mut rejects = 0;
+    pub fn iterations(mut self, iterations: usize) -> Self {
+        self.config.iterations = iterations;
+        self
+    }
 
-        let beta = 1./self.temperature;
-        let mut energy_new = self.model.value();
-        let mut energy_old;
+    /// For models with integer energies, precompute the acceptance probabilities
+    /// `exp(-beta dE)` for `dE = 1, ..., max_delta`, which replaces the call of `exp` in
+    /// most acceptance tests. Other changes of the energy are still accepted correctly.
+    pub fn delta_table(mut self, max_delta: usize) -> Self {
+        self.config.delta_table = max_delta;
+        self
+    }
 
-        writeln!(file, "{}", self.model.header())?;
+    /// the seed used by `run_seeded`, if not set, a seed is drawn from entropy
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Self {
+        self.config.seed = Some(seed.into());
+        self
+    }
 
-        // simulate
-        for i in 0..self.t_eq + self.iterations {
-            for _ in 0..self.sweep {
-                energy_old = energy_new;
-                self.model.change(&mut rng);
-                tries += 1;
-                energy_new = self.model.value();
-
-                let p_acc = ((energy_old - energy_new) * beta).exp();
-                if p_acc < rng.gen_range(0., 1.) {
-                    self.model.undo();
-                    rejects += 1;
-                    energy_new = energy_old;
-                }
+    /// Traverse the inverse temperatures `betas` in order, e.g., from hot to cold, with
+    /// the same configuration instead of simulating at `beta`, to reach the equilibrium
+    /// at low temperatures. At each temperature, `sweeps` sweeps are performed, except
+    /// for the last `measure_last` ones, by default only the last one, where `t_eq`
+    /// sweeps of equilibration are followed by `iterations` measurements. The records
+    /// of every measured temperature follow a comment with its inverse temperature,
+    /// e.g.:
+    ///
+    /// ```ignore
+    /// let report = Metropolis::new(model)
+    ///    .anneal([0.1, 0.2, 0.3, 0.4, 0.44, 0.46], 100)
+    ///    .measure_last(2)
+    ///    .t_eq(1000)
+    ///    .iterations(10000)
+    ///    .build()?
+    ///    .run(&mut rng, outfile)?;
+    /// ```
+    ///
+    /// Only `run` and `run_seeded` traverse the chain, which the hook sees as one
+    /// simulation with consecutive sweeps.
+    pub fn anneal(mut self, betas: impl IntoIterator<Item = f64>, sweeps: usize) -> Self {
+        self.config.chain = betas.into_iter().collect();
+        self.config.chain_sweeps = sweeps;
+        self
+    }
+
+    /// the number of the last temperatures of `anneal` with a full measurement
+    pub fn measure_last(mut self, measured: usize) -> Self {
+        self.config.chain_measured = measured;
+        self
+    }
+
+    /// Measure `observable` in a column `name` of the records, e.g.:
+    ///
+    /// ```ignore
+    /// let report = Metropolis::new(model)
+    ///    .observable("energy", |m: &Ising2D| m.energy() as f64)
+    ///    .observable("m2", |m: &Ising2D| (m.magnetization() as f64).powi(2))
+    ///    .build()?
+    ///    .run(&mut rng, outfile)?;
+    /// println!("<m^2> = {} +- {}", report.observables[1].mean, report.observables[1].error);
+    /// ```
+    ///
+    /// Every call adds a column. With observables, the records consist of their values
+    /// instead of the record of the model, after a header of their names, and the
+    /// report contains their statistics, see `Report::observables`. The names must not
+    /// contain whitespace.
+    pub fn observable(mut self, name: impl Into<String>, observable: impl Fn(&MC) -> f64 + 'static) -> Self {
+        self.observables.push((name.into(), Box::new(observable)));
+        self
+    }
+
+    /// a hook called after every sweep, see `SweepHook`
+    pub fn on_sweep(mut self, hook: impl FnMut(&MC, &SweepState) -> ControlFlow<()> + 'static) -> Self {
+        self.hook = Some(Box::new(hook));
+        self
+    }
+
+    /// a token to cancel the simulation, see `CancelToken`
+    pub fn cancel_token(mut self, token: impl Into<CancelToken>) -> Self {
+        self.cancel = Some(token.into());
+        self
+    }
+
+    /// metrics, which are updated after every sweep, see `Metrics`
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// validate the parameters and return the configured sampler
+    pub fn build(self) -> Result<MetropolisRun<MC>> {
+        self.config.validate()?;
+        for (name, _) in &self.observables {
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(LargedevError::InvalidParameter(format!("invalid name of an observable {:?}", name)))
+            }
+        }
+        Ok(MetropolisRun {
+            model: self.model,
+            uniforms: Uniforms::new(self.config.sweep),
+            config: self.config,
+            moments: vec![Moments::new(); self.observables.len()],
+            observables: self.observables,
+            hook: self.hook,
+            cancel: self.cancel,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics,
+        })
+    }
+}
+
+/// A configured Metropolis sampler, see `Metropolis` for the builder.
+pub struct MetropolisRun<MC> {
+    model: MC,
+    /// random numbers for the acceptance tests of one sweep
+    uniforms: Uniforms,
+    config: MetropolisConfig,
+    observables: Observables<MC>,
+    /// the statistics of the `observables` in the current run
+    moments: Vec<Moments>,
+    hook: Option<SweepHook<MC>>,
+    cancel: Option<CancelToken>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+}
+
+impl<MC: MarkovChain> MetropolisRun<MC> {
+    pub fn config(&self) -> &MetropolisConfig {
+        &self.config
+    }
+
+    /// the current state of the model
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    /// consume the sampler and return the model in its current state
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// call the hook, if any, and return whether the simulation should stop,
+    /// either because the hook requests it, or because the run was cancelled
+    fn call_hook(&mut self, state: &SweepState) -> bool {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.update(state);
+        }
+        if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return true
+        }
+        match &mut self.hook {
+            Some(hook) => hook(&self.model, state).is_break(),
+            None => false,
+        }
+    }
+
+    /// the header of the records, with the names of the observables, if any
+    fn header(&self) -> String {
+        if self.observables.is_empty() {
+            return self.model.header()
+        }
+        let names: Vec<&str> = self.observables.iter().map(|(name, _)| name.as_str()).collect();
+        format!("# {}", names.join(" "))
+    }
+
+    /// restart the statistics of the observables
+    fn reset_moments(&mut self) {
+        self.moments = vec![Moments::new(); self.observables.len()];
+    }
+
+    /// Write the record of the current state to `record`, which is cleared first, and
+    /// add the values of the observables, if any, to their statistics.
+    fn write_record(&mut self, record: &mut String) -> Result<()> {
+        record.clear();
+        if self.observables.is_empty() {
+            self.model.write_record(record)?;
+            return Ok(())
+        }
+        for (i, ((_, observable), moments)) in self.observables.iter().zip(&mut self.moments).enumerate() {
+            let value = observable(&self.model);
+            moments.update(value);
+            if i > 0 {
+                record.push(' ');
+            }
+            write!(record, "{}", value)?;
+        }
+        Ok(())
+    }
+
+    /// the statistics of the observables in the current run
+    fn observable_reports(&self) -> Vec<ObservableReport> {
+        self.observables.iter()
+            .zip(&self.moments)
+            .map(|((name, _), moments)| match moments.finalize() {
+                Ok(summary) => ObservableReport {
+                    name: name.clone(),
+                    mean: summary.mean,
+                    variance: summary.variance,
+                    error: summary.error,
+                    min: summary.min,
+                    max: summary.max,
+                },
+                Err(_) => ObservableReport {
+                    name: name.clone(),
+                    mean: f64::NAN,
+                    variance: f64::NAN,
+                    error: f64::NAN,
+                    min: f64::NAN,
+                    max: f64::NAN,
+                },
+            })
+            .collect()
+    }
+
+    /// Simulate and write the records of the samples to `file`, which can be any
+    /// `Write`, e.g., a `File`, a `Vec<u8>` or a writer, which forwards the lines to a
+    /// callback. The output of all other samplers works the same way.
+    pub fn run(&mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<Report> {
+        self.simulate(&mut rng, file, None)
+    }
+
+    /// like `run`, but with a random number generator initialized from the configured
+    /// `seed` (or from entropy), which is written to the output and the report
+    pub fn run_seeded(&mut self, file: &mut impl Write) -> Result<Report> {
+        let seed = self.config.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate(&mut seed.rng(), file, Some(seed))
+    }
+
+    /// Like `run`, but log every random number and the outcome of every proposal, such
+    /// that `replay` can reproduce the trajectory exactly, see `MoveLog`. The log needs
+    /// `8` bytes per random number, so it is meant for short runs, e.g., to debug a model.
+    /// Since all numbers are derived from whole `u64`, the trajectory differs from the
+    /// one of `run` with the same random number generator.
+    pub fn run_recorded(&mut self, rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<(Report, MoveLog)> {
+        let mut log = MoveLog::new();
+        let mut draws = Vec::new();
+        let mut steps = self.start(file, None)?;
+        let mut rng = Recording { rng, draws: &mut draws };
+        while !steps.step_observed(&mut rng, usize::MAX, |outcome| log.push(outcome))? {}
+        let report = steps.finalize()?;
+        log.draws = draws;
+        Ok((report, log))
+    }
+
+    /// Run the simulation again with the random numbers of `log` instead of a random
+    /// number generator and compare the outcomes of the proposals with the logged ones.
+    /// The model must be in the same initial state as for `run_recorded` and the
+    /// parameters must be the same, then the output and the report are identical,
+    /// unless the model behaves differently, which the `diverged` proposal of the
+    /// report reveals.
+    pub fn replay(&mut self, log: &MoveLog, file: &mut impl Write) -> Result<ReplayReport> {
+        let mut rng = Replaying { draws: &log.draws, position: 0, exhausted: false };
+        let mut proposals = 0;
+        let mut diverged = None;
+        let mut steps = self.start(file, None)?;
+        while !steps.step_observed(&mut rng, usize::MAX, |outcome| {
+            if diverged.is_none() && log.outcome(proposals) != Some(outcome) {
+                diverged = Some(proposals);
             }
+            proposals += 1;
+        })? {}
+        let report = steps.finalize()?;
+        if diverged.is_none() && proposals != log.len() {
+            diverged = Some(proposals.min(log.len()));
+        }
+        trace_event!(info!(diverged, exhausted = rng.exhausted, "replayed"));
+        Ok(ReplayReport {
+            diverged,
+            exhausted: rng.exhausted,
+            report,
+        })
+    }
+
+    fn sweep(&mut self, rng: &mut impl Rng, acceptance: &Acceptance, energy: &mut f64, report: &mut Report) {
+        sweep(&mut self.model, rng, &mut self.uniforms, self.config.sweep, acceptance, energy, report);
+    }
+
+    fn sweep_observed(&mut self, rng: &mut impl Rng, acceptance: &Acceptance, energy: &mut f64, report: &mut Report, observe: impl FnMut(Outcome)) {
+        sweep_observed(&mut self.model, rng, &mut self.uniforms, self.config.sweep, acceptance, energy, report, observe);
+    }
+
+    fn acceptance(&self) -> Acceptance {
+        Acceptance::new(self.config.beta, self.config.delta_table)
+    }
 
-            if i > self.t_eq {
-                writeln!(file, "{}", self.model.save())?;
+    fn simulate(&mut self, rng: &mut impl Rng, file: &mut impl Write, seed: Option<Seed>) -> Result<Report> {
+        if !self.config.chain.is_empty() {
+            return self.anneal(rng, file, seed)
+        }
+        trace_span!(_span = info_span!("metropolis", beta = self.config.beta, sweep = self.config.sweep, iterations = self.config.iterations));
+        let mut steps = self.start(file, seed)?;
+        while !steps.step(rng, usize::MAX)? {}
+        steps.finalize()
+    }
+
+    /// traverse the temperatures of the chain, see `Metropolis::anneal`
+    fn anneal(&mut self, rng: &mut impl Rng, file: &mut impl Write, seed: Option<Seed>) -> Result<Report> {
+        let mut file = BufWriter::new(file);
+        if let Some(seed) = seed {
+            writeln!(file, "# seed = {}", seed)?;
+        }
+        writeln!(file, "{}", self.header())?;
+        self.reset_moments();
+        self.uniforms.reset();
+
+        let chain = self.config.chain.clone();
+        let first_measured = chain.len() - self.config.chain_measured;
+        let measurement = self.config.t_eq + self.config.iterations;
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.set_phase(0, Some(first_measured * self.config.chain_sweeps + self.config.chain_measured * measurement));
+        }
+
+        let mut report = Report {
+            seed,
+            ..Report::default()
+        };
+        let mut energy = self.model.value();
+        let mut record = String::new();
+        let mut t = 0;
+        'chain: for (i, &beta) in chain.iter().enumerate() {
+            trace_event!(debug!(beta, t, energy, acceptance = report.acceptance(), "anneal"));
+            let measured = i >= first_measured;
+            if measured {
+                writeln!(file, "# beta = {}", beta)?;
+            }
+            let acceptance = Acceptance::new(beta, self.config.delta_table);
+            let sweeps = if measured { measurement } else { self.config.chain_sweeps };
+            for s in 0..sweeps {
+                self.sweep(rng, &acceptance, &mut energy, &mut report);
+                t += 1;
+                trace_event!(trace!(t, energy, "sweep"));
+
+                if measured && s >= self.config.t_eq {
+                    self.write_record(&mut record)?;
+                    writeln!(file, "{}", record)?;
+                }
+
+                let state = SweepState { sweep: t, energy, tries: report.tries, rejects: report.rejects, invalid: report.invalid, lnf: None, g: None, h: None };
+                if self.call_hook(&state) {
+                    report.stopped = true;
+                    break 'chain;
+                }
             }
         }
+        file.flush()?;
+        report.observables = self.observable_reports();
+        trace_event!(info!(tries = report.tries, acceptance = report.acceptance(), stopped = report.stopped, "finished"));
+        Ok(report)
+    }
 
-        Ok((tries, rejects))
+    /// Start the simulation step by step instead of in one call of `run`, such that an
+    /// external scheduler can drive it, e.g., interleaved with other simulations. The
+    /// header is written to `file` immediately, the returned `MetropolisSteps` performs
+    /// the sweeps with `step` and finishes the output with `finalize`, e.g.:
+    ///
+    /// ```ignore
+    /// let mut steps = run.init(&mut outfile)?;
+    /// while !steps.step(&mut rng, 100)? {
+    ///     // do something else in between
+    /// }
+    /// let report = steps.finalize()?;
+    /// ```
+    ///
+    /// The result is the same as the one of `run` with the same random numbers.
+    pub fn init<W: Write>(&mut self, file: W) -> Result<MetropolisSteps<'_, MC, W>> {
+        self.start(file, None)
     }
 
-    pub fn exec(mut self, mut rng: &mut impl Rng, mut file: &mut File) -> io::Result<MC> {
-        self.run(&mut rng, &mut file)?;
+    fn start<W: Write>(&mut self, file: W, seed: Option<Seed>) -> Result<MetropolisSteps<'_, MC, W>> {
+        // buffer the output, since it is written sample by sample
+        let mut file = BufWriter::new(file);
+
+        if let Some(seed) = seed {
+            writeln!(file, "# seed = {}", seed)?;
+        }
+        writeln!(file, "{}", self.header())?;
+        self.reset_moments();
+        // the numbers left over from the last run stem from another generator, also
+        // the ones of the acceptance tests must be drawn within a recording
+        self.uniforms.reset();
+
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.set_phase(0, Some(self.config.t_eq + self.config.iterations));
+        }
+
+        Ok(MetropolisSteps {
+            acceptance: self.acceptance(),
+            energy: self.model.value(),
+            run: self,
+            file,
+            record: String::new(),
+            report: Report {
+                seed,
+                ..Report::default()
+            },
+            sweep: 0,
+        })
+    }
+
+    /// Iterate over the measurements instead of writing them to a file. After the
+    /// equilibration of `t_eq` sweeps, every call of `next` performs one sweep and
+    /// yields the current value of the model, `iterations` times, e.g.:
+    ///
+    /// ```ignore
+    /// let mean = run.samples(&mut rng)
+    ///    .map(|s| s.value)
+    ///    .sum::<f64>() / iterations as f64;
+    /// ```
+    ///
+    /// The sweep hook is not called, the `CancelToken` ends the iteration.
+    pub fn samples<'a, R: Rng + ?Sized>(&'a mut self, rng: &'a mut R) -> Samples<'a, MC, R> {
+        // the numbers left over from the last run stem from another generator
+        self.uniforms.reset();
+        let energy = self.model.value();
+        Samples {
+            acceptance: self.acceptance(),
+            run: self,
+            rng,
+            energy,
+            sweep: 0,
+        }
+    }
+
+    pub fn exec(mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<MC> {
+        self.run(&mut rng, file)?;
         Ok(self.model)
     }
 
-    pub fn downhill(&mut self, mut rng: &mut impl Rng) -> f64 {
+    /// Greedy minimization of the value: `iterations` changes are proposed and every
+    /// change, which increases the value, is undone. Returns the final value.
+    pub fn downhill(&mut self, mut rng: &mut (impl Rng + ?Sized)) -> f64 {
         let mut energy_new = self.model.value();
         let mut energy_old;
 
         // simulate
-        for _ in 0..self.iterations {
+        for _ in 0..self.config.iterations {
+            if self.model.try_change(&mut rng) != Proposal::Valid {
+                continue;
+            }
             energy_old = energy_new;
-            self.model.change(&mut rng);
             energy_new = self.model.value();
 
-            if energy_old > energy_new {
+            if energy_old < energy_new {
                 self.model.undo();
                 energy_new = energy_old;
             }
@@ -119,17 +615,21 @@ impl<MC: MarkovChain> Metropolis<MC> {
         energy_new
     }
 
-    pub fn uphill(&mut self, mut rng: &mut impl Rng) -> f64 {
+    /// Greedy maximization of the value, like `downhill`, but every change, which
+    /// decreases the value, is undone. Returns the final value.
+    pub fn uphill(&mut self, mut rng: &mut (impl Rng + ?Sized)) -> f64 {
         let mut energy_new = self.model.value();
         let mut energy_old;
 
         // simulate
-        for _ in 0..self.iterations {
+        for _ in 0..self.config.iterations {
+            if self.model.try_change(&mut rng) != Proposal::Valid {
+                continue;
+            }
             energy_old = energy_new;
-            self.model.change(&mut rng);
             energy_new = self.model.value();
 
-            if energy_old < energy_new {
+            if energy_old > energy_new {
                 self.model.undo();
                 energy_new = energy_old;
             }
@@ -137,3 +637,197 @@ impl<MC: MarkovChain> Metropolis<MC> {
         energy_new
     }
 }
+
+/// The Metropolis acceptance probabilities at the inverse temperature `beta`, with a
+/// lookup table for small integer increases of the energy.
+pub(crate) struct Acceptance {
+    beta: f64,
+    /// `exp(-beta dE)` at index `dE`
+    table: Vec<f64>,
+}
+
+impl Acceptance {
+    pub(crate) fn new(beta: f64, max_delta: usize) -> Self {
+        let table = if max_delta > 0 {
+            (0..=max_delta).map(|d| MetropolisRule.probability(ln_boltzmann(beta, d as f64))).collect()
+        } else {
+            Vec::new()
+        };
+        Acceptance { beta, table }
+    }
+
+    /// whether to accept a change of the energy by `delta` with the logarithmic
+    /// proposal ratio `ln_ratio` for the uniform random number `u`
+    fn accept(&self, delta: f64, ln_ratio: f64, u: f64) -> bool {
+        if ln_ratio == 0. && delta > 0. && delta.fract() == 0. && delta < self.table.len() as f64 {
+            return u < self.table[delta as usize]
+        }
+        MetropolisRule.accept(ln_ratio + ln_boltzmann(self.beta, delta), u)
+    }
+}
+
+/// Perform one sweep of `sweep` change attempts on `model` with the `acceptance`
+/// probabilities, starting from the current `energy`, which is updated. The random
+/// numbers for the acceptance tests are taken from `uniforms`. The proposals are
+/// counted in `report`.
+pub(crate) fn sweep<MC: MarkovChain>(model: &mut MC, rng: &mut impl Rng, uniforms: &mut Uniforms, sweep: usize, acceptance: &Acceptance, energy: &mut f64, report: &mut Report) {
+    sweep_observed(model, rng, uniforms, sweep, acceptance, energy, report, |_| ());
+}
+
+/// like `sweep`, but pass the outcome of every proposal to `observe`
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sweep_observed<MC: MarkovChain>(model: &mut MC, mut rng: &mut impl Rng, uniforms: &mut Uniforms, sweep: usize, acceptance: &Acceptance, energy: &mut f64, report: &mut Report, mut observe: impl FnMut(Outcome)) {
+    for _ in 0..sweep {
+        report.tries += 1;
+        match model.try_change(&mut rng) {
+            Proposal::Valid => (),
+            Proposal::Invalid => {
+                report.invalid += 1;
+                observe(Outcome::Invalid);
+                continue;
+            }
+            Proposal::Unchanged => {
+                observe(Outcome::Unchanged);
+                continue
+            }
+        }
+        let energy_old = *energy;
+        let energy_new = model.value();
+
+        if !acceptance.accept(energy_new - energy_old, model.ln_proposal_ratio(), uniforms.next(rng)) {
+            model.undo();
+            report.rejects += 1;
+            observe(Outcome::Rejected);
+        } else {
+            *energy = energy_new;
+            observe(Outcome::Accepted);
+        }
+    }
+}
+
+/// A Metropolis simulation in progress, see `MetropolisRun::init`.
+pub struct MetropolisSteps<'a, MC, W: Write> {
+    run: &'a mut MetropolisRun<MC>,
+    file: BufWriter<W>,
+    acceptance: Acceptance,
+    energy: f64,
+    /// buffer for the records, reused to avoid allocations
+    record: String,
+    report: Report,
+    /// number of completed sweeps, including the equilibration
+    sweep: usize,
+}
+
+impl<'a, MC: MarkovChain, W: Write> MetropolisSteps<'a, MC, W> {
+    /// Perform up to `n` sweeps and write the records of the samples among them.
+    /// Returns whether the simulation is finished, i.e., whether all sweeps are done or
+    /// the hook or the `CancelToken` stopped it.
+    pub fn step(&mut self, rng: &mut (impl Rng + ?Sized), n: usize) -> Result<bool> {
+        self.step_observed(rng, n, |_| ())
+    }
+
+    /// like `step`, but pass the outcome of every proposal to `observe`
+    pub(crate) fn step_observed(&mut self, mut rng: &mut (impl Rng + ?Sized), n: usize, mut observe: impl FnMut(Outcome)) -> Result<bool> {
+        let t_eq = self.run.config.t_eq;
+        for _ in 0..n {
+            if self.is_finished() {
+                break;
+            }
+            self.run.sweep_observed(&mut rng, &self.acceptance, &mut self.energy, &mut self.report, &mut observe);
+            self.sweep += 1;
+            let (t, energy) = (self.sweep, self.energy);
+            trace_event!(trace!(t, energy, "sweep"));
+            if t == t_eq {
+                trace_event!(debug!(t, acceptance = self.report.acceptance(), "equilibrated"));
+            }
+
+            if t > t_eq {
+                self.run.write_record(&mut self.record)?;
+                writeln!(self.file, "{}", self.record)?;
+            }
+
+            let report = &self.report;
+            let state = SweepState { sweep: t, energy, tries: report.tries, rejects: report.rejects, invalid: report.invalid, lnf: None, g: None, h: None };
+            if self.run.call_hook(&state) {
+                self.report.stopped = true;
+            }
+        }
+        Ok(self.is_finished())
+    }
+
+    /// whether all sweeps are done or the simulation was stopped
+    pub fn is_finished(&self) -> bool {
+        self.report.stopped || self.sweep >= self.run.config.t_eq + self.run.config.iterations
+    }
+
+    /// number of completed sweeps, including the equilibration
+    pub fn sweeps(&self) -> usize {
+        self.sweep
+    }
+
+    /// the current state of the model
+    pub fn model(&self) -> &MC {
+        &self.run.model
+    }
+
+    /// the counts of the proposals so far
+    pub fn report(&self) -> &Report {
+        &self.report
+    }
+
+    /// Flush the output and return the report, also if the simulation is not finished.
+    pub fn finalize(mut self) -> Result<Report> {
+        self.file.flush()?;
+        let mut report = self.report;
+        report.observables = self.run.observable_reports();
+        trace_event!(info!(tries = report.tries, acceptance = report.acceptance(), stopped = report.stopped, "finished"));
+        Ok(report)
+    }
+}
+
+/// A single measurement of a Metropolis simulation, see `MetropolisRun::samples`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sample {
+    /// number of completed sweeps, including the equilibration
+    pub sweep: usize,
+    /// value of the model after this sweep
+    pub value: f64,
+}
+
+/// An iterator over the measurements of a Metropolis simulation, see `MetropolisRun::samples`.
+pub struct Samples<'a, MC, R: ?Sized> {
+    run: &'a mut MetropolisRun<MC>,
+    rng: &'a mut R,
+    acceptance: Acceptance,
+    energy: f64,
+    sweep: usize,
+}
+
+impl<'a, MC: MarkovChain, R: Rng + ?Sized> Iterator for Samples<'a, MC, R> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        let t_eq = self.run.config.t_eq;
+        if self.sweep >= t_eq + self.run.config.iterations
+            || self.run.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+        {
+            return None
+        }
+
+        // the counts of the proposals are not reported by the iterator
+        let mut report = Report::default();
+        while self.sweep < t_eq {
+            self.run.sweep(&mut self.rng, &self.acceptance, &mut self.energy, &mut report);
+            self.sweep += 1;
+        }
+
+        self.run.sweep(&mut self.rng, &self.acceptance, &mut self.energy, &mut report);
+        self.sweep += 1;
+
+        Some(Sample {
+            sweep: self.sweep,
+            value: self.energy,
+        })
+    }
+}
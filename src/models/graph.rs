@@ -0,0 +1,184 @@
+use std::collections::HashSet;
+
+use rand::Rng;
+
+use crate::{LargedevError, Model, Result};
+use crate::markovchain::{MarkovChain, Restorable};
+use crate::simple::DirectSamplable;
+
+/// The observable of `ErdosRenyi`, which is used as its `value`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GraphObservable {
+    /// number of nodes in the largest connected component
+    LargestComponent,
+    /// number of connected components, including isolated nodes
+    Components,
+}
+
+/// An Erdős–Rényi random graph `G(n, m)` of `n` nodes and `m` edges, which are drawn
+/// uniformly from all pairs of distinct nodes. The `value` is a component observable,
+/// see `GraphObservable`. The `change` rewires a random edge to a random pair of nodes,
+/// which are not connected yet, which keeps the uniform distribution over all graphs
+/// with `m` edges, e.g.:
+///
+/// ```ignore
+/// // mean degree 2 * m / n = 1
+/// let model = ErdosRenyi::random(100, 50, GraphObservable::LargestComponent, &mut rng)?;
+/// let report = WangLandau::new(model)
+///    .range(1., 101.)
+///    .bins(100)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ErdosRenyi {
+    n: usize,
+    edges: Vec<(usize, usize)>,
+    /// the edges as ordered pairs, to test for multiple edges
+    present: HashSet<(usize, usize)>,
+    observable: GraphObservable,
+    /// index and previous end points of the last rewired edge
+    last: Option<(usize, (usize, usize))>,
+}
+
+impl ErdosRenyi {
+    /// a random graph of `n` nodes and `m` edges
+    pub fn random(n: usize, m: usize, observable: GraphObservable, rng: &mut impl Rng) -> Result<Self> {
+        if n < 2 {
+            return Err(LargedevError::InvalidConfig("the graph needs at least two nodes".into()))
+        }
+        // at least one pair needs to be free for rewiring
+        if m >= n * (n - 1) / 2 {
+            return Err(LargedevError::InvalidConfig(format!("{} edges are too many for {} nodes", m, n)))
+        }
+        let mut model = ErdosRenyi {
+            n,
+            edges: Vec::with_capacity(m),
+            present: HashSet::with_capacity(m),
+            observable,
+            last: None,
+        };
+        model.edges.resize(m, (0, 0));
+        model.reconstruct(rng);
+        Ok(model)
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.n
+    }
+
+    pub fn edges(&self) -> &[(usize, usize)] {
+        &self.edges
+    }
+
+    pub fn observable(&self) -> GraphObservable {
+        self.observable
+    }
+
+    /// the sizes of all connected components, in no particular order
+    pub fn component_sizes(&self) -> Vec<usize> {
+        let mut parent: Vec<usize> = (0..self.n).collect();
+        let mut size = vec![1; self.n];
+        for &(a, b) in &self.edges {
+            let (ra, rb) = (find(&mut parent, a), find(&mut parent, b));
+            if ra != rb {
+                // union by size
+                let (big, small) = if size[ra] >= size[rb] { (ra, rb) } else { (rb, ra) };
+                parent[small] = big;
+                size[big] += size[small];
+            }
+        }
+        (0..self.n).filter(|&i| parent[i] == i).map(|i| size[i]).collect()
+    }
+
+    /// a uniformly random pair of distinct nodes, which is not connected yet
+    fn free_pair(&self, rng: &mut impl Rng) -> (usize, usize) {
+        loop {
+            let a = rng.gen_range(0..self.n);
+            let b = rng.gen_range(0..self.n);
+            let pair = (a.min(b), a.max(b));
+            if a != b && !self.present.contains(&pair) {
+                return pair
+            }
+        }
+    }
+
+    fn set(&mut self, e: usize, pair: (usize, usize)) {
+        self.present.remove(&self.edges[e]);
+        self.present.insert(pair);
+        self.edges[e] = pair;
+    }
+}
+
+/// the root of `i` in the union-find forest `parent`, with path halving
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+impl Model for ErdosRenyi {
+    fn value(&self) -> f64 {
+        let sizes = self.component_sizes();
+        match self.observable {
+            GraphObservable::LargestComponent => sizes.into_iter().max().unwrap_or(0) as f64,
+            GraphObservable::Components => sizes.len() as f64,
+        }
+    }
+
+    fn header(&self) -> String {
+        match self.observable {
+            GraphObservable::LargestComponent => "# size of the largest component".into(),
+            GraphObservable::Components => "# number of components".into(),
+        }
+    }
+}
+
+impl MarkovChain for ErdosRenyi {
+    fn change(&mut self, rng: &mut impl Rng) {
+        if self.edges.is_empty() {
+            self.last = None;
+            return
+        }
+        let e = rng.gen_range(0..self.edges.len());
+        let pair = self.free_pair(rng);
+        self.last = Some((e, self.edges[e]));
+        self.set(e, pair);
+    }
+
+    fn undo(&mut self) {
+        if let Some((e, pair)) = self.last.take() {
+            self.set(e, pair);
+        }
+    }
+}
+
+impl DirectSamplable for ErdosRenyi {
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        self.present.clear();
+        for e in 0..self.edges.len() {
+            let pair = self.free_pair(rng);
+            self.present.insert(pair);
+            self.edges[e] = pair;
+        }
+        self.last = None;
+    }
+}
+
+impl Restorable for ErdosRenyi {
+    type Snapshot = Vec<(usize, usize)>;
+
+    fn snapshot(&self) -> Vec<(usize, usize)> {
+        self.edges.clone()
+    }
+
+    fn restore(&mut self, snapshot: &Vec<(usize, usize)>) {
+        self.edges.clone_from(snapshot);
+        self.present = self.edges.iter().cloned().collect();
+        self.last = None;
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::BTreeSet;
+
+use rand::Rng;
+
+use crate::{LargedevError, Model, Result};
+use crate::markovchain::{MarkovChain, Proposal, Restorable};
+use crate::simple::DirectSamplable;
+
+use super::{LatticeWalk, LevyWalk, SelfAvoidingWalk};
+
+/// A model, whose configuration defines a set of points, e.g., the sites of a walk.
+pub trait PointSet {
+    /// dimension of the points
+    fn dimension(&self) -> usize;
+
+    /// the coordinates of all points, `dimension()` consecutive values per point
+    fn coordinates(&self) -> Vec<f64>;
+}
+
+impl PointSet for LatticeWalk {
+    fn dimension(&self) -> usize {
+        LatticeWalk::dimension(self)
+    }
+
+    fn coordinates(&self) -> Vec<f64> {
+        self.positions().iter().flatten().map(|&x| x as f64).collect()
+    }
+}
+
+impl PointSet for LevyWalk {
+    fn dimension(&self) -> usize {
+        2
+    }
+
+    fn coordinates(&self) -> Vec<f64> {
+        self.positions().iter().flat_map(|&(x, y)| vec![x, y]).collect()
+    }
+}
+
+impl PointSet for SelfAvoidingWalk {
+    fn dimension(&self) -> usize {
+        2
+    }
+
+    fn coordinates(&self) -> Vec<f64> {
+        self.positions().iter().flat_map(|&(x, y)| vec![x as f64, y as f64]).collect()
+    }
+}
+
+/// The observable of the convex hull, which is used as the `value` of `ConvexHull`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HullMeasure {
+    /// the area in 2D, the volume in 3D
+    Volume,
+    /// the perimeter in 2D, the surface area in 3D
+    Surface,
+}
+
+/// A wrapper around a model with a `PointSet`, e.g., a walk, whose `value` is the
+/// volume or the surface of the convex hull of the points, in two or three dimensions.
+/// The proposals of the wrapped model are used unchanged, such that large deviations
+/// of the hull can be sampled directly, e.g.:
+///
+/// ```ignore
+/// let model = ConvexHull::new(LatticeWalk::random(100, 2, &mut rng)?, HullMeasure::Volume)?;
+/// let report = WangLandau::new(model)
+///    .range(0., 2500.)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+///
+/// The hull is calculated from scratch for every `value`, which takes `O(n log n)`
+/// time in 2D and `O(n^2)` in the worst case in 3D.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConvexHull<M> {
+    model: M,
+    measure: HullMeasure,
+}
+
+impl<M: PointSet> ConvexHull<M> {
+    pub fn new(model: M, measure: HullMeasure) -> Result<Self> {
+        let d = model.dimension();
+        if d != 2 && d != 3 {
+            return Err(LargedevError::InvalidConfig(format!("convex hulls are implemented in 2 and 3 dimensions, not {}", d)))
+        }
+        Ok(ConvexHull {
+            model,
+            measure,
+        })
+    }
+
+    pub fn measure(&self) -> HullMeasure {
+        self.measure
+    }
+
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut M {
+        &mut self.model
+    }
+
+    pub fn into_inner(self) -> M {
+        self.model
+    }
+}
+
+impl<M: PointSet> Model for ConvexHull<M> {
+    fn value(&self) -> f64 {
+        let coordinates = self.model.coordinates();
+        match self.model.dimension() {
+            2 => {
+                let points: Vec<[f64; 2]> = coordinates.chunks(2).map(|p| [p[0], p[1]]).collect();
+                let hull = hull_2d(&points);
+                match self.measure {
+                    HullMeasure::Volume => area_2d(&hull),
+                    HullMeasure::Surface => perimeter_2d(&hull),
+                }
+            }
+            _ => {
+                let points: Vec<[f64; 3]> = coordinates.chunks(3).map(|p| [p[0], p[1], p[2]]).collect();
+                let (volume, surface) = hull_3d(&points);
+                match self.measure {
+                    HullMeasure::Volume => volume,
+                    HullMeasure::Surface => surface,
+                }
+            }
+        }
+    }
+
+    fn header(&self) -> String {
+        match self.measure {
+            HullMeasure::Volume => "# hull volume".into(),
+            HullMeasure::Surface => "# hull surface".into(),
+        }
+    }
+}
+
+impl<M: PointSet + MarkovChain> MarkovChain for ConvexHull<M> {
+    fn change(&mut self, rng: &mut impl Rng) {
+        self.model.change(rng)
+    }
+
+    fn try_change(&mut self, rng: &mut impl Rng) -> Proposal {
+        self.model.try_change(rng)
+    }
+
+    fn undo(&mut self) {
+        self.model.undo()
+    }
+
+    fn ln_proposal_ratio(&self) -> f64 {
+        self.model.ln_proposal_ratio()
+    }
+}
+
+impl<M: PointSet + DirectSamplable> DirectSamplable for ConvexHull<M> {
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        self.model.reconstruct(rng)
+    }
+}
+
+impl<M: PointSet + Restorable> Restorable for ConvexHull<M> {
+    type Snapshot = M::Snapshot;
+
+    fn snapshot(&self) -> M::Snapshot {
+        self.model.snapshot()
+    }
+
+    fn restore(&mut self, snapshot: &M::Snapshot) {
+        self.model.restore(snapshot)
+    }
+}
+
+/// z component of the cross product of `a - o` and `b - o`
+fn cross_2d(o: [f64; 2], a: [f64; 2], b: [f64; 2]) -> f64 {
+    (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+}
+
+/// The convex hull of `points` in counterclockwise order, without collinear points,
+/// with Andrew's monotone chain algorithm.
+pub fn hull_2d(points: &[[f64; 2]]) -> Vec<[f64; 2]> {
+    let mut points = points.to_vec();
+    points.sort_by(|a, b| a.partial_cmp(b).expect("the coordinates must not be NaN"));
+    points.dedup();
+    if points.len() < 3 {
+        return points
+    }
+
+    let mut hull: Vec<[f64; 2]> = Vec::with_capacity(2 * points.len());
+    // lower hull, then upper hull
+    for pass in 0..2 {
+        let start = hull.len();
+        let iter: Box<dyn Iterator<Item = &[f64; 2]>> = if pass == 0 {
+            Box::new(points.iter())
+        } else {
+            Box::new(points.iter().rev())
+        };
+        for &p in iter {
+            while hull.len() >= start + 2 && cross_2d(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0. {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        // the last point is the first of the other half
+        hull.pop();
+    }
+    hull
+}
+
+/// area of a polygon, e.g., of `hull_2d`, by the shoelace formula
+pub fn area_2d(polygon: &[[f64; 2]]) -> f64 {
+    let n = polygon.len();
+    (0..n).map(|i| {
+        let (a, b) = (polygon[i], polygon[(i + 1) % n]);
+        a[0] * b[1] - a[1] * b[0]
+    }).sum::<f64>().abs() / 2.
+}
+
+/// perimeter of a closed polygon, e.g., of `hull_2d`
+pub fn perimeter_2d(polygon: &[[f64; 2]]) -> f64 {
+    let n = polygon.len();
+    if n < 2 {
+        return 0.
+    }
+    (0..n).map(|i| {
+        let (a, b) = (polygon[i], polygon[(i + 1) % n]);
+        (a[0] - b[0]).hypot(a[1] - b[1])
+    }).sum()
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn norm(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+/// six times the signed volume of the tetrahedron `a, b, c, d`, positive if `d` is in
+/// front of the counterclockwise triangle `a, b, c`
+fn orient(a: [f64; 3], b: [f64; 3], c: [f64; 3], d: [f64; 3]) -> f64 {
+    dot(cross(sub(b, a), sub(c, a)), sub(d, a))
+}
+
+/// The volume and the surface area of the convex hull of `points` in three dimensions,
+/// with an incremental algorithm. Degenerate point sets, e.g., in a plane, have the
+/// volume zero and the surface area of both sides of the flat hull is not calculated,
+/// i.e., it is zero as well.
+pub fn hull_3d(points: &[[f64; 3]]) -> (f64, f64) {
+    if points.len() < 4 {
+        return (0., 0.)
+    }
+    // tolerance of the orientation tests relative to the extent of the points
+    let extent = points.iter()
+        .flat_map(|p| p.iter())
+        .fold(0f64, |m, x| m.max(x.abs()))
+        .max(1.);
+    let eps = 1e-12 * extent.powi(3);
+
+    // initial tetrahedron of points far apart
+    let a = 0;
+    let b = (0..points.len())
+        .max_by(|&i, &j| norm(sub(points[i], points[a])).partial_cmp(&norm(sub(points[j], points[a]))).unwrap())
+        .unwrap();
+    let line = sub(points[b], points[a]);
+    let c = (0..points.len())
+        .max_by(|&i, &j| {
+            norm(cross(line, sub(points[i], points[a])))
+                .partial_cmp(&norm(cross(line, sub(points[j], points[a])))).unwrap()
+        })
+        .unwrap();
+    let d = (0..points.len())
+        .max_by(|&i, &j| {
+            orient(points[a], points[b], points[c], points[i]).abs()
+                .partial_cmp(&orient(points[a], points[b], points[c], points[j]).abs()).unwrap()
+        })
+        .unwrap();
+    if orient(points[a], points[b], points[c], points[d]).abs() <= eps {
+        return (0., 0.)
+    }
+
+    // faces are counterclockwise seen from the outside
+    let mut faces: Vec<[usize; 3]> = if orient(points[a], points[b], points[c], points[d]) < 0. {
+        vec![[a, b, c], [a, d, b], [b, d, c], [c, d, a]]
+    } else {
+        vec![[a, c, b], [a, b, d], [b, c, d], [c, a, d]]
+    };
+
+    let mut visible_edges = BTreeSet::new();
+    for (i, &p) in points.iter().enumerate() {
+        if i == a || i == b || i == c || i == d {
+            continue
+        }
+        let visible: Vec<bool> = faces.iter()
+            .map(|f| orient(points[f[0]], points[f[1]], points[f[2]], p) > eps)
+            .collect();
+        if !visible.iter().any(|&v| v) {
+            continue
+        }
+
+        // the horizon consists of the edges of visible faces, whose neighbor is hidden
+        visible_edges.clear();
+        for (f, _) in faces.iter().zip(&visible).filter(|(_, &v)| v) {
+            for k in 0..3 {
+                visible_edges.insert((f[k], f[(k + 1) % 3]));
+            }
+        }
+        let mut new_faces: Vec<[usize; 3]> = faces.iter()
+            .zip(&visible)
+            .filter(|(_, &v)| !v)
+            .map(|(f, _)| *f)
+            .collect();
+        for &(u, v) in &visible_edges {
+            if !visible_edges.contains(&(v, u)) {
+                new_faces.push([u, v, i]);
+            }
+        }
+        faces = new_faces;
+    }
+
+    // the centroid of the initial tetrahedron is inside of the hull
+    let mut center = [0.; 3];
+    for &k in &[a, b, c, d] {
+        for (x, y) in center.iter_mut().zip(&points[k]) {
+            *x += y / 4.;
+        }
+    }
+    let mut volume = 0.;
+    let mut surface = 0.;
+    for f in &faces {
+        let (p, q, r) = (points[f[0]], points[f[1]], points[f[2]]);
+        volume += orient(p, q, r, center).abs() / 6.;
+        surface += norm(cross(sub(q, p), sub(r, p))) / 2.;
+    }
+    (volume, surface)
+}
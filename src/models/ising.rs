@@ -0,0 +1,157 @@
+use std::fmt;
+
+use rand::Rng;
+
+use crate::{LargedevError, Model, Result};
+use crate::markovchain::{MarkovChain, Restorable};
+use crate::simple::DirectSamplable;
+
+/// The ferromagnetic Ising model on a square lattice of `L x L` spins with periodic
+/// boundary conditions and the energy `E = -sum_<ij> s_i s_j`. The `value` is the
+/// energy, the records contain the energy and the magnetization `M = sum_i s_i`.
+/// The `change` flips a single random spin, both observables are updated
+/// incrementally, e.g.:
+///
+/// ```ignore
+/// let model = Ising2D::new(32)?;
+/// let report = Metropolis::new(model)
+///    .temperature(2.269)
+///    .sweep(32 * 32)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ising2D {
+    l: usize,
+    spins: Vec<i8>,
+    energy: i64,
+    magnetization: i64,
+    /// the spin flipped by the last `change`
+    last: usize,
+}
+
+impl Ising2D {
+    /// a lattice of `l x l` spins, which all point up, i.e., a ground state
+    pub fn new(l: usize) -> Result<Self> {
+        if l < 2 {
+            return Err(LargedevError::InvalidConfig("the lattice needs at least 2 x 2 spins".into()))
+        }
+        let n = l * l;
+        Ok(Ising2D {
+            l,
+            spins: vec![1; n],
+            energy: -2 * n as i64,
+            magnetization: n as i64,
+            last: 0,
+        })
+    }
+
+    /// a lattice of `l x l` uniformly random spins
+    pub fn random(l: usize, rng: &mut impl Rng) -> Result<Self> {
+        let mut model = Ising2D::new(l)?;
+        model.reconstruct(rng);
+        Ok(model)
+    }
+
+    /// linear size of the lattice
+    pub fn size(&self) -> usize {
+        self.l
+    }
+
+    pub fn spins(&self) -> &[i8] {
+        &self.spins
+    }
+
+    pub fn energy(&self) -> i64 {
+        self.energy
+    }
+
+    pub fn magnetization(&self) -> i64 {
+        self.magnetization
+    }
+
+    /// sum of the four neighbors of spin `i`
+    fn neighbors(&self, i: usize) -> i64 {
+        let l = self.l;
+        let (x, y) = (i % l, i / l);
+        let left = (x + l - 1) % l + y * l;
+        let right = (x + 1) % l + y * l;
+        let up = x + (y + l - 1) % l * l;
+        let down = x + (y + 1) % l * l;
+        (self.spins[left] + self.spins[right] + self.spins[up] + self.spins[down]) as i64
+    }
+
+    fn flip(&mut self, i: usize) {
+        let s = self.spins[i] as i64;
+        self.energy += 2 * s * self.neighbors(i);
+        self.magnetization -= 2 * s;
+        self.spins[i] = -self.spins[i];
+    }
+
+    /// calculate both observables from scratch
+    fn recalculate(&mut self) {
+        let l = self.l;
+        let mut energy = 0;
+        for i in 0..l * l {
+            let (x, y) = (i % l, i / l);
+            // count every bond once, to the right and down
+            let right = (x + 1) % l + y * l;
+            let down = x + (y + 1) % l * l;
+            energy -= (self.spins[i] * (self.spins[right] + self.spins[down])) as i64;
+        }
+        self.energy = energy;
+        self.magnetization = self.spins.iter().map(|&s| s as i64).sum();
+    }
+}
+
+impl Model for Ising2D {
+    fn value(&self) -> f64 {
+        self.energy as f64
+    }
+
+    fn header(&self) -> String {
+        "# energy magnetization".into()
+    }
+
+    fn save(&self) -> String {
+        format!("{} {}", self.energy, self.magnetization)
+    }
+
+    fn write_record(&self, w: &mut dyn fmt::Write) -> fmt::Result {
+        write!(w, "{} {}", self.energy, self.magnetization)
+    }
+}
+
+impl MarkovChain for Ising2D {
+    fn change(&mut self, rng: &mut impl Rng) {
+        self.last = rng.gen_range(0..self.spins.len());
+        self.flip(self.last);
+    }
+
+    fn undo(&mut self) {
+        self.flip(self.last);
+    }
+}
+
+impl DirectSamplable for Ising2D {
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        for s in &mut self.spins {
+            *s = if rng.gen::<bool>() { 1 } else { -1 };
+        }
+        self.recalculate();
+    }
+}
+
+impl Restorable for Ising2D {
+    type Snapshot = Vec<i8>;
+
+    fn snapshot(&self) -> Vec<i8> {
+        self.spins.clone()
+    }
+
+    fn restore(&mut self, snapshot: &Vec<i8>) {
+        self.spins.clone_from(snapshot);
+        self.recalculate();
+    }
+}
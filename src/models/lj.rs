@@ -0,0 +1,176 @@
+use rand::Rng;
+
+use crate::{LargedevError, Model, Result};
+use crate::markovchain::{MarkovChain, Restorable};
+use crate::simple::DirectSamplable;
+
+/// A system of `n` Lennard-Jones particles in a periodic cubic box of the side length
+/// `L` in three dimensions, with the reduced pair potential `4 (r^-12 - r^-6)` and the
+/// minimum image convention. The `value` is the potential energy, which is a
+/// continuous observable. The `change` displaces a single random particle uniformly
+/// within a cube of the side length `2 * step`, the energy is updated in `O(n)` time.
+/// The step size can be adjusted between runs, e.g., to reach a reasonable acceptance
+/// rate:
+///
+/// ```ignore
+/// let model = LennardJones::random(32, 0.5, &mut rng)?;
+/// let mut run = Metropolis::new(model)
+///    .temperature(1.5)
+///    .sweep(32)
+///    .build()?;
+/// run.model_mut().set_step(0.1)?;
+/// let report = run.run(&mut rng, outfile)?;
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LennardJones {
+    box_length: f64,
+    positions: Vec<[f64; 3]>,
+    energy: f64,
+    step: f64,
+    /// the particle moved by the last `change`, its previous position and the energy
+    last: (usize, [f64; 3], f64),
+}
+
+impl LennardJones {
+    /// `n` particles on a simple cubic lattice in a box with the density `n / L^3`
+    pub fn new(n: usize, density: f64) -> Result<Self> {
+        if n < 2 {
+            return Err(LargedevError::InvalidConfig("the system needs at least two particles".into()))
+        }
+        if density.is_nan() || density <= 0. {
+            return Err(LargedevError::InvalidConfig(format!("the density must be positive, not {}", density)))
+        }
+        let box_length = (n as f64 / density).cbrt();
+        let k = (n as f64).cbrt().ceil() as usize;
+        let a = box_length / k as f64;
+        let positions = (0..n)
+            .map(|i| [(i % k) as f64 * a, (i / k % k) as f64 * a, (i / (k * k)) as f64 * a])
+            .collect();
+        let mut model = LennardJones {
+            box_length,
+            positions,
+            energy: 0.,
+            step: 0.1,
+            last: (0, [0.; 3], 0.),
+        };
+        model.energy = model.recalculate();
+        Ok(model)
+    }
+
+    /// `n` particles at uniformly random positions in a box with the density `n / L^3`
+    pub fn random(n: usize, density: f64, rng: &mut impl Rng) -> Result<Self> {
+        let mut model = LennardJones::new(n, density)?;
+        model.reconstruct(rng);
+        Ok(model)
+    }
+
+    pub fn positions(&self) -> &[[f64; 3]] {
+        &self.positions
+    }
+
+    pub fn box_length(&self) -> f64 {
+        self.box_length
+    }
+
+    pub fn energy(&self) -> f64 {
+        self.energy
+    }
+
+    /// maximum displacement of a particle in every direction
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    pub fn set_step(&mut self, step: f64) -> Result<()> {
+        if step.is_nan() || step <= 0. {
+            return Err(LargedevError::InvalidConfig(format!("the step size must be positive, not {}", step)))
+        }
+        self.step = step;
+        Ok(())
+    }
+
+    /// the pair potential of two particles at the positions `a` and `b`
+    fn pair(&self, a: &[f64; 3], b: &[f64; 3]) -> f64 {
+        let l = self.box_length;
+        let r2: f64 = a.iter().zip(b).map(|(x, y)| {
+            let d = x - y;
+            // minimum image
+            let d = d - l * (d / l).round();
+            d * d
+        }).sum();
+        let r6 = 1. / (r2 * r2 * r2);
+        4. * (r6 * r6 - r6)
+    }
+
+    /// the interaction energy of the particle `i` at the position `p` with all others
+    fn interaction(&self, i: usize, p: &[f64; 3]) -> f64 {
+        self.positions.iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .map(|(_, q)| self.pair(p, q))
+            .sum()
+    }
+
+    fn recalculate(&self) -> f64 {
+        let n = self.positions.len();
+        (0..n).flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+            .map(|(i, j)| self.pair(&self.positions[i], &self.positions[j]))
+            .sum()
+    }
+}
+
+impl Model for LennardJones {
+    fn value(&self) -> f64 {
+        self.energy
+    }
+
+    fn header(&self) -> String {
+        "# potential energy".into()
+    }
+}
+
+impl MarkovChain for LennardJones {
+    fn change(&mut self, rng: &mut impl Rng) {
+        let i = rng.gen_range(0..self.positions.len());
+        let old = self.positions[i];
+        let mut new = old;
+        for x in &mut new {
+            *x = (*x + rng.gen_range(-self.step..self.step)).rem_euclid(self.box_length);
+        }
+        self.last = (i, old, self.energy);
+        self.energy += self.interaction(i, &new) - self.interaction(i, &old);
+        self.positions[i] = new;
+    }
+
+    fn undo(&mut self) {
+        let (i, old, energy) = self.last;
+        self.positions[i] = old;
+        self.energy = energy;
+    }
+}
+
+impl DirectSamplable for LennardJones {
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        let l = self.box_length;
+        for p in &mut self.positions {
+            for x in p.iter_mut() {
+                *x = rng.gen_range(0.0..l);
+            }
+        }
+        self.energy = self.recalculate();
+    }
+}
+
+impl Restorable for LennardJones {
+    type Snapshot = Vec<[f64; 3]>;
+
+    fn snapshot(&self) -> Vec<[f64; 3]> {
+        self.positions.clone()
+    }
+
+    fn restore(&mut self, snapshot: &Vec<[f64; 3]>) {
+        self.positions.clone_from(snapshot);
+        self.energy = self.recalculate();
+    }
+}
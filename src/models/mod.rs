@@ -0,0 +1,42 @@
+//! Reference implementations of common models, which can be used as templates for
+//! own models, and to cross-validate the samplers against exact results.
+//! They are only compiled with the `models` feature.
+
+mod ising;
+pub use ising::Ising2D;
+
+mod potts;
+pub use potts::Potts2D;
+
+mod spinglass;
+pub use spinglass::SpinGlass2D;
+
+mod walk;
+pub use walk::{LatticeWalk, LevyWalk};
+
+mod saw;
+pub use saw::SelfAvoidingWalk;
+
+mod hull;
+pub use hull::{area_2d, hull_2d, hull_3d, perimeter_2d, ConvexHull, HullMeasure, PointSet};
+
+mod tsp;
+pub use tsp::Tsp;
+
+mod graph;
+pub use graph::{ErdosRenyi, GraphObservable};
+
+mod partition;
+pub use partition::NumberPartitioning;
+
+mod lj;
+pub use lj::LennardJones;
+
+mod spheres;
+pub use spheres::HardSpheres;
+
+mod rna;
+pub use rna::RnaStructure;
+
+mod percolation;
+pub use percolation::{Percolation, PercolationKind};
@@ -0,0 +1,129 @@
+use rand::Rng;
+
+use crate::{LargedevError, Model, Result};
+use crate::markovchain::{MarkovChain, Restorable};
+use crate::simple::DirectSamplable;
+
+/// The number partitioning problem, i.e., the division of `n` positive integers into
+/// two subsets, whose sums are as equal as possible. The `value` is the absolute
+/// difference `|sum_i s_i a_i|` of the subset sums, where `s_i = ±1` denotes the subset
+/// of the number `a_i`. The `change` moves a single random number to the other subset.
+/// Since the `2^n` configurations can be enumerated for small `n`, the density of
+/// states is cheap to validate exactly, e.g.:
+///
+/// ```ignore
+/// let model = NumberPartitioning::random(20, 10, &mut rng)?;
+/// let total = model.numbers().iter().sum::<u64>() as f64;
+/// let report = WangLandau::new(model)
+///    .range(0., total + 1.)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NumberPartitioning {
+    numbers: Vec<u64>,
+    signs: Vec<i8>,
+    difference: i64,
+    /// the number moved by the last `change`
+    last: usize,
+}
+
+impl NumberPartitioning {
+    /// all `numbers` start in the same subset
+    pub fn new(numbers: Vec<u64>) -> Result<Self> {
+        if numbers.is_empty() {
+            return Err(LargedevError::InvalidConfig("the problem needs at least one number".into()))
+        }
+        let signs = vec![1; numbers.len()];
+        let mut model = NumberPartitioning {
+            numbers,
+            signs,
+            difference: 0,
+            last: 0,
+        };
+        model.recalculate();
+        Ok(model)
+    }
+
+    /// `n` uniformly random numbers of `bits` bits, i.e., in `1..2^bits`, in a random
+    /// partition
+    pub fn random(n: usize, bits: u32, rng: &mut impl Rng) -> Result<Self> {
+        if !(1..=32).contains(&bits) {
+            return Err(LargedevError::InvalidConfig("the numbers must have between 1 and 32 bits".into()))
+        }
+        let numbers = (0..n).map(|_| rng.gen_range(1..1 << bits)).collect();
+        let mut model = NumberPartitioning::new(numbers)?;
+        model.reconstruct(rng);
+        Ok(model)
+    }
+
+    pub fn numbers(&self) -> &[u64] {
+        &self.numbers
+    }
+
+    /// the subset `±1` of every number
+    pub fn signs(&self) -> &[i8] {
+        &self.signs
+    }
+
+    /// the signed difference of the subset sums
+    pub fn difference(&self) -> i64 {
+        self.difference
+    }
+
+    fn flip(&mut self, i: usize) {
+        self.difference -= 2 * self.signs[i] as i64 * self.numbers[i] as i64;
+        self.signs[i] = -self.signs[i];
+    }
+
+    fn recalculate(&mut self) {
+        self.difference = self.numbers.iter()
+            .zip(&self.signs)
+            .map(|(&a, &s)| s as i64 * a as i64)
+            .sum();
+    }
+}
+
+impl Model for NumberPartitioning {
+    fn value(&self) -> f64 {
+        self.difference.abs() as f64
+    }
+
+    fn header(&self) -> String {
+        "# absolute difference of the subset sums".into()
+    }
+}
+
+impl MarkovChain for NumberPartitioning {
+    fn change(&mut self, rng: &mut impl Rng) {
+        self.last = rng.gen_range(0..self.signs.len());
+        self.flip(self.last);
+    }
+
+    fn undo(&mut self) {
+        self.flip(self.last);
+    }
+}
+
+impl DirectSamplable for NumberPartitioning {
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        for s in &mut self.signs {
+            *s = if rng.gen::<bool>() { 1 } else { -1 };
+        }
+        self.recalculate();
+    }
+}
+
+impl Restorable for NumberPartitioning {
+    type Snapshot = Vec<i8>;
+
+    fn snapshot(&self) -> Vec<i8> {
+        self.signs.clone()
+    }
+
+    fn restore(&mut self, snapshot: &Vec<i8>) {
+        self.signs.clone_from(snapshot);
+        self.recalculate();
+    }
+}
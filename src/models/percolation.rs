@@ -0,0 +1,210 @@
+use rand::Rng;
+
+use crate::{LargedevError, Model, Result};
+use crate::markovchain::{MarkovChain, Proposal, Restorable};
+use crate::simple::DirectSamplable;
+
+/// Whether the sites or the bonds of the lattice are occupied in `Percolation`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PercolationKind {
+    /// clusters of occupied neighboring sites
+    Site,
+    /// clusters of sites connected by occupied bonds, every site belongs to a cluster
+    Bond,
+}
+
+/// A site or bond percolation configuration on a square lattice of `L x L` sites with
+/// open boundaries, where every site or bond is occupied independently with the
+/// probability `p`. The `value` is the number of sites in the largest cluster.
+/// The `change` redraws the occupation of a single random site or bond with the
+/// probability `p`, which toggles it or leaves it as it is, signaled as
+/// `Proposal::Unchanged`. Since the change itself samples the percolation ensemble,
+/// a flat histogram method samples the largest cluster size with respect to the
+/// probability `p`, e.g.:
+///
+/// ```ignore
+/// let model = Percolation::random(32, PercolationKind::Site, 0.5, &mut rng)?;
+/// let report = WangLandau::new(model)
+///    .range(0., 1025.)
+///    .bins(1025)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+///
+/// The clusters are calculated from scratch for every `value`, which takes
+/// `O(L^2)` time.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Percolation {
+    l: usize,
+    kind: PercolationKind,
+    p: f64,
+    /// the occupation of every site, or every bond in the order of `bonds`
+    occupied: Vec<bool>,
+    /// the pairs of neighboring sites
+    bonds: Vec<(usize, usize)>,
+    /// the site or bond toggled by the last `change`, if it was toggled
+    last: Option<usize>,
+}
+
+impl Percolation {
+    /// an empty lattice of `l x l` sites
+    pub fn new(l: usize, kind: PercolationKind, p: f64) -> Result<Self> {
+        if l < 1 {
+            return Err(LargedevError::InvalidConfig("the lattice needs at least one site".into()))
+        }
+        if !(0. ..=1.).contains(&p) {
+            return Err(LargedevError::InvalidConfig(format!("the occupation probability must be in [0, 1], not {}", p)))
+        }
+        let mut bonds = Vec::with_capacity(2 * l * l);
+        for i in 0..l * l {
+            let (x, y) = (i % l, i / l);
+            if x + 1 < l {
+                bonds.push((i, i + 1));
+            }
+            if y + 1 < l {
+                bonds.push((i, i + l));
+            }
+        }
+        let elements = match kind {
+            PercolationKind::Site => l * l,
+            PercolationKind::Bond => bonds.len(),
+        };
+        Ok(Percolation {
+            l,
+            kind,
+            p,
+            occupied: vec![false; elements],
+            bonds,
+            last: None,
+        })
+    }
+
+    /// a random configuration of `l x l` sites with the occupation probability `p`
+    pub fn random(l: usize, kind: PercolationKind, p: f64, rng: &mut impl Rng) -> Result<Self> {
+        let mut model = Percolation::new(l, kind, p)?;
+        model.reconstruct(rng);
+        Ok(model)
+    }
+
+    /// linear size of the lattice
+    pub fn size(&self) -> usize {
+        self.l
+    }
+
+    pub fn kind(&self) -> PercolationKind {
+        self.kind
+    }
+
+    pub fn probability(&self) -> f64 {
+        self.p
+    }
+
+    /// the occupation of every site, or every bond in the order of `bonds`
+    pub fn occupied(&self) -> &[bool] {
+        &self.occupied
+    }
+
+    /// the pairs of neighboring sites, which are connected by the bonds
+    pub fn bonds(&self) -> &[(usize, usize)] {
+        &self.bonds
+    }
+
+    /// the sizes of all clusters, in no particular order
+    pub fn cluster_sizes(&self) -> Vec<usize> {
+        let n = self.l * self.l;
+        let mut parent: Vec<usize> = (0..n).collect();
+        let mut size = vec![1; n];
+        for (k, &(a, b)) in self.bonds.iter().enumerate() {
+            let connected = match self.kind {
+                PercolationKind::Site => self.occupied[a] && self.occupied[b],
+                PercolationKind::Bond => self.occupied[k],
+            };
+            if !connected {
+                continue
+            }
+            let (ra, rb) = (find(&mut parent, a), find(&mut parent, b));
+            if ra != rb {
+                // union by size
+                let (big, small) = if size[ra] >= size[rb] { (ra, rb) } else { (rb, ra) };
+                parent[small] = big;
+                size[big] += size[small];
+            }
+        }
+        (0..n)
+            .filter(|&i| parent[i] == i)
+            .filter(|&i| self.kind == PercolationKind::Bond || self.occupied[i])
+            .map(|i| size[i])
+            .collect()
+    }
+}
+
+/// the root of `i` in the union-find forest `parent`, with path halving
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+impl Model for Percolation {
+    fn value(&self) -> f64 {
+        self.cluster_sizes().into_iter().max().unwrap_or(0) as f64
+    }
+
+    fn header(&self) -> String {
+        "# size of the largest cluster".into()
+    }
+}
+
+impl MarkovChain for Percolation {
+    fn change(&mut self, rng: &mut impl Rng) {
+        self.try_change(rng);
+    }
+
+    fn try_change(&mut self, rng: &mut impl Rng) -> Proposal {
+        self.last = None;
+        if self.occupied.is_empty() {
+            return Proposal::Unchanged
+        }
+        let i = rng.gen_range(0..self.occupied.len());
+        let occupied = rng.gen_bool(self.p);
+        if occupied == self.occupied[i] {
+            return Proposal::Unchanged
+        }
+        self.occupied[i] = occupied;
+        self.last = Some(i);
+        Proposal::Valid
+    }
+
+    fn undo(&mut self) {
+        if let Some(i) = self.last.take() {
+            self.occupied[i] = !self.occupied[i];
+        }
+    }
+}
+
+impl DirectSamplable for Percolation {
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        let p = self.p;
+        for o in &mut self.occupied {
+            *o = rng.gen_bool(p);
+        }
+        self.last = None;
+    }
+}
+
+impl Restorable for Percolation {
+    type Snapshot = Vec<bool>;
+
+    fn snapshot(&self) -> Vec<bool> {
+        self.occupied.clone()
+    }
+
+    fn restore(&mut self, snapshot: &Vec<bool>) {
+        self.occupied.clone_from(snapshot);
+        self.last = None;
+    }
+}
@@ -0,0 +1,187 @@
+use std::fmt;
+
+use rand::Rng;
+
+use crate::{LargedevError, Model, Result};
+use crate::markovchain::{MarkovChain, Restorable};
+use crate::simple::DirectSamplable;
+
+/// The ferromagnetic `q`-state Potts model on a square lattice of `L x L` spins with
+/// periodic boundary conditions and the energy `E = -sum_<ij> delta(s_i, s_j)`. The
+/// `value` is the energy, the records contain the energy and the number of spins in
+/// the most common state. The `change` sets a single random spin to a random different
+/// state. The transition at `T_c = 1 / ln(1 + sqrt(q))` is of first order for `q > 4`,
+/// such that the density of states has a pronounced double peak structure, which is a
+/// hard test for flat histogram methods, e.g.:
+///
+/// ```ignore
+/// let model = Potts2D::new(16, 10)?;
+/// let report = WindowedWangLandau::new(model)
+///    .range(-512., 1.)
+///    .bins(513)
+///    .windows(8)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Potts2D {
+    l: usize,
+    q: u8,
+    spins: Vec<u8>,
+    /// number of spins in every state
+    counts: Vec<usize>,
+    energy: i64,
+    /// the spin changed by the last `change` and its previous state
+    last: (usize, u8),
+}
+
+impl Potts2D {
+    /// a lattice of `l x l` spins with `q` states, which are all in the state 0, i.e.,
+    /// a ground state
+    pub fn new(l: usize, q: u8) -> Result<Self> {
+        if l < 2 {
+            return Err(LargedevError::InvalidConfig("the lattice needs at least 2 x 2 spins".into()))
+        }
+        if q < 2 {
+            return Err(LargedevError::InvalidConfig("the model needs at least two states".into()))
+        }
+        let n = l * l;
+        let mut counts = vec![0; q as usize];
+        counts[0] = n;
+        Ok(Potts2D {
+            l,
+            q,
+            spins: vec![0; n],
+            counts,
+            energy: -2 * n as i64,
+            last: (0, 0),
+        })
+    }
+
+    /// a lattice of `l x l` uniformly random spins with `q` states
+    pub fn random(l: usize, q: u8, rng: &mut impl Rng) -> Result<Self> {
+        let mut model = Potts2D::new(l, q)?;
+        model.reconstruct(rng);
+        Ok(model)
+    }
+
+    /// linear size of the lattice
+    pub fn size(&self) -> usize {
+        self.l
+    }
+
+    /// number of states
+    pub fn states(&self) -> u8 {
+        self.q
+    }
+
+    pub fn spins(&self) -> &[u8] {
+        &self.spins
+    }
+
+    pub fn energy(&self) -> i64 {
+        self.energy
+    }
+
+    /// number of spins in the most common state
+    pub fn majority(&self) -> usize {
+        self.counts.iter().cloned().max().unwrap_or(0)
+    }
+
+    /// number of the four neighbors of spin `i`, which are in the state `s`
+    fn equal_neighbors(&self, i: usize, s: u8) -> i64 {
+        let l = self.l;
+        let (x, y) = (i % l, i / l);
+        let left = (x + l - 1) % l + y * l;
+        let right = (x + 1) % l + y * l;
+        let up = x + (y + l - 1) % l * l;
+        let down = x + (y + 1) % l * l;
+        [left, right, up, down].iter().filter(|&&j| self.spins[j] == s).count() as i64
+    }
+
+    fn set(&mut self, i: usize, s: u8) {
+        let old = self.spins[i];
+        self.energy += self.equal_neighbors(i, old) - self.equal_neighbors(i, s);
+        self.counts[old as usize] -= 1;
+        self.counts[s as usize] += 1;
+        self.spins[i] = s;
+    }
+
+    /// calculate the energy and the counts from scratch
+    fn recalculate(&mut self) {
+        let l = self.l;
+        let mut energy = 0;
+        for i in 0..l * l {
+            let (x, y) = (i % l, i / l);
+            // count every bond once, to the right and down
+            let right = (x + 1) % l + y * l;
+            let down = x + (y + 1) % l * l;
+            energy -= (self.spins[i] == self.spins[right]) as i64 + (self.spins[i] == self.spins[down]) as i64;
+        }
+        self.energy = energy;
+        self.counts = vec![0; self.q as usize];
+        for &s in &self.spins {
+            self.counts[s as usize] += 1;
+        }
+    }
+}
+
+impl Model for Potts2D {
+    fn value(&self) -> f64 {
+        self.energy as f64
+    }
+
+    fn header(&self) -> String {
+        "# energy majority".into()
+    }
+
+    fn save(&self) -> String {
+        format!("{} {}", self.energy, self.majority())
+    }
+
+    fn write_record(&self, w: &mut dyn fmt::Write) -> fmt::Result {
+        write!(w, "{} {}", self.energy, self.majority())
+    }
+}
+
+impl MarkovChain for Potts2D {
+    fn change(&mut self, rng: &mut impl Rng) {
+        let i = rng.gen_range(0..self.spins.len());
+        let old = self.spins[i];
+        // a uniformly random state different from the current one
+        let mut s = rng.gen_range(0..self.q - 1);
+        if s >= old {
+            s += 1;
+        }
+        self.last = (i, old);
+        self.set(i, s);
+    }
+
+    fn undo(&mut self) {
+        let (i, s) = self.last;
+        self.set(i, s);
+    }
+}
+
+impl DirectSamplable for Potts2D {
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        for s in &mut self.spins {
+            *s = rng.gen_range(0..self.q);
+        }
+        self.recalculate();
+    }
+}
+
+impl Restorable for Potts2D {
+    type Snapshot = Vec<u8>;
+
+    fn snapshot(&self) -> Vec<u8> {
+        self.spins.clone()
+    }
+
+    fn restore(&mut self, snapshot: &Vec<u8>) {
+        self.spins.clone_from(snapshot);
+        self.recalculate();
+    }
+}
@@ -0,0 +1,184 @@
+use rand::Rng;
+
+use crate::{LargedevError, Model, Result};
+use crate::markovchain::{MarkovChain, Proposal, Restorable};
+
+/// minimum number of unpaired bases enclosed by a pair, i.e., a hairpin loop
+const MIN_LOOP: usize = 3;
+
+/// A simplified RNA secondary structure, i.e., a non-crossing matching of the bases of
+/// a sequence. Two bases `i < j` can only pair if they are complementary, i.e., `AU`,
+/// `GC` or the wobble pair `GU`, and enclose at least three other bases, and no two
+/// pairs `(i, j)` and `(k, l)` may cross, i.e., `i < k < j < l`. The `value` is the
+/// number of pairs. The `change` draws a random pair of bases, which is removed if the
+/// bases are paired with each other, and inserted if both are unpaired. Proposals,
+/// which would violate the constraints, are rejected as `Proposal::Invalid`, e.g.:
+///
+/// ```ignore
+/// let model = RnaStructure::new("GGGAAAUCCCGCGAAAGCGUUUAAACCC")?;
+/// let report = WangLandau::new(model)
+///    .range(0., 15.)
+///    .bins(15)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RnaStructure {
+    /// the bases, or `None` for a homopolymer, whose bases can all pair
+    sequence: Option<Vec<u8>>,
+    /// the partner of every base
+    partner: Vec<Option<usize>>,
+    pairs: usize,
+    /// the last pair and whether it was inserted or removed
+    last: Option<(usize, usize, bool)>,
+}
+
+impl RnaStructure {
+    /// the open structure without pairs of a sequence of the bases `A`, `C`, `G` and `U`
+    pub fn new(sequence: &str) -> Result<Self> {
+        let sequence = sequence.to_ascii_uppercase().into_bytes();
+        if let Some(b) = sequence.iter().find(|b| !b"ACGU".contains(b)) {
+            return Err(LargedevError::InvalidConfig(
+                format!("the sequence contains the base '{}', only A, C, G and U are allowed", *b as char)
+            ))
+        }
+        let mut model = RnaStructure::homopolymer(sequence.len())?;
+        model.sequence = Some(sequence);
+        Ok(model)
+    }
+
+    /// the open structure of a homopolymer of `n` bases, whose bases can all pair
+    pub fn homopolymer(n: usize) -> Result<Self> {
+        if n < MIN_LOOP + 2 {
+            return Err(LargedevError::InvalidConfig(
+                format!("a pair needs at least {} bases, but the sequence has {}", MIN_LOOP + 2, n)
+            ))
+        }
+        Ok(RnaStructure {
+            sequence: None,
+            partner: vec![None; n],
+            pairs: 0,
+            last: None,
+        })
+    }
+
+    /// number of bases
+    pub fn len(&self) -> usize {
+        self.partner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.partner.is_empty()
+    }
+
+    pub fn pairs(&self) -> usize {
+        self.pairs
+    }
+
+    /// the structure in dot-bracket notation
+    pub fn dot_bracket(&self) -> String {
+        self.partner.iter().enumerate().map(|(i, p)| match p {
+            None => '.',
+            Some(j) if i < *j => '(',
+            Some(_) => ')',
+        }).collect()
+    }
+
+    fn complementary(&self, i: usize, j: usize) -> bool {
+        match &self.sequence {
+            None => true,
+            Some(s) => matches!(
+                (s[i], s[j]),
+                (b'A', b'U') | (b'U', b'A') | (b'G', b'C') | (b'C', b'G') | (b'G', b'U') | (b'U', b'G')
+            ),
+        }
+    }
+
+    /// whether `i < j` can pair without violating the constraints
+    fn allowed(&self, i: usize, j: usize) -> bool {
+        j - i > MIN_LOOP
+            && self.partner[i].is_none()
+            && self.partner[j].is_none()
+            && self.complementary(i, j)
+            // all pairs of the enclosed bases have to be enclosed as well
+            && self.partner[i + 1..j].iter().all(|p| p.is_none_or(|k| i < k && k < j))
+    }
+
+    fn insert(&mut self, i: usize, j: usize) {
+        self.partner[i] = Some(j);
+        self.partner[j] = Some(i);
+        self.pairs += 1;
+    }
+
+    fn remove(&mut self, i: usize, j: usize) {
+        self.partner[i] = None;
+        self.partner[j] = None;
+        self.pairs -= 1;
+    }
+}
+
+impl Model for RnaStructure {
+    fn value(&self) -> f64 {
+        self.pairs as f64
+    }
+
+    fn header(&self) -> String {
+        "# number of pairs".into()
+    }
+}
+
+impl MarkovChain for RnaStructure {
+    /// An insertion or removal of a pair, which is rejected if it violates the
+    /// constraints. Since `change` can not signal this, the structure stays unchanged
+    /// in this case.
+    fn change(&mut self, rng: &mut impl Rng) {
+        self.try_change(rng);
+    }
+
+    fn try_change(&mut self, rng: &mut impl Rng) -> Proposal {
+        self.last = None;
+        let n = self.partner.len();
+        if n <= MIN_LOOP + 1 {
+            return Proposal::Invalid
+        }
+        let a = rng.gen_range(0..n);
+        let b = rng.gen_range(0..n);
+        let (i, j) = (a.min(b), a.max(b));
+        if i == j {
+            return Proposal::Invalid
+        }
+        if self.partner[i] == Some(j) {
+            self.remove(i, j);
+            self.last = Some((i, j, false));
+        } else if self.allowed(i, j) {
+            self.insert(i, j);
+            self.last = Some((i, j, true));
+        } else {
+            return Proposal::Invalid
+        }
+        Proposal::Valid
+    }
+
+    fn undo(&mut self) {
+        match self.last.take() {
+            Some((i, j, true)) => self.remove(i, j),
+            Some((i, j, false)) => self.insert(i, j),
+            None => (),
+        }
+    }
+}
+
+impl Restorable for RnaStructure {
+    type Snapshot = Vec<Option<usize>>;
+
+    fn snapshot(&self) -> Vec<Option<usize>> {
+        self.partner.clone()
+    }
+
+    fn restore(&mut self, snapshot: &Vec<Option<usize>>) {
+        self.partner.clone_from(snapshot);
+        self.pairs = self.partner.iter().filter(|p| p.is_some()).count() / 2;
+        self.last = None;
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+
+use rand::Rng;
+
+use crate::{LargedevError, Model, Result};
+use crate::markovchain::{MarkovChain, Proposal, Restorable};
+use crate::perm::Growable;
+
+/// the four steps on the square lattice
+const STEPS: [(i64, i64); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+/// the seven non-trivial symmetries of the square lattice, as rows of 2x2 matrices
+const SYMMETRIES: [[i64; 4]; 7] = [
+    // rotations by 90, 180 and 270 degrees
+    [0, -1, 1, 0],
+    [-1, 0, 0, -1],
+    [0, 1, -1, 0],
+    // reflections at the axes and the diagonals
+    [1, 0, 0, -1],
+    [-1, 0, 0, 1],
+    [0, 1, 1, 0],
+    [0, -1, -1, 0],
+];
+
+/// A self-avoiding walk of `n` steps on the square lattice, starting at the origin.
+/// The `value` is the end-to-end distance. The `change` is a pivot move, which applies
+/// a random symmetry of the lattice to the part of the walk after a random site.
+/// Pivots, which lead to an intersection, are rejected as `Proposal::Invalid`, e.g.:
+///
+/// ```ignore
+/// let model = SelfAvoidingWalk::new(1000)?;
+/// let report = Metropolis::new(model)
+///    .sweep(100)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// println!("{} pivots violated the self-avoidance", report.invalid);
+/// ```
+///
+/// The walk can also be grown step by step for `Perm`, starting from a single site.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SelfAvoidingWalk {
+    positions: Vec<(i64, i64)>,
+    /// the sites occupied by the fixed part of the walk, reused between proposals
+    occupied: HashSet<(i64, i64)>,
+    /// the positions before the last pivot, if it was applied
+    last: Vec<(i64, i64)>,
+    pivoted: bool,
+}
+
+impl SelfAvoidingWalk {
+    /// a straight walk of `n` steps along the first axis
+    pub fn new(n: usize) -> Result<Self> {
+        if n < 1 {
+            return Err(LargedevError::InvalidConfig("the walk needs at least one step".into()))
+        }
+        Ok(SelfAvoidingWalk {
+            positions: (0..=n as i64).map(|x| (x, 0)).collect(),
+            occupied: HashSet::with_capacity(n + 1),
+            last: Vec::with_capacity(n + 1),
+            pivoted: false,
+        })
+    }
+
+    /// number of steps
+    pub fn len(&self) -> usize {
+        self.positions.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// coordinates of all `n+1` sites of the walk, including the origin
+    pub fn positions(&self) -> &[(i64, i64)] {
+        &self.positions
+    }
+
+    pub fn end(&self) -> (i64, i64) {
+        self.positions[self.positions.len() - 1]
+    }
+
+    /// apply the symmetry `s` around the site `k` to all following sites, or return
+    /// `false` without changing the walk, if the result would intersect itself
+    fn pivot(&mut self, k: usize, s: &[i64; 4]) -> bool {
+        let (px, py) = self.positions[k];
+        let transform = |(x, y): (i64, i64)| {
+            let (dx, dy) = (x - px, y - py);
+            (px + s[0] * dx + s[1] * dy, py + s[2] * dx + s[3] * dy)
+        };
+
+        // the new positions are checked against the fixed part and each other
+        self.occupied.clear();
+        self.occupied.extend(self.positions[..=k].iter().cloned());
+        self.pivoted = false;
+        let occupied = &mut self.occupied;
+        if self.positions[k + 1..].iter().any(|&p| !occupied.insert(transform(p))) {
+            return false
+        }
+
+        self.last.clone_from(&self.positions);
+        for p in &mut self.positions[k + 1..] {
+            *p = transform(*p);
+        }
+        self.pivoted = true;
+        true
+    }
+}
+
+impl Model for SelfAvoidingWalk {
+    fn value(&self) -> f64 {
+        let (x, y) = self.end();
+        ((x * x + y * y) as f64).sqrt()
+    }
+
+    fn header(&self) -> String {
+        "# end-to-end distance".into()
+    }
+}
+
+impl MarkovChain for SelfAvoidingWalk {
+    /// A pivot move, which is rejected if it violates the self-avoidance. Since
+    /// `change` can not signal this, the walk stays unchanged in this case.
+    fn change(&mut self, rng: &mut impl Rng) {
+        self.try_change(rng);
+    }
+
+    fn try_change(&mut self, rng: &mut impl Rng) -> Proposal {
+        // pivots at the last site do not change the walk
+        let k = rng.gen_range(0..self.positions.len() - 1);
+        let s = SYMMETRIES[rng.gen_range(0..SYMMETRIES.len())];
+        if self.pivot(k, &s) {
+            Proposal::Valid
+        } else {
+            Proposal::Invalid
+        }
+    }
+
+    fn undo(&mut self) {
+        if self.pivoted {
+            std::mem::swap(&mut self.positions, &mut self.last);
+            self.pivoted = false;
+        }
+    }
+}
+
+impl Restorable for SelfAvoidingWalk {
+    type Snapshot = Vec<(i64, i64)>;
+
+    fn snapshot(&self) -> Vec<(i64, i64)> {
+        self.positions.clone()
+    }
+
+    fn restore(&mut self, snapshot: &Vec<(i64, i64)>) {
+        self.positions.clone_from(snapshot);
+        self.pivoted = false;
+    }
+}
+
+/// Growth of athermal walks, i.e., every free neighbor of the end has the weight `1`.
+/// The growth has to start with `reset`, since the pivots use the occupied sites as a
+/// buffer.
+impl Growable for SelfAvoidingWalk {
+    fn reset(&mut self) {
+        self.positions.clear();
+        self.positions.push((0, 0));
+        self.occupied.clear();
+        self.occupied.insert((0, 0));
+        self.pivoted = false;
+    }
+
+    fn candidates(&self, weights: &mut Vec<f64>) {
+        let (x, y) = self.end();
+        weights.extend(STEPS.iter().map(|(dx, dy)| {
+            if self.occupied.contains(&(x + dx, y + dy)) { 0. } else { 1. }
+        }));
+    }
+
+    fn grow(&mut self, candidate: usize) {
+        let (x, y) = self.end();
+        let (dx, dy) = STEPS[candidate];
+        self.positions.push((x + dx, y + dy));
+        self.occupied.insert((x + dx, y + dy));
+    }
+
+    fn shrink(&mut self) {
+        let end = self.positions.pop().unwrap();
+        self.occupied.remove(&end);
+    }
+}
@@ -0,0 +1,160 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use crate::{LargedevError, Model, Result};
+use crate::markovchain::{MarkovChain, Proposal, Restorable};
+
+/// A gas of `n` hard disks of unit diameter in a periodic square box of the side length
+/// `L`. The `value` is the mean distance of the disks to their nearest neighbors. The
+/// `change` displaces a single random disk uniformly within a square of the side length
+/// `2 * step`; displacements, which lead to an overlap, are rejected as
+/// `Proposal::Invalid` and leave the configuration unchanged, e.g.:
+///
+/// ```ignore
+/// let model = HardSpheres::new(64, 0.5)?;
+/// let report = Metropolis::new(model)
+///    .sweep(64)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// println!("{} displacements lead to overlaps", report.invalid);
+/// ```
+///
+/// The nearest-neighbor distances are calculated from scratch for every `value`, which
+/// takes `O(n^2)` time.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HardSpheres {
+    box_length: f64,
+    positions: Vec<[f64; 2]>,
+    step: f64,
+    /// the disk moved by the last `change` and its previous position, if it was moved
+    last: Option<(usize, [f64; 2])>,
+}
+
+impl HardSpheres {
+    /// `n` disks on a square lattice in a box with the packing fraction
+    /// `eta = n pi / (4 L^2)`
+    pub fn new(n: usize, packing_fraction: f64) -> Result<Self> {
+        if n < 2 {
+            return Err(LargedevError::InvalidConfig("the gas needs at least two disks".into()))
+        }
+        if packing_fraction.is_nan() || packing_fraction <= 0. {
+            return Err(LargedevError::InvalidConfig(format!("the packing fraction must be positive, not {}", packing_fraction)))
+        }
+        let box_length = (n as f64 * PI / 4. / packing_fraction).sqrt();
+        let k = (n as f64).sqrt().ceil() as usize;
+        let a = box_length / k as f64;
+        if a < 1. {
+            return Err(LargedevError::InvalidConfig(format!("the packing fraction {} is too large for the initial lattice", packing_fraction)))
+        }
+        let positions = (0..n)
+            .map(|i| [(i % k) as f64 * a, (i / k) as f64 * a])
+            .collect();
+        Ok(HardSpheres {
+            box_length,
+            positions,
+            step: 0.1,
+            last: None,
+        })
+    }
+
+    pub fn positions(&self) -> &[[f64; 2]] {
+        &self.positions
+    }
+
+    pub fn box_length(&self) -> f64 {
+        self.box_length
+    }
+
+    /// maximum displacement of a disk in every direction
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    pub fn set_step(&mut self, step: f64) -> Result<()> {
+        if step.is_nan() || step <= 0. {
+            return Err(LargedevError::InvalidConfig(format!("the step size must be positive, not {}", step)))
+        }
+        self.step = step;
+        Ok(())
+    }
+
+    /// squared distance of `a` and `b` with the minimum image convention
+    fn distance2(&self, a: &[f64; 2], b: &[f64; 2]) -> f64 {
+        let l = self.box_length;
+        a.iter().zip(b).map(|(x, y)| {
+            let d = x - y;
+            let d = d - l * (d / l).round();
+            d * d
+        }).sum()
+    }
+
+    /// the distance of every disk to its nearest neighbor
+    pub fn nearest_neighbor_distances(&self) -> Vec<f64> {
+        self.positions.iter().enumerate().map(|(i, p)| {
+            self.positions.iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, q)| self.distance2(p, q))
+                .fold(f64::INFINITY, f64::min)
+                .sqrt()
+        }).collect()
+    }
+}
+
+impl Model for HardSpheres {
+    fn value(&self) -> f64 {
+        let d = self.nearest_neighbor_distances();
+        d.iter().sum::<f64>() / d.len() as f64
+    }
+
+    fn header(&self) -> String {
+        "# mean nearest-neighbor distance".into()
+    }
+}
+
+impl MarkovChain for HardSpheres {
+    /// A displacement, which is rejected if it leads to an overlap. Since `change`
+    /// can not signal this, the configuration stays unchanged in this case.
+    fn change(&mut self, rng: &mut impl Rng) {
+        self.try_change(rng);
+    }
+
+    fn try_change(&mut self, rng: &mut impl Rng) -> Proposal {
+        self.last = None;
+        let i = rng.gen_range(0..self.positions.len());
+        let mut new = self.positions[i];
+        for x in &mut new {
+            *x = (*x + rng.gen_range(-self.step..self.step)).rem_euclid(self.box_length);
+        }
+        let overlap = self.positions.iter()
+            .enumerate()
+            .any(|(j, q)| j != i && self.distance2(&new, q) < 1.);
+        if overlap {
+            return Proposal::Invalid
+        }
+        self.last = Some((i, self.positions[i]));
+        self.positions[i] = new;
+        Proposal::Valid
+    }
+
+    fn undo(&mut self) {
+        if let Some((i, old)) = self.last.take() {
+            self.positions[i] = old;
+        }
+    }
+}
+
+impl Restorable for HardSpheres {
+    type Snapshot = Vec<[f64; 2]>;
+
+    fn snapshot(&self) -> Vec<[f64; 2]> {
+        self.positions.clone()
+    }
+
+    fn restore(&mut self, snapshot: &Vec<[f64; 2]>) {
+        self.positions.clone_from(snapshot);
+        self.last = None;
+    }
+}
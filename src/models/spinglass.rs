@@ -0,0 +1,197 @@
+use std::fmt;
+
+use rand::Rng;
+
+use crate::{LargedevError, Model, Result, Seed};
+use crate::markovchain::{MarkovChain, Restorable};
+use crate::simple::DirectSamplable;
+
+/// The Edwards–Anderson `±J` spin glass on a square lattice of `L x L` spins with
+/// periodic boundary conditions and the energy `E = -sum_<ij> J_ij s_i s_j`. The
+/// couplings `J_ij = ±1` are quenched disorder, which is drawn with equal probability
+/// from the random number generator of the `disorder` seed, such that the same seed
+/// always leads to the same realization. The `value` is the energy, the records
+/// contain the energy and the magnetization. The `change` flips a single random spin.
+///
+/// The rugged energy landscape makes the low temperature phase hard to equilibrate
+/// with single spin flips, such that the model is a benchmark for, e.g.,
+/// `ParallelTempering`:
+///
+/// ```ignore
+/// let model = SpinGlass2D::new(16, 42)?;
+/// let temperatures = (0..16).map(|k| 0.2 * 1.15f64.powi(k)).collect();
+/// let report = ParallelTempering::new(model)
+///    .temperatures(temperatures)
+///    .sweep(16 * 16)
+///    .iterations(10000)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpinGlass2D {
+    l: usize,
+    disorder: Seed,
+    spins: Vec<i8>,
+    /// the coupling of every spin to its right and lower neighbor
+    right: Vec<i8>,
+    down: Vec<i8>,
+    energy: i64,
+    magnetization: i64,
+    /// the spin flipped by the last `change`
+    last: usize,
+}
+
+impl SpinGlass2D {
+    /// a lattice of `l x l` spins, which all point up, with the couplings drawn from
+    /// the `disorder` seed
+    pub fn new(l: usize, disorder: impl Into<Seed>) -> Result<Self> {
+        if l < 2 {
+            return Err(LargedevError::InvalidConfig("the lattice needs at least 2 x 2 spins".into()))
+        }
+        let disorder = disorder.into();
+        let n = l * l;
+        let mut rng = disorder.rng();
+        let mut coupling = || if rng.gen::<bool>() { 1 } else { -1 };
+        let right = (0..n).map(|_| coupling()).collect();
+        let down = (0..n).map(|_| coupling()).collect();
+        let mut model = SpinGlass2D {
+            l,
+            disorder,
+            spins: vec![1; n],
+            right,
+            down,
+            energy: 0,
+            magnetization: 0,
+            last: 0,
+        };
+        model.recalculate();
+        Ok(model)
+    }
+
+    /// a lattice of `l x l` uniformly random spins with the couplings drawn from
+    /// the `disorder` seed
+    pub fn random(l: usize, disorder: impl Into<Seed>, rng: &mut impl Rng) -> Result<Self> {
+        let mut model = SpinGlass2D::new(l, disorder)?;
+        model.reconstruct(rng);
+        Ok(model)
+    }
+
+    /// linear size of the lattice
+    pub fn size(&self) -> usize {
+        self.l
+    }
+
+    /// the seed of the couplings
+    pub fn disorder(&self) -> Seed {
+        self.disorder
+    }
+
+    pub fn spins(&self) -> &[i8] {
+        &self.spins
+    }
+
+    pub fn energy(&self) -> i64 {
+        self.energy
+    }
+
+    pub fn magnetization(&self) -> i64 {
+        self.magnetization
+    }
+
+    /// the overlap `q = sum_i s_i t_i / N` with the spins `t_i` of another replica of
+    /// the same disorder
+    pub fn overlap(&self, other: &SpinGlass2D) -> f64 {
+        assert_eq!(self.disorder, other.disorder, "the replicas need the same disorder");
+        let q: i64 = self.spins.iter().zip(&other.spins).map(|(&s, &t)| (s * t) as i64).sum();
+        q as f64 / self.spins.len() as f64
+    }
+
+    /// local field `sum_j J_ij s_j` of the four neighbors of spin `i`
+    fn field(&self, i: usize) -> i64 {
+        let l = self.l;
+        let (x, y) = (i % l, i / l);
+        let left = (x + l - 1) % l + y * l;
+        let right = (x + 1) % l + y * l;
+        let up = x + (y + l - 1) % l * l;
+        let down = x + (y + 1) % l * l;
+        (self.right[left] * self.spins[left]
+            + self.right[i] * self.spins[right]
+            + self.down[up] * self.spins[up]
+            + self.down[i] * self.spins[down]) as i64
+    }
+
+    fn flip(&mut self, i: usize) {
+        let s = self.spins[i] as i64;
+        self.energy += 2 * s * self.field(i);
+        self.magnetization -= 2 * s;
+        self.spins[i] = -self.spins[i];
+    }
+
+    /// calculate both observables from scratch
+    fn recalculate(&mut self) {
+        let l = self.l;
+        let mut energy = 0;
+        for i in 0..l * l {
+            let (x, y) = (i % l, i / l);
+            // count every bond once, to the right and down
+            let right = (x + 1) % l + y * l;
+            let down = x + (y + 1) % l * l;
+            energy -= (self.spins[i] * (self.right[i] * self.spins[right] + self.down[i] * self.spins[down])) as i64;
+        }
+        self.energy = energy;
+        self.magnetization = self.spins.iter().map(|&s| s as i64).sum();
+    }
+}
+
+impl Model for SpinGlass2D {
+    fn value(&self) -> f64 {
+        self.energy as f64
+    }
+
+    fn header(&self) -> String {
+        format!("# disorder = {}\n# energy magnetization", self.disorder)
+    }
+
+    fn save(&self) -> String {
+        format!("{} {}", self.energy, self.magnetization)
+    }
+
+    fn write_record(&self, w: &mut dyn fmt::Write) -> fmt::Result {
+        write!(w, "{} {}", self.energy, self.magnetization)
+    }
+}
+
+impl MarkovChain for SpinGlass2D {
+    fn change(&mut self, rng: &mut impl Rng) {
+        self.last = rng.gen_range(0..self.spins.len());
+        self.flip(self.last);
+    }
+
+    fn undo(&mut self) {
+        self.flip(self.last);
+    }
+}
+
+impl DirectSamplable for SpinGlass2D {
+    /// new random spins, the couplings stay the same
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        for s in &mut self.spins {
+            *s = if rng.gen::<bool>() { 1 } else { -1 };
+        }
+        self.recalculate();
+    }
+}
+
+impl Restorable for SpinGlass2D {
+    type Snapshot = Vec<i8>;
+
+    fn snapshot(&self) -> Vec<i8> {
+        self.spins.clone()
+    }
+
+    fn restore(&mut self, snapshot: &Vec<i8>) {
+        self.spins.clone_from(snapshot);
+        self.recalculate();
+    }
+}
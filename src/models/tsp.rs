@@ -0,0 +1,187 @@
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::{LargedevError, Model, Result};
+use crate::markovchain::{MarkovChain, Restorable};
+use crate::simple::DirectSamplable;
+
+/// the last change of the tour, which is needed to undo it
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum Last {
+    None,
+    /// the tour positions `i..=j` were reversed
+    TwoOpt(usize, usize),
+    /// a segment was moved, `previous` holds the tour before
+    OrOpt,
+}
+
+/// A traveling salesperson problem of cities in the plane, whose `value` is the length
+/// of the closed tour. The `change` is either a 2-opt move, i.e., the reversal of a
+/// random part of the tour, or an or-opt move, i.e., moving a random segment of up to
+/// three cities to another random position of the tour. The change of the length is
+/// calculated in constant time. The model can be optimized with, e.g., `downhill` or
+/// a Metropolis simulation at low temperature, or the distribution of tour lengths can
+/// be sampled with `WangLandau`, e.g.:
+///
+/// ```ignore
+/// let model = Tsp::random(100, &mut rng)?;
+/// let mut run = Metropolis::new(model)
+///    .temperature(0.01)
+///    .iterations(100000)
+///    .build()?;
+/// let length = run.downhill(&mut rng);
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tsp {
+    cities: Vec<(f64, f64)>,
+    tour: Vec<usize>,
+    length: f64,
+    last: Last,
+    previous: Vec<usize>,
+    previous_length: f64,
+}
+
+impl Tsp {
+    /// the tour visits the `cities` in the given order
+    pub fn new(cities: Vec<(f64, f64)>) -> Result<Self> {
+        if cities.len() < 4 {
+            return Err(LargedevError::InvalidConfig("the problem needs at least four cities".into()))
+        }
+        let tour = (0..cities.len()).collect();
+        let mut model = Tsp {
+            cities,
+            tour,
+            length: 0.,
+            last: Last::None,
+            previous: Vec::new(),
+            previous_length: 0.,
+        };
+        model.length = model.recalculate();
+        Ok(model)
+    }
+
+    /// `n` cities uniformly distributed in the unit square with a random tour
+    pub fn random(n: usize, rng: &mut impl Rng) -> Result<Self> {
+        let cities = (0..n).map(|_| (rng.gen(), rng.gen())).collect();
+        let mut model = Tsp::new(cities)?;
+        model.reconstruct(rng);
+        Ok(model)
+    }
+
+    pub fn cities(&self) -> &[(f64, f64)] {
+        &self.cities
+    }
+
+    /// the indices of the cities in the order of the tour
+    pub fn tour(&self) -> &[usize] {
+        &self.tour
+    }
+
+    pub fn length(&self) -> f64 {
+        self.length
+    }
+
+    fn distance(&self, a: usize, b: usize) -> f64 {
+        let (p, q) = (self.cities[a], self.cities[b]);
+        (p.0 - q.0).hypot(p.1 - q.1)
+    }
+
+    /// the city at the position `i` of the tour, cyclically
+    fn at(&self, i: usize) -> usize {
+        self.tour[i % self.tour.len()]
+    }
+
+    fn recalculate(&self) -> f64 {
+        (0..self.tour.len()).map(|i| self.distance(self.at(i), self.at(i + 1))).sum()
+    }
+
+    /// reverse the tour at the positions `i..=j` with `1 <= i < j < n`
+    fn two_opt(&mut self, i: usize, j: usize) {
+        let (a, b) = (self.at(i - 1), self.at(i));
+        let (c, d) = (self.at(j), self.at(j + 1));
+        self.length += self.distance(a, c) + self.distance(b, d) - self.distance(a, b) - self.distance(c, d);
+        self.tour[i..=j].reverse();
+    }
+
+    /// move the segment of `len` cities starting at position `i` behind the city,
+    /// which is at position `k` after the removal of the segment
+    fn or_opt(&mut self, i: usize, len: usize, k: usize) {
+        let n = self.tour.len();
+        let (p, s0) = (self.at(i + n - 1), self.at(i));
+        let (s1, q) = (self.at(i + len - 1), self.at(i + len));
+        self.length += self.distance(p, q) - self.distance(p, s0) - self.distance(s1, q);
+
+        let segment: Vec<usize> = (i..i + len).map(|m| self.at(m)).collect();
+        let mut rest: Vec<usize> = (i + len..i + n).map(|m| self.at(m)).collect();
+        let (a, b) = (rest[k], rest[(k + 1) % rest.len()]);
+        self.length += self.distance(a, s0) + self.distance(s1, b) - self.distance(a, b);
+
+        rest.splice(k + 1..k + 1, segment);
+        self.tour = rest;
+    }
+}
+
+impl Model for Tsp {
+    fn value(&self) -> f64 {
+        self.length
+    }
+
+    fn header(&self) -> String {
+        "# tour length".into()
+    }
+}
+
+impl MarkovChain for Tsp {
+    fn change(&mut self, rng: &mut impl Rng) {
+        let n = self.tour.len();
+        self.previous_length = self.length;
+        if rng.gen::<bool>() {
+            let i = rng.gen_range(1..n - 1);
+            let j = rng.gen_range(i + 1..n);
+            self.two_opt(i, j);
+            self.last = Last::TwoOpt(i, j);
+        } else {
+            let len = rng.gen_range(1..4.min(n - 2));
+            let i = rng.gen_range(0..n);
+            // the segment is inserted behind a city different from its predecessor
+            let k = rng.gen_range(0..n - len - 1);
+            self.previous.clone_from(&self.tour);
+            self.or_opt(i, len, k);
+            self.last = Last::OrOpt;
+        }
+    }
+
+    fn undo(&mut self) {
+        match std::mem::replace(&mut self.last, Last::None) {
+            Last::None => return,
+            Last::TwoOpt(i, j) => self.tour[i..=j].reverse(),
+            Last::OrOpt => std::mem::swap(&mut self.tour, &mut self.previous),
+        }
+        self.length = self.previous_length;
+    }
+}
+
+impl DirectSamplable for Tsp {
+    /// a uniformly random tour through the same cities
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        self.tour.shuffle(rng);
+        self.length = self.recalculate();
+        self.last = Last::None;
+    }
+}
+
+impl Restorable for Tsp {
+    type Snapshot = Vec<usize>;
+
+    fn snapshot(&self) -> Vec<usize> {
+        self.tour.clone()
+    }
+
+    fn restore(&mut self, snapshot: &Vec<usize>) {
+        self.tour.clone_from(snapshot);
+        self.length = self.recalculate();
+        self.last = Last::None;
+    }
+}
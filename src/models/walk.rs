@@ -0,0 +1,285 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use crate::{LargedevError, Model, Result};
+use crate::markovchain::{MarkovChain, Restorable};
+use crate::simple::DirectSamplable;
+
+/// A random walk of `n` steps on the hypercubic lattice in `d` dimensions, starting at
+/// the origin. The `value` is the end-to-end distance. The `change` redraws the
+/// direction of a single random step, which corresponds to a crankshaft-like
+/// rotation of the rest of the walk, e.g.:
+///
+/// ```ignore
+/// let model = LatticeWalk::random(100, 2, &mut rng)?;
+/// let report = WangLandau::new(model)
+///    .range(0., 100.)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatticeWalk {
+    d: usize,
+    /// the direction of every step, `2k` is a step in the positive direction `k`,
+    /// `2k+1` a step in the negative direction
+    steps: Vec<u8>,
+    end: Vec<i64>,
+    /// the step changed by the last `change` and its previous direction
+    last: (usize, u8),
+}
+
+impl LatticeWalk {
+    /// a straight walk of `n` steps in `d` dimensions along the first axis
+    pub fn new(n: usize, d: usize) -> Result<Self> {
+        if n < 1 {
+            return Err(LargedevError::InvalidConfig("the walk needs at least one step".into()))
+        }
+        if !(1..=127).contains(&d) {
+            return Err(LargedevError::InvalidConfig("the dimension must be between 1 and 127".into()))
+        }
+        let mut end = vec![0; d];
+        end[0] = n as i64;
+        Ok(LatticeWalk {
+            d,
+            steps: vec![0; n],
+            end,
+            last: (0, 0),
+        })
+    }
+
+    /// a uniformly random walk of `n` steps in `d` dimensions
+    pub fn random(n: usize, d: usize, rng: &mut impl Rng) -> Result<Self> {
+        let mut model = LatticeWalk::new(n, d)?;
+        model.reconstruct(rng);
+        Ok(model)
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.d
+    }
+
+    /// number of steps
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// coordinates of the end of the walk
+    pub fn end(&self) -> &[i64] {
+        &self.end
+    }
+
+    /// coordinates of all `n+1` sites of the walk, including the origin
+    pub fn positions(&self) -> Vec<Vec<i64>> {
+        let mut x = vec![0; self.d];
+        let mut positions = Vec::with_capacity(self.steps.len() + 1);
+        positions.push(x.clone());
+        for &s in &self.steps {
+            x[s as usize / 2] += direction(s);
+            positions.push(x.clone());
+        }
+        positions
+    }
+
+    fn set(&mut self, i: usize, s: u8) {
+        let old = self.steps[i];
+        self.end[old as usize / 2] -= direction(old);
+        self.end[s as usize / 2] += direction(s);
+        self.steps[i] = s;
+    }
+
+    fn recalculate(&mut self) {
+        self.end = vec![0; self.d];
+        for &s in &self.steps {
+            self.end[s as usize / 2] += direction(s);
+        }
+    }
+}
+
+/// the sign of the step direction `s`
+fn direction(s: u8) -> i64 {
+    1 - 2 * (s % 2) as i64
+}
+
+impl Model for LatticeWalk {
+    fn value(&self) -> f64 {
+        (self.end.iter().map(|x| x * x).sum::<i64>() as f64).sqrt()
+    }
+
+    fn header(&self) -> String {
+        "# end-to-end distance".into()
+    }
+}
+
+impl MarkovChain for LatticeWalk {
+    fn change(&mut self, rng: &mut impl Rng) {
+        let i = rng.gen_range(0..self.steps.len());
+        let s = rng.gen_range(0..2 * self.d as u8);
+        self.last = (i, self.steps[i]);
+        self.set(i, s);
+    }
+
+    fn undo(&mut self) {
+        let (i, s) = self.last;
+        self.set(i, s);
+    }
+}
+
+impl DirectSamplable for LatticeWalk {
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        let directions = 2 * self.d as u8;
+        for s in &mut self.steps {
+            *s = rng.gen_range(0..directions);
+        }
+        self.recalculate();
+    }
+}
+
+impl Restorable for LatticeWalk {
+    type Snapshot = Vec<u8>;
+
+    fn snapshot(&self) -> Vec<u8> {
+        self.steps.clone()
+    }
+
+    fn restore(&mut self, snapshot: &Vec<u8>) {
+        self.steps.clone_from(snapshot);
+        self.recalculate();
+    }
+}
+
+/// A Lévy flight of `n` steps in the plane, starting at the origin. Every step has a
+/// uniformly random direction and a length `l >= 1` from the Pareto distribution
+/// `p(l) ~ l^(-1-alpha)`, which is heavy tailed for `0 < alpha < 2`. The `value` is
+/// the end-to-end distance. The `change` redraws a single random step.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LevyWalk {
+    alpha: f64,
+    /// the displacement of every step
+    steps: Vec<(f64, f64)>,
+    end: (f64, f64),
+    /// the step changed by the last `change`, its previous displacement and the
+    /// previous end, which `undo` restores exactly
+    last: (usize, (f64, f64), (f64, f64)),
+}
+
+impl LevyWalk {
+    /// a random Lévy flight of `n` steps with the tail exponent `alpha`
+    pub fn random(n: usize, alpha: f64, rng: &mut impl Rng) -> Result<Self> {
+        if n < 1 {
+            return Err(LargedevError::InvalidConfig("the walk needs at least one step".into()))
+        }
+        if alpha.is_nan() || alpha <= 0. {
+            return Err(LargedevError::InvalidConfig("the tail exponent must be positive".into()))
+        }
+        let mut model = LevyWalk {
+            alpha,
+            steps: vec![(0., 0.); n],
+            end: (0., 0.),
+            last: (0, (0., 0.), (0., 0.)),
+        };
+        model.reconstruct(rng);
+        Ok(model)
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// number of steps
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// coordinates of the end of the walk
+    pub fn end(&self) -> (f64, f64) {
+        self.end
+    }
+
+    /// coordinates of all `n+1` sites of the walk, including the origin
+    pub fn positions(&self) -> Vec<(f64, f64)> {
+        let mut x = (0., 0.);
+        let mut positions = Vec::with_capacity(self.steps.len() + 1);
+        positions.push(x);
+        for &(dx, dy) in &self.steps {
+            x = (x.0 + dx, x.1 + dy);
+            positions.push(x);
+        }
+        positions
+    }
+
+    fn draw(&self, rng: &mut impl Rng) -> (f64, f64) {
+        let phi = rng.gen_range(0.0..2. * PI);
+        // inverse transform sampling of the Pareto distribution, `1 - u` avoids `u = 0`
+        let u: f64 = rng.gen();
+        let l = (1. - u).powf(-1. / self.alpha);
+        (l * phi.cos(), l * phi.sin())
+    }
+
+    fn set(&mut self, i: usize, step: (f64, f64)) {
+        let old = self.steps[i];
+        self.end = (self.end.0 - old.0 + step.0, self.end.1 - old.1 + step.1);
+        self.steps[i] = step;
+    }
+
+    fn recalculate(&mut self) {
+        self.end = self.steps.iter().fold((0., 0.), |(x, y), (dx, dy)| (x + dx, y + dy));
+    }
+}
+
+impl Model for LevyWalk {
+    fn value(&self) -> f64 {
+        self.end.0.hypot(self.end.1)
+    }
+
+    fn header(&self) -> String {
+        "# end-to-end distance".into()
+    }
+}
+
+impl MarkovChain for LevyWalk {
+    fn change(&mut self, rng: &mut impl Rng) {
+        let i = rng.gen_range(0..self.steps.len());
+        let step = self.draw(rng);
+        self.last = (i, self.steps[i], self.end);
+        self.set(i, step);
+    }
+
+    fn undo(&mut self) {
+        let (i, step, end) = self.last;
+        self.steps[i] = step;
+        self.end = end;
+    }
+}
+
+impl DirectSamplable for LevyWalk {
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        for i in 0..self.steps.len() {
+            self.steps[i] = self.draw(rng);
+        }
+        self.recalculate();
+    }
+}
+
+impl Restorable for LevyWalk {
+    type Snapshot = Vec<(f64, f64)>;
+
+    fn snapshot(&self) -> Vec<(f64, f64)> {
+        self.steps.clone()
+    }
+
+    fn restore(&mut self, snapshot: &Vec<(f64, f64)>) {
+        self.steps.clone_from(snapshot);
+        self.recalculate();
+    }
+}
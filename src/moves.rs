@@ -0,0 +1,326 @@
+use std::fmt;
+
+use rand::{Rng, RngCore};
+
+use crate::{LargedevError, Model, Result};
+use crate::markovchain::{MarkovChain, Proposal, Restorable};
+
+/// A single proposal kernel acting on a model of type `M`, e.g., a local spin flip,
+/// a global shift or a cluster move. In contrast to `MarkovChain::change` the move
+/// is not part of the model, such that different moves can be combined into a
+/// `MoveSet` and chosen per sampler.
+pub trait Move<M> {
+    /// introduce a change to `model` to propose it as the next state in the chain
+    fn propose(&mut self, model: &mut M, rng: &mut dyn RngCore);
+
+    /// undo the previous `propose` on `model`
+    fn undo(&mut self, model: &mut M);
+
+    /// propose a change and signal whether it is valid, see `MarkovChain::try_change`,
+    /// by default it calls `propose` and is always valid
+    fn try_propose(&mut self, model: &mut M, rng: &mut dyn RngCore) -> Proposal {
+        self.propose(model, rng);
+        Proposal::Valid
+    }
+
+    /// logarithm of the ratio of the proposal probabilities `q(new -> old) / q(old -> new)`
+    /// of the previous `propose`, see `MarkovChain::ln_proposal_ratio`
+    fn ln_proposal_ratio(&self, _model: &M) -> f64 {
+        0.
+    }
+
+    /// a name to identify this move, e.g., in acceptance statistics
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// A `Move` which uses the `change` and `undo` methods of the model itself,
+/// such that the native move of a `MarkovChain` can be mixed with other moves.
+#[derive(Clone, Debug, Default)]
+pub struct NativeMove;
+
+impl<MC: MarkovChain> Move<MC> for NativeMove {
+    fn propose(&mut self, model: &mut MC, mut rng: &mut dyn RngCore) {
+        model.change(&mut rng);
+    }
+
+    fn undo(&mut self, model: &mut MC) {
+        model.undo();
+    }
+
+    fn try_propose(&mut self, model: &mut MC, mut rng: &mut dyn RngCore) -> Proposal {
+        model.try_change(&mut rng)
+    }
+
+    fn ln_proposal_ratio(&self, model: &MC) -> f64 {
+        model.ln_proposal_ratio()
+    }
+}
+
+/// Acceptance statistics of a single move of a `MoveSet`.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MoveStats {
+    /// how often this move was proposed
+    pub tries: usize,
+    /// how often a proposal of this move was rejected, i.e., undone
+    pub rejects: usize,
+    /// how often a proposal of this move was invalid
+    pub invalid: usize,
+}
+
+impl MoveStats {
+    /// fraction of accepted proposals, `None` if the move was never proposed
+    pub fn acceptance(&self) -> Option<f64> {
+        if self.tries == 0 {
+            return None
+        }
+        Some((self.tries - self.rejects - self.invalid) as f64 / self.tries as f64)
+    }
+}
+
+/// A weighted mixture of moves, one of which is chosen for each proposal with a
+/// probability proportional to its weight. The acceptance of each move is tracked,
+/// which is useful to tune the mixture, e.g.:
+///
+/// ```ignore
+/// let moves = MoveSet::new()
+///     .with_weight(LocalFlip, 0.9)
+///     .with_weight(ClusterMove, 0.1);
+/// ```
+pub struct MoveSet<M> {
+    moves: Vec<Box<dyn Move<M>>>,
+    /// relative probability to choose each move
+    weights: Vec<f64>,
+    /// sum of all `weights`
+    total_weight: f64,
+    stats: Vec<MoveStats>,
+    /// index of the move used for the last proposal
+    last: Option<usize>,
+}
+
+impl<M> MoveSet<M> {
+    pub fn new() -> Self {
+        MoveSet::<M> {
+            moves: Vec::new(),
+            weights: Vec::new(),
+            total_weight: 0.,
+            stats: Vec::new(),
+            last: None,
+        }
+    }
+
+    /// add a move with weight 1 to the set
+    pub fn with(self, m: impl Move<M> + 'static) -> Self {
+        self.with_weight(m, 1.)
+    }
+
+    /// add a move to the set, which is chosen with a probability proportional to `weight`,
+    /// which has to be positive and finite, see `validate`
+    pub fn with_weight(mut self, m: impl Move<M> + 'static, weight: f64) -> Self {
+        self.moves.push(Box::new(m));
+        self.weights.push(weight);
+        self.total_weight += weight;
+        self.stats.push(MoveStats::default());
+        self
+    }
+
+    /// check that the set contains at least one move and that all weights are positive
+    /// and finite, `WithMoves::new` calls this for every set
+    pub fn validate(&self) -> Result<()> {
+        if self.moves.is_empty() {
+            return Err(LargedevError::InvalidParameter("the move set is empty".into()))
+        }
+        if let Some(w) = self.weights.iter().find(|w| !(w.is_finite() && **w > 0.)) {
+            return Err(LargedevError::InvalidParameter(format!("the weights of the moves must be positive and finite, not {}", w)))
+        }
+        if !self.total_weight.is_finite() {
+            return Err(LargedevError::InvalidParameter("the sum of the weights of the moves is not finite".into()))
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    /// index of the move, which was used for the last proposal
+    pub fn last(&self) -> Option<usize> {
+        self.last
+    }
+
+    /// the names of all moves in the order they were added
+    pub fn names(&self) -> Vec<&str> {
+        self.moves.iter().map(|m| m.name()).collect()
+    }
+
+    /// acceptance statistics of all moves in the order they were added
+    pub fn stats(&self) -> &[MoveStats] {
+        &self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        for s in &mut self.stats {
+            *s = MoveStats::default();
+        }
+    }
+
+    fn choose(&self, rng: &mut dyn RngCore) -> usize {
+        let mut r = rng.gen_range(0.0..self.total_weight);
+        for (n, w) in self.weights.iter().enumerate() {
+            if r < *w {
+                return n
+            }
+            r -= w;
+        }
+        // only reachable due to rounding errors
+        self.weights.len() - 1
+    }
+
+    /// propose a change of `model` with a randomly chosen move
+    /// if the proposal is invalid or unchanged, the model is unchanged and a following
+    /// `undo` does nothing
+    /// an empty set, which `WithMoves::new` rejects, proposes nothing and is invalid
+    pub fn propose(&mut self, model: &mut M, rng: &mut dyn RngCore) -> Proposal {
+        if self.moves.is_empty() {
+            self.last = None;
+            return Proposal::Invalid
+        }
+        let idx = self.choose(rng);
+        let proposal = self.moves[idx].try_propose(model, rng);
+        self.stats[idx].tries += 1;
+        match proposal {
+            Proposal::Valid => self.last = Some(idx),
+            Proposal::Invalid => {
+                self.stats[idx].invalid += 1;
+                self.last = None;
+            }
+            Proposal::Unchanged => self.last = None,
+        }
+        proposal
+    }
+
+    /// undo the last proposal
+    pub fn undo(&mut self, model: &mut M) {
+        if let Some(idx) = self.last.take() {
+            self.moves[idx].undo(model);
+            self.stats[idx].rejects += 1;
+        }
+    }
+
+    /// logarithm of the proposal ratio of the last proposal
+    /// since the move is chosen independently of the state, the probabilities
+    /// to choose the move cancel and only the ratio of the move itself remains
+    pub fn ln_proposal_ratio(&self, model: &M) -> f64 {
+        match self.last {
+            Some(idx) => self.moves[idx].ln_proposal_ratio(model),
+            None => 0.,
+        }
+    }
+}
+
+impl<M> Default for MoveSet<M> {
+    fn default() -> Self {
+        MoveSet::new()
+    }
+}
+
+/// Combines a model with a `MoveSet` into a `MarkovChain`, such that it can be
+/// used with any of the samplers. This way the proposals can be chosen, e.g.:
+///
+/// ```ignore
+/// let chain = WithMoves::new(model, MoveSet::new().with(LocalFlip).with(Shift))?;
+/// let report = Metropolis::new(chain)
+///    .temperature(2.269)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+pub struct WithMoves<M> {
+    model: M,
+    moves: MoveSet<M>,
+}
+
+impl<M: Model> WithMoves<M> {
+    /// combine `model` with a set of `moves`, which is rejected if it is not valid,
+    /// see `MoveSet::validate`
+    pub fn new(model: M, moves: MoveSet<M>) -> Result<Self> {
+        moves.validate()?;
+        Ok(WithMoves::<M> {
+            model,
+            moves,
+        })
+    }
+
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut M {
+        &mut self.model
+    }
+
+    pub fn moves(&self) -> &MoveSet<M> {
+        &self.moves
+    }
+
+    pub fn moves_mut(&mut self) -> &mut MoveSet<M> {
+        &mut self.moves
+    }
+
+    pub fn into_inner(self) -> (M, MoveSet<M>) {
+        (self.model, self.moves)
+    }
+}
+
+impl<M: Model> Model for WithMoves<M> {
+    fn value(&self) -> f64 {
+        self.model.value()
+    }
+
+    fn header(&self) -> String {
+        self.model.header()
+    }
+
+    fn save(&self) -> String {
+        self.model.save()
+    }
+
+    fn write_record(&self, w: &mut dyn fmt::Write) -> fmt::Result {
+        self.model.write_record(w)
+    }
+}
+
+impl<M: Model> MarkovChain for WithMoves<M> {
+    fn change(&mut self, rng: &mut impl Rng) {
+        self.moves.propose(&mut self.model, rng);
+    }
+
+    fn try_change(&mut self, rng: &mut impl Rng) -> Proposal {
+        self.moves.propose(&mut self.model, rng)
+    }
+
+    fn undo(&mut self) {
+        self.moves.undo(&mut self.model);
+    }
+
+    fn ln_proposal_ratio(&self) -> f64 {
+        self.moves.ln_proposal_ratio(&self.model)
+    }
+}
+
+impl<M: Restorable> Restorable for WithMoves<M> {
+    type Snapshot = M::Snapshot;
+
+    fn snapshot(&self) -> Self::Snapshot {
+        self.model.snapshot()
+    }
+
+    fn restore(&mut self, snapshot: &Self::Snapshot) {
+        self.model.restore(snapshot)
+    }
+}
@@ -0,0 +1,428 @@
+use std::io::{BufWriter, Write};
+use std::thread;
+
+use rand::Rng;
+
+use crate::histogram::Histogram;
+use crate::acceptance::{AcceptanceRule, MetropolisRule};
+use crate::markovchain::{MarkovChain, Proposal};
+use crate::error::{LargedevError, Result};
+use crate::report::Report;
+use crate::seed::{DefaultRng, Seed};
+use crate::hooks::CancelToken;
+use crate::uniforms::Uniforms;
+use crate::wanglandau;
+
+/// Parameters of a multicanonical simulation, see `Multicanonical` for the builder.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MulticanonicalConfig {
+    /// lower and upper bound for the energy of the sampled window
+    pub range: Option<(f64, f64)>,
+    /// number of bins of the weights
+    pub bins: usize,
+    /// how many change attempts per sweep
+    pub sweep: usize,
+    /// number of iterations of the weights
+    pub iterations: usize,
+    /// sweeps of every walker per iteration
+    pub iteration_sweeps: usize,
+    /// number of walkers, each on its own thread, which contribute to every iteration
+    pub walkers: usize,
+    /// how the weights are updated from the merged histogram of an iteration
+    pub update: WeightUpdate,
+    /// master seed for `run_seeded`
+    pub seed: Option<Seed>,
+}
+
+impl Default for MulticanonicalConfig {
+    fn default() -> Self {
+        MulticanonicalConfig {
+            range: None,
+            bins: 100,
+            sweep: 1,
+            iterations: 20,
+            iteration_sweeps: 1000,
+            walkers: 1,
+            update: WeightUpdate::default(),
+            seed: None,
+        }
+    }
+}
+
+impl MulticanonicalConfig {
+    pub fn validate(&self) -> Result<()> {
+        let (low, high) = match self.range {
+            Some(range) => range,
+            None => return Err(LargedevError::InvalidParameter("the energy range must be specified".into()))
+        };
+        Histogram::new(low, high, self.bins)?;
+        if self.sweep == 0 {
+            return Err(LargedevError::InvalidParameter("sweep must be positive".into()))
+        }
+        if self.iterations == 0 || self.iteration_sweeps == 0 {
+            return Err(LargedevError::InvalidParameter("at least one iteration of one sweep is needed".into()))
+        }
+        if self.walkers == 0 {
+            return Err(LargedevError::InvalidParameter("at least one walker is needed".into()))
+        }
+        Ok(())
+    }
+}
+
+/// The rule to update the weights from the merged histogram `H` of an iteration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WeightUpdate {
+    /// `ln g += ln H` for all visited bins, which forgets the previous iterations,
+    /// such that the statistical error does not decrease with more iterations
+    #[default]
+    Naive,
+    /// The accumulated statistics recursion of Berg (Comput. Phys. Commun. 153, 397),
+    /// which updates the differences `ln g(i+1) - ln g(i)` of neighboring bins by
+    /// `kappa (ln H(i+1) - ln H(i))`, if both bins are visited. The reliability
+    /// factor `r = H(i) H(i+1) / (H(i) + H(i+1))` of every pair of bins is accumulated
+    /// over all iterations, and `kappa` is the share of the current iteration in the
+    /// accumulated factor, such that every difference is an average of all
+    /// iterations, weighted by their reliability. The difference across a bin, which
+    /// is never visited, e.g., an impossible energy, is never updated, so the bins
+    /// should not contain such gaps.
+    Berg,
+}
+
+/// A builder used to set up multicanonical sampling, which iteratively refines
+/// weights `exp(-ln g(E))`, such that the histogram of visits becomes flat. In every
+/// iteration, all walkers simulate with fixed weights in parallel, their histograms are
+/// merged and the weights are updated from the merged data by `ln g += ln H` for all
+/// visited bins. In contrast to Wang-Landau sampling, every iteration is a proper
+/// Markov chain, so more walkers cut the wall-clock time of an iteration without
+/// biasing it. Every walker uses an independent random number stream, derived from a
+/// master seed, e.g.:
+///
+/// ```ignore
+/// let report = Multicanonical::new(model)
+///    .range(low, high)
+///    .bins(100)
+///    .iterations(30)
+///    .iteration_sweeps(10000)
+///    .walkers(8)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+pub struct Multicanonical<MC> {
+    model: MC,
+    config: MulticanonicalConfig,
+    cancel: Option<CancelToken>,
+}
+
+impl<MC: MarkovChain> Multicanonical<MC> {
+    pub fn new(model: MC) -> Self {
+        Multicanonical::with_config(model, MulticanonicalConfig::default())
+    }
+
+    pub fn with_config(model: MC, config: MulticanonicalConfig) -> Self {
+        Multicanonical::<MC> {
+            model,
+            config,
+            cancel: None,
+        }
+    }
+
+    /// the energy window to sample, `low` is included, `high` is excluded
+    pub fn range(mut self, low: f64, high: f64) -> Self {
+        self.config.range = Some((low, high));
+        self
+    }
+
+    pub fn bins(mut self, bins: usize) -> Self {
+        self.config.bins = bins;
+        self
+    }
+
+    pub fn sweep(mut self, sweep: usize) -> Self {
+        self.config.sweep = sweep;
+        self
+    }
+
+    pub fn iterations(mut self, iterations: usize) -> Self {
+        self.config.iterations = iterations;
+        self
+    }
+
+    pub fn iteration_sweeps(mut self, iteration_sweeps: usize) -> Self {
+        self.config.iteration_sweeps = iteration_sweeps;
+        self
+    }
+
+    pub fn walkers(mut self, walkers: usize) -> Self {
+        self.config.walkers = walkers;
+        self
+    }
+
+    /// the update rule of the weights, `WeightUpdate::Naive` by default
+    pub fn update(mut self, update: WeightUpdate) -> Self {
+        self.config.update = update;
+        self
+    }
+
+    /// the master seed used by `run_seeded`, if not set, a seed is drawn from entropy
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Self {
+        self.config.seed = Some(seed.into());
+        self
+    }
+
+    /// a token to cancel the simulation of all walkers, see `CancelToken`
+    pub fn cancel_token(mut self, token: impl Into<CancelToken>) -> Self {
+        self.cancel = Some(token.into());
+        self
+    }
+
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// validate the parameters and return the configured sampler
+    pub fn build(self) -> Result<MulticanonicalRun<MC>> {
+        self.config.validate()?;
+        Ok(MulticanonicalRun {
+            model: self.model,
+            config: self.config,
+            cancel: self.cancel,
+        })
+    }
+}
+
+/// A configured multicanonical sampler, see `Multicanonical` for the builder.
+pub struct MulticanonicalRun<MC> {
+    /// the initial state, which is copied to every walker
+    model: MC,
+    config: MulticanonicalConfig,
+    cancel: Option<CancelToken>,
+}
+
+/// The state of one walker, which is kept between the iterations.
+struct Walker<MC> {
+    model: MC,
+    rng: DefaultRng,
+    uniforms: Uniforms,
+    energy: f64,
+}
+
+impl<MC: MarkovChain + Clone + Send> MulticanonicalRun<MC> {
+    pub fn config(&self) -> &MulticanonicalConfig {
+        &self.config
+    }
+
+    /// the initial state of the model, which is copied to every walker
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// The master seed of the walkers is drawn from `rng`, the logarithm of the
+    /// density of states, i.e., of the final weights, is written like the one of
+    /// `WangLandauRun::run`. The report accumulates the reports of all walkers. It
+    /// needs threads, which are not available on, e.g., `wasm32-unknown-unknown`.
+    pub fn run(&mut self, rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<Report> {
+        let master = Seed::from_rng(rng);
+        self.simulate(master, file, None)
+    }
+
+    /// like `run`, but with the master seed from the configuration (or from entropy),
+    /// which is written to the output and the report
+    pub fn run_seeded(&mut self, file: &mut impl Write) -> Result<Report> {
+        let seed = self.config.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate(seed, file, Some(seed))
+    }
+
+    fn simulate(&mut self, master: Seed, file: &mut impl Write, seed: Option<Seed>) -> Result<Report> {
+        let mut file = BufWriter::new(file);
+
+        let (g, mut report) = self.estimate(master)?;
+        report.seed = seed;
+        report.streams = (0..self.config.walkers).map(|k| master.stream(k as u64)).collect();
+
+        wanglandau::write_dos(&mut file, &g, seed, &report.streams)?;
+        file.flush()?;
+
+        Ok(report)
+    }
+
+    /// iterate the weights with all walkers and return the final estimate of the
+    /// logarithm of the density of states
+    fn estimate(&self, master: Seed) -> Result<(Histogram, Report)> {
+        let (low, high) = self.config.range.unwrap();
+        let mut g = Histogram::new(low, high, self.config.bins)?;
+
+        let mut walkers: Vec<Walker<MC>> = (0..self.config.walkers).map(|k| {
+            let mut model = self.model.clone();
+            let mut rng = master.stream(k as u64).rng();
+            wanglandau::find_start(&mut model, low, high, &mut rng);
+            let energy = model.value();
+            Walker { model, rng, uniforms: Uniforms::new(self.config.sweep), energy }
+        }).collect();
+
+        trace_span!(_span = info_span!("multicanonical", low, high, bins = self.config.bins, walkers = self.config.walkers));
+        let mut report = Report::default();
+        // the accumulated reliability of the differences of neighboring bins
+        let mut reliability = vec![0.; self.config.bins - 1];
+        for _iteration in 0..self.config.iterations {
+            if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                report.stopped = true;
+                break
+            }
+
+            let results: Vec<(Histogram, Report)> = thread::scope(|s| {
+                let workers: Vec<_> = walkers.iter_mut().map(|walker| {
+                    let (g, config) = (&g, &self.config);
+                    s.spawn(move || iterate(walker, g, config))
+                }).collect();
+
+                workers.into_iter()
+                    .map(|w| w.join().expect("a walker panicked"))
+                    .collect()
+            });
+
+            // merge the histograms of all walkers
+            let mut h = Histogram::new(low, high, self.config.bins)?;
+            for (hk, r) in results {
+                for (i, &x) in hk.data().iter().enumerate() {
+                    *h.idx(i) += x;
+                }
+                report.tries += r.tries;
+                report.rejects += r.rejects;
+                report.invalid += r.invalid;
+            }
+
+            match self.config.update {
+                WeightUpdate::Naive => {
+                    for (i, &x) in h.data().iter().enumerate() {
+                        if x > 0. {
+                            *g.idx(i) += x.ln();
+                        }
+                    }
+                }
+                WeightUpdate::Berg => berg(&mut g, &h, &mut reliability),
+            }
+            trace_event!(debug!(iteration = _iteration + 1, visited = h.data().iter().filter(|&&x| x > 0.).count(), acceptance = report.acceptance(), "updated the weights"));
+        }
+
+        Ok((g, report))
+    }
+}
+
+/// Update the weights `g` with the histogram `h` by the recursion of Berg, see
+/// `WeightUpdate::Berg`, `reliability` accumulates the factors of all iterations.
+fn berg(g: &mut Histogram, h: &Histogram, reliability: &mut [f64]) {
+    let old = g.data().to_vec();
+    let h = h.data();
+    for i in 0..reliability.len() {
+        let mut difference = old[i + 1] - old[i];
+        let (a, b) = (h[i], h[i + 1]);
+        if a > 0. && b > 0. {
+            let r = a * b / (a + b);
+            reliability[i] += r;
+            difference += r / reliability[i] * (b.ln() - a.ln());
+        }
+        *g.idx(i + 1) = *g.idx(i) + difference;
+    }
+}
+
+/// Simulate one iteration of `walker` with the fixed weights `exp(-g)` and return its
+/// histogram of visits and the counts of its proposals.
+fn iterate<MC: MarkovChain>(walker: &mut Walker<MC>, g: &Histogram, config: &MulticanonicalConfig) -> (Histogram, Report) {
+    let mut h = g.clone();
+    h.reset();
+    let mut report = Report::default();
+    for _ in 0..config.iteration_sweeps {
+        for _ in 0..config.sweep {
+            walker.energy = step(walker, g, &mut report);
+            h.count(walker.energy);
+        }
+    }
+    (h, report)
+}
+
+/// Propose a change of the model of `walker` and accept it according to the weights
+/// `exp(-g)`. The proposal is counted in `report`, returns the new energy.
+fn step<MC: MarkovChain>(walker: &mut Walker<MC>, g: &Histogram, report: &mut Report) -> f64 {
+    let old_e = walker.energy;
+    report.tries += 1;
+    match walker.model.try_change(&mut walker.rng) {
+        Proposal::Valid => (),
+        Proposal::Invalid => {
+            report.invalid += 1;
+            return old_e
+        }
+        Proposal::Unchanged => return old_e,
+    }
+    let new_e = walker.model.value();
+
+    let ln_weight = match (g.at(old_e), g.at(new_e)) {
+        (Some(old), Some(new)) => old - new + walker.model.ln_proposal_ratio(),
+        _ => f64::NEG_INFINITY,
+    };
+
+    if !MetropolisRule.accept(ln_weight, walker.uniforms.next(&mut walker.rng)) {
+        walker.model.undo();
+        report.rejects += 1;
+        old_e
+    } else {
+        new_e
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(data: &[f64]) -> Histogram {
+        let mut h = Histogram::new(0., data.len() as f64, data.len()).unwrap();
+        for (i, &x) in data.iter().enumerate() {
+            *h.idx(i) = x;
+        }
+        h
+    }
+
+    fn assert_close(a: &[f64], b: &[f64]) {
+        assert!(a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-12), "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn berg_accumulates_the_reliability() {
+        let ln4 = 4f64.ln();
+        let mut g = histogram(&[0., 0., 0.]);
+        let mut reliability = vec![0.; 2];
+
+        // the first iteration determines the differences completely, with the
+        // reliabilities 1 * 4 / 5 and 4 * 4 / 8
+        berg(&mut g, &histogram(&[1., 4., 4.]), &mut reliability);
+        assert_close(&reliability, &[0.8, 2.]);
+        assert_close(g.data(), &[0., ln4, ln4]);
+
+        // the second one shifts them by their shares 2 / 2.8 and 0.8 / 2.8
+        berg(&mut g, &histogram(&[4., 4., 1.]), &mut reliability);
+        assert_close(&reliability, &[2.8, 2.8]);
+        assert_close(g.data(), &[0., ln4, ln4 - 2. / 7. * ln4]);
+
+        // an unvisited bin keeps the differences to its neighbors
+        berg(&mut g, &histogram(&[0., 2., 2.]), &mut reliability);
+        assert_close(&reliability, &[2.8, 3.8]);
+        assert_close(g.data(), &[0., ln4, ln4 - 2. / 7. * ln4]);
+    }
+}
@@ -0,0 +1,344 @@
+use std::io::{BufWriter, Write};
+
+use rand::Rng;
+
+use crate::Model;
+use crate::error::{LargedevError, Result};
+use crate::report::PermReport;
+use crate::seed::Seed;
+use crate::hooks::CancelToken;
+
+/// A trait for models, which can be grown step by step, e.g., polymers, which are
+/// grown monomer by monomer. It is needed by `Perm`.
+pub trait Growable: Model {
+    /// return to the initial configuration, e.g., a single monomer
+    fn reset(&mut self);
+
+    /// Write the weights of all possible next steps to `weights`, which is empty when
+    /// this is called, e.g., `1` for allowed and `0` for forbidden steps of an athermal
+    /// polymer, or the Boltzmann factors of the steps. No positive weight is a dead end.
+    fn candidates(&self, weights: &mut Vec<f64>);
+
+    /// perform the next step `candidate`, an index into the weights of `candidates`
+    fn grow(&mut self, candidate: usize);
+
+    /// undo the last `grow`
+    fn shrink(&mut self);
+}
+
+/// Parameters of the pruned-enriched Rosenbluth method, see `Perm` for the builder.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PermConfig {
+    /// number of steps of the grown configurations
+    pub length: usize,
+    /// number of tours, i.e., of starts from the initial configuration
+    pub tours: usize,
+    /// configurations, whose weight exceeds `enrich` times the estimate of the
+    /// partition function, are copied
+    pub enrich: f64,
+    /// configurations, whose weight is below `prune` times the estimate of the
+    /// partition function, are removed with the probability `1/2`
+    pub prune: f64,
+    /// seed for `run_seeded`
+    pub seed: Option<Seed>,
+}
+
+impl Default for PermConfig {
+    fn default() -> Self {
+        PermConfig {
+            length: 100,
+            tours: 1,
+            enrich: 3.,
+            prune: 1. / 3.,
+            seed: None,
+        }
+    }
+}
+
+impl PermConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.length == 0 {
+            return Err(LargedevError::InvalidParameter("length must be positive".into()))
+        }
+        if self.tours == 0 {
+            return Err(LargedevError::InvalidParameter("tours must be positive".into()))
+        }
+        if self.enrich.is_nan() || self.enrich <= 1. {
+            return Err(LargedevError::InvalidParameter(format!("enrich must be larger than 1, not {}", self.enrich)))
+        }
+        if !(0. ..1.).contains(&self.prune) {
+            return Err(LargedevError::InvalidParameter(format!("prune must be in [0, 1), not {}", self.prune)))
+        }
+        Ok(())
+    }
+}
+
+/// A builder used to set up the pruned-enriched Rosenbluth method (PERM) on some model,
+/// which implements the `Growable` trait. Configurations are grown step by step, every
+/// step is chosen proportional to the weights of the candidates and the weight of the
+/// configuration is multiplied by their sum, like in Rosenbluth sampling. To prevent
+/// the weights from spreading over many orders of magnitude, configurations with a
+/// high weight are enriched, i.e., copied, and configurations with a low weight are
+/// pruned, see Grassberger, Phys. Rev. E 56, 3682 (1997). The growth is depth first,
+/// such that only one configuration is stored at a time, e.g.:
+///
+/// ```ignore
+/// let report = Perm::new(model)
+///    .length(1000)
+///    .tours(10000)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// println!("ln Z = {}", report.ln_z[1000]);
+/// ```
+///
+/// For every configuration, which reaches the full `length`, the output has a line
+/// with the logarithm of its weight, followed by its record. Averages are weighted with
+/// these weights. The report contains the estimates of the partition function at every
+/// length, i.e., the average weight per tour. Without enrichment and pruning, i.e.,
+/// `enrich = inf` and `prune = 0`, this is plain Rosenbluth sampling.
+pub struct Perm<G> {
+    model: G,
+    config: PermConfig,
+    cancel: Option<CancelToken>,
+}
+
+impl<G: Growable> Perm<G> {
+    pub fn new(model: G) -> Self {
+        Perm::with_config(model, PermConfig::default())
+    }
+
+    pub fn with_config(model: G, config: PermConfig) -> Self {
+        Perm::<G> {
+            model,
+            config,
+            cancel: None,
+        }
+    }
+
+    pub fn length(mut self, length: usize) -> Self {
+        self.config.length = length;
+        self
+    }
+
+    pub fn tours(mut self, tours: usize) -> Self {
+        self.config.tours = tours;
+        self
+    }
+
+    /// the thresholds of enrichment and pruning relative to the estimate of the
+    /// partition function, see `PermConfig`
+    pub fn thresholds(mut self, enrich: f64, prune: f64) -> Self {
+        self.config.enrich = enrich;
+        self.config.prune = prune;
+        self
+    }
+
+    /// the seed used by `run_seeded`, if not set, a seed is drawn from entropy
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Self {
+        self.config.seed = Some(seed.into());
+        self
+    }
+
+    /// a token to cancel the simulation after the current tour, see `CancelToken`
+    pub fn cancel_token(mut self, token: impl Into<CancelToken>) -> Self {
+        self.cancel = Some(token.into());
+        self
+    }
+
+    pub fn model(&self) -> &G {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut G {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> G {
+        self.model
+    }
+
+    /// validate the parameters and return the configured sampler
+    pub fn build(self) -> Result<PermRun<G>> {
+        self.config.validate()?;
+        Ok(PermRun {
+            model: self.model,
+            config: self.config,
+            cancel: self.cancel,
+        })
+    }
+}
+
+/// A configured PERM sampler, see `Perm` for the builder.
+pub struct PermRun<G> {
+    model: G,
+    config: PermConfig,
+    cancel: Option<CancelToken>,
+}
+
+/// A configuration on the stack of the depth first growth, which is continued `copies`
+/// more times with the logarithm of the weight `ln_w`.
+struct Branch {
+    copies: usize,
+    ln_w: f64,
+}
+
+impl<G: Growable> PermRun<G> {
+    pub fn config(&self) -> &PermConfig {
+        &self.config
+    }
+
+    /// the current state of the model
+    pub fn model(&self) -> &G {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut G {
+        &mut self.model
+    }
+
+    /// consume the sampler and return the model in its current state
+    pub fn into_model(self) -> G {
+        self.model
+    }
+
+    pub fn run(&mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<PermReport> {
+        self.simulate(&mut rng, file, None)
+    }
+
+    /// like `run`, but with a random number generator initialized from the configured
+    /// `seed` (or from entropy), which is written to the output and the report
+    pub fn run_seeded(&mut self, file: &mut impl Write) -> Result<PermReport> {
+        let seed = self.config.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate(&mut seed.rng(), file, Some(seed))
+    }
+
+    fn simulate(&mut self, rng: &mut impl Rng, file: &mut impl Write, seed: Option<Seed>) -> Result<PermReport> {
+        // buffer the output, since it is written sample by sample
+        let mut file = BufWriter::new(file);
+
+        if let Some(seed) = seed {
+            writeln!(file, "# seed = {}", seed)?;
+        }
+        writeln!(file, "# ln W, i.e., the logarithm of the weight, followed by")?;
+        writeln!(file, "{}", self.model.header())?;
+
+        let length = self.config.length;
+        let (ln_enrich, ln_prune) = (self.config.enrich.ln(), self.config.prune.ln());
+        // logarithm of the sum of the weights at every length
+        let mut ln_sum = vec![f64::NEG_INFINITY; length + 1];
+        let mut samples = 0;
+        let mut tours = 0;
+        let mut stopped = false;
+
+        // buffers reused to avoid allocations
+        let mut record = String::new();
+        let mut weights = Vec::new();
+        let mut stack: Vec<Branch> = Vec::with_capacity(length);
+
+        trace_span!(_span = info_span!("perm", length, tours = self.config.tours));
+        while tours < self.config.tours {
+            if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                stopped = true;
+                break;
+            }
+            tours += 1;
+            let ln_tours = (tours as f64).ln();
+            self.model.reset();
+
+            // add a configuration of weight `ln_w` at the depth of the stack and, if it
+            // is continued, push it, returns whether it was pushed
+            let mut enter = |model: &G, stack: &mut Vec<Branch>, ln_w: f64, rng: &mut _| -> Result<bool> {
+                let n = stack.len();
+                ln_sum[n] = ln_add(ln_sum[n], ln_w);
+                if n == length {
+                    record.clear();
+                    model.write_record(&mut record)?;
+                    writeln!(file, "{} {}", ln_w, record)?;
+                    samples += 1;
+                    return Ok(false)
+                }
+                let ln_z = ln_sum[n] - ln_tours;
+                let branch = if ln_w > ln_z + ln_enrich {
+                    Branch { copies: 2, ln_w: ln_w - 2f64.ln() }
+                } else if ln_w < ln_z + ln_prune {
+                    if Rng::gen::<bool>(rng) {
+                        return Ok(false)
+                    }
+                    Branch { copies: 1, ln_w: ln_w + 2f64.ln() }
+                } else {
+                    Branch { copies: 1, ln_w }
+                };
+                stack.push(branch);
+                Ok(true)
+            };
+
+            enter(&self.model, &mut stack, 0., rng)?;
+            while let Some(top) = stack.last_mut() {
+                if top.copies == 0 {
+                    stack.pop();
+                    // the initial configuration was not grown
+                    if !stack.is_empty() {
+                        self.model.shrink();
+                    }
+                    continue;
+                }
+                top.copies -= 1;
+                let ln_w = top.ln_w;
+
+                weights.clear();
+                self.model.candidates(&mut weights);
+                let total: f64 = weights.iter().sum();
+                if total <= 0. {
+                    // dead end
+                    continue;
+                }
+                let mut u = rng.gen::<f64>() * total;
+                let candidate = weights.iter()
+                    .position(|&w| {
+                        u -= w;
+                        u < 0.
+                    })
+                    // rounding errors can leave a tiny rest
+                    .unwrap_or_else(|| weights.iter().rposition(|&w| w > 0.).unwrap());
+
+                self.model.grow(candidate);
+                if !enter(&self.model, &mut stack, ln_w + total.ln(), rng)? {
+                    self.model.shrink();
+                }
+            }
+            trace_event!(trace!(tour = tours, samples, "tour"));
+        }
+
+        let ln_tours = (tours as f64).ln();
+        let ln_z: Vec<f64> = ln_sum.iter().map(|s| s - ln_tours).collect();
+        let values: Vec<String> = ln_z.iter().map(|z| z.to_string()).collect();
+        writeln!(file, "# ln Z at the lengths 0 to {}", length)?;
+        writeln!(file, "# {}", values.join(" "))?;
+        file.flush()?;
+        trace_event!(info!(tours, samples, ln_z = ln_z[length], stopped, "finished"));
+
+        Ok(PermReport {
+            ln_z,
+            tours,
+            samples,
+            seed,
+            stopped,
+        })
+    }
+
+    pub fn exec(mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<G> {
+        self.run(&mut rng, file)?;
+        Ok(self.model)
+    }
+}
+
+/// `ln(exp(a) + exp(b))` without overflow
+fn ln_add(a: f64, b: f64) -> f64 {
+    let (hi, lo) = if a > b { (a, b) } else { (b, a) };
+    if lo == f64::NEG_INFINITY {
+        hi
+    } else {
+        hi + (lo - hi).exp().ln_1p()
+    }
+}
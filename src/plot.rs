@@ -0,0 +1,131 @@
+//! Quick-look figures of histograms, densities of states and time series with
+//! `plotters`, e.g., to generate a figure next to the output of every run. The format
+//! is chosen by the extension of the path, `.svg` for SVG and PNG otherwise. It is only
+//! compiled with the `plot` feature.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use plotters::coord::Shift;
+use plotters::prelude::*;
+
+use crate::error::{LargedevError, Result};
+use crate::histogram::Histogram;
+
+const SIZE: (u32, u32) = (800, 600);
+
+fn plot_error(e: impl std::fmt::Display) -> LargedevError {
+    LargedevError::Io(io::Error::other(format!("plotting failed: {}", e)))
+}
+
+/// a line chart of `points` with the given labels
+fn line_chart<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    caption: &str,
+    x_label: &str,
+    y_label: &str,
+    points: &[(f64, f64)],
+) -> Result<()> {
+    let finite = || points.iter().filter(|(x, y)| x.is_finite() && y.is_finite());
+    let (mut x0, mut x1, mut y0, mut y1) = finite().fold(
+        (f64::INFINITY, f64::NEG_INFINITY, f64::INFINITY, f64::NEG_INFINITY),
+        |(x0, x1, y0, y1), &(x, y)| (x0.min(x), x1.max(x), y0.min(y), y1.max(y))
+    );
+    if x0 > x1 {
+        return Err(LargedevError::TooFewSamples)
+    }
+    // avoid empty ranges for constant data
+    if x0 == x1 {
+        x0 -= 0.5;
+        x1 += 0.5;
+    }
+    if y0 == y1 {
+        y0 -= 0.5;
+        y1 += 0.5;
+    }
+    let margin = 0.05 * (y1 - y0);
+
+    root.fill(&WHITE).map_err(plot_error)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 24))
+        .margin(15)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(x0..x1, y0 - margin..y1 + margin)
+        .map_err(plot_error)?;
+    chart.configure_mesh()
+        .x_desc(x_label)
+        .y_desc(y_label)
+        .draw()
+        .map_err(plot_error)?;
+    chart.draw_series(LineSeries::new(finite().cloned(), &BLUE))
+        .map_err(plot_error)?;
+    root.present().map_err(plot_error)?;
+    Ok(())
+}
+
+/// draw a line chart to `path` with the backend chosen by the extension
+fn plot(path: &Path, caption: &str, x_label: &str, y_label: &str, points: &[(f64, f64)]) -> Result<()> {
+    if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("svg")) {
+        line_chart(SVGBackend::new(path, SIZE).into_drawing_area(), caption, x_label, y_label, points)
+    } else {
+        line_chart(BitMapBackend::new(path, SIZE).into_drawing_area(), caption, x_label, y_label, points)
+    }
+}
+
+/// plot the counts of the histogram `h` as steps over the bins
+pub fn histogram(h: &Histogram, path: impl AsRef<Path>) -> Result<()> {
+    let borders = h.borders();
+    let points: Vec<(f64, f64)> = h.data()
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &y)| vec![(borders[i], y), (borders[i + 1], y)])
+        .collect();
+    plot(path.as_ref(), "histogram", "value", "count", &points)
+}
+
+/// plot the logarithmic density of states `g`, e.g., of a Wang-Landau simulation,
+/// at the centers of its bins
+pub fn density_of_states(g: &Histogram, path: impl AsRef<Path>) -> Result<()> {
+    let points: Vec<(f64, f64)> = g.centers().into_iter().zip(g.data().iter().cloned()).collect();
+    plot(path.as_ref(), "density of states", "value", "ln g", &points)
+}
+
+/// Plot the density of states in the output `data` of a Wang-Landau simulation,
+/// i.e., of `WangLandauRun::run` and its parallel variants, e.g.:
+///
+/// ```ignore
+/// let report = WangLandau::new(model)
+///    .build()?
+///    .run(&mut rng, &mut File::create("dos.dat")?)?;
+/// plot::density_of_states_file("dos.dat", "dos.png")?;
+/// ```
+pub fn density_of_states_file(data: impl AsRef<Path>, path: impl AsRef<Path>) -> Result<()> {
+    let content = fs::read_to_string(data)?;
+    let invalid = |msg: &str| LargedevError::Io(io::Error::new(io::ErrorKind::InvalidData, msg.to_string()));
+    let mut lines = content.lines().filter(|l| !l.starts_with('#'));
+    let mut parse = |name: &str| -> Result<Vec<f64>> {
+        let line = lines.next().ok_or_else(|| invalid(&format!("the {} are missing", name)))?;
+        line.split_whitespace()
+            .map(|x| x.parse().map_err(|_| invalid(&format!("the {} contain '{}'", name, x))))
+            .collect()
+    };
+    let borders = parse("borders")?;
+    let data = parse("values")?;
+    if borders.len() != data.len() + 1 {
+        return Err(invalid("the number of borders does not match the number of values"))
+    }
+    let points: Vec<(f64, f64)> = borders.windows(2)
+        .map(|b| (b[0] + b[1]) / 2.)
+        .zip(data)
+        .collect();
+    plot(path.as_ref(), "density of states", "value", "ln g", &points)
+}
+
+/// plot the `values` over their index, e.g., the energy of a Metropolis simulation
+/// over the samples
+pub fn time_series(values: &[f64], path: impl AsRef<Path>) -> Result<()> {
+    let points: Vec<(f64, f64)> = values.iter().enumerate().map(|(t, &y)| (t as f64, y)).collect();
+    plot(path.as_ref(), "time series", "sample", "value", &points)
+}
@@ -0,0 +1,287 @@
+use std::io::{BufWriter, Write};
+use std::ops::ControlFlow;
+
+use rand::Rng;
+
+use crate::markovchain::MarkovChain;
+use crate::error::{LargedevError, Result};
+use crate::report::Report;
+use crate::seed::Seed;
+use crate::hooks::{CancelToken, SweepHook, SweepState};
+use crate::wanglandau::{self, HistogramCriterion, WangLandau, WangLandauConfig, WangLandauRun};
+
+/// Parameters of a Wang-Landau simulation followed by a production run, see
+/// `WangLandauProduction` for the builder.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WangLandauProductionConfig {
+    /// parameters of the Wang-Landau simulation, which estimates the weights
+    pub wang_landau: WangLandauConfig,
+    /// equilibration time of the production run in sweeps
+    pub t_eq: usize,
+    /// how many samples to record in the production run, one per sweep
+    pub iterations: usize,
+}
+
+impl Default for WangLandauProductionConfig {
+    fn default() -> Self {
+        WangLandauProductionConfig {
+            wang_landau: WangLandauConfig::default(),
+            t_eq: 0,
+            iterations: 1,
+        }
+    }
+}
+
+impl WangLandauProductionConfig {
+    pub fn validate(&self) -> Result<()> {
+        self.wang_landau.validate()?;
+        if self.iterations == 0 {
+            return Err(LargedevError::InvalidParameter("iterations must be positive".into()))
+        }
+        Ok(())
+    }
+}
+
+/// A builder used to set up the usual two-stage workflow of flat-histogram methods:
+/// Wang-Landau sampling estimates the logarithm of the density of states `ln g`, which
+/// defines the multicanonical ensemble with the weights `exp(-ln g(E))`. A long
+/// production run samples this ensemble with the fixed weights, i.e., a proper Markov
+/// chain, and records arbitrary observables of the model, e.g.:
+///
+/// ```ignore
+/// let report = WangLandau::new(model)
+///    .range(low, high)
+///    .bins(100)
+///    .sweep(100)
+///    .production(1_000_000)
+///    .t_eq(1000)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+///
+/// The output starts with the density of states like the one of `WangLandauRun::run`,
+/// followed by one line per sample with the value `E` of the model, the logarithm
+/// `ln g(E)` of the reweighting factor to the unbiased ensemble and the record of the
+/// model. An ensemble average at the temperature `T` is `sum_i O_i w_i / sum_i w_i`
+/// with `w_i = exp(ln g(E_i) - E_i / T)`, which is exact, even if `ln g` is only a rough
+/// estimate, as long as the production run covers the relevant values. The sweep hook
+/// is called in both stages, with `lnf = None` in the production run.
+pub struct WangLandauProduction<MC> {
+    model: MC,
+    config: WangLandauProductionConfig,
+    hook: Option<SweepHook<MC>>,
+    cancel: Option<CancelToken>,
+}
+
+impl<MC: MarkovChain> WangLandau<MC> {
+    /// follow the Wang-Landau simulation with a production run of `iterations` samples,
+    /// see `WangLandauProduction`
+    pub fn production(self, iterations: usize) -> WangLandauProduction<MC> {
+        let (model, config, hook, cancel) = self.into_parts();
+        WangLandauProduction {
+            model,
+            config: WangLandauProductionConfig {
+                wang_landau: config,
+                iterations,
+                ..WangLandauProductionConfig::default()
+            },
+            hook,
+            cancel,
+        }
+    }
+}
+
+impl<MC: MarkovChain> WangLandauProduction<MC> {
+    pub fn new(model: MC) -> Self {
+        WangLandauProduction::with_config(model, WangLandauProductionConfig::default())
+    }
+
+    pub fn with_config(model: MC, config: WangLandauProductionConfig) -> Self {
+        WangLandauProduction::<MC> {
+            model,
+            config,
+            hook: None,
+            cancel: None,
+        }
+    }
+
+    /// the energy window to sample, `low` is included, `high` is excluded
+    pub fn range(mut self, low: f64, high: f64) -> Self {
+        self.config.wang_landau.range = Some((low, high));
+        self
+    }
+
+    pub fn bins(mut self, bins: usize) -> Self {
+        self.config.wang_landau.bins = bins;
+        self
+    }
+
+    pub fn sweep(mut self, sweep: usize) -> Self {
+        self.config.wang_landau.sweep = sweep;
+        self
+    }
+
+    pub fn lnf_final(mut self, lnf_final: f64) -> Self {
+        self.config.wang_landau.lnf_final = lnf_final;
+        self
+    }
+
+    /// the exponent of the schedule `t^-alpha` in the second phase, see `WangLandau::alpha`
+    pub fn alpha(mut self, alpha: f64) -> Self {
+        self.config.wang_landau.alpha = alpha;
+        self
+    }
+
+    /// when a stage of the first phase is complete, see `HistogramCriterion`
+    pub fn criterion(mut self, criterion: HistogramCriterion) -> Self {
+        self.config.wang_landau.criterion = criterion;
+        self
+    }
+
+    /// the seed used by `run_seeded`, if not set, a seed is drawn from entropy
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Self {
+        self.config.wang_landau.seed = Some(seed.into());
+        self
+    }
+
+    pub fn t_eq(mut self, t_eq: usize) -> Self {
+        self.config.t_eq = t_eq;
+        self
+    }
+
+    pub fn iterations(mut self, iterations: usize) -> Self {
+        self.config.iterations = iterations;
+        self
+    }
+
+    /// a hook called after every sweep of both stages, see `SweepHook`
+    pub fn on_sweep(mut self, hook: impl FnMut(&MC, &SweepState) -> ControlFlow<()> + 'static) -> Self {
+        self.hook = Some(Box::new(hook));
+        self
+    }
+
+    /// a token to cancel the simulation, see `CancelToken`
+    pub fn cancel_token(mut self, token: impl Into<CancelToken>) -> Self {
+        self.cancel = Some(token.into());
+        self
+    }
+
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// validate the parameters and return the configured sampler
+    pub fn build(self) -> Result<WangLandauProductionRun<MC>> {
+        self.config.validate()?;
+        let wang_landau = WangLandau::from_parts(self.model, self.config.wang_landau.clone(), self.hook, self.cancel);
+        Ok(WangLandauProductionRun {
+            wang_landau: wang_landau.build()?,
+            config: self.config,
+        })
+    }
+}
+
+/// A configured Wang-Landau simulation followed by a production run, see
+/// `WangLandauProduction` for the builder.
+pub struct WangLandauProductionRun<MC> {
+    /// the sampler of the first stage, which also performs the steps of the second
+    wang_landau: WangLandauRun<MC>,
+    config: WangLandauProductionConfig,
+}
+
+impl<MC: MarkovChain> WangLandauProductionRun<MC> {
+    pub fn config(&self) -> &WangLandauProductionConfig {
+        &self.config
+    }
+
+    /// the current state of the model
+    pub fn model(&self) -> &MC {
+        self.wang_landau.model()
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        self.wang_landau.model_mut()
+    }
+
+    /// consume the sampler and return the model in its current state
+    pub fn into_model(self) -> MC {
+        self.wang_landau.into_model()
+    }
+
+    /// Run both stages and write the density of states and the samples of the
+    /// production run, see `WangLandauProduction`. The report accumulates the proposals
+    /// of both stages. If the first stage is stopped, the production run is skipped.
+    pub fn run(&mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<Report> {
+        self.simulate(&mut rng, file, None)
+    }
+
+    /// like `run`, but with a random number generator initialized from the configured
+    /// `seed` (or from entropy), which is written to the output and the report
+    pub fn run_seeded(&mut self, file: &mut impl Write) -> Result<Report> {
+        let seed = self.config.wang_landau.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate(&mut seed.rng(), file, Some(seed))
+    }
+
+    fn simulate(&mut self, mut rng: &mut impl Rng, file: &mut impl Write, seed: Option<Seed>) -> Result<Report> {
+        let mut file = BufWriter::new(file);
+
+        let mut report = Report {
+            seed,
+            ..Report::default()
+        };
+        let g = self.wang_landau.estimate(&mut rng, &mut report)?;
+        wanglandau::write_dos(&mut file, &g, seed, &report.streams)?;
+        if report.stopped {
+            file.flush()?;
+            return Ok(report)
+        }
+
+        writeln!(file, "# production run with the weights exp(-ln g(E)) of the density of states above")?;
+        writeln!(file, "# value E, ln g(E), followed by")?;
+        writeln!(file, "{}", self.wang_landau.model().header())?;
+
+        // buffer for the records, reused to avoid allocations
+        let mut record = String::new();
+        let mut energy = self.wang_landau.model().value();
+
+        trace_span!(_span = info_span!("production", t_eq = self.config.t_eq, iterations = self.config.iterations));
+        for i in 0..self.config.t_eq + self.config.iterations {
+            for _ in 0..self.config.wang_landau.sweep {
+                energy = self.wang_landau.step(&g, energy, &mut rng, &mut report);
+            }
+            trace_event!(trace!(t = i + 1, energy, "sweep"));
+
+            if i >= self.config.t_eq {
+                // the walker never leaves the range of `g`
+                let ln_g = g.at(energy).unwrap();
+                record.clear();
+                self.wang_landau.model().write_record(&mut record)?;
+                writeln!(file, "{} {} {}", energy, ln_g, record)?;
+            }
+
+            let state = SweepState { sweep: i + 1, energy, tries: report.tries, rejects: report.rejects, invalid: report.invalid, lnf: None, g: Some(&g), h: None };
+            if self.wang_landau.call_hook(&state) {
+                report.stopped = true;
+                break;
+            }
+        }
+        trace_event!(info!(tries = report.tries, acceptance = report.acceptance(), stopped = report.stopped, "finished"));
+
+        file.flush()?;
+        Ok(report)
+    }
+
+    pub fn exec(mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<MC> {
+        self.run(&mut rng, file)?;
+        Ok(self.into_model())
+    }
+}
@@ -0,0 +1,260 @@
+use rand::{Error, Rng, RngCore};
+
+use crate::error::{LargedevError, Result};
+use crate::replay;
+
+/// the initial direction numbers `(s, a, m_1 .. m_s)` of the Sobol sequence for the
+/// dimensions 2 to 16, see Joe and Kuo, SIAM J. Sci. Comput. 30, 2635 (2008)
+const SOBOL: [(u32, u32, [u32; 6]); 15] = [
+    (1, 0, [1, 0, 0, 0, 0, 0]),
+    (2, 1, [1, 3, 0, 0, 0, 0]),
+    (3, 1, [1, 3, 1, 0, 0, 0]),
+    (3, 2, [1, 1, 1, 0, 0, 0]),
+    (4, 1, [1, 1, 3, 3, 0, 0]),
+    (4, 4, [1, 3, 5, 13, 0, 0]),
+    (5, 2, [1, 1, 5, 5, 17, 0]),
+    (5, 4, [1, 1, 5, 5, 5, 0]),
+    (5, 7, [1, 1, 7, 11, 19, 0]),
+    (5, 11, [1, 1, 5, 1, 1, 0]),
+    (5, 13, [1, 1, 1, 3, 11, 0]),
+    (5, 14, [1, 3, 5, 5, 31, 0]),
+    (6, 1, [1, 3, 3, 9, 7, 49]),
+    (6, 13, [1, 1, 1, 15, 21, 21]),
+    (6, 16, [1, 3, 1, 13, 27, 49]),
+];
+
+/// number of bits of the points of the Sobol sequence, i.e., it repeats after `2^32`
+/// points
+const BITS: usize = 32;
+
+#[derive(Clone, Debug)]
+enum Sequence {
+    /// the primes, which are the bases of the radical inverses of the dimensions, and
+    /// the shifts modulo one
+    Halton { bases: Vec<u64>, shifts: Vec<f64> },
+    /// the direction numbers of every dimension and the current point, to which the
+    /// digital shift is applied
+    Sobol { directions: Vec<[u32; BITS]>, point: Vec<u32>, shifts: Vec<u32> },
+}
+
+/// A "random number generator", which yields the coordinates of a low-discrepancy
+/// sequence in the unit cube `[0, 1)^d` instead of pseudo-random numbers, for
+/// quasi-Monte Carlo sampling. The `n`-th call of, e.g., `gen::<f64>()` returns the
+/// `n mod d`-th coordinate of the current point, after `d` calls it continues with the
+/// next point. Therefore it can drive the `reconstruct` method of a `DirectSamplable`
+/// model, which draws exactly `d` uniform numbers per sample, e.g., an integrand of `d`
+/// variables. For smooth observables, the error of the mean decreases almost like
+/// `1/N` instead of `1/sqrt(N)` with the number of samples `N`, e.g.:
+///
+/// ```ignore
+/// let mut qrng = QuasiRng::sobol(3)?;
+/// let report = Simple::new(model)
+///    .iterations(1 << 16)
+///    .build()?
+///    .run(&mut qrng, outfile)?;
+/// ```
+///
+/// Numbers with fewer bits than a coordinate, e.g., `gen::<bool>()` or
+/// `gen_range`, are derived from the leading bits of a coordinate and consume it as
+/// well. `gen_range` of integers rejects a coordinate, if it falls into the small
+/// zone, which does not map evenly onto the range, and draws the next one instead. Then
+/// the sample consumes an additional coordinate and the later draws are shifted to the
+/// next dimension. Floating point ranges never reject. Since the points are not independent, the error of the mean can not be
+/// estimated from the variance. Instead, a few independent `randomized` copies of the
+/// sequence give independent estimates, whose spread is the error.
+#[derive(Clone, Debug)]
+pub struct QuasiRng {
+    sequence: Sequence,
+    dimensions: usize,
+    /// index of the current point
+    index: u64,
+    /// the next coordinate of the current point
+    coordinate: usize,
+}
+
+impl QuasiRng {
+    /// the Halton sequence of `dimensions` dimensions, whose `k`-th coordinate is the
+    /// radical inverse of the index in the base of the `k`-th prime, it is often only
+    /// good for a few dimensions
+    pub fn halton(dimensions: usize) -> Result<QuasiRng> {
+        if dimensions == 0 {
+            return Err(LargedevError::InvalidParameter("a sequence needs at least one dimension".into()))
+        }
+        let mut bases = Vec::with_capacity(dimensions);
+        let mut candidate = 2;
+        while bases.len() < dimensions {
+            if bases.iter().take_while(|&&p| p * p <= candidate).all(|&p| candidate % p != 0) {
+                bases.push(candidate);
+            }
+            candidate += 1;
+        }
+        Ok(QuasiRng {
+            sequence: Sequence::Halton { bases, shifts: vec![0.; dimensions] },
+            dimensions,
+            index: 0,
+            coordinate: 0,
+        })
+    }
+
+    /// the Sobol sequence of up to 16 dimensions, after `2^32` points it starts over
+    /// with the first point
+    pub fn sobol(dimensions: usize) -> Result<QuasiRng> {
+        if dimensions == 0 || dimensions > SOBOL.len() + 1 {
+            return Err(LargedevError::InvalidParameter(format!("the Sobol sequence is available for 1 to {} dimensions, not {}", SOBOL.len() + 1, dimensions)))
+        }
+        let mut directions = Vec::with_capacity(dimensions);
+        // the first dimension is the van der Corput sequence in base 2
+        let mut first = [0; BITS];
+        for (i, v) in first.iter_mut().enumerate() {
+            *v = 1 << (BITS - 1 - i);
+        }
+        directions.push(first);
+        for &(s, a, m) in SOBOL.iter().take(dimensions - 1) {
+            let s = s as usize;
+            let mut v = [0u32; BITS];
+            for i in 0..s.min(BITS) {
+                v[i] = m[i] << (BITS - 1 - i);
+            }
+            // the recurrence of the primitive polynomial with the coefficients `a`
+            for i in s..BITS {
+                let mut x = v[i - s] ^ (v[i - s] >> s);
+                for k in 1..s {
+                    if (a >> (s - 1 - k)) & 1 == 1 {
+                        x ^= v[i - k];
+                    }
+                }
+                v[i] = x;
+            }
+            directions.push(v);
+        }
+        Ok(QuasiRng {
+            sequence: Sequence::Sobol { directions, point: vec![0; dimensions], shifts: vec![0; dimensions] },
+            dimensions,
+            index: 0,
+            coordinate: 0,
+        })
+    }
+
+    /// Randomize the sequence with random shifts drawn from `rng`, a shift modulo one
+    /// for Halton and a digital shift, which preserves the structure of the points, for
+    /// Sobol. Independently randomized copies are independent estimates.
+    pub fn randomized(mut self, rng: &mut impl Rng) -> Self {
+        match &mut self.sequence {
+            Sequence::Halton { shifts, .. } => shifts.iter_mut().for_each(|s| *s = rng.gen()),
+            Sequence::Sobol { shifts, .. } => shifts.iter_mut().for_each(|s| *s = rng.gen()),
+        }
+        self
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// the index of the current point, i.e., the number of completed points
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// Skip the remaining coordinates of the current point and continue with the next
+    /// one, e.g., if a sample drew fewer numbers than there are dimensions.
+    pub fn next_point(&mut self) {
+        if self.coordinate > 0 {
+            self.advance();
+        }
+    }
+
+    fn advance(&mut self) {
+        if let Sequence::Sobol { directions, point, .. } = &mut self.sequence {
+            // Gray code order: the next point differs in the direction of the lowest
+            // zero bit of the index
+            let c = (!self.index).trailing_zeros() as usize;
+            if c < BITS {
+                for (x, v) in point.iter_mut().zip(directions.iter()) {
+                    *x ^= v[c];
+                }
+            } else {
+                // all `2^32` points are used, the sequence repeats
+                point.iter_mut().for_each(|x| *x = 0);
+            }
+        }
+        self.index += 1;
+        self.coordinate = 0;
+    }
+
+    /// the next coordinate in `[0, 1)` as a fraction of `2^64`
+    fn next_coordinate(&mut self) -> u64 {
+        let k = self.coordinate;
+        let x = match &self.sequence {
+            Sequence::Halton { bases, shifts } => {
+                let x = radical_inverse(self.index, bases[k]) + shifts[k];
+                let x = if x >= 1. { x - 1. } else { x };
+                // `x < 1` is mapped below `2^64`
+                (x * 2f64.powi(64)).min(u64::MAX as f64) as u64
+            }
+            Sequence::Sobol { point, shifts, .. } => ((point[k] ^ shifts[k]) as u64) << 32,
+        };
+        self.coordinate += 1;
+        if self.coordinate == self.dimensions {
+            self.advance();
+        }
+        x
+    }
+}
+
+/// the digits of `n` in `base` mirrored at the decimal point
+fn radical_inverse(mut n: u64, base: u64) -> f64 {
+    let inverse = 1. / base as f64;
+    let mut scale = inverse;
+    let mut x = 0.;
+    while n > 0 {
+        x += (n % base) as f64 * scale;
+        n /= base;
+        scale *= inverse;
+    }
+    x
+}
+
+impl RngCore for QuasiRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_coordinate() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.next_coordinate()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        replay::fill_bytes_via_u64(self, dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> std::result::Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sobol_repeats_after_all_points() {
+        let mut qrng = QuasiRng::sobol(3).unwrap();
+        let first: Vec<u64> = (0..6).map(|_| qrng.next_u64()).collect();
+
+        // the point with the index `2^32 - 1` has the Gray code `2^31`
+        let mut qrng = QuasiRng::sobol(3).unwrap();
+        qrng.index = (1 << BITS) - 1;
+        if let Sequence::Sobol { directions, point, .. } = &mut qrng.sequence {
+            for (x, v) in point.iter_mut().zip(directions.iter()) {
+                *x = v[BITS - 1];
+            }
+        }
+        for _ in 0..3 {
+            qrng.next_u64();
+        }
+        assert_eq!(qrng.index(), 1 << BITS);
+        let again: Vec<u64> = (0..6).map(|_| qrng.next_u64()).collect();
+        assert_eq!(first, again);
+    }
+}
@@ -0,0 +1,171 @@
+use rand::{Error, RngCore};
+
+/// The outcome of a proposed change of a Markov chain, see `MoveLog`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Outcome {
+    Accepted,
+    Rejected,
+    /// the proposal violated a hard constraint
+    Invalid,
+    /// the proposal did not change the model
+    Unchanged,
+}
+
+impl Outcome {
+    fn from_bits(bits: u8) -> Outcome {
+        match bits & 0b11 {
+            0 => Outcome::Accepted,
+            1 => Outcome::Rejected,
+            2 => Outcome::Invalid,
+            _ => Outcome::Unchanged,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            Outcome::Accepted => 0,
+            Outcome::Rejected => 1,
+            Outcome::Invalid => 2,
+            Outcome::Unchanged => 3,
+        }
+    }
+}
+
+/// A log of a Markov chain simulation, which consists of every random number drawn
+/// during the run and the outcome of every proposal in two bits. It is recorded by
+/// `MetropolisRun::run_recorded` and reproduces the exact trajectory with
+/// `MetropolisRun::replay`, independent of the random number generator of the original
+/// run. A replay with a different implementation of the same model, e.g., one which
+/// recalculates the energy from scratch instead of an incremental update, reveals the
+/// first proposal, at which both disagree, e.g., due to a bug in `undo`. A replay with
+/// additional measurements in the records or a sweep hook measures them exactly on the
+/// original trajectory.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MoveLog {
+    /// the random numbers, every number drawn by the model or the sampler is derived
+    /// from one of them
+    pub(crate) draws: Vec<u64>,
+    /// the outcomes, four per byte
+    outcomes: Vec<u8>,
+    /// number of proposals
+    proposals: usize,
+}
+
+impl MoveLog {
+    pub fn new() -> Self {
+        MoveLog::default()
+    }
+
+    /// number of logged proposals
+    pub fn len(&self) -> usize {
+        self.proposals
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.proposals == 0
+    }
+
+    /// number of logged random numbers
+    pub fn draws(&self) -> usize {
+        self.draws.len()
+    }
+
+    /// the outcome of the `i`-th proposal
+    pub fn outcome(&self, i: usize) -> Option<Outcome> {
+        if i < self.proposals {
+            Some(Outcome::from_bits(self.outcomes[i / 4] >> (2 * (i % 4))))
+        } else {
+            None
+        }
+    }
+
+    /// the outcomes of all proposals in order
+    pub fn outcomes(&self) -> impl Iterator<Item = Outcome> + '_ {
+        (0..self.proposals).map(move |i| self.outcome(i).unwrap())
+    }
+
+    pub(crate) fn push(&mut self, outcome: Outcome) {
+        if self.outcomes.len() * 4 == self.proposals {
+            self.outcomes.push(0);
+        }
+        *self.outcomes.last_mut().unwrap() |= outcome.bits() << (2 * (self.proposals % 4));
+        self.proposals += 1;
+    }
+}
+
+/// Fill `dest` with the big-endian bytes of `rng.next_u64()`, such that the bytes are
+/// derived from the same numbers as everything else, which `Recording` records and
+/// `Replaying` replays. The generators of the crate implement `fill_bytes` with it.
+pub(crate) fn fill_bytes_via_u64<R: RngCore + ?Sized>(rng: &mut R, dest: &mut [u8]) {
+    for chunk in dest.chunks_mut(8) {
+        let bytes = rng.next_u64().to_be_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
+
+/// A random number generator, which forwards the numbers of `rng` and appends them to
+/// `draws`.
+pub(crate) struct Recording<'a, R: ?Sized> {
+    pub(crate) rng: &'a mut R,
+    pub(crate) draws: &'a mut Vec<u64>,
+}
+
+impl<R: RngCore + ?Sized> RngCore for Recording<'_, R> {
+    // all numbers are derived from `next_u64`, such that they can be replayed
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let x = self.rng.next_u64();
+        self.draws.push(x);
+        x
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        fill_bytes_via_u64(self, dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> std::result::Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// A random number generator, which replays the `draws` of a `Recording`. After they
+/// are exhausted, it yields zeros and sets `exhausted`.
+pub(crate) struct Replaying<'a> {
+    pub(crate) draws: &'a [u64],
+    pub(crate) position: usize,
+    pub(crate) exhausted: bool,
+}
+
+impl RngCore for Replaying<'_> {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self.draws.get(self.position) {
+            Some(&x) => {
+                self.position += 1;
+                x
+            }
+            None => {
+                self.exhausted = true;
+                0
+            }
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        fill_bytes_via_u64(self, dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> std::result::Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+use crate::histogram::Histogram;
+use crate::seed::Seed;
+use crate::tempering::LadderChange;
+
+/// Summary of a run of a Markov chain sampler, like `Metropolis` or `WangLandau`.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Report {
+    /// number of proposed changes
+    pub tries: usize,
+    /// number of rejected changes
+    pub rejects: usize,
+    /// number of invalid proposals, which violated a hard constraint
+    pub invalid: usize,
+    /// the seed of the random number generator, if the run was started from a seed
+    pub seed: Option<Seed>,
+    /// whether the run was stopped before completion, e.g., by a hook or a `CancelToken`
+    pub stopped: bool,
+    /// the seeds of the independent random number streams of the walkers, replicas or
+    /// windows of samplers with multiple ones, the `k`-th is `stream(k)` of the master
+    /// seed, empty for samplers with a single stream
+    pub streams: Vec<Seed>,
+    /// the statistics of the phases of samplers with multiple phases, e.g., of the
+    /// three phases of `WangLandau`, in order, empty for samplers without phases
+    pub phases: Vec<PhaseReport>,
+    /// the statistics of the named observables of `Metropolis::observable`, in the
+    /// order of the columns, empty for samplers without them
+    pub observables: Vec<ObservableReport>,
+    /// the temperatures inserted and removed by `ParallelTempering::adapt`, in order,
+    /// empty for other samplers
+    pub ladder: Vec<LadderChange>,
+}
+
+impl Report {
+    /// fraction of accepted changes
+    pub fn acceptance(&self) -> f64 {
+        (self.tries - self.rejects - self.invalid) as f64 / self.tries as f64
+    }
+}
+
+/// Wall time and proposals of one phase of a sampler, see `Report::phases`.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PhaseReport {
+    /// number of the phase, e.g., `3` for the entropic sampling of `WangLandau`
+    pub phase: usize,
+    /// wall time of the phase in seconds, `NaN` on `wasm32-unknown-unknown`, which has
+    /// no clock
+    pub seconds: f64,
+    /// number of sweeps in this phase
+    pub sweeps: usize,
+    /// number of proposed changes in this phase
+    pub tries: usize,
+    /// number of rejected changes in this phase
+    pub rejects: usize,
+    /// number of invalid proposals in this phase
+    pub invalid: usize,
+}
+
+impl PhaseReport {
+    /// fraction of accepted changes in this phase
+    pub fn acceptance(&self) -> f64 {
+        (self.tries - self.rejects - self.invalid) as f64 / self.tries as f64
+    }
+}
+
+/// The statistics of the measurements of a named observable, see `Report::observables`.
+/// All are `NaN` for less than two measurements.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ObservableReport {
+    pub name: String,
+    pub mean: f64,
+    pub variance: f64,
+    /// the standard error of the mean, which assumes uncorrelated measurements, see
+    /// `analysis::blocking` for correlated ones
+    pub error: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Summary of a replay of a `MoveLog`, see `MetropolisRun::replay`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReplayReport {
+    /// the index of the first proposal, whose outcome differs from the log, or which
+    /// is missing from the log or the replay, `None` if the trajectory is reproduced
+    pub diverged: Option<usize>,
+    /// whether the replay needed more random numbers than the log contains
+    pub exhausted: bool,
+    pub report: Report,
+}
+
+/// Summary of a run of `Simple` sampling.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SimpleReport {
+    /// mean of the sampled values
+    pub mean: f64,
+    /// variance of the sampled values
+    pub variance: f64,
+    /// standard error of the mean, i.e., the square root of the sample variance over
+    /// the number of values
+    pub error: f64,
+    /// skewness of the sampled values
+    pub skewness: f64,
+    /// excess kurtosis of the sampled values, which is `0` for a normal distribution
+    pub kurtosis: f64,
+    /// smallest sampled value
+    pub min: f64,
+    /// largest sampled value
+    pub max: f64,
+    /// the histogram of the sampled values, if it was configured
+    pub histogram: Option<Histogram>,
+    /// number of values outside of the range of the histogram
+    pub outside: usize,
+    /// the controlled estimate of the mean, if a control variate was registered
+    pub control: Option<ControlReport>,
+    /// the seed of the random number generator, if the run was started from a seed
+    pub seed: Option<Seed>,
+    /// the seeds of the random number streams of the loops of `run_parallel`, the
+    /// `k`-th is `stream(k)` of the master seed, empty for the other runs
+    pub streams: Vec<Seed>,
+    /// whether the run was stopped before completion, e.g., by a `CancelToken`
+    pub stopped: bool,
+}
+
+/// The estimate of the mean with a control variate, see `Simple::control_variate`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ControlReport {
+    /// the controlled estimate of the mean of the sampled values
+    pub mean: f64,
+    /// its standard error
+    pub error: f64,
+    /// the optimal coefficient of the control variate, estimated from the samples
+    pub beta: f64,
+    /// the fraction `1 - rho^2` of the variance, which remains with the control variate,
+    /// where `rho` is the correlation of the values and the control variate
+    pub reduction: f64,
+}
+
+/// Summary of a run of `Simple` with antithetic pairs, see `SimpleRun::run_antithetic`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AntitheticReport {
+    /// standard error of the mean, estimated from the means of the pairs
+    pub error: f64,
+    /// the variance of the mean of a pair relative to the one of two independent
+    /// samples, values below `1` are an improvement
+    pub reduction: f64,
+    /// number of pairs
+    pub pairs: usize,
+    /// the statistics of all samples, whose `error` neglects the correlation of the pairs
+    pub report: SimpleReport,
+}
+
+/// Summary of a run of `Simple` with importance weights, see `SimpleRun::run_weighted`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WeightedReport {
+    /// weighted mean of the sampled values
+    pub mean: f64,
+    /// weighted variance of the sampled values
+    pub variance: f64,
+    /// the Kish effective sample size `(sum w)^2 / sum w^2` of all samples
+    pub ess: f64,
+    /// the weighted histogram of the sampled values, if it was configured, whose
+    /// counts are in units of the mean weight, i.e., they sum with `outside` to the
+    /// number of samples
+    pub histogram: Option<Histogram>,
+    /// the weighted count of the values outside of the range of the histogram
+    pub outside: f64,
+    /// the Kish effective sample size of every bin of the histogram, if it was
+    /// configured, see `WeightedHistogram::ess`, bins with a small effective sample size
+    /// are unreliable
+    pub histogram_ess: Option<Vec<f64>>,
+    /// the logarithm of the mean weight, i.e., of the ratio of the normalizations
+    pub ln_normalization: f64,
+    /// the seed of the random number generator, if the run was started from a seed
+    pub seed: Option<Seed>,
+    /// whether the run was stopped before completion, e.g., by a `CancelToken`
+    pub stopped: bool,
+}
+
+/// Summary of a run of `Perm`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PermReport {
+    /// the logarithm of the estimated partition function at every length, starting at
+    /// the initial configuration with `0`
+    pub ln_z: Vec<f64>,
+    /// number of completed tours
+    pub tours: usize,
+    /// number of configurations, which reached the full length
+    pub samples: usize,
+    /// the seed of the random number generator, if the run was started from a seed
+    pub seed: Option<Seed>,
+    /// whether the run was stopped before completion, e.g., by a `CancelToken`
+    pub stopped: bool,
+}
+
+/// Summary of a run of `Ams`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AmsReport {
+    /// the estimate of the probability `P(value > threshold)`
+    pub probability: f64,
+    /// its logarithm, which is finite even if the probability underflows
+    pub ln_probability: f64,
+    /// lower bound of the asymptotic 95% confidence interval
+    pub lower: f64,
+    /// upper bound of the asymptotic 95% confidence interval
+    pub upper: f64,
+    /// number of iterations, i.e., of levels
+    pub iterations: usize,
+    /// the seed of the random number generator, if the run was started from a seed
+    pub seed: Option<Seed>,
+    /// whether the run was stopped before the threshold was reached, e.g., by a `CancelToken`
+    pub stopped: bool,
+}
+
+/// Summary of a run of `CrossEntropy`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CrossEntropyReport {
+    /// the importance sampling estimate of the probability `P(value >= threshold)`
+    pub probability: f64,
+    /// its logarithm, which is finite even if the probability underflows
+    pub ln_probability: f64,
+    /// the standard error of the estimate of the probability
+    pub error: f64,
+    /// the final parameters of the sampling distribution
+    pub parameters: Vec<f64>,
+    /// number of iterations, i.e., of levels
+    pub iterations: usize,
+    /// the seed of the random number generator, if the run was started from a seed
+    pub seed: Option<Seed>,
+    /// whether the run was stopped before the threshold was reached, e.g., by a `CancelToken`
+    pub stopped: bool,
+}
+
+/// Summary of a run of `TailPipeline`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TailReport {
+    /// the centers of the bins
+    pub centers: Vec<f64>,
+    /// the mean over the runs of the estimates of `ln P` in every bin, `NaN` where it
+    /// was not estimated
+    pub ln_p: Vec<f64>,
+    /// the standard error of `ln_p`
+    pub error: Vec<f64>,
+    /// the smallest number of bins, in which simple and Wang-Landau sampling were matched
+    pub overlap: usize,
+    /// the proposals of the Wang-Landau simulations of all runs
+    pub report: Report,
+}
+
+/// Summary of a self-test of Wang-Landau sampling, see `WangLandauConfig::self_test`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SelfTestReport {
+    /// the possible energies of the test model
+    pub energies: Vec<f64>,
+    /// the exact logarithm of the density of states at the energies
+    pub exact: Vec<f64>,
+    /// the estimate of the logarithm of the density of states, with the same
+    /// normalization as `exact`
+    pub estimate: Vec<f64>,
+    /// the largest absolute difference of `estimate` and `exact`
+    pub max_deviation: f64,
+    /// the proposals of the Wang-Landau simulation
+    pub report: Report,
+}
@@ -0,0 +1,247 @@
+//! A common interface of all samplers, such that generic code, e.g., a driver of
+//! experiments, can configure, run and report any of them, see `Sampler`.
+
+use std::io::Write;
+
+use rand::RngCore;
+
+use crate::error::Result;
+use crate::ams::{Ams, AmsConfig, AmsRun};
+use crate::crossentropy::{CrossEntropy, CrossEntropyConfig, CrossEntropyRun, Parametric};
+use crate::kernel::{KernelWangLandau, KernelWangLandauConfig, KernelWangLandauRun};
+use crate::markovchain::{MarkovChain, Restorable};
+use crate::perm::{Growable, Perm, PermConfig, PermRun};
+use crate::report::{AmsReport, CrossEntropyReport, PermReport, Report, SimpleReport, TailReport};
+use crate::seed::Seed;
+use crate::simple::{DirectSamplable, Simple, SimpleConfig, SimpleRun};
+use crate::metropolis::{Metropolis, MetropolisConfig, MetropolisRun};
+use crate::wanglandau::{WangLandau, WangLandauConfig, WangLandauRun};
+use crate::shared::{SharedWangLandau, SharedWangLandauConfig, SharedWangLandauRun};
+use crate::production::{WangLandauProduction, WangLandauProductionConfig, WangLandauProductionRun};
+use crate::tail::{TailPipeline, TailPipelineConfig, TailPipelineRun};
+use crate::windows::{WindowedWangLandau, WindowedWangLandauConfig, WindowedWangLandauRun};
+use crate::multicanonical::{Multicanonical, MulticanonicalConfig, MulticanonicalRun};
+use crate::sensemble::{SEnsemble, SEnsembleConfig, SEnsembleRun};
+use crate::tempering::{ParallelTempering, ParallelTemperingConfig, ParallelTemperingRun};
+
+/// A configured sampler, i.e., the `...Run` type of an algorithm, e.g., `MetropolisRun`.
+/// Generic code can set up any algorithm from its configuration with `configure` and
+/// run it through this trait, e.g.:
+///
+/// ```ignore
+/// fn experiment<S: Sampler>(model: S::Model, config: S::Config, file: &mut File) -> Result<S::Report> {
+///     let mut sampler = S::configure(model, config)?;
+///     sampler.run_seeded(file)
+/// }
+/// ```
+///
+/// Hooks, cancel tokens and metrics are only set by the builders, whose `build` returns
+/// the same type. To switch the algorithm at runtime, see `DynSampler`.
+pub trait Sampler {
+    /// the type of the simulated model
+    type Model;
+    /// the parameters of the algorithm, e.g., `MetropolisConfig`
+    type Config;
+    /// the summary of a run, e.g., `Report`
+    type Report;
+
+    /// validate `config` and set up the sampler for `model`, like `with_config`
+    /// followed by `build` of the builder
+    fn configure(model: Self::Model, config: Self::Config) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// the name of the algorithm, e.g., `"Metropolis"`
+    fn name(&self) -> &'static str;
+
+    fn config(&self) -> &Self::Config;
+
+    /// the current state of the model, of the replica at the first temperature for
+    /// `ParallelTempering`
+    fn model(&self) -> &Self::Model;
+
+    /// simulate with the random numbers of `rng` and write the output to `file`
+    fn run(&mut self, rng: &mut dyn RngCore, file: &mut dyn Write) -> Result<Self::Report>;
+
+    /// simulate from the seed of the configuration, or one drawn from entropy, and
+    /// write the output, which starts with the seed, to `file`
+    fn run_seeded(&mut self, file: &mut dyn Write) -> Result<Self::Report>;
+}
+
+/// The summary of a run of any sampler, see `DynSampler`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum SamplerReport {
+    /// the report of a Markov chain sampler, e.g., `Metropolis` or `WangLandau`
+    Markov(Report),
+    Simple(SimpleReport),
+    Perm(PermReport),
+    Ams(AmsReport),
+    CrossEntropy(CrossEntropyReport),
+    Tail(TailReport),
+}
+
+impl SamplerReport {
+    /// the seed of the random number generator, if the run was started from a seed
+    pub fn seed(&self) -> Option<Seed> {
+        match self {
+            SamplerReport::Markov(r) => r.seed,
+            SamplerReport::Simple(r) => r.seed,
+            SamplerReport::Perm(r) => r.seed,
+            SamplerReport::Ams(r) => r.seed,
+            SamplerReport::CrossEntropy(r) => r.seed,
+            SamplerReport::Tail(r) => r.report.seed,
+        }
+    }
+
+    /// whether the run was stopped before completion, e.g., by a `CancelToken`
+    pub fn stopped(&self) -> bool {
+        match self {
+            SamplerReport::Markov(r) => r.stopped,
+            SamplerReport::Simple(r) => r.stopped,
+            SamplerReport::Perm(r) => r.stopped,
+            SamplerReport::Ams(r) => r.stopped,
+            SamplerReport::CrossEntropy(r) => r.stopped,
+            SamplerReport::Tail(r) => r.report.stopped,
+        }
+    }
+}
+
+impl From<Report> for SamplerReport {
+    fn from(report: Report) -> Self {
+        SamplerReport::Markov(report)
+    }
+}
+
+impl From<SimpleReport> for SamplerReport {
+    fn from(report: SimpleReport) -> Self {
+        SamplerReport::Simple(report)
+    }
+}
+
+impl From<PermReport> for SamplerReport {
+    fn from(report: PermReport) -> Self {
+        SamplerReport::Perm(report)
+    }
+}
+
+impl From<AmsReport> for SamplerReport {
+    fn from(report: AmsReport) -> Self {
+        SamplerReport::Ams(report)
+    }
+}
+
+impl From<CrossEntropyReport> for SamplerReport {
+    fn from(report: CrossEntropyReport) -> Self {
+        SamplerReport::CrossEntropy(report)
+    }
+}
+
+impl From<TailReport> for SamplerReport {
+    fn from(report: TailReport) -> Self {
+        SamplerReport::Tail(report)
+    }
+}
+
+/// An object safe version of `Sampler` for models of type `M`, which is implemented for
+/// every `Sampler`, such that the algorithm can be chosen at runtime, e.g.:
+///
+/// ```ignore
+/// let mut sampler: Box<dyn DynSampler<Ising2D>> = match algorithm {
+///     "metropolis" => Box::new(MetropolisRun::configure(model, metropolis)?),
+///     "wang-landau" => Box::new(WangLandauRun::configure(model, wang_landau)?),
+///     _ => return Err(LargedevError::InvalidParameter(format!("unknown algorithm {}", algorithm))),
+/// };
+/// let report = sampler.dyn_run_seeded(&mut file)?;
+/// ```
+pub trait DynSampler<M> {
+    /// see `Sampler::name`
+    fn dyn_name(&self) -> &'static str;
+
+    /// see `Sampler::model`
+    fn dyn_model(&self) -> &M;
+
+    /// see `Sampler::run`
+    fn dyn_run(&mut self, rng: &mut dyn RngCore, file: &mut dyn Write) -> Result<SamplerReport>;
+
+    /// see `Sampler::run_seeded`
+    fn dyn_run_seeded(&mut self, file: &mut dyn Write) -> Result<SamplerReport>;
+}
+
+impl<S> DynSampler<S::Model> for S
+where
+    S: Sampler,
+    S::Report: Into<SamplerReport>,
+{
+    fn dyn_name(&self) -> &'static str {
+        self.name()
+    }
+
+    fn dyn_model(&self) -> &S::Model {
+        self.model()
+    }
+
+    fn dyn_run(&mut self, rng: &mut dyn RngCore, file: &mut dyn Write) -> Result<SamplerReport> {
+        self.run(rng, file).map(Into::into)
+    }
+
+    fn dyn_run_seeded(&mut self, file: &mut dyn Write) -> Result<SamplerReport> {
+        self.run_seeded(file).map(Into::into)
+    }
+}
+
+/// implement `Sampler` for a run type by forwarding to its builder and its methods
+macro_rules! impl_sampler {
+    ([$m:ident: $($bound:tt)+] $run:ident, $builder:ident, $config:ty, $report:ty, $name:literal) => {
+        impl_sampler!([$m: $($bound)+] $run, $builder, $config, $report, $name, |run| $run::model(run));
+    };
+    // with an expression for the model of `run`, if there are multiple ones
+    ([$m:ident: $($bound:tt)+] $run:ident, $builder:ident, $config:ty, $report:ty, $name:literal, |$s:ident| $model:expr) => {
+        impl<$m: $($bound)+> Sampler for $run<$m> {
+            type Model = $m;
+            type Config = $config;
+            type Report = $report;
+
+            fn configure(model: $m, config: $config) -> Result<Self> {
+                $builder::with_config(model, config).build()
+            }
+
+            fn name(&self) -> &'static str {
+                $name
+            }
+
+            fn config(&self) -> &$config {
+                $run::config(self)
+            }
+
+            fn model(&self) -> &$m {
+                let $s = self;
+                $model
+            }
+
+            fn run(&mut self, rng: &mut dyn RngCore, mut file: &mut dyn Write) -> Result<$report> {
+                $run::run(self, rng, &mut file)
+            }
+
+            fn run_seeded(&mut self, mut file: &mut dyn Write) -> Result<$report> {
+                $run::run_seeded(self, &mut file)
+            }
+        }
+    };
+}
+
+impl_sampler!([DS: DirectSamplable] SimpleRun, Simple, SimpleConfig, SimpleReport, "Simple");
+impl_sampler!([MC: MarkovChain] MetropolisRun, Metropolis, MetropolisConfig, Report, "Metropolis");
+impl_sampler!([MC: MarkovChain] WangLandauRun, WangLandau, WangLandauConfig, Report, "WangLandau");
+impl_sampler!([MC: MarkovChain] KernelWangLandauRun, KernelWangLandau, KernelWangLandauConfig, Report, "KernelWangLandau");
+impl_sampler!([MC: MarkovChain] WangLandauProductionRun, WangLandauProduction, WangLandauProductionConfig, Report, "WangLandauProduction");
+impl_sampler!([MC: MarkovChain + Clone + Send] SharedWangLandauRun, SharedWangLandau, SharedWangLandauConfig, Report, "SharedWangLandau");
+impl_sampler!([MC: MarkovChain + Clone + Send] WindowedWangLandauRun, WindowedWangLandau, WindowedWangLandauConfig, Report, "WindowedWangLandau");
+impl_sampler!([MC: MarkovChain + Clone + Send] MulticanonicalRun, Multicanonical, MulticanonicalConfig, Report, "Multicanonical");
+impl_sampler!([MC: MarkovChain + Clone + Send] ParallelTemperingRun, ParallelTempering, ParallelTemperingConfig, Report, "ParallelTempering", |run| run.model(0));
+impl_sampler!([MC: MarkovChain + Restorable] SEnsembleRun, SEnsemble, SEnsembleConfig, Report, "SEnsemble");
+impl_sampler!([M: DirectSamplable + MarkovChain + Clone] TailPipelineRun, TailPipeline, TailPipelineConfig, TailReport, "TailPipeline");
+impl_sampler!([MC: MarkovChain + Restorable + DirectSamplable] AmsRun, Ams, AmsConfig, AmsReport, "Ams");
+impl_sampler!([P: Parametric] CrossEntropyRun, CrossEntropy, CrossEntropyConfig, CrossEntropyReport, "CrossEntropy");
+impl_sampler!([G: Growable] PermRun, Perm, PermConfig, PermReport, "Perm");
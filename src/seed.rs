@@ -0,0 +1,105 @@
+use std::fmt;
+use std::io::{self, Write};
+
+use rand::{RngCore, SeedableRng};
+use rand::rngs::OsRng;
+use rand_pcg::Pcg64;
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+/// The random number generator used by the samplers, if they are given a `Seed`
+/// instead of a generator.
+pub type DefaultRng = Pcg64;
+
+/// A recommended fast random number generator for cheap models, where the generation
+/// of random numbers is a significant part of the run time. It can be passed to
+/// all samplers, e.g., `run(&mut Seed::new(42).fast_rng(), outfile)`.
+pub type FastRng = Xoshiro256PlusPlus;
+
+/// The seed of a random number generator. Use it to make a run reproducible,
+/// e.g., to revisit a specific rare trajectory. The seed used by a sampler is
+/// stored in its report and in the metadata of its output. Samplers with multiple
+/// walkers, replicas or windows derive their independent streams from it with
+/// `stream` and store their seeds as `streams` as well, e.g.:
+///
+/// ```ignore
+/// let report = Metropolis::new(model)
+///    .seed(42)
+///    .build()?
+///    .run_seeded(outfile)?;
+/// assert_eq!(report.seed, Some(Seed::new(42)));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Seed(u64);
+
+impl Seed {
+    pub fn new(seed: u64) -> Seed {
+        Seed(seed)
+    }
+
+    /// draw a fresh seed from the entropy source of the operating system, or of the
+    /// browser on `wasm32-unknown-unknown`
+    pub fn from_entropy() -> Seed {
+        Seed(OsRng.next_u64())
+    }
+
+    /// draw a seed from an existing random number generator
+    pub fn from_rng(rng: &mut (impl RngCore + ?Sized)) -> Seed {
+        Seed(rng.next_u64())
+    }
+
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+
+    /// a new random number generator initialized with this seed
+    pub fn rng(&self) -> DefaultRng {
+        DefaultRng::seed_from_u64(self.0)
+    }
+
+    /// a new fast random number generator initialized with this seed, see `FastRng`
+    pub fn fast_rng(&self) -> FastRng {
+        FastRng::seed_from_u64(self.0)
+    }
+
+    /// Derive the seed of the independent stream `n`, e.g., for the `n`-th of
+    /// multiple parallel components. The derivation is deterministic, such that
+    /// all streams are reproducible from this seed alone.
+    pub fn stream(&self, n: u64) -> Seed {
+        // mix the seed and the stream index with the splitmix64 finalizer, such
+        // that neighboring seeds and indices lead to uncorrelated streams
+        let mut z = splitmix64(self.0) ^ n.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        z = splitmix64(z);
+        Seed(z)
+    }
+}
+
+/// Write the seeds of the independent `streams` of a sampler with multiple ones to the
+/// metadata of the output, if there are any, such that every component can be
+/// reproduced on its own.
+pub(crate) fn write_streams(file: &mut impl Write, streams: &[Seed]) -> io::Result<()> {
+    if !streams.is_empty() {
+        let seeds: Vec<String> = streams.iter().map(|s| s.to_string()).collect();
+        writeln!(file, "# streams = {}", seeds.join(" "))?;
+    }
+    Ok(())
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl From<u64> for Seed {
+    fn from(seed: u64) -> Self {
+        Seed(seed)
+    }
+}
+
+impl fmt::Display for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
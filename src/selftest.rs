@@ -0,0 +1,143 @@
+use std::io::{BufWriter, Write};
+
+use rand::Rng;
+
+use crate::Model;
+use crate::markovchain::MarkovChain;
+use crate::wanglandau::{write_dos, WangLandau, WangLandauConfig};
+use crate::error::Result;
+use crate::report::{Report, SelfTestReport};
+use crate::seed::Seed;
+
+/// linear size of the lattice of the self-test
+const L: usize = 4;
+
+/// the energies of the 4x4 Ising model, `-28` and `28` are not possible
+const ENERGIES: [i64; 15] = [-32, -24, -20, -16, -12, -8, -4, 0, 4, 8, 12, 16, 20, 24, 32];
+
+/// the number of configurations at every energy, which sum to `2^16`
+const DEGENERACIES: [f64; 15] = [2., 32., 64., 424., 1728., 6688., 13568., 20524., 13568., 6688., 1728., 424., 64., 32., 2.];
+
+/// The ferromagnetic Ising model on a periodic `4 x 4` lattice, whose density of states
+/// is known exactly. Since the energies `-28` and `28` are not possible, the `value`
+/// is the index of the energy in `ENERGIES`, such that every bin of width one is
+/// reachable.
+#[derive(Clone, Debug)]
+struct SmallIsing {
+    /// the spins as the bits of the integer, a set bit is a spin up
+    spins: u16,
+    energy: i64,
+    /// the spin flipped by the last `change`
+    last: usize,
+}
+
+impl SmallIsing {
+    fn new() -> Self {
+        SmallIsing {
+            spins: u16::MAX,
+            energy: -2 * (L * L) as i64,
+            last: 0,
+        }
+    }
+
+    fn spin(&self, i: usize) -> i64 {
+        if self.spins & (1 << i) != 0 { 1 } else { -1 }
+    }
+
+    fn flip(&mut self, i: usize) {
+        let (x, y) = (i % L, i / L);
+        let neighbors = self.spin((x + L - 1) % L + y * L)
+            + self.spin((x + 1) % L + y * L)
+            + self.spin(x + (y + L - 1) % L * L)
+            + self.spin(x + (y + 1) % L * L);
+        self.energy += 2 * self.spin(i) * neighbors;
+        self.spins ^= 1 << i;
+    }
+}
+
+impl Model for SmallIsing {
+    fn value(&self) -> f64 {
+        ENERGIES.iter().position(|&e| e == self.energy).expect("the energy must be possible") as f64
+    }
+}
+
+impl MarkovChain for SmallIsing {
+    fn change(&mut self, rng: &mut impl Rng) {
+        self.last = rng.gen_range(0..L * L);
+        self.flip(self.last);
+    }
+
+    fn undo(&mut self) {
+        self.flip(self.last);
+    }
+}
+
+/// `ln sum_i exp(x_i)` without overflow
+fn ln_sum(x: &[f64]) -> f64 {
+    let max = x.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    max + x.iter().map(|v| (v - max).exp()).sum::<f64>().ln()
+}
+
+impl WangLandauConfig {
+    /// Run `WangLandau` with these parameters on the ferromagnetic `4 x 4` Ising model
+    /// with periodic boundaries, whose density of states is known exactly, and compare
+    /// the estimate with the exact one. The `range` and the `bins` are replaced by one
+    /// bin per energy, the other parameters, e.g., `sweep`, `lnf_final` and `criterion`,
+    /// are used as they are. The estimated density of states is written to `file`.
+    /// This verifies that the build and the parameters produce correct densities of
+    /// states, e.g.:
+    ///
+    /// ```ignore
+    /// let config = WangLandauConfig { lnf_final: 1e-6, ..WangLandauConfig::default() };
+    /// let report = config.self_test(&mut rng, &mut std::io::sink())?;
+    /// assert!(report.max_deviation < 0.05);
+    /// ```
+    pub fn self_test(&self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<SelfTestReport> {
+        self.test(&mut rng, file, None)
+    }
+
+    /// like `self_test`, but with a random number generator initialized from the
+    /// configured `seed` (or from entropy), which is written to the output and the report
+    pub fn self_test_seeded(&self, file: &mut impl Write) -> Result<SelfTestReport> {
+        let seed = self.seed.unwrap_or_else(Seed::from_entropy);
+        self.test(&mut seed.rng(), file, Some(seed))
+    }
+
+    fn test(&self, rng: &mut impl Rng, file: &mut impl Write, seed: Option<Seed>) -> Result<SelfTestReport> {
+        let config = WangLandauConfig {
+            range: Some((-0.5, ENERGIES.len() as f64 - 0.5)),
+            bins: ENERGIES.len(),
+            ..self.clone()
+        };
+        let mut run = WangLandau::with_config(SmallIsing::new(), config).build()?;
+        let mut report = Report {
+            seed,
+            ..Report::default()
+        };
+        let g = run.estimate(rng, &mut report)?;
+
+        let mut file = BufWriter::new(file);
+        write_dos(&mut file, &g, seed, &[])?;
+        file.flush()?;
+        let mut estimate = g.data().to_vec();
+
+        // both are normalized to the total number of configurations
+        let exact: Vec<f64> = DEGENERACIES.iter().map(|g| g.ln()).collect();
+        let shift = ln_sum(&exact) - ln_sum(&estimate);
+        for g in &mut estimate {
+            *g += shift;
+        }
+        let max_deviation = estimate.iter()
+            .zip(&exact)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0., f64::max);
+
+        Ok(SelfTestReport {
+            energies: ENERGIES.iter().map(|&e| e as f64).collect(),
+            exact,
+            estimate,
+            max_deviation,
+            report,
+        })
+    }
+}
@@ -0,0 +1,463 @@
+use std::io::{BufWriter, Write};
+use std::ops::ControlFlow;
+
+use rand::Rng;
+
+use crate::markovchain::{MarkovChain, Restorable};
+use crate::error::{LargedevError, Result};
+use crate::acceptance::{AcceptanceRule, MetropolisRule, ln_boltzmann};
+use crate::metropolis::{self, Acceptance};
+use crate::report::Report;
+use crate::seed::Seed;
+use crate::hooks::{CancelToken, SweepHook, SweepState};
+use crate::uniforms::Uniforms;
+
+/// The time-additive observable `A` of a trajectory, which is biased by the field `s`,
+/// see `SEnsemble`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TimeAdditive {
+    /// the sum of the values of the model after every time slice
+    #[default]
+    Value,
+    /// the number of accepted changes, i.e., the dynamical activity
+    Activity,
+}
+
+/// Parameters of a simulation in the s-ensemble, see `SEnsemble` for the builder.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SEnsembleConfig {
+    /// the conjugate field, trajectories are weighted with `exp(-s A)`
+    pub s: f64,
+    /// temperature of the Metropolis dynamics, which generates the trajectories
+    pub temperature: f64,
+    /// the time-additive observable
+    pub observable: TimeAdditive,
+    /// number of time slices of a trajectory
+    pub length: usize,
+    /// how many change moves of the dynamics does one time slice have
+    pub sweep: usize,
+    /// how many trajectories are proposed between two samples
+    pub proposals: usize,
+    /// equilibration time in samples
+    pub t_eq: usize,
+    /// how many trajectories to sample
+    pub iterations: usize,
+    /// seed for `run_seeded`
+    pub seed: Option<Seed>,
+}
+
+impl Default for SEnsembleConfig {
+    fn default() -> Self {
+        SEnsembleConfig {
+            s: 0.,
+            temperature: 1.,
+            observable: TimeAdditive::default(),
+            length: 100,
+            sweep: 1,
+            proposals: 100,
+            t_eq: 0,
+            iterations: 1,
+            seed: None,
+        }
+    }
+}
+
+impl SEnsembleConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !self.s.is_finite() {
+            return Err(LargedevError::InvalidParameter(format!("s must be finite, not {}", self.s)))
+        }
+        if self.temperature.is_nan() || self.temperature <= 0. {
+            return Err(LargedevError::InvalidParameter(format!("temperature must be positive, not {}", self.temperature)))
+        }
+        if self.length == 0 {
+            return Err(LargedevError::InvalidParameter("length must be positive".into()))
+        }
+        if self.sweep == 0 {
+            return Err(LargedevError::InvalidParameter("sweep must be positive".into()))
+        }
+        if self.proposals == 0 {
+            return Err(LargedevError::InvalidParameter("proposals must be positive".into()))
+        }
+        if self.iterations == 0 {
+            return Err(LargedevError::InvalidParameter("iterations must be positive".into()))
+        }
+        Ok(())
+    }
+}
+
+/// A stored trajectory of the dynamics: the configurations at the start and after
+/// every time slice, the seeds of the random numbers, which drove each slice, and the
+/// contribution of each slice to the time-additive observable.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Trajectory<S> {
+    states: Vec<S>,
+    noise: Vec<u64>,
+    contributions: Vec<f64>,
+}
+
+impl<S> Trajectory<S> {
+    /// number of time slices
+    pub fn len(&self) -> usize {
+        self.noise.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.noise.is_empty()
+    }
+
+    /// the `len() + 1` configurations, starting with the initial one
+    pub fn states(&self) -> &[S] {
+        &self.states
+    }
+
+    /// the seed of the random numbers of every time slice
+    pub fn noise(&self) -> &[u64] {
+        &self.noise
+    }
+
+    /// the contribution of every time slice to the observable
+    pub fn contributions(&self) -> &[f64] {
+        &self.contributions
+    }
+
+    /// the time-additive observable `A` of the whole trajectory
+    pub fn observable(&self) -> f64 {
+        self.contributions.iter().sum()
+    }
+}
+
+/// A builder used to sample trajectories of the Metropolis dynamics of a model in the
+/// s-ensemble, i.e., trajectories `x` with the probability `P(x) exp(-s A(x)) / Z(s)`,
+/// where `P` is the probability of the unbiased dynamics started in equilibrium and `A`
+/// a time-additive observable, see `TimeAdditive`. This gives access to the large
+/// deviations of dynamical observables, e.g., via the scaled cumulant generating
+/// function `psi(s) = lim ln Z(s) / length`, e.g.:
+///
+/// ```ignore
+/// let report = SEnsemble::new(model)
+///    .s(0.1)
+///    .temperature(2.)
+///    .length(1000)
+///    .sweep(100)
+///    .iterations(10000)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+///
+/// A trajectory is determined by its initial configuration and the seeds of the
+/// random numbers of its time slices. A proposal either redraws the seed of one slice
+/// and regenerates the trajectory from there on, or moves the initial configuration by
+/// one sweep of the equilibrium dynamics, which needs a reversible dynamics, and
+/// regenerates the whole trajectory. Both are accepted with the probability
+/// `min(1, exp(-s dA))`. For each sample the output has a line with `A`, `s A`, i.e.,
+/// the logarithm of the reweighting factor to the unbiased ensemble, and the record of
+/// the final configuration. Like at different temperatures for `Metropolis`, the
+/// reweighted histograms of `A` at different `s` can be combined to its distribution.
+/// The report counts the proposals of trajectories.
+pub struct SEnsemble<MC> {
+    model: MC,
+    config: SEnsembleConfig,
+    hook: Option<SweepHook<MC>>,
+    cancel: Option<CancelToken>,
+}
+
+impl<MC: MarkovChain + Restorable> SEnsemble<MC> {
+    pub fn new(model: MC) -> Self {
+        SEnsemble::with_config(model, SEnsembleConfig::default())
+    }
+
+    pub fn with_config(model: MC, config: SEnsembleConfig) -> Self {
+        SEnsemble::<MC> {
+            model,
+            config,
+            hook: None,
+            cancel: None,
+        }
+    }
+
+    pub fn s(mut self, s: f64) -> Self {
+        self.config.s = s;
+        self
+    }
+
+    pub fn temperature(mut self, t: f64) -> Self {
+        self.config.temperature = t;
+        self
+    }
+
+    pub fn observable(mut self, observable: TimeAdditive) -> Self {
+        self.config.observable = observable;
+        self
+    }
+
+    pub fn length(mut self, length: usize) -> Self {
+        self.config.length = length;
+        self
+    }
+
+    pub fn sweep(mut self, sweep: usize) -> Self {
+        self.config.sweep = sweep;
+        self
+    }
+
+    pub fn proposals(mut self, proposals: usize) -> Self {
+        self.config.proposals = proposals;
+        self
+    }
+
+    pub fn t_eq(mut self, t_eq: usize) -> Self {
+        self.config.t_eq = t_eq;
+        self
+    }
+
+    pub fn iterations(mut self, iterations: usize) -> Self {
+        self.config.iterations = iterations;
+        self
+    }
+
+    /// the seed used by `run_seeded`, if not set, a seed is drawn from entropy
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Self {
+        self.config.seed = Some(seed.into());
+        self
+    }
+
+    /// a hook called after every sample with the final configuration of the current
+    /// trajectory and its observable as `energy`, see `SweepHook`
+    pub fn on_sweep(mut self, hook: impl FnMut(&MC, &SweepState) -> ControlFlow<()> + 'static) -> Self {
+        self.hook = Some(Box::new(hook));
+        self
+    }
+
+    /// a token to cancel the simulation, see `CancelToken`
+    pub fn cancel_token(mut self, token: impl Into<CancelToken>) -> Self {
+        self.cancel = Some(token.into());
+        self
+    }
+
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// validate the parameters and return the configured sampler
+    pub fn build(self) -> Result<SEnsembleRun<MC>> {
+        self.config.validate()?;
+        Ok(SEnsembleRun {
+            model: self.model,
+            uniforms: Uniforms::new(self.config.sweep),
+            acceptance: Acceptance::new(1./self.config.temperature, 0),
+            config: self.config,
+            trajectory: None,
+            hook: self.hook,
+            cancel: self.cancel,
+        })
+    }
+}
+
+/// A configured sampler of the s-ensemble, see `SEnsemble` for the builder.
+pub struct SEnsembleRun<MC: Restorable> {
+    model: MC,
+    /// random numbers for the acceptance tests of one time slice
+    uniforms: Uniforms,
+    /// acceptance probabilities of the dynamics
+    acceptance: Acceptance,
+    config: SEnsembleConfig,
+    /// the current trajectory, once the simulation started
+    trajectory: Option<Trajectory<MC::Snapshot>>,
+    hook: Option<SweepHook<MC>>,
+    cancel: Option<CancelToken>,
+}
+
+impl<MC: MarkovChain + Restorable> SEnsembleRun<MC> {
+    pub fn config(&self) -> &SEnsembleConfig {
+        &self.config
+    }
+
+    /// the current state of the model
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    /// consume the sampler and return the model in its current state
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// the current trajectory, `None` before the first run
+    pub fn trajectory(&self) -> Option<&Trajectory<MC::Snapshot>> {
+        self.trajectory.as_ref()
+    }
+
+    /// call the hook, if any, and return whether the simulation should stop,
+    /// either because the hook requests it, or because the run was cancelled
+    fn call_hook(&mut self, state: &SweepState) -> bool {
+        if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return true
+        }
+        match &mut self.hook {
+            Some(hook) => hook(&self.model, state).is_break(),
+            None => false,
+        }
+    }
+
+    /// Simulate and write the observable, the logarithm of the reweighting factor and
+    /// the record of the final configuration of the sampled trajectories to `file`.
+    /// The trajectories are continued from the previous run, if any.
+    pub fn run(&mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<Report> {
+        self.simulate(&mut rng, file, None)
+    }
+
+    /// like `run`, but with a random number generator initialized from the configured
+    /// `seed` (or from entropy), which is written to the output and the report
+    pub fn run_seeded(&mut self, file: &mut impl Write) -> Result<Report> {
+        let seed = self.config.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate(&mut seed.rng(), file, Some(seed))
+    }
+
+    /// Perform the time slices `from..` of a trajectory, which starts in the current
+    /// configuration of the model, driven by the seeds `noise[from..]`. The
+    /// configurations after each slice and the contributions to the observable are
+    /// appended to `states` and `contributions`.
+    fn extend(&mut self, noise: &[u64], from: usize, states: &mut Vec<MC::Snapshot>, contributions: &mut Vec<f64>) {
+        let mut energy = self.model.value();
+        for &n in &noise[from..] {
+            // the counts of the dynamics are not reported
+            let mut report = Report::default();
+            self.uniforms.reset();
+            metropolis::sweep(&mut self.model, &mut Seed::new(n).rng(), &mut self.uniforms, self.config.sweep, &self.acceptance, &mut energy, &mut report);
+            contributions.push(match self.config.observable {
+                TimeAdditive::Value => energy,
+                TimeAdditive::Activity => (report.tries - report.rejects - report.invalid) as f64,
+            });
+            states.push(self.model.snapshot());
+        }
+    }
+
+    /// Propose a new trajectory and accept it with the bias `exp(-s dA)`. The proposal
+    /// is counted in `report`.
+    fn step(&mut self, trajectory: &mut Trajectory<MC::Snapshot>, states: &mut Vec<MC::Snapshot>, contributions: &mut Vec<f64>, rng: &mut impl Rng, report: &mut Report) {
+        report.tries += 1;
+        let length = trajectory.len();
+        states.clear();
+        contributions.clear();
+
+        // the slice from which on the trajectory changes, `length` moves the start
+        let k = rng.gen_range(0..=length);
+        let old_noise;
+        let from = if k == length {
+            old_noise = None;
+            self.model.restore(&trajectory.states[0]);
+            let mut energy = self.model.value();
+            // the numbers left over from the last slice stem from its seed
+            self.uniforms.reset();
+            // the counts of the dynamics are not reported
+            metropolis::sweep(&mut self.model, rng, &mut self.uniforms, self.config.sweep, &self.acceptance, &mut energy, &mut Report::default());
+            states.push(self.model.snapshot());
+            0
+        } else {
+            old_noise = Some(trajectory.noise[k]);
+            trajectory.noise[k] = rng.gen();
+            self.model.restore(&trajectory.states[k]);
+            k
+        };
+        self.extend(&trajectory.noise, from, states, contributions);
+
+        let delta: f64 = contributions.iter().sum::<f64>() - trajectory.contributions[from..].iter().sum::<f64>();
+        if MetropolisRule.accept(ln_boltzmann(self.config.s, delta), rng.gen()) {
+            // the initial configuration is only part of `states` if it changed
+            let offset = if old_noise.is_some() { from + 1 } else { from };
+            trajectory.states.truncate(offset);
+            trajectory.states.append(states);
+            trajectory.contributions.truncate(from);
+            trajectory.contributions.append(contributions);
+        } else {
+            if let Some(n) = old_noise {
+                trajectory.noise[k] = n;
+            }
+            report.rejects += 1;
+        }
+    }
+
+    fn simulate(&mut self, mut rng: &mut impl Rng, file: &mut impl Write, seed: Option<Seed>) -> Result<Report> {
+        // buffer the output, since it is written sample by sample
+        let mut file = BufWriter::new(file);
+
+        let mut report = Report {
+            seed,
+            ..Report::default()
+        };
+
+        let mut trajectory = match self.trajectory.take() {
+            Some(trajectory) => trajectory,
+            None => {
+                let noise: Vec<u64> = (0..self.config.length).map(|_| rng.gen()).collect();
+                let mut states = vec![self.model.snapshot()];
+                let mut contributions = Vec::with_capacity(self.config.length);
+                self.extend(&noise, 0, &mut states, &mut contributions);
+                Trajectory { states, noise, contributions }
+            }
+        };
+        // buffers for the proposed parts of the trajectories, reused to avoid allocations
+        let mut states = Vec::with_capacity(self.config.length + 1);
+        let mut contributions = Vec::with_capacity(self.config.length);
+
+        if let Some(seed) = seed {
+            writeln!(file, "# seed = {}", seed)?;
+        }
+        writeln!(file, "# s = {}, length = {}", self.config.s, self.config.length)?;
+        writeln!(file, "# observable A, s A, i.e., the logarithm of the reweighting factor to the unbiased ensemble, followed by")?;
+        writeln!(file, "{}", self.model.header())?;
+
+        // buffer for the records, reused to avoid allocations
+        let mut record = String::new();
+
+        trace_span!(_span = info_span!("s_ensemble", s = self.config.s, length = self.config.length, iterations = self.config.iterations));
+        for i in 0..self.config.t_eq + self.config.iterations {
+            for _ in 0..self.config.proposals {
+                self.step(&mut trajectory, &mut states, &mut contributions, &mut rng, &mut report);
+            }
+            let a = trajectory.observable();
+            trace_event!(trace!(t = i + 1, a, "sample"));
+            // leave the model in the final configuration of the current trajectory
+            self.model.restore(&trajectory.states[self.config.length]);
+
+            if i >= self.config.t_eq {
+                record.clear();
+                self.model.write_record(&mut record)?;
+                writeln!(file, "{} {} {}", a, self.config.s * a, record)?;
+            }
+
+            let state = SweepState { sweep: i + 1, energy: a, tries: report.tries, rejects: report.rejects, invalid: report.invalid, lnf: None, g: None, h: None };
+            if self.call_hook(&state) {
+                report.stopped = true;
+                break;
+            }
+        }
+        self.trajectory = Some(trajectory);
+
+        file.flush()?;
+        trace_event!(info!(tries = report.tries, acceptance = report.acceptance(), stopped = report.stopped, "finished"));
+
+        Ok(report)
+    }
+
+    pub fn exec(mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<MC> {
+        self.run(&mut rng, file)?;
+        Ok(self.model)
+    }
+}
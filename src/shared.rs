@@ -0,0 +1,469 @@
+use std::io::{BufWriter, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+
+use rand::Rng;
+
+use crate::histogram::Histogram;
+use crate::acceptance::{AcceptanceRule, MetropolisRule};
+use crate::markovchain::{MarkovChain, Proposal};
+use crate::error::{LargedevError, Result};
+use crate::report::Report;
+use crate::seed::Seed;
+use crate::hooks::{CancelToken, SweepHook};
+use crate::uniforms::Uniforms;
+use crate::wanglandau::{self, HistogramCriterion, WangLandau, WangLandauConfig};
+
+/// Parameters of a shared-histogram Wang-Landau simulation, see `SharedWangLandau`
+/// for the builder.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SharedWangLandauConfig {
+    /// parameters of the Wang-Landau simulation, which are shared by all walkers
+    pub wang_landau: WangLandauConfig,
+    /// number of walkers, each on its own thread
+    pub walkers: usize,
+}
+
+impl Default for SharedWangLandauConfig {
+    fn default() -> Self {
+        SharedWangLandauConfig {
+            wang_landau: WangLandauConfig::default(),
+            walkers: 1,
+        }
+    }
+}
+
+impl SharedWangLandauConfig {
+    pub fn validate(&self) -> Result<()> {
+        self.wang_landau.validate()?;
+        if !self.wang_landau.masked.is_empty() {
+            return Err(LargedevError::InvalidParameter("masked bins are not supported by SharedWangLandau".into()))
+        }
+        if self.walkers == 0 {
+            return Err(LargedevError::InvalidParameter("at least one walker is needed".into()))
+        }
+        Ok(())
+    }
+}
+
+/// A builder used to set up Wang-Landau sampling with multiple walkers, which update
+/// one shared estimate of the density of states without synchronization. This is not
+/// strictly a Markov chain anymore, but gives quick exploratory estimates, which scale
+/// with the number of cores. Every walker uses an independent random number stream,
+/// derived from a master seed, e.g.:
+///
+/// ```ignore
+/// let report = WangLandau::new(model)
+///    .range(low, high)
+///    .bins(100)
+///    .shared(8)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+///
+/// The refinement parameter follows the `t^-alpha` schedule of `WangLandau`, where `t` counts
+/// the sweeps of all walkers, but the final entropic sampling phase is not performed.
+/// All bins of the range need to be reachable. Sweep hooks are not supported.
+pub struct SharedWangLandau<MC> {
+    model: MC,
+    config: SharedWangLandauConfig,
+    hook: Option<SweepHook<MC>>,
+    cancel: Option<CancelToken>,
+}
+
+impl<MC: MarkovChain> WangLandau<MC> {
+    /// use the shared-histogram variant with `walkers` walkers, see `SharedWangLandau`
+    pub fn shared(self, walkers: usize) -> SharedWangLandau<MC> {
+        let (model, config, hook, cancel) = self.into_parts();
+        SharedWangLandau {
+            model,
+            config: SharedWangLandauConfig { wang_landau: config, walkers },
+            hook,
+            cancel,
+        }
+    }
+}
+
+impl<MC: MarkovChain> SharedWangLandau<MC> {
+    pub fn new(model: MC) -> Self {
+        SharedWangLandau::with_config(model, SharedWangLandauConfig::default())
+    }
+
+    pub fn with_config(model: MC, config: SharedWangLandauConfig) -> Self {
+        SharedWangLandau::<MC> {
+            model,
+            config,
+            hook: None,
+            cancel: None,
+        }
+    }
+
+    /// the energy window to sample, `low` is included, `high` is excluded
+    pub fn range(mut self, low: f64, high: f64) -> Self {
+        self.config.wang_landau.range = Some((low, high));
+        self
+    }
+
+    pub fn bins(mut self, bins: usize) -> Self {
+        self.config.wang_landau.bins = bins;
+        self
+    }
+
+    pub fn sweep(mut self, sweep: usize) -> Self {
+        self.config.wang_landau.sweep = sweep;
+        self
+    }
+
+    pub fn lnf_final(mut self, lnf_final: f64) -> Self {
+        self.config.wang_landau.lnf_final = lnf_final;
+        self
+    }
+
+    /// the exponent of the schedule `t^-alpha` in the second phase, see `WangLandau::alpha`
+    pub fn alpha(mut self, alpha: f64) -> Self {
+        self.config.wang_landau.alpha = alpha;
+        self
+    }
+
+    /// when a stage of the first phase is complete, see `HistogramCriterion`
+    pub fn criterion(mut self, criterion: HistogramCriterion) -> Self {
+        self.config.wang_landau.criterion = criterion;
+        self
+    }
+
+    /// the master seed used by `run_seeded`, if not set, a seed is drawn from entropy
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Self {
+        self.config.wang_landau.seed = Some(seed.into());
+        self
+    }
+
+    pub fn walkers(mut self, walkers: usize) -> Self {
+        self.config.walkers = walkers;
+        self
+    }
+
+    /// a token to cancel the simulation of all walkers, see `CancelToken`
+    pub fn cancel_token(mut self, token: impl Into<CancelToken>) -> Self {
+        self.cancel = Some(token.into());
+        self
+    }
+
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// validate the parameters and return the configured sampler
+    pub fn build(self) -> Result<SharedWangLandauRun<MC>> {
+        self.config.validate()?;
+        if self.hook.is_some() {
+            return Err(LargedevError::InvalidParameter("sweep hooks are not supported by the shared-histogram variant".into()))
+        }
+        Ok(SharedWangLandauRun {
+            model: self.model,
+            config: self.config,
+            cancel: self.cancel,
+        })
+    }
+}
+
+/// A configured shared-histogram Wang-Landau sampler, see `SharedWangLandau` for the builder.
+pub struct SharedWangLandauRun<MC> {
+    /// the initial state, which is copied to every walker
+    model: MC,
+    config: SharedWangLandauConfig,
+    cancel: Option<CancelToken>,
+}
+
+/// A histogram, whose bins can be updated concurrently. The values are stored as the
+/// bits of `f64` in atomic integers.
+struct AtomicHistogram {
+    low: f64,
+    high: f64,
+    /// `bins / (high - low)`, like in `Histogram`
+    scale: f64,
+    bins: Vec<AtomicU64>,
+}
+
+impl AtomicHistogram {
+    fn new(low: f64, high: f64, bins: usize) -> Self {
+        AtomicHistogram {
+            low,
+            high,
+            scale: bins as f64 / (high - low),
+            bins: (0..bins).map(|_| AtomicU64::new(0f64.to_bits())).collect(),
+        }
+    }
+
+    fn index(&self, value: f64) -> Option<usize> {
+        let x = (value - self.low) * self.scale;
+        if x >= 0. && x < self.bins.len() as f64 {
+            Some(x as usize)
+        } else {
+            None
+        }
+    }
+
+    fn get(&self, idx: usize) -> f64 {
+        f64::from_bits(self.bins[idx].load(Ordering::Relaxed))
+    }
+
+    fn add(&self, value: f64, amount: f64) {
+        if let Some(idx) = self.index(value) {
+            // the closure always returns `Some`, so the update can not fail
+            self.bins[idx].fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
+                Some((f64::from_bits(x) + amount).to_bits())
+            }).ok();
+        }
+    }
+
+    fn min(&self) -> f64 {
+        (0..self.bins.len()).map(|i| self.get(i)).fold(f64::INFINITY, f64::min)
+    }
+
+    fn mean(&self) -> f64 {
+        (0..self.bins.len()).map(|i| self.get(i)).sum::<f64>() / self.bins.len() as f64
+    }
+
+    fn reset(&self) {
+        for b in &self.bins {
+            b.store(0f64.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    fn to_histogram(&self) -> Result<Histogram> {
+        let mut g = Histogram::new(self.low, self.high, self.bins.len())?;
+        for i in 0..self.bins.len() {
+            *g.idx(i) = self.get(i);
+        }
+        Ok(g)
+    }
+}
+
+/// number of sweeps of all walkers between the checks of the auxiliary histogram,
+/// like the sweeps between the checks of `WangLandau`
+const CHECK_INTERVAL: usize = 1000;
+
+/// The schedule of the refinement parameter, which is shared by all walkers. The
+/// walkers read the current `lnf` for every update, such that a walker, which was
+/// suspended for a while, does not use an outdated value.
+struct Schedule {
+    /// the bits of the current `lnf`
+    lnf: AtomicU64,
+    /// whether the simulation is still in the first phase, where `lnf` is halved,
+    /// whenever the auxiliary histogram meets the criterion
+    first_phase: AtomicBool,
+    done: AtomicBool,
+    /// number of sweeps of all walkers
+    t: AtomicUsize,
+    /// held by the walker, which checks the auxiliary histogram
+    check: Mutex<()>,
+}
+
+impl Schedule {
+    fn new() -> Self {
+        Schedule {
+            lnf: AtomicU64::new(1f64.to_bits()),
+            first_phase: AtomicBool::new(true),
+            done: AtomicBool::new(false),
+            t: AtomicUsize::new(0),
+            check: Mutex::new(()),
+        }
+    }
+
+    fn lnf(&self) -> f64 {
+        f64::from_bits(self.lnf.load(Ordering::Relaxed))
+    }
+
+    /// Count a finished sweep and update the refinement parameter. In the first phase,
+    /// only the walker, whose sweep completes an interval of `CHECK_INTERVAL` sweeps,
+    /// checks the auxiliary histogram.
+    fn update(&self, h: &AtomicHistogram, config: &WangLandauConfig) {
+        let t = self.t.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.first_phase.load(Ordering::Relaxed) && t.is_multiple_of(CHECK_INTERVAL) {
+            let _check = self.check.lock().expect("a walker panicked");
+            if config.criterion.is_met(h.min(), h.mean()) {
+                h.reset();
+                let lnf = self.lnf() / 2.;
+                self.lnf.store(lnf.to_bits(), Ordering::Relaxed);
+                trace_event!(debug!(lnf, t, "ln f"));
+                if lnf <= config.schedule(t) {
+                    self.first_phase.store(false, Ordering::Relaxed);
+                    trace_event!(info!(t, "begin phase 2"));
+                }
+            }
+        }
+        if !self.first_phase.load(Ordering::Relaxed) {
+            let lnf = config.schedule(t);
+            self.lnf.store(lnf.to_bits(), Ordering::Relaxed);
+            if lnf <= config.lnf_final {
+                self.done.store(true, Ordering::Relaxed);
+                trace_event!(info!(lnf, t, "finished"));
+            }
+        }
+    }
+}
+
+impl<MC: MarkovChain + Clone + Send> SharedWangLandauRun<MC> {
+    pub fn config(&self) -> &SharedWangLandauConfig {
+        &self.config
+    }
+
+    /// the initial state of the model, which is copied to every walker
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// The master seed of the walkers is drawn from `rng`, the density of states is
+    /// written like the one of `WangLandauRun::run`. The report accumulates the
+    /// reports of all walkers. It needs threads, which are not available on,
+    /// e.g., `wasm32-unknown-unknown`.
+    pub fn run(&mut self, rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<Report> {
+        let master = Seed::from_rng(rng);
+        self.simulate(master, file, None)
+    }
+
+    /// like `run`, but with the master seed from the configuration (or from entropy),
+    /// which is written to the output and the report
+    pub fn run_seeded(&mut self, file: &mut impl Write) -> Result<Report> {
+        let seed = self.config.wang_landau.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate(seed, file, Some(seed))
+    }
+
+    fn simulate(&mut self, master: Seed, file: &mut impl Write, seed: Option<Seed>) -> Result<Report> {
+        let mut file = BufWriter::new(file);
+
+        let (g, mut report) = self.estimate(master)?;
+        report.seed = seed;
+        report.streams = (0..self.config.walkers).map(|k| master.stream(k as u64)).collect();
+
+        wanglandau::write_dos(&mut file, &g, seed, &report.streams)?;
+        file.flush()?;
+
+        Ok(report)
+    }
+
+    /// simulate all walkers in parallel and return the shared density of states
+    fn estimate(&self, master: Seed) -> Result<(Histogram, Report)> {
+        let (low, high) = self.config.wang_landau.range.unwrap();
+        let bins = self.config.wang_landau.bins;
+
+        let g = AtomicHistogram::new(low, high, bins);
+        let h = AtomicHistogram::new(low, high, bins);
+        let schedule = Schedule::new();
+
+        trace_span!(_span = info_span!("shared_wang_landau", low, high, bins, walkers = self.config.walkers));
+        let reports: Vec<Report> = thread::scope(|s| {
+            let workers: Vec<_> = (0..self.config.walkers).map(|k| {
+                let model = self.model.clone();
+                let (config, cancel) = (&self.config.wang_landau, self.cancel.as_ref());
+                let (g, h, schedule) = (&g, &h, &schedule);
+                s.spawn(move || walk(model, master.stream(k as u64), config, cancel, g, h, schedule))
+            }).collect();
+
+            workers.into_iter()
+                .map(|w| w.join().expect("a walker panicked"))
+                .collect()
+        });
+
+        let mut report = Report::default();
+        for r in reports {
+            report.tries += r.tries;
+            report.rejects += r.rejects;
+            report.invalid += r.invalid;
+            report.stopped |= r.stopped;
+        }
+
+        Ok((g.to_histogram()?, report))
+    }
+}
+
+/// the loop of a single walker, which updates the shared histograms `g` and `h`
+/// and the shared `schedule` after every sweep
+fn walk<MC: MarkovChain>(
+    mut model: MC,
+    seed: Seed,
+    config: &WangLandauConfig,
+    cancel: Option<&CancelToken>,
+    g: &AtomicHistogram,
+    h: &AtomicHistogram,
+    schedule: &Schedule,
+) -> Report {
+    trace_span!(_span = debug_span!("walker", seed = %seed));
+    let mut rng = seed.rng();
+    let mut uniforms = Uniforms::new(config.sweep);
+    let mut report = Report::default();
+
+    wanglandau::find_start(&mut model, g.low, g.high, &mut rng);
+    let mut energy = model.value();
+
+    loop {
+        if cancel.is_some_and(CancelToken::is_cancelled) {
+            report.stopped = true;
+            break
+        }
+        if schedule.done.load(Ordering::Relaxed) {
+            break
+        }
+
+        for _ in 0..config.sweep {
+            let failed = report.rejects + report.invalid;
+            energy = step(&mut model, g, energy, &mut rng, &mut uniforms, &mut report);
+            g.add(energy, schedule.lnf());
+            let counted = config.criterion.counts_rejections() || report.rejects + report.invalid == failed;
+            if counted && schedule.first_phase.load(Ordering::Relaxed) {
+                h.add(energy, 1.);
+            }
+        }
+
+        schedule.update(h, config);
+    }
+
+    report
+}
+
+/// Propose a change of `model` and accept it according to the current shared estimate
+/// `g` of the density of states. The proposal is counted in `report`, returns the new
+/// energy.
+fn step<MC: MarkovChain>(model: &mut MC, g: &AtomicHistogram, old_e: f64, mut rng: &mut impl Rng, uniforms: &mut Uniforms, report: &mut Report) -> f64 {
+    report.tries += 1;
+    match model.try_change(&mut rng) {
+        Proposal::Valid => (),
+        Proposal::Invalid => {
+            report.invalid += 1;
+            return old_e
+        }
+        Proposal::Unchanged => return old_e,
+    }
+    let new_e = model.value();
+
+    let ln_weight = match (g.index(old_e), g.index(new_e)) {
+        (Some(old), Some(new)) => g.get(old) - g.get(new) + model.ln_proposal_ratio(),
+        _ => f64::NEG_INFINITY,
+    };
+
+    if !MetropolisRule.accept(ln_weight, uniforms.next(rng)) {
+        model.undo();
+        report.rejects += 1;
+        old_e
+    } else {
+        new_e
+    }
+}
@@ -1,9 +1,20 @@
-use std::io::{self, Write};
-use std::fs::File;
+use std::fmt;
+use std::io::{BufWriter, Write};
+use std::ops::ControlFlow;
 
-use rand::Rng;
+use rand::{Rng, RngCore};
+use rand::seq::SliceRandom;
+use rayon::prelude::*;
 
 use crate::Model;
+use crate::histogram::{Histogram, WeightedHistogram};
+#[cfg(feature = "tracing")]
+use crate::histogram::{ASCII_HEIGHT, ASCII_WIDTH};
+use crate::error::{LargedevError, Result};
+use crate::report::{AntitheticReport, ControlReport, SimpleReport, WeightedReport};
+use crate::seed::{self, Seed};
+use crate::hooks::{CancelToken, SampleHook};
+use crate::replay::{fill_bytes_via_u64, Recording};
 
 /// An trait which implements the `reconstruct` method to generate a new uniform sample
 /// of the implementing model.
@@ -11,97 +22,1137 @@ pub trait DirectSamplable: Model {
     fn reconstruct(&mut self, rng: &mut impl Rng);
 }
 
-/// A struct used to perform simple sampling on some model, which implements the
-/// `DirectSamplable` trait. This follows the builder pattern to specify all parameters.
-/// The `run` method executes the sampling, e.g.:
+/// An extension of `DirectSamplable` for models, which are not sampled from the
+/// distribution of interest `p`, but from some other distribution `q`, e.g., a tilted
+/// one, which puts more weight on the tails. Each sample carries the importance weight
+/// `p / q`, which is used by `SimpleRun::run_weighted`.
+pub trait ImportanceWeighted: DirectSamplable {
+    /// the logarithm of the importance weight `p / q` of the current sample, it may be
+    /// shifted by a constant, if the normalization of `p` is unknown
+    fn ln_weight(&self) -> f64;
+}
+
+/// An extension of `DirectSamplable` for models with antithetic samples, which are
+/// needed by `SimpleRun::run_antithetic`. The antithetic partner of a sample is
+/// constructed from the mirrored random numbers `1 - u` of the uniform numbers `u`,
+/// which constructed the sample. For models, whose observables are monotonic in the
+/// random numbers, the values of the partners are negatively correlated, which reduces
+/// the variance of their mean.
+pub trait Antithetic: DirectSamplable {
+    /// Construct the antithetic partner of the current sample. `mirrored` replays the
+    /// mirrored random numbers of the last `reconstruct` in the same order and
+    /// continues with fresh ones, if more are drawn. By default, this is just
+    /// `reconstruct` with `mirrored`, which is correct for models, which draw their
+    /// random numbers with, e.g., `gen::<f64>()`, but can be overwritten, e.g., for
+    /// rejection sampling, which does not draw a fixed number of random numbers.
+    fn reconstruct_antithetic(&mut self, mirrored: &mut impl Rng) {
+        self.reconstruct(mirrored)
+    }
+}
+
+/// An extension of `DirectSamplable` for models, whose samples are determined by a point
+/// of the unit cube `[0, 1)^d` of a fixed dimension `d`, e.g., `d` uncertain parameters
+/// given by the inverse of their cumulative distributions, where `reconstruct` uses a
+/// uniformly random point. It is needed by `SimpleRun::run_latin_hypercube`.
+pub trait UnitCube: DirectSamplable {
+    /// the dimension `d` of the unit cube
+    fn dimensions(&self) -> usize;
+
+    /// set the model to the sample of the point `u`, which has `d` coordinates
+    fn set_point(&mut self, u: &[f64]);
+}
+
+/// Parameters of a simple sampling simulation, see `Simple` for the builder.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SimpleConfig {
+    /// how many values to sample
+    pub iterations: usize,
+    /// whether `run_weighted` resamples the weighted samples to equal weights
+    pub resample: bool,
+    /// lower and upper bound and number of bins of a histogram of the values, which
+    /// `run` fills and returns in the report
+    pub histogram: Option<(f64, f64, usize)>,
+    /// whether `run` writes the record of every sample
+    pub records: bool,
+    /// whether to emit a chart of the histogram as an event of the `tracing` feature,
+    /// see `Simple::verbose`
+    pub verbose: bool,
+    /// seed for `run_seeded`
+    pub seed: Option<Seed>,
+}
+
+impl Default for SimpleConfig {
+    fn default() -> Self {
+        SimpleConfig {
+            iterations: 2,
+            resample: false,
+            histogram: None,
+            records: true,
+            verbose: false,
+            seed: None,
+        }
+    }
+}
+
+impl SimpleConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.iterations < 2 {
+            return Err(LargedevError::InvalidParameter("iterations must be at least 2 to estimate the variance".into()))
+        }
+        if let Some((low, high, bins)) = self.histogram {
+            Histogram::new(low, high, bins)?;
+        }
+        Ok(())
+    }
+}
+
+/// A builder used to set up simple sampling on some model, which implements the
+/// `DirectSamplable` trait. After all parameters are specified, `build` validates them
+/// and returns a `SimpleRun`, whose `run` method executes the sampling, e.g.:
 ///
-/// ```
-/// let (mean, var) = Simple::new(model)
+/// ```ignore
+/// let report = Simple::new(model)
 ///    .iterations(1000)
+///    .build()?
 ///    .run(&mut rng, outfile)?;
 /// ```
 pub struct Simple<DS> {
-    /// file handle of the output file
     model: DS,
-    /// how many values to sample (total number of change moves is (`iterations` + `t_eq`) * `sweep`)
-    iterations: usize,
+    config: SimpleConfig,
+    hook: Option<SampleHook<DS>>,
+    control: Option<ControlVariate<DS>>,
+    cancel: Option<CancelToken>,
 }
 
 impl<DS: DirectSamplable> Simple<DS> {
     pub fn new(model: DS) -> Self {
+        Simple::with_config(model, SimpleConfig::default())
+    }
+
+    pub fn with_config(model: DS, config: SimpleConfig) -> Self {
         Simple::<DS> {
             model,
-            iterations: 1,
+            config,
+            hook: None,
+            control: None,
+            cancel: None,
         }
     }
 
-    pub fn iterations(&mut self, iterations: usize) -> &mut Self {
-        assert!(iterations > 0);
-        self.iterations = iterations;
+    pub fn iterations(mut self, iterations: usize) -> Self {
+        self.config.iterations = iterations;
         self
     }
 
-    pub fn run(&mut self, mut rng: &mut impl Rng, file: &mut File) -> io::Result<(f64, f64)> {
-        let mut mean = Mean::new();
-        // simulate
-        for _ in 0..self.iterations {
-            self.model.reconstruct(&mut rng);
-            let val = self.model.value();
-            mean.update(val);
-            writeln!(file, "{}", self.model.save())?;
+    /// resample the output of `run_weighted` to equal weights, see `SimpleRun::run_weighted`
+    pub fn resample(mut self, resample: bool) -> Self {
+        self.config.resample = resample;
+        self
+    }
+
+    /// Fill a histogram of the values with `bins` bins between `low` and `high`, which
+    /// is returned in the report of `run`. For a large number of samples, storing the
+    /// distribution is much cheaper than the records, which can be switched off with
+    /// `records(false)`.
+    pub fn histogram(mut self, low: f64, high: f64, bins: usize) -> Self {
+        self.config.histogram = Some((low, high, bins));
+        self
+    }
+
+    /// whether `run` writes the record of every sample, `true` by default
+    pub fn records(mut self, records: bool) -> Self {
+        self.config.records = records;
+        self
+    }
+
+    /// Emit a chart of the histogram, if any, with the estimates at the end of the run
+    /// as an `info` event of the `tracing` feature, see `Histogram::to_ascii`, e.g., to
+    /// check a run on a remote machine in its log. Without the feature, the chart can
+    /// be drawn from the histogram of the report.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.config.verbose = verbose;
+        self
+    }
+
+    /// the seed used by `run_seeded`, if not set, a seed is drawn from entropy
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Self {
+        self.config.seed = Some(seed.into());
+        self
+    }
+
+    /// a hook called after every sample, see `SampleHook`
+    pub fn on_sample(mut self, hook: impl FnMut(&DS, f64) -> ControlFlow<()> + 'static) -> Self {
+        self.hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Register a control variate, i.e., a cheap `observable` of the model, whose exact
+    /// `mean` is known and which is correlated with the value. The report contains the
+    /// controlled estimate of the mean of the value `mean(value) - beta (mean(observable)
+    /// - mean)` with the coefficient `beta`, which minimizes its variance, estimated
+    /// on the fly from the covariance of the value and the observable, see
+    /// `ControlReport`. It is not used by `run_weighted`.
+    pub fn control_variate(mut self, observable: impl Fn(&DS) -> f64 + Send + Sync + 'static, mean: f64) -> Self {
+        self.control = Some(ControlVariate { observable: Box::new(observable), mean });
+        self
+    }
+
+    /// a token to cancel the simulation, see `CancelToken`
+    pub fn cancel_token(mut self, token: impl Into<CancelToken>) -> Self {
+        self.cancel = Some(token.into());
+        self
+    }
+
+    pub fn model(&self) -> &DS {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut DS {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> DS {
+        self.model
+    }
+
+    /// validate the parameters and return the configured sampler
+    pub fn build(self) -> Result<SimpleRun<DS>> {
+        self.config.validate()?;
+        if self.control.as_ref().is_some_and(|c| !c.mean.is_finite()) {
+            return Err(LargedevError::InvalidParameter("the mean of the control variate must be finite".into()))
         }
+        Ok(SimpleRun {
+            model: self.model,
+            config: self.config,
+            hook: self.hook,
+            control: self.control,
+            cancel: self.cancel,
+        })
+    }
+}
 
-        let (mean, var) = mean.finalize();
-        Ok((mean, var))
+/// A configured simple sampler, see `Simple` for the builder.
+pub struct SimpleRun<DS> {
+    model: DS,
+    config: SimpleConfig,
+    hook: Option<SampleHook<DS>>,
+    control: Option<ControlVariate<DS>>,
+    cancel: Option<CancelToken>,
+}
+
+/// An observable with a known mean, see `Simple::control_variate`.
+struct ControlVariate<DS> {
+    observable: Box<dyn Fn(&DS) -> f64 + Send + Sync>,
+    mean: f64,
+}
+
+/// The parts of a `SimpleRun`, which are needed to draw samples.
+struct Context<'a, DS> {
+    config: &'a SimpleConfig,
+    hook: Option<&'a mut SampleHook<DS>>,
+    control: Option<&'a ControlVariate<DS>>,
+    cancel: Option<&'a CancelToken>,
+}
+
+impl<DS: DirectSamplable> SimpleRun<DS> {
+    pub fn config(&self) -> &SimpleConfig {
+        &self.config
+    }
+
+    /// the current state of the model
+    pub fn model(&self) -> &DS {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut DS {
+        &mut self.model
+    }
+
+    /// consume the sampler and return the model in its current state
+    pub fn into_model(self) -> DS {
+        self.model
+    }
+
+    /// the model and the parts of the sampler, which are needed by `sample`
+    fn parts(&mut self) -> (&mut DS, Context<'_, DS>) {
+        let context = Context {
+            config: &self.config,
+            hook: self.hook.as_mut(),
+            control: self.control.as_ref(),
+            cancel: self.cancel.as_ref(),
+        };
+        (&mut self.model, context)
+    }
+
+    /// the known mean of the control variate, if any
+    fn control_mean(&self) -> Option<f64> {
+        self.control.as_ref().map(|c| c.mean)
+    }
+
+    pub fn run(&mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<SimpleReport> {
+        self.simulate(&mut rng, file, None)
     }
 
-    pub fn exec(mut self, mut rng: &mut impl Rng, mut file: &mut File) -> io::Result<DS> {
-        self.run(&mut rng, &mut file)?;
+    /// like `run`, but with a random number generator initialized from the configured
+    /// `seed` (or from entropy), which is written to the output and the report
+    pub fn run_seeded(&mut self, file: &mut impl Write) -> Result<SimpleReport> {
+        let seed = self.config.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate(&mut seed.rng(), file, Some(seed))
+    }
+
+    fn simulate(&mut self, rng: &mut impl Rng, file: &mut impl Write, seed: Option<Seed>) -> Result<SimpleReport> {
+        trace_span!(_span = info_span!("simple", iterations = self.config.iterations, records = self.config.records));
+        let mut steps = self.start(file, seed)?;
+        while !steps.step(rng, usize::MAX)? {}
+        steps.finalize()
+    }
+
+    /// Start the sampling step by step instead of in one call of `run`, such that an
+    /// external scheduler can drive it, see `MetropolisRun::init`. The returned
+    /// `SimpleSteps` draws the samples with `step` and writes the histogram, if any,
+    /// and returns the report with `finalize`. The result is the same as the one of
+    /// `run` with the same random numbers.
+    pub fn init<W: Write>(&mut self, file: W) -> Result<SimpleSteps<'_, DS, W>> {
+        self.start(file, None)
+    }
+
+    fn start<W: Write>(&mut self, file: W, seed: Option<Seed>) -> Result<SimpleSteps<'_, DS, W>> {
+        // buffer the output, since it is written sample by sample
+        let mut file = BufWriter::new(file);
+
+        if let Some(seed) = seed {
+            writeln!(file, "# seed = {}", seed)?;
+        }
+
+        Ok(SimpleSteps {
+            partial: Partial::new(&self.config, self.control.is_some()),
+            run: self,
+            file,
+            seed,
+            done: 0,
+        })
+    }
+
+    pub fn exec(mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<DS> {
+        self.run(&mut rng, file)?;
         Ok(self.model)
     }
 }
 
-/// `Mean` enables the calculation of the mean and variance on the fly without the
-/// need to save all encountered values, as necessary for the naive approach.
-/// It offers the `update` method to feed a new value into the mean and the `finalize`
-/// method to obtain the mean and variance of all feeded values.
+/// A simple sampling simulation in progress, see `SimpleRun::init`.
+pub struct SimpleSteps<'a, DS, W: Write> {
+    run: &'a mut SimpleRun<DS>,
+    file: BufWriter<W>,
+    partial: Partial,
+    seed: Option<Seed>,
+    /// number of drawn samples
+    done: usize,
+}
+
+impl<'a, DS: DirectSamplable, W: Write> SimpleSteps<'a, DS, W> {
+    /// Draw up to `n` samples and write their records, if they are configured. Returns
+    /// whether the simulation is finished, i.e., whether all samples are drawn or the
+    /// hook or the `CancelToken` stopped it.
+    pub fn step(&mut self, mut rng: &mut (impl Rng + ?Sized), n: usize) -> Result<bool> {
+        if !self.partial.stopped {
+            let n = n.min(self.run.config.iterations - self.done);
+            let before = self.partial.moments.count;
+            let (model, context) = self.run.parts();
+            sample(model, context, &mut self.partial, n, |model, _| model.reconstruct(&mut rng), &mut self.file)?;
+            self.done += (self.partial.moments.count - before) as usize;
+        }
+        Ok(self.is_finished())
+    }
+
+    /// whether all samples are drawn or the simulation was stopped
+    pub fn is_finished(&self) -> bool {
+        self.partial.stopped || self.done >= self.run.config.iterations
+    }
+
+    /// number of drawn samples
+    pub fn samples(&self) -> usize {
+        self.done
+    }
+
+    /// the current state of the model
+    pub fn model(&self) -> &DS {
+        &self.run.model
+    }
+
+    /// Write the histogram, if any, flush the output and return the report of the
+    /// samples drawn so far, also if the simulation is not finished.
+    pub fn finalize(mut self) -> Result<SimpleReport> {
+        finish(self.partial, &self.run.config, self.run.control_mean(), &mut self.file, self.seed)
+    }
+}
+
+impl<DS: DirectSamplable + Clone + Send> SimpleRun<DS> {
+    /// Sample like `run`, but split the iterations into `threads` independent loops on
+    /// a thread pool, `0` uses one loop per core. Every loop samples a clone of the
+    /// model with a random number generator from its own stream of a master seed, which
+    /// is drawn from `rng`, and the means, variances and histograms of the loops are
+    /// merged. The records are buffered per loop and written in the order of the
+    /// loops, such that the output is reproducible for the same number of threads, but
+    /// for many samples they should be switched off with `records(false)`. The model is
+    /// left in the state of the first loop. The sample hook can not be shared by the
+    /// loops, so it is an error to set one. It needs threads, which are not available
+    /// on, e.g., `wasm32-unknown-unknown`.
+    pub fn run_parallel(&mut self, threads: usize, rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<SimpleReport> {
+        let master = Seed::from_rng(rng);
+        self.simulate_parallel(threads, master, file, None)
+    }
+
+    /// like `run_parallel`, but with the master seed from the configuration (or from
+    /// entropy), which is written to the output and the report
+    pub fn run_parallel_seeded(&mut self, threads: usize, file: &mut impl Write) -> Result<SimpleReport> {
+        let seed = self.config.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate_parallel(threads, seed, file, Some(seed))
+    }
+
+    fn simulate_parallel(&mut self, threads: usize, master: Seed, file: &mut impl Write, seed: Option<Seed>) -> Result<SimpleReport> {
+        if self.hook.is_some() {
+            return Err(LargedevError::InvalidParameter("the sample hook can not be called from multiple threads".into()))
+        }
+        let mut file = BufWriter::new(file);
+
+        if let Some(seed) = seed {
+            writeln!(file, "# seed = {}", seed)?;
+        }
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|e| LargedevError::InvalidParameter(format!("can not create the thread pool: {}", e)))?;
+        let loops = pool.current_num_threads();
+        let iterations = self.config.iterations;
+        let streams: Vec<Seed> = (0..loops).map(|k| master.stream(k as u64)).collect();
+        seed::write_streams(&mut file, &streams)?;
+
+        trace_span!(_span = info_span!("simple_parallel", iterations, loops, records = self.config.records));
+        let (config, control, cancel) = (&self.config, self.control.as_ref(), self.cancel.as_ref());
+        let jobs: Vec<_> = (0..loops).map(|k| (k, self.model.clone())).collect();
+        let results: Vec<Result<(DS, Partial, Vec<u8>)>> = pool.install(|| {
+            jobs.into_par_iter()
+                .map(|(k, mut model)| {
+                    // the remainder is distributed over the first loops
+                    let n = iterations / loops + usize::from(k < iterations % loops);
+                    let mut rng = streams[k].rng();
+                    let mut records = Vec::new();
+                    let context = Context { config, hook: None, control, cancel };
+                    let mut partial = Partial::new(config, control.is_some());
+                    sample(&mut model, context, &mut partial, n, |model, _| model.reconstruct(&mut rng), &mut records)?;
+                    Ok((model, partial, records))
+                })
+                .collect()
+        });
+
+        let mut merged: Option<Partial> = None;
+        for result in results {
+            let (model, partial, records) = result?;
+            file.write_all(&records)?;
+            merged = Some(match merged {
+                None => {
+                    self.model = model;
+                    partial
+                }
+                Some(mut merged) => {
+                    merged.merge(&partial);
+                    merged
+                }
+            });
+        }
+        // the pool has at least one thread
+        let mut report = finish(merged.unwrap(), &self.config, self.control_mean(), &mut file, seed)?;
+        report.streams = streams;
+        Ok(report)
+    }
+}
+
+impl<DS: Antithetic> SimpleRun<DS> {
+    /// Sample like `run`, but in pairs of a sample and its antithetic partner, see
+    /// `Antithetic`. The records, the histogram and the statistics of the report contain
+    /// all samples, but since the partners are not independent, the standard error of
+    /// the mean is estimated from the means of the pairs. The report contains the
+    /// variance reduction, i.e., the ratio of the variance of the mean of a pair and
+    /// the one of two independent samples.
+    pub fn run_antithetic(&mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<AntitheticReport> {
+        self.simulate_antithetic(&mut rng, file, None)
+    }
+
+    /// like `run_antithetic`, but with a random number generator initialized from the
+    /// configured `seed` (or from entropy), which is written to the output and the report
+    pub fn run_antithetic_seeded(&mut self, file: &mut impl Write) -> Result<AntitheticReport> {
+        let seed = self.config.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate_antithetic(&mut seed.rng(), file, Some(seed))
+    }
+
+    fn simulate_antithetic(&mut self, rng: &mut impl Rng, file: &mut impl Write, seed: Option<Seed>) -> Result<AntitheticReport> {
+        // buffer the output, since it is written sample by sample
+        let mut file = BufWriter::new(file);
+
+        if let Some(seed) = seed {
+            writeln!(file, "# seed = {}", seed)?;
+        }
+
+        // only complete pairs are sampled
+        let n = self.config.iterations / 2 * 2;
+        let mut pairs = Moments::new();
+        // the random numbers of the first sample of the current pair and its value
+        let mut numbers = Vec::new();
+        let mut first = 0.;
+
+        trace_span!(_span = info_span!("simple_antithetic", iterations = n, records = self.config.records));
+        let draw = |model: &mut DS, i: usize| {
+            // the odd samples are the partners of the preceding ones
+            if i % 2 == 1 {
+                model.reconstruct_antithetic(&mut Mirror { rng: &mut *rng, numbers: &numbers, position: 0 });
+                pairs.update((first + model.value()) / 2.);
+            } else {
+                numbers.clear();
+                model.reconstruct(&mut Recording { rng: &mut *rng, draws: &mut numbers });
+                first = model.value();
+            }
+        };
+        let control = self.control_mean();
+        let mut partial = Partial::new(&self.config, control.is_some());
+        let (model, context) = self.parts();
+        sample(model, context, &mut partial, n, draw, &mut file)?;
+        let report = finish(partial, &self.config, control, &mut file, seed)?;
+        let count = pairs.count as usize;
+        let pairs = pairs.finalize()?;
+        let reduction = pairs.variance / (report.variance / 2.);
+        trace_event!(info!(reduction, "antithetic"));
+
+        Ok(AntitheticReport {
+            error: pairs.error,
+            reduction,
+            pairs: count,
+            report,
+        })
+    }
+}
+
+/// A random number generator, which replays the mirrored `numbers` of a `Recording`,
+/// i.e., their bitwise complements, such that a uniform `u` becomes `1 - u`, and
+/// continues with fresh numbers from `rng`.
+struct Mirror<'a, R: ?Sized> {
+    rng: &'a mut R,
+    numbers: &'a [u64],
+    position: usize,
+}
+
+impl<R: RngCore + ?Sized> RngCore for Mirror<'_, R> {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self.numbers.get(self.position) {
+            Some(x) => {
+                self.position += 1;
+                !x
+            }
+            None => self.rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        fill_bytes_via_u64(self, dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> std::result::Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl<DS: UnitCube> SimpleRun<DS> {
+    /// Sample like `run`, but with a Latin hypercube of `iterations` points instead of
+    /// independent ones, i.e., every coordinate is stratified into `iterations`
+    /// intervals of equal width, each of which contains exactly one point, with
+    /// independent random permutations of the intervals for the coordinates and a
+    /// uniformly random position within them. This covers the unit cube more evenly
+    /// and reduces the variance of the means of observables, which are dominated by
+    /// additive effects of the coordinates. Since the points are not independent, the
+    /// error of the mean can not be estimated from the variance, so the errors of the
+    /// report, also the one of the control variate, are `NaN`. It can be estimated from
+    /// the spread of the means of independent runs with different seeds.
+    pub fn run_latin_hypercube(&mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<SimpleReport> {
+        self.simulate_latin_hypercube(&mut rng, file, None)
+    }
+
+    /// like `run_latin_hypercube`, but with a random number generator initialized from
+    /// the configured `seed` (or from entropy), which is written to the output and the
+    /// report
+    pub fn run_latin_hypercube_seeded(&mut self, file: &mut impl Write) -> Result<SimpleReport> {
+        let seed = self.config.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate_latin_hypercube(&mut seed.rng(), file, Some(seed))
+    }
+
+    fn simulate_latin_hypercube(&mut self, rng: &mut impl Rng, file: &mut impl Write, seed: Option<Seed>) -> Result<SimpleReport> {
+        // buffer the output, since it is written sample by sample
+        let mut file = BufWriter::new(file);
+
+        if let Some(seed) = seed {
+            writeln!(file, "# seed = {}", seed)?;
+        }
+
+        let n = self.config.iterations;
+        let d = self.model.dimensions();
+        // the interval of every coordinate for every point
+        let permutations: Vec<Vec<usize>> = (0..d)
+            .map(|_| {
+                let mut p: Vec<usize> = (0..n).collect();
+                p.shuffle(rng);
+                p
+            })
+            .collect();
+        // buffer for the point, reused to avoid allocations
+        let mut u = vec![0.; d];
+
+        trace_span!(_span = info_span!("simple_latin_hypercube", iterations = n, dimensions = d, records = self.config.records));
+        let draw = |model: &mut DS, i: usize| {
+            for (x, p) in u.iter_mut().zip(&permutations) {
+                *x = (p[i] as f64 + rng.gen::<f64>()) / n as f64;
+            }
+            model.set_point(&u);
+        };
+        let control = self.control_mean();
+        let mut partial = Partial::new(&self.config, control.is_some());
+        let (model, context) = self.parts();
+        sample(model, context, &mut partial, n, draw, &mut file)?;
+        let mut report = finish(partial, &self.config, control, &mut file, seed)?;
+        // the errors of `finish` assume independent points
+        report.error = f64::NAN;
+        if let Some(control) = &mut report.control {
+            control.error = f64::NAN;
+        }
+        Ok(report)
+    }
+}
+
+impl<DS: ImportanceWeighted> SimpleRun<DS> {
+    /// Sample like `run`, but with the importance weights of the model, see
+    /// `ImportanceWeighted`. The output has a line with the logarithm of the weight and
+    /// the record of every sample, unless the records are switched off. With
+    /// `resample`, the output is instead the result of systematic resampling, i.e.,
+    /// `iterations` records of the samples, where each sample occurs with an expected
+    /// frequency proportional to its weight, such that they can be analyzed like
+    /// unweighted samples of `p`, e.g., in a histogram. The report contains the
+    /// weighted mean and variance, the effective sample size, the logarithm of the mean
+    /// weight, which estimates the ratio of the normalizations of `p` and `q` without
+    /// bias, and the weighted histogram, if it is configured. The statistics are
+    /// calculated on the fly, only the resampling keeps all records.
+    pub fn run_weighted(&mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<WeightedReport> {
+        self.simulate_weighted(&mut rng, file, None)
+    }
+
+    /// like `run_weighted`, but with a random number generator initialized from the
+    /// configured `seed` (or from entropy), which is written to the output and the report
+    pub fn run_weighted_seeded(&mut self, file: &mut impl Write) -> Result<WeightedReport> {
+        let seed = self.config.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate_weighted(&mut seed.rng(), file, Some(seed))
+    }
+
+    fn simulate_weighted(&mut self, mut rng: &mut impl Rng, file: &mut impl Write, seed: Option<Seed>) -> Result<WeightedReport> {
+        // buffer the output, since it is written sample by sample
+        let mut file = BufWriter::new(file);
+
+        if let Some(seed) = seed {
+            writeln!(file, "# seed = {}", seed)?;
+        }
+        if self.config.resample {
+            writeln!(file, "# systematically resampled to equal weights")?;
+            writeln!(file, "{}", self.model.header())?;
+        } else if self.config.records {
+            writeln!(file, "# ln w, i.e., the logarithm of the importance weight, followed by")?;
+            writeln!(file, "{}", self.model.header())?;
+        }
+
+        let mut moments = WeightedMoments::new(self.config.histogram);
+        // the weights and records are only kept for the resampling
+        let mut ln_weights = Vec::new();
+        let mut records = Vec::new();
+        let mut stopped = false;
+        // buffer for the records, reused to avoid allocations
+        let mut record = String::new();
+        trace_span!(_span = info_span!("simple_weighted", iterations = self.config.iterations, resample = self.config.resample));
+        for _ in 0..self.config.iterations {
+            if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                stopped = true;
+                break;
+            }
+            self.model.reconstruct(&mut rng);
+            let ln_w = self.model.ln_weight();
+            let value = self.model.value();
+            moments.update(value, ln_w);
+            if self.config.resample {
+                record.clear();
+                self.model.write_record(&mut record)?;
+                ln_weights.push(ln_w);
+                records.push(record.clone());
+            } else if self.config.records {
+                record.clear();
+                self.model.write_record(&mut record)?;
+                writeln!(file, "{} {}", ln_w, record)?;
+            }
+            // the sample, which stops the run, is kept like in `run`
+            if let Some(hook) = &mut self.hook {
+                if hook(&self.model, value).is_break() {
+                    stopped = true;
+                    break;
+                }
+            }
+        }
+        let n = moments.count;
+        if n < 2 {
+            return Err(LargedevError::TooFewSamples)
+        }
+        if moments.sum == 0. {
+            return Err(LargedevError::InvalidParameter("all importance weights are zero".into()))
+        }
+
+        let (mean, variance) = (moments.mean, moments.m2 / moments.sum);
+        let ess = moments.sum * moments.sum / moments.sum2;
+        let ln_normalization = moments.shift + (moments.sum / n as f64).ln();
+
+        if self.config.resample {
+            // systematic resampling: one uniform offset for `n` equidistant points in
+            // the cumulative distribution of the weights
+            let offset: f64 = rng.gen();
+            let mut cumulative = 0.;
+            let mut k = 0;
+            for (ln_w, record) in ln_weights.iter().zip(&records) {
+                cumulative += (ln_w - moments.shift).exp() / moments.sum * n as f64;
+                while k < n && (k as f64 + offset) < cumulative {
+                    writeln!(file, "{}", record)?;
+                    k += 1;
+                }
+            }
+            // rounding errors can leave the last points beyond the cumulative sum
+            if let Some(record) = records.last() {
+                for _ in k..n {
+                    writeln!(file, "{}", record)?;
+                }
+            }
+        }
+
+        // the weighted counts in units of the mean weight, such that they sum to the
+        // number of samples
+        let scale = n as f64 / moments.sum;
+        let histogram_ess = moments.histogram.as_ref().map(WeightedHistogram::ess);
+        let mut histogram = moments.histogram.map(|h| h.sums().clone());
+        if let Some(histogram) = &mut histogram {
+            for i in 0..histogram.bins() {
+                *histogram.idx(i) *= scale;
+            }
+        }
+        let outside = moments.outside * scale;
+        if let Some(histogram) = &histogram {
+            write_histogram(&mut file, histogram, outside, self.config.records || self.config.resample)?;
+        }
+
+        file.flush()?;
+        trace_event!(info!(mean, variance, ess, ln_normalization, stopped, "finished"));
+
+        Ok(WeightedReport {
+            mean,
+            variance,
+            ess,
+            histogram,
+            outside,
+            histogram_ess,
+            ln_normalization,
+            seed,
+            stopped,
+        })
+    }
+}
+
+/// The accumulated statistics of a part of a simple sampling simulation.
+struct Partial {
+    moments: Moments,
+    /// the joint moments of the value and the control variate, if any
+    control: Option<CoMoments>,
+    histogram: Option<Histogram>,
+    /// number of values outside of the histogram
+    outside: usize,
+    stopped: bool,
+}
+
+impl Partial {
+    /// empty statistics for the histogram of `config` and the joint moments with the
+    /// control variate, if there is one
+    fn new(config: &SimpleConfig, control: bool) -> Self {
+        Partial {
+            moments: Moments::new(),
+            control: if control { Some(CoMoments::new()) } else { None },
+            // `validate` ensures that the histogram is constructable
+            histogram: config.histogram.map(|(low, high, bins)| Histogram::new(low, high, bins).unwrap()),
+            outside: 0,
+            stopped: false,
+        }
+    }
+
+    /// add the statistics of `other`, whose histogram has the same bins
+    fn merge(&mut self, other: &Partial) {
+        self.moments.merge(&other.moments);
+        if let (Some(control), Some(other)) = (&mut self.control, &other.control) {
+            control.merge(other);
+        }
+        if let (Some(histogram), Some(other)) = (&mut self.histogram, &other.histogram) {
+            for (i, &count) in other.data().iter().enumerate() {
+                *histogram.idx(i) += count;
+            }
+        }
+        self.outside += other.outside;
+        self.stopped |= other.stopped;
+    }
+}
+
+/// Draw `iterations` samples of `model`, where `draw(model, i)` generates the `i`-th, add
+/// them to `partial` and write their records to `file`, if they are configured.
+fn sample<DS: DirectSamplable>(
+    model: &mut DS,
+    context: Context<'_, DS>,
+    partial: &mut Partial,
+    iterations: usize,
+    mut draw: impl FnMut(&mut DS, usize),
+    file: &mut impl Write,
+) -> Result<()> {
+    let Context { config, mut hook, control, cancel } = context;
+    // buffer for the records, reused to avoid allocations
+    let mut record = String::new();
+    for i in 0..iterations {
+        if cancel.is_some_and(CancelToken::is_cancelled) {
+            partial.stopped = true;
+            break;
+        }
+        draw(model, i);
+        let val = model.value();
+        partial.moments.update(val);
+        if let (Some(moments), Some(control)) = (&mut partial.control, control) {
+            moments.update(val, (control.observable)(model));
+        }
+        if let Some(histogram) = &mut partial.histogram {
+            match histogram.index_of(val) {
+                Some(idx) => *histogram.idx(idx) += 1.,
+                None => partial.outside += 1,
+            }
+        }
+        if config.records {
+            record.clear();
+            model.write_record(&mut record)?;
+            writeln!(file, "{}", record)?;
+        }
+        if let Some(hook) = &mut hook {
+            if hook(model, val).is_break() {
+                partial.stopped = true;
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// write the histogram of `partial` to `file` and summarize it in the report, with the
+/// known mean of the control variate, if any
+fn finish(partial: Partial, config: &SimpleConfig, control_mean: Option<f64>, file: &mut impl Write, seed: Option<Seed>) -> Result<SimpleReport> {
+    let summary = partial.moments.finalize()?;
+    let control = match (&partial.control, control_mean) {
+        (Some(moments), Some(mean)) => Some(moments.finalize(mean)?),
+        _ => None,
+    };
+    if let Some(histogram) = &partial.histogram {
+        write_histogram(file, histogram, partial.outside, config.records)?;
+    }
+    file.flush()?;
+    let stopped = partial.stopped;
+    #[cfg(feature = "tracing")]
+    if config.verbose {
+        if let Some(histogram) = &partial.histogram {
+            trace_event!(info!(mean = summary.mean, error = summary.error, variance = summary.variance, "histogram\n{}", histogram.to_ascii(ASCII_WIDTH, ASCII_HEIGHT)));
+        }
+    }
+    trace_event!(info!(mean = summary.mean, variance = summary.variance, stopped, "finished"));
+
+    Ok(SimpleReport {
+        mean: summary.mean,
+        variance: summary.variance,
+        error: summary.error,
+        skewness: summary.skewness,
+        kurtosis: summary.kurtosis,
+        min: summary.min,
+        max: summary.max,
+        histogram: partial.histogram,
+        outside: partial.outside,
+        control,
+        seed,
+        streams: Vec::new(),
+        stopped,
+    })
+}
+
+/// Write the `histogram` after the `records` as a comment, such that they can still be
+/// parsed, or as the only content of the output without the records.
+fn write_histogram(file: &mut impl Write, histogram: &Histogram, outside: impl fmt::Display, records: bool) -> Result<()> {
+    let prefix = if records { "# " } else { "" };
+    writeln!(file, "# histogram of the values: lower border, upper border, count")?;
+    let borders = histogram.borders();
+    for (i, count) in histogram.data().iter().enumerate() {
+        writeln!(file, "{}{} {} {}", prefix, borders[i], borders[i + 1], count)?;
+    }
+    writeln!(file, "# {} values outside of the histogram", outside)?;
+    Ok(())
+}
+
+/// `Moments` enables the calculation of the mean, variance and higher moments on the
+/// fly without the need to save all encountered values, as necessary for the naive
+/// approach. It offers the `update` method to feed a new value into the moments, the
+/// `merge` method to combine the moments of two parts of the values and the `finalize`
+/// method to obtain the statistics of all feeded values.
 #[derive(Clone, Debug)]
-struct Mean {
+pub(crate) struct Moments {
     count: u64,
     mean: f64,
+    /// the sums of the second, third and fourth power of the distances from the mean
     m2: f64,
+    m3: f64,
+    m4: f64,
+    min: f64,
+    max: f64,
+}
+
+/// the statistics of the values fed into `Moments`
+pub(crate) struct Summary {
+    pub(crate) mean: f64,
+    pub(crate) variance: f64,
+    pub(crate) error: f64,
+    pub(crate) skewness: f64,
+    pub(crate) kurtosis: f64,
+    pub(crate) min: f64,
+    pub(crate) max: f64,
 }
 
-impl Mean {
-    fn new() -> Mean {
-        Mean {
+impl Moments {
+    pub(crate) fn new() -> Moments {
+        Moments {
             count: 0,
             mean: 0.,
             m2: 0.,
+            m3: 0.,
+            m4: 0.,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
         }
     }
 
-    // https://en.wikipedia.org/wiki/Algorithms_for_calculating_variance#Welford's_online_algorithm
+    // https://en.wikipedia.org/wiki/Algorithms_for_calculating_variance#Higher-order_statistics
 
-    // For a new value newValue, compute the new count, new mean, the new M2.
-    // mean accumulates the mean of the entire dataset
-    // M2 aggregates the squared distance from the mean
-    // count aggregates the number of samples seen so far
-    fn update(&mut self, new_value: f64) {
+    // For a new value newValue, compute the new count, new mean and the new sums of
+    // the powers of the distances from the mean, which extends Welford's algorithm
+    // for the variance. The higher sums need the old values of the lower ones.
+    pub(crate) fn update(&mut self, new_value: f64) {
+        let n1 = self.count as f64;
         self.count += 1;
+        let n = self.count as f64;
         let delta = new_value - self.mean;
-        self.mean += delta / self.count as f64;
-        let delta2 = new_value - self.mean;
-        self.m2 += delta * delta2;
+        let delta_n = delta / n;
+        let delta_n2 = delta_n * delta_n;
+        let term = delta * delta_n * n1;
+        self.mean += delta_n;
+        self.m4 += term * delta_n2 * (n * n - 3. * n + 3.) + 6. * delta_n2 * self.m2 - 4. * delta_n * self.m3;
+        self.m3 += term * delta_n * (n - 2.) - 3. * delta_n * self.m2;
+        self.m2 += term;
+        self.min = self.min.min(new_value);
+        self.max = self.max.max(new_value);
     }
 
-    /// Retrieve the mean, variance and sample variance from an aggregate
-    fn finalize(&self) -> (f64, f64) {
-        let (mean, variance, _sample_variance) = (self.mean, self.m2 / self.count as f64, self.m2 / (self.count - 1) as f64);
+    /// add the values of `other`, see Pébay, Formulas for Robust, One-Pass Parallel
+    /// Computation of Covariances and Arbitrary-Order Statistical Moments (2008)
+    fn merge(&mut self, other: &Moments) {
+        let count = self.count + other.count;
+        if count == 0 {
+            return
+        }
+        let (na, nb, n) = (self.count as f64, other.count as f64, count as f64);
+        let delta = other.mean - self.mean;
+        let delta2 = delta * delta;
+        self.m4 += other.m4
+            + delta2 * delta2 * na * nb * (na * na - na * nb + nb * nb) / (n * n * n)
+            + 6. * delta2 * (na * na * other.m2 + nb * nb * self.m2) / (n * n)
+            + 4. * delta * (na * other.m3 - nb * self.m3) / n;
+        self.m3 += other.m3
+            + delta2 * delta * na * nb * (na - nb) / (n * n)
+            + 3. * delta * (na * other.m2 - nb * self.m2) / n;
+        self.m2 += other.m2 + delta2 * na * nb / n;
+        self.mean += delta * nb / n;
+        self.count = count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Retrieve the statistics from an aggregate, the variance is the one of the values,
+    /// the standard error of the mean is estimated with the sample variance
+    pub(crate) fn finalize(&self) -> Result<Summary> {
         if self.count < 2 {
-            panic!("too few samples")
+            return Err(LargedevError::TooFewSamples)
+        }
+        let n = self.count as f64;
+        let sample_variance = self.m2 / (n - 1.);
+
+        Ok(Summary {
+            mean: self.mean,
+            variance: self.m2 / n,
+            error: (sample_variance / n).sqrt(),
+            skewness: n.sqrt() * self.m3 / self.m2.powf(1.5),
+            kurtosis: n * self.m4 / (self.m2 * self.m2) - 3.,
+            min: self.min,
+            max: self.max,
+        })
+    }
+}
+
+/// The weighted mean, variance and histogram of values with the logarithms of their
+/// weights, calculated on the fly. All weights are stored relative to the largest one
+/// seen so far, to avoid overflows, and the accumulated sums are rescaled, whenever it
+/// changes.
+#[derive(Clone, Debug)]
+struct WeightedMoments {
+    count: u64,
+    /// the logarithm of the largest weight, relative to which the weights are stored
+    shift: f64,
+    /// the sums of the weights and of their squares
+    sum: f64,
+    sum2: f64,
+    mean: f64,
+    /// the weighted sum of the squared distances from the mean
+    m2: f64,
+    histogram: Option<WeightedHistogram>,
+    /// the sum of the weights of values outside of the histogram
+    outside: f64,
+}
+
+impl WeightedMoments {
+    /// `histogram` has the bounds and bins of the histogram, `validate` ensures that it
+    /// is constructable
+    fn new(histogram: Option<(f64, f64, usize)>) -> WeightedMoments {
+        WeightedMoments {
+            count: 0,
+            shift: f64::NEG_INFINITY,
+            sum: 0.,
+            sum2: 0.,
+            mean: 0.,
+            m2: 0.,
+            histogram: histogram.map(|(low, high, bins)| WeightedHistogram::new(low, high, bins).unwrap()),
+            outside: 0.,
+        }
+    }
+
+    // West, Updating mean and variance estimates: an improved method (1979)
+    fn update(&mut self, value: f64, ln_w: f64) {
+        self.count += 1;
+        if ln_w == f64::NEG_INFINITY {
+            return
+        }
+        if ln_w > self.shift {
+            let factor = (self.shift - ln_w).exp();
+            self.sum *= factor;
+            self.sum2 *= factor * factor;
+            self.m2 *= factor;
+            self.outside *= factor;
+            if let Some(histogram) = &mut self.histogram {
+                histogram.scale(factor);
+            }
+            self.shift = ln_w;
+        }
+        let w = (ln_w - self.shift).exp();
+        self.sum += w;
+        self.sum2 += w * w;
+        let delta = value - self.mean;
+        self.mean += delta * w / self.sum;
+        self.m2 += w * delta * (value - self.mean);
+        if let Some(histogram) = &mut self.histogram {
+            match histogram.sums().index_of(value) {
+                Some(_) => histogram.add(value, w),
+                None => self.outside += w,
+            }
+        }
+    }
+}
+
+/// The joint moments of values `x` and the values `c` of a control variate, calculated
+/// on the fly like `Moments`, which determine the controlled estimate of the mean of `x`.
+#[derive(Clone, Debug)]
+struct CoMoments {
+    count: u64,
+    mean_x: f64,
+    mean_c: f64,
+    /// the sums of the squared distances from the means and of their products
+    m2_x: f64,
+    m2_c: f64,
+    m_xc: f64,
+}
+
+impl CoMoments {
+    fn new() -> CoMoments {
+        CoMoments {
+            count: 0,
+            mean_x: 0.,
+            mean_c: 0.,
+            m2_x: 0.,
+            m2_c: 0.,
+            m_xc: 0.,
+        }
+    }
+
+    fn update(&mut self, x: f64, c: f64) {
+        self.count += 1;
+        let n = self.count as f64;
+        let dx = x - self.mean_x;
+        let dc = c - self.mean_c;
+        self.mean_x += dx / n;
+        self.mean_c += dc / n;
+        self.m2_x += dx * (x - self.mean_x);
+        self.m2_c += dc * (c - self.mean_c);
+        self.m_xc += dx * (c - self.mean_c);
+    }
+
+    /// add the values of `other`, like `Moments::merge`
+    fn merge(&mut self, other: &CoMoments) {
+        let count = self.count + other.count;
+        if count == 0 {
+            return
+        }
+        let (na, nb, n) = (self.count as f64, other.count as f64, count as f64);
+        let dx = other.mean_x - self.mean_x;
+        let dc = other.mean_c - self.mean_c;
+        self.m2_x += other.m2_x + dx * dx * na * nb / n;
+        self.m2_c += other.m2_c + dc * dc * na * nb / n;
+        self.m_xc += other.m_xc + dx * dc * na * nb / n;
+        self.mean_x += dx * nb / n;
+        self.mean_c += dc * nb / n;
+        self.count = count;
+    }
+
+    /// the controlled estimate with the known `mean` of the control variate
+    fn finalize(&self, mean: f64) -> Result<ControlReport> {
+        if self.count < 3 {
+            return Err(LargedevError::TooFewSamples)
         }
+        let n = self.count as f64;
+        // a constant control variate can not reduce the variance
+        let beta = if self.m2_c > 0. { self.m_xc / self.m2_c } else { 0. };
+        let residual = (self.m2_x - beta * self.m_xc).max(0.);
+        // one degree of freedom is lost by the estimate of `beta`
+        let variance = residual / (n - 2.);
 
-        (mean, variance)
+        Ok(ControlReport {
+            mean: self.mean_x - beta * (self.mean_c - mean),
+            error: (variance / n).sqrt(),
+            beta,
+            reduction: if self.m2_x > 0. { residual / self.m2_x } else { 1. },
+        })
     }
 }
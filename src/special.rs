@@ -0,0 +1,148 @@
+//! Special functions for the p-values of the statistical tests, e.g., of
+//! `Histogram::chi_square`.
+
+/// maximal number of terms of the series and continued fractions
+const MAX_ITERATIONS: usize = 1000;
+
+/// relative accuracy, at which the series and continued fractions stop
+const EPS: f64 = 1e-14;
+
+/// The logarithm of the gamma function for `x > 0` by the approximation of Lanczos,
+/// which is accurate to about 15 digits.
+pub(crate) fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 14] = [
+        57.156_235_665_862_92, -59.597_960_355_475_49, 14.136_097_974_741_746,
+        -0.491_913_816_097_620_2, 3.399_464_998_481_189e-5, 4.652_362_892_704_858e-5,
+        -9.837_447_530_487_956e-5, 1.580_887_032_249_125e-4, -2.102_644_417_241_049e-4,
+        2.174_396_181_152_126_5e-4, -1.643_181_065_367_639e-4, 8.441_822_398_385_275e-5,
+        -2.619_083_840_158_141e-5, 3.689_918_265_953_162_5e-6,
+    ];
+    let tmp = x + 5.242_187_5;
+    let tmp = (x + 0.5) * tmp.ln() - tmp;
+    let mut series = 0.999_999_999_999_997_1;
+    for (j, c) in COEFFICIENTS.iter().enumerate() {
+        series += c / (x + 1. + j as f64);
+    }
+    tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+/// The regularized upper incomplete gamma function `Q(a, x) = Gamma(a, x) / Gamma(a)`
+/// for `a > 0` and `x >= 0`, e.g., the probability of a chi-square distribution with
+/// `k` degrees of freedom to exceed `c` is `Q(k / 2, c / 2)`.
+pub(crate) fn gamma_q(a: f64, x: f64) -> f64 {
+    if x <= 0. {
+        return 1.
+    }
+    let prefactor = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1. {
+        // the series of the lower function `P = 1 - Q`
+        let mut term = 1. / a;
+        let mut sum = term;
+        for n in 1..MAX_ITERATIONS {
+            term *= x / (a + n as f64);
+            sum += term;
+            if term.abs() < sum.abs() * EPS {
+                break
+            }
+        }
+        1. - sum * prefactor
+    } else {
+        // the continued fraction of `Q` by the modified method of Lentz
+        let tiny = f64::MIN_POSITIVE / EPS;
+        let mut b = x + 1. - a;
+        let mut c = 1. / tiny;
+        let mut d = 1. / b;
+        let mut h = d;
+        for i in 1..MAX_ITERATIONS {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1. / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.).abs() < EPS {
+                break
+            }
+        }
+        prefactor * h
+    }
+}
+
+/// The complementary cumulative distribution `Q(lambda) = 2 sum_j (-1)^(j-1) exp(-2 j^2
+/// lambda^2)` of the Kolmogorov distribution, i.e., the asymptotic p-value of the
+/// scaled distance `lambda` of the Kolmogorov-Smirnov test.
+pub(crate) fn kolmogorov_q(lambda: f64) -> f64 {
+    // the alternating series converges too slowly for small `lambda`, where `Q = 1`
+    if lambda < 0.2 {
+        return 1.
+    }
+    let mut sum = 0.;
+    let mut sign = 1.;
+    for j in 1..=100 {
+        let j = j as f64;
+        let term = sign * (-2. * j * j * lambda * lambda).exp();
+        sum += term;
+        if term.abs() < EPS * sum.abs() {
+            break
+        }
+        sign = -sign;
+    }
+    (2. * sum).clamp(0., 1.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// compare with the relative `tolerance`, or the absolute one for `exact = 0`
+    fn assert_close(x: f64, exact: f64, tolerance: f64) {
+        let scale = if exact == 0. { 1. } else { exact.abs() };
+        assert!((x - exact).abs() <= tolerance * scale, "{} instead of {}", x, exact);
+    }
+
+    #[test]
+    fn ln_gamma_of_known_values() {
+        assert_close(ln_gamma(1.), 0., 1e-14);
+        assert_close(ln_gamma(2.), 0., 1e-14);
+        // `Gamma(1/2) = sqrt(pi)`
+        assert_close(ln_gamma(0.5), 0.5 * std::f64::consts::PI.ln(), 1e-14);
+        // `Gamma(10) = 9!`
+        assert_close(ln_gamma(10.), 362880_f64.ln(), 1e-14);
+        assert_close(ln_gamma(100.), 359.134_205_369_575_4, 1e-14);
+    }
+
+    #[test]
+    fn gamma_q_of_known_values() {
+        assert_eq!(gamma_q(2., 0.), 1.);
+        // `Q(1, x) = exp(-x)` on both sides of `x = a + 1`, where the method changes
+        for x in [0.5, 1.9, 2.1, 10., 50.] {
+            assert_close(gamma_q(1., x), (-x).exp(), 1e-12);
+        }
+        // `Q(1/2, x) = erfc(sqrt(x))`
+        assert_close(gamma_q(0.5, 1.), 0.157_299_207_050_285_13, 1e-12);
+        // `Q(n, x) = exp(-x) sum_k x^k / k!` for `k < n`, e.g., the p-value of a
+        // chi-square of 20 with 10 degrees of freedom
+        let poisson = |n: usize, x: f64| (0..n).map(|k| (k as f64 * x.ln() - x - ln_gamma(k as f64 + 1.)).exp()).sum::<f64>();
+        for (n, x) in [(2, 1.), (2, 3.), (5, 10.), (20, 5.)] {
+            assert_close(gamma_q(n as f64, x), poisson(n, x), 1e-12);
+        }
+        assert_close(gamma_q(5., 10.), 0.029_252_688_076_961_13, 1e-12);
+    }
+
+    #[test]
+    fn kolmogorov_q_of_known_values() {
+        assert_eq!(kolmogorov_q(0.1), 1.);
+        assert_close(kolmogorov_q(0.5), 0.963_945_243_664_875_1, 1e-12);
+        assert_close(kolmogorov_q(1.), 0.269_999_671_677_354_56, 1e-12);
+        // the critical value of the test at the 5% level
+        assert_close(kolmogorov_q(1.36), 0.049_485_876_755_377_876, 1e-12);
+        assert_close(kolmogorov_q(2.), 6.709_252_557_796_953e-4, 1e-12);
+    }
+}
@@ -0,0 +1,377 @@
+use std::io::{BufWriter, Write};
+
+use rand::Rng;
+
+use crate::markovchain::MarkovChain;
+use crate::simple::DirectSamplable;
+use crate::error::{LargedevError, Result};
+use crate::histogram::Histogram;
+use crate::report::{Report, TailReport};
+use crate::seed::Seed;
+use crate::hooks::CancelToken;
+use crate::wanglandau::{WangLandau, WangLandauConfig};
+
+/// Parameters of the tail pipeline, see `TailPipeline` for the builder.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TailPipelineConfig {
+    /// the range of the estimated distribution, `low` is included, `high` is excluded
+    pub range: Option<(f64, f64)>,
+    /// number of bins of the estimated distribution, the bins of the Wang-Landau
+    /// simulation are the ones of this grid within its range
+    pub bins: usize,
+    /// how many simple samples to draw per run
+    pub samples: usize,
+    /// the minimal number of simple samples of a bin to use it
+    pub min_count: usize,
+    /// number of independent runs, whose deviations give the errors, which are `NaN`
+    /// for a single run
+    pub runs: usize,
+    /// parameters of the Wang-Landau simulation of the rare region, whose `range` has
+    /// to lie on the grid
+    pub wang_landau: WangLandauConfig,
+    /// seed for `run_seeded`
+    pub seed: Option<Seed>,
+}
+
+impl Default for TailPipelineConfig {
+    fn default() -> Self {
+        TailPipelineConfig {
+            range: None,
+            bins: 100,
+            samples: 100000,
+            min_count: 100,
+            runs: 4,
+            wang_landau: WangLandauConfig::default(),
+            seed: None,
+        }
+    }
+}
+
+impl TailPipelineConfig {
+    pub fn validate(&self) -> Result<()> {
+        let (low, high) = match self.range {
+            Some(range) => range,
+            None => return Err(LargedevError::InvalidParameter("the range has to be set".into()))
+        };
+        Histogram::new(low, high, self.bins)?;
+        if self.samples == 0 {
+            return Err(LargedevError::InvalidParameter("samples must be positive".into()))
+        }
+        if self.min_count == 0 {
+            return Err(LargedevError::InvalidParameter("min_count must be positive".into()))
+        }
+        if self.runs == 0 {
+            return Err(LargedevError::InvalidParameter("runs must be positive".into()))
+        }
+        self.wang_landau.validate()?;
+        // `validate` of the Wang-Landau configuration ensures that the range is set
+        let (rare_low, rare_high) = self.wang_landau.range.unwrap();
+        if rare_low < low || rare_high > high {
+            return Err(LargedevError::InvalidParameter(format!("the rare region [{}, {}) must lie within the range [{}, {})", rare_low, rare_high, low, high)))
+        }
+        let width = (high - low) / self.bins as f64;
+        let on_grid = |x: f64| {
+            let k = (x - low) / width;
+            (k - k.round()).abs() < 1e-6
+        };
+        if !on_grid(rare_low) || !on_grid(rare_high) {
+            return Err(LargedevError::InvalidParameter(format!("the rare region [{}, {}) must lie on the grid of bins of width {}", rare_low, rare_high, width)))
+        }
+        Ok(())
+    }
+}
+
+/// A builder used to estimate the full distribution `P(value)` of some model, from its
+/// typical region to far into a tail, by combining `Simple` sampling with `WangLandau`
+/// sampling of the tail. Simple sampling estimates the distribution where it has at
+/// least `min_count` samples per bin, Wang-Landau sampling estimates the density of
+/// states up to a constant in the rare region. The constant is fixed by matching both
+/// in the bins of their overlap, weighted by the numbers of simple samples, and the
+/// glued distribution is normalized. The model has to be `DirectSamplable` and a
+/// `MarkovChain`, whose unbiased distribution is the one of simple sampling, e.g.:
+///
+/// ```ignore
+/// let report = TailPipeline::new(model)
+///    .range(0., 100.)
+///    .bins(100)
+///    .samples(1_000_000)
+///    .rare(60., 100.)
+///    .sweep(100)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+///
+/// Everything is repeated in `runs` independent runs, the output has a line with the
+/// center of every bin, the mean of the estimates of `ln P`, its standard error and
+/// the number of runs, in which the bin was estimated. Bins, which were not estimated
+/// in any run, are omitted.
+pub struct TailPipeline<M> {
+    model: M,
+    config: TailPipelineConfig,
+    cancel: Option<CancelToken>,
+}
+
+impl<M: DirectSamplable + MarkovChain + Clone> TailPipeline<M> {
+    pub fn new(model: M) -> Self {
+        TailPipeline::with_config(model, TailPipelineConfig::default())
+    }
+
+    pub fn with_config(model: M, config: TailPipelineConfig) -> Self {
+        TailPipeline::<M> {
+            model,
+            config,
+            cancel: None,
+        }
+    }
+
+    /// the range of the estimated distribution, `low` is included, `high` is excluded
+    pub fn range(mut self, low: f64, high: f64) -> Self {
+        self.config.range = Some((low, high));
+        self
+    }
+
+    pub fn bins(mut self, bins: usize) -> Self {
+        self.config.bins = bins;
+        self
+    }
+
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.config.samples = samples;
+        self
+    }
+
+    pub fn min_count(mut self, min_count: usize) -> Self {
+        self.config.min_count = min_count;
+        self
+    }
+
+    pub fn runs(mut self, runs: usize) -> Self {
+        self.config.runs = runs;
+        self
+    }
+
+    /// the range of the rare region, which is sampled by Wang-Landau sampling
+    pub fn rare(mut self, low: f64, high: f64) -> Self {
+        self.config.wang_landau.range = Some((low, high));
+        self
+    }
+
+    /// how many change moves does one sweep of the Wang-Landau sampling have
+    pub fn sweep(mut self, sweep: usize) -> Self {
+        self.config.wang_landau.sweep = sweep;
+        self
+    }
+
+    pub fn lnf_final(mut self, lnf_final: f64) -> Self {
+        self.config.wang_landau.lnf_final = lnf_final;
+        self
+    }
+
+    /// the seed used by `run_seeded`, if not set, a seed is drawn from entropy
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Self {
+        self.config.seed = Some(seed.into());
+        self
+    }
+
+    /// a token to cancel the simulation, see `CancelToken`
+    pub fn cancel_token(mut self, token: impl Into<CancelToken>) -> Self {
+        self.cancel = Some(token.into());
+        self
+    }
+
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut M {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> M {
+        self.model
+    }
+
+    /// validate the parameters and return the configured pipeline, the number of bins
+    /// of the Wang-Landau simulation is set from the grid
+    pub fn build(mut self) -> Result<TailPipelineRun<M>> {
+        if let (Some((low, high)), Some((rare_low, rare_high))) = (self.config.range, self.config.wang_landau.range) {
+            let width = (high - low) / self.config.bins as f64;
+            self.config.wang_landau.bins = ((rare_high - rare_low) / width).round().max(1.) as usize;
+        }
+        self.config.validate()?;
+        Ok(TailPipelineRun {
+            model: self.model,
+            config: self.config,
+            cancel: self.cancel,
+        })
+    }
+}
+
+/// A configured tail pipeline, see `TailPipeline` for the builder.
+pub struct TailPipelineRun<M> {
+    model: M,
+    config: TailPipelineConfig,
+    cancel: Option<CancelToken>,
+}
+
+impl<M: DirectSamplable + MarkovChain + Clone> TailPipelineRun<M> {
+    pub fn config(&self) -> &TailPipelineConfig {
+        &self.config
+    }
+
+    /// the current state of the model
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut M {
+        &mut self.model
+    }
+
+    /// consume the pipeline and return the model in its current state
+    pub fn into_model(self) -> M {
+        self.model
+    }
+
+    pub fn run(&mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<TailReport> {
+        self.simulate(&mut rng, file, None)
+    }
+
+    /// like `run`, but with a random number generator initialized from the configured
+    /// `seed` (or from entropy), which is written to the output and the report
+    pub fn run_seeded(&mut self, file: &mut impl Write) -> Result<TailReport> {
+        let seed = self.config.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate(&mut seed.rng(), file, Some(seed))
+    }
+
+    /// One independent estimate of `ln P` in every bin, `NaN` where neither method
+    /// estimated it. Returns the estimate and the number of bins of the overlap.
+    fn estimate(&mut self, mut rng: &mut impl Rng, report: &mut Report) -> Result<(Vec<f64>, usize)> {
+        // the range is ensured by `validate`
+        let (low, high) = self.config.range.unwrap();
+        let bins = self.config.bins;
+        let width = (high - low) / bins as f64;
+
+        let mut counts = Histogram::new(low, high, bins)?;
+        for _ in 0..self.config.samples {
+            self.model.reconstruct(&mut rng);
+            counts.count(self.model.value());
+        }
+
+        let mut builder = WangLandau::with_config(self.model.clone(), self.config.wang_landau.clone());
+        if let Some(cancel) = &self.cancel {
+            builder = builder.cancel_token(cancel.clone());
+        }
+        let g = builder.build()?.estimate(&mut rng, report)?;
+
+        let centers = counts.centers();
+        let counts = counts.data();
+        let ln_simple: Vec<f64> = counts.iter()
+            .map(|&c| if c >= self.config.min_count as f64 {
+                (c / (self.config.samples as f64 * width)).ln()
+            } else {
+                f64::NAN
+            })
+            .collect();
+        // the estimate of Wang-Landau sampling, if its range was trimmed, it is smaller
+        let ln_g: Vec<f64> = centers.iter()
+            .map(|&x| g.at(x).unwrap_or(f64::NAN))
+            .collect();
+
+        // the shift of `ln g`, weighted with the numbers of samples, whose variances
+        // of `ln P` are approximately inverse to them
+        let mut overlap = 0;
+        let mut shift = 0.;
+        let mut total = 0.;
+        for j in 0..bins {
+            if !ln_simple[j].is_nan() && !ln_g[j].is_nan() {
+                overlap += 1;
+                shift += counts[j] * (ln_simple[j] - ln_g[j]);
+                total += counts[j];
+            }
+        }
+        if overlap == 0 {
+            return Err(LargedevError::TooFewSamples)
+        }
+        shift /= total;
+
+        let mut ln_p: Vec<f64> = ln_simple.iter().zip(&ln_g)
+            .map(|(&s, &g)| if s.is_nan() { g + shift } else { s })
+            .collect();
+
+        // normalize the glued distribution
+        let max = ln_p.iter().cloned().filter(|p| !p.is_nan()).fold(f64::NEG_INFINITY, f64::max);
+        let norm = max + ln_p.iter().filter(|p| !p.is_nan()).map(|p| (p - max).exp() * width).sum::<f64>().ln();
+        for p in &mut ln_p {
+            *p -= norm;
+        }
+        Ok((ln_p, overlap))
+    }
+
+    fn simulate(&mut self, mut rng: &mut impl Rng, file: &mut impl Write, seed: Option<Seed>) -> Result<TailReport> {
+        // buffer the output, since it is written bin by bin
+        let mut file = BufWriter::new(file);
+
+        if let Some(seed) = seed {
+            writeln!(file, "# seed = {}", seed)?;
+        }
+
+        let mut report = Report {
+            seed,
+            ..Report::default()
+        };
+        let mut estimates = Vec::with_capacity(self.config.runs);
+        let mut overlap = usize::MAX;
+        trace_span!(_span = info_span!("tail_pipeline", bins = self.config.bins, runs = self.config.runs));
+        for _run in 0..self.config.runs {
+            if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                report.stopped = true;
+                break;
+            }
+            let (ln_p, o) = self.estimate(&mut rng, &mut report)?;
+            trace_event!(debug!(run = _run, overlap = o, "glued"));
+            overlap = overlap.min(o);
+            estimates.push(ln_p);
+        }
+        if report.stopped && estimates.is_empty() {
+            file.flush()?;
+            return Err(LargedevError::TooFewSamples)
+        }
+
+        // the range is ensured by `validate`
+        let (low, high) = self.config.range.unwrap();
+        let centers = Histogram::new(low, high, self.config.bins)?.centers();
+        let mut ln_p = Vec::with_capacity(self.config.bins);
+        let mut error = Vec::with_capacity(self.config.bins);
+        writeln!(file, "# matched in an overlap of at least {} bins", overlap)?;
+        writeln!(file, "# value, ln P(value), standard error, runs")?;
+        for (j, x) in centers.iter().enumerate() {
+            let values: Vec<f64> = estimates.iter().map(|e| e[j]).filter(|p| !p.is_nan()).collect();
+            let n = values.len() as f64;
+            let mean = values.iter().sum::<f64>() / n;
+            let variance = values.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / (n - 1.);
+            let err = (variance / n).sqrt();
+            if !values.is_empty() {
+                writeln!(file, "{} {} {} {}", x, mean, err, values.len())?;
+            }
+            ln_p.push(mean);
+            error.push(err);
+        }
+        file.flush()?;
+        trace_event!(info!(runs = estimates.len(), overlap, stopped = report.stopped, "finished"));
+
+        Ok(TailReport {
+            centers,
+            ln_p,
+            error,
+            overlap,
+            report,
+        })
+    }
+
+    pub fn exec(mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<M> {
+        self.run(&mut rng, file)?;
+        Ok(self.model)
+    }
+}
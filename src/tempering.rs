@@ -0,0 +1,1086 @@
+use std::io::{BufWriter, Write};
+use std::sync::mpsc;
+use std::thread;
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::markovchain::MarkovChain;
+use crate::error::{LargedevError, Result};
+use crate::report::Report;
+use crate::seed::{self, DefaultRng, Seed};
+use crate::hooks::CancelToken;
+use crate::acceptance::{AcceptanceRule, MetropolisRule, ln_boltzmann};
+use crate::metropolis::{self, Acceptance};
+use crate::moves::MoveStats;
+use crate::uniforms::Uniforms;
+
+/// Parameters of a parallel tempering simulation, see `ParallelTempering` for the builder.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParallelTemperingConfig {
+    /// temperatures of the replicas, swaps are proposed between neighbors in this order,
+    /// negative temperatures favor large energies
+    pub temperatures: Vec<f64>,
+    /// how many change moves does one sweep have
+    pub sweep: usize,
+    /// equilibration time in sweeps
+    pub t_eq: usize,
+    /// how many values to sample per temperature
+    pub iterations: usize,
+    /// number of sweeps between two rounds of swap proposals
+    pub exchange: usize,
+    /// which pairs of temperatures are proposed for swaps in every round
+    pub swap_scheme: SwapScheme,
+    /// number of rounds of the feedback optimization of the temperatures before the
+    /// equilibration, see `ParallelTempering::feedback`
+    pub feedback_rounds: usize,
+    /// sweeps per round of the feedback optimization
+    pub feedback_sweeps: usize,
+    /// number of rounds, in which temperatures are inserted or removed to keep the
+    /// acceptance of the swaps in `acceptance_band`, see `ParallelTempering::adapt`
+    pub adapt_rounds: usize,
+    /// sweeps per round of the adaptation
+    pub adapt_sweeps: usize,
+    /// the lower and upper bound of the desired acceptance of the swaps
+    pub acceptance_band: (f64, f64),
+    /// master seed for `run_seeded`
+    pub seed: Option<Seed>,
+}
+
+impl Default for ParallelTemperingConfig {
+    fn default() -> Self {
+        ParallelTemperingConfig {
+            temperatures: Vec::new(),
+            sweep: 1,
+            t_eq: 0,
+            iterations: 1,
+            exchange: 1,
+            swap_scheme: SwapScheme::default(),
+            feedback_rounds: 0,
+            feedback_sweeps: 0,
+            adapt_rounds: 0,
+            adapt_sweeps: 0,
+            acceptance_band: (0.2, 0.4),
+            seed: None,
+        }
+    }
+}
+
+impl ParallelTemperingConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.temperatures.is_empty() {
+            return Err(LargedevError::InvalidParameter("at least one temperature is needed".into()))
+        }
+        if let Some(t) = self.temperatures.iter().find(|t| !t.is_finite() || **t == 0.) {
+            return Err(LargedevError::InvalidParameter(format!("temperatures must be finite and nonzero, not {}", t)))
+        }
+        // the geometric means of the adaptation and the interpolation of the feedback
+        // do not cross the infinite temperature between positive and negative ones
+        let mixed = self.temperatures.iter().any(|&t| t > 0.) && self.temperatures.iter().any(|&t| t < 0.);
+        if mixed && (self.adapt_rounds > 0 || self.feedback_rounds > 0) {
+            return Err(LargedevError::InvalidParameter("the adaptation and the feedback need temperatures of the same sign".into()))
+        }
+        if self.sweep == 0 {
+            return Err(LargedevError::InvalidParameter("sweep must be positive".into()))
+        }
+        if self.iterations == 0 {
+            return Err(LargedevError::InvalidParameter("iterations must be positive".into()))
+        }
+        if self.exchange == 0 {
+            return Err(LargedevError::InvalidParameter("exchange must be positive".into()))
+        }
+        if self.swap_scheme == SwapScheme::SuwaTodo && self.temperatures.len() < 3 {
+            return Err(LargedevError::InvalidParameter("the Suwa-Todo swaps need at least three temperatures".into()))
+        }
+        if self.feedback_rounds > 0 {
+            if self.feedback_sweeps == 0 {
+                return Err(LargedevError::InvalidParameter("the feedback rounds need at least one sweep".into()))
+            }
+            if self.temperatures.len() < 3 {
+                return Err(LargedevError::InvalidParameter("the feedback needs at least three temperatures".into()))
+            }
+            let t = &self.temperatures;
+            let increasing = t.windows(2).all(|w| w[0] < w[1]);
+            let decreasing = t.windows(2).all(|w| w[0] > w[1]);
+            if !increasing && !decreasing {
+                return Err(LargedevError::InvalidParameter("the feedback needs strictly monotonic temperatures".into()))
+            }
+        }
+        if self.adapt_rounds > 0 {
+            if self.adapt_sweeps == 0 {
+                return Err(LargedevError::InvalidParameter("the adaptation rounds need at least one sweep".into()))
+            }
+            if self.temperatures.len() < 2 {
+                return Err(LargedevError::InvalidParameter("the adaptation needs at least two temperatures".into()))
+            }
+            if self.swap_scheme == SwapScheme::SuwaTodo {
+                return Err(LargedevError::InvalidParameter("the adaptation needs the acceptances of pairs, not of the Suwa-Todo windows".into()))
+            }
+            let (low, high) = self.acceptance_band;
+            if !(0. < low && low < high && high < 1.) {
+                return Err(LargedevError::InvalidParameter(format!("the acceptance band ({}, {}) must be within (0, 1)", low, high)))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The pairs of temperatures, which are proposed for swaps in every round. Swaps of
+/// not neighboring temperatures only help, if their distributions of the energy
+/// overlap, which depends on the system, such that the best scheme should be found by
+/// comparing the round trips of the replicas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SwapScheme {
+    /// all even pairs of neighbors `(0, 1), (2, 3), ...` and all odd pairs
+    /// `(1, 2), (3, 4), ...` in alternating rounds
+    #[default]
+    EvenOdd,
+    /// `n-1` uniformly random pairs of neighbors, one after another
+    RandomNeighbors,
+    /// `n-1` uniformly random pairs of any two temperatures, one after another
+    RandomPairs,
+    /// all `n(n-1)/2` pairs of temperatures once in a random order
+    AllPairs,
+    /// All windows of three neighboring temperatures starting at `k = offset`,
+    /// `offset+3`, ..., where the offset cycles through `0, 1, 2` in successive
+    /// rounds. The replicas of a window are assigned to one of the six permutations of
+    /// its temperatures with the geometric allocation of Suwa and Todo
+    /// (10.1103/PhysRevLett.105.120603), which minimizes the probability to keep the
+    /// current assignment and satisfies the global balance instead of the detailed
+    /// balance.
+    SuwaTodo,
+}
+
+/// A builder used to set up parallel tempering, i.e., Metropolis sampling of copies of
+/// a model at multiple temperatures, which exchange their temperatures from time to
+/// time. Every replica runs on its own thread with its own random number stream and
+/// the replicas are synchronized after every sweep. Swaps exchange the temperatures
+/// of the replicas instead of their configurations, such that no model is copied
+/// during the simulation, e.g.:
+///
+/// ```ignore
+/// let report = ParallelTempering::new(model)
+///    .temperatures(vec![1.0, 1.5, 2.0, 2.5])
+///    .sweep(100)
+///    .iterations(1000)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+pub struct ParallelTempering<MC> {
+    model: MC,
+    config: ParallelTemperingConfig,
+    cancel: Option<CancelToken>,
+}
+
+impl<MC: MarkovChain> ParallelTempering<MC> {
+    pub fn new(model: MC) -> Self {
+        ParallelTempering::with_config(model, ParallelTemperingConfig::default())
+    }
+
+    pub fn with_config(model: MC, config: ParallelTemperingConfig) -> Self {
+        ParallelTempering::<MC> {
+            model,
+            config,
+            cancel: None,
+        }
+    }
+
+    pub fn temperatures(mut self, temperatures: Vec<f64>) -> Self {
+        self.config.temperatures = temperatures;
+        self
+    }
+
+    pub fn t_eq(mut self, t_eq: usize) -> Self {
+        self.config.t_eq = t_eq;
+        self
+    }
+
+    pub fn sweep(mut self, sweep: usize) -> Self {
+        self.config.sweep = sweep;
+        self
+    }
+
+    pub fn iterations(mut self, iterations: usize) -> Self {
+        self.config.iterations = iterations;
+        self
+    }
+
+    pub fn exchange(mut self, exchange: usize) -> Self {
+        self.config.exchange = exchange;
+        self
+    }
+
+    /// the pairs of temperatures proposed for swaps, `SwapScheme::EvenOdd` by default
+    pub fn swap_scheme(mut self, scheme: SwapScheme) -> Self {
+        self.config.swap_scheme = scheme;
+        self
+    }
+
+    /// Optimize the temperatures by `rounds` rounds of `sweeps` sweeps before the
+    /// equilibration, such that the replicas diffuse as fast as possible between the
+    /// lowest and the highest temperature, which stay fixed. Every replica is labeled by
+    /// the end of the ladder, which it visited last, and the fraction `f` of the replicas
+    /// coming from the first temperature is measured at every temperature. The new
+    /// temperatures are placed with the density `sqrt(|df/dT| / dT)`, which maximizes
+    /// the number of round trips (Katzgraber et al., 10.1088/1742-5468/2006/03/P03018).
+    /// The number of sweeps should be doubled in every round, but a few rounds with
+    /// enough sweeps for some round trips usually suffice. The optimized temperatures
+    /// are written to the output and are available from `config` after the run.
+    pub fn feedback(mut self, rounds: usize, sweeps: usize) -> Self {
+        self.config.feedback_rounds = rounds;
+        self.config.feedback_sweeps = sweeps;
+        self
+    }
+
+    /// Adapt the number of temperatures by up to `rounds` rounds of `sweeps` sweeps
+    /// before the feedback and the equilibration. After every round, a temperature is
+    /// inserted at the geometric mean of every pair of neighbors, whose swaps are
+    /// accepted less often than the lower bound of the `acceptance_band`, and an
+    /// inner temperature is removed, if the swaps with both of its neighbors are
+    /// accepted more often than the upper bound and the product of both acceptances,
+    /// which estimates the acceptance without it, is above the center of the band.
+    /// The adaptation stops early, once all acceptances are within the band. An
+    /// inserted replica starts as a copy of the replica at the preceding temperature.
+    /// The number of changes is written to the output and the changes are listed in
+    /// the report and by `ladder_changes` after the run. It needs the acceptances of the pairs of
+    /// neighbors, so it is not supported with `SwapScheme::SuwaTodo`, which counts
+    /// windows of three temperatures instead.
+    pub fn adapt(mut self, rounds: usize, sweeps: usize) -> Self {
+        self.config.adapt_rounds = rounds;
+        self.config.adapt_sweeps = sweeps;
+        self
+    }
+
+    /// the desired acceptance of the swaps for `adapt`, `(0.2, 0.4)` by default
+    pub fn acceptance_band(mut self, low: f64, high: f64) -> Self {
+        self.config.acceptance_band = (low, high);
+        self
+    }
+
+    /// the master seed used by `run_seeded`, if not set, a seed is drawn from entropy
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Self {
+        self.config.seed = Some(seed.into());
+        self
+    }
+
+    /// a token to cancel the simulation of all replicas, see `CancelToken`
+    pub fn cancel_token(mut self, token: impl Into<CancelToken>) -> Self {
+        self.cancel = Some(token.into());
+        self
+    }
+
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+}
+
+impl<MC: MarkovChain + Clone> ParallelTempering<MC> {
+    /// validate the parameters and return the configured sampler, with one copy of
+    /// the model per temperature
+    pub fn build(self) -> Result<ParallelTemperingRun<MC>> {
+        self.config.validate()?;
+        let n = self.config.temperatures.len();
+        Ok(ParallelTemperingRun {
+            models: vec![self.model; n],
+            temperature_of: (0..n).collect(),
+            stats: vec![MoveStats::default(); n.saturating_sub(1)],
+            flow: Vec::new(),
+            ladder_changes: Vec::new(),
+            config: self.config,
+            cancel: self.cancel,
+        })
+    }
+}
+
+/// A configured parallel tempering sampler, see `ParallelTempering` for the builder.
+pub struct ParallelTemperingRun<MC> {
+    /// the replicas, which keep their index, while their temperatures change
+    models: Vec<MC>,
+    /// the index of the temperature of every replica
+    temperature_of: Vec<usize>,
+    /// statistics of the swaps between neighboring temperatures
+    stats: Vec<MoveStats>,
+    /// the fraction of replicas, which last visited the first temperature, at every
+    /// temperature in the last round of the feedback
+    flow: Vec<f64>,
+    /// the temperatures inserted and removed by the adaptation of the last run
+    ladder_changes: Vec<LadderChange>,
+    config: ParallelTemperingConfig,
+    cancel: Option<CancelToken>,
+}
+
+/// A change of the temperatures by `ParallelTempering::adapt` after the round `round`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LadderChange {
+    Inserted { round: usize, temperature: f64 },
+    Removed { round: usize, temperature: f64 },
+}
+
+/// instructions from the coordinating thread to a replica
+enum Command {
+    /// perform a sweep at the given inverse temperature and report the energy,
+    /// and the record of the model, if it should be measured
+    Sweep { beta: f64, measure: bool },
+    Stop,
+}
+
+/// the answer of a replica after a sweep
+struct Answer {
+    replica: usize,
+    energy: f64,
+    record: Option<Result<String>>,
+}
+
+impl<MC: MarkovChain + Clone + Send> ParallelTemperingRun<MC> {
+    pub fn config(&self) -> &ParallelTemperingConfig {
+        &self.config
+    }
+
+    /// the current state of the replica at the `k`-th temperature
+    pub fn model(&self, k: usize) -> &MC {
+        &self.models[self.replica_at(k)]
+    }
+
+    pub fn model_mut(&mut self, k: usize) -> &mut MC {
+        let r = self.replica_at(k);
+        &mut self.models[r]
+    }
+
+    /// consume the sampler and return the replicas ordered by their current temperature
+    pub fn into_models(self) -> Vec<MC> {
+        let mut models: Vec<_> = self.temperature_of.into_iter().zip(self.models).collect();
+        models.sort_by_key(|(k, _)| *k);
+        models.into_iter().map(|(_, m)| m).collect()
+    }
+
+    /// Statistics of the swap proposals, the `k`-th entry belongs to the swaps between
+    /// the temperatures `k` and `k+1`. Swaps between not neighboring temperatures are
+    /// not counted. For `SwapScheme::SuwaTodo`, the entry `k` counts the windows
+    /// starting at `k`, which are rejected, if they keep their assignment.
+    pub fn swap_stats(&self) -> &[MoveStats] {
+        &self.stats
+    }
+
+    /// The fraction of the replicas at every temperature, which visited the first
+    /// temperature more recently than the last, measured in the last round of the
+    /// feedback, see `ParallelTempering::feedback`. It is empty without feedback.
+    pub fn flow(&self) -> &[f64] {
+        &self.flow
+    }
+
+    /// the temperatures inserted and removed by `ParallelTempering::adapt` in the last run
+    pub fn ladder_changes(&self) -> &[LadderChange] {
+        &self.ladder_changes
+    }
+
+    fn replica_at(&self, k: usize) -> usize {
+        self.temperature_of.iter().position(|&t| t == k).unwrap()
+    }
+
+    /// The master seed of the replicas is drawn from `rng`. After the header, every
+    /// measurement writes one line per temperature, which starts with the index of the
+    /// temperature, followed by the record of the replica at this temperature. The
+    /// report accumulates the reports of all replicas. The streams of the replicas of
+    /// the initial temperatures come first in the metadata and the report, followed
+    /// by the stream of the swaps and the ones of inserted replicas. It needs threads,
+    /// which are not available on, e.g., `wasm32-unknown-unknown`.
+    pub fn run(&mut self, rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<Report> {
+        let master = Seed::from_rng(rng);
+        self.simulate(master, file, None)
+    }
+
+    /// like `run`, but with the master seed from the configuration (or from entropy),
+    /// which is written to the output and the report
+    pub fn run_seeded(&mut self, file: &mut impl Write) -> Result<Report> {
+        let seed = self.config.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate(seed, file, Some(seed))
+    }
+
+    pub fn exec(mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<Vec<MC>> {
+        self.run(&mut rng, file)?;
+        Ok(self.into_models())
+    }
+
+    fn simulate(&mut self, master: Seed, file: &mut impl Write, seed: Option<Seed>) -> Result<Report> {
+        let mut file = BufWriter::new(file);
+
+        if let Some(seed) = seed {
+            writeln!(file, "# seed = {}", seed)?;
+        }
+        let header = self.models[0].header();
+
+        let n = self.models.len();
+        let mut rngs: Vec<DefaultRng> = (0..n).map(|replica| master.stream(replica as u64).rng()).collect();
+        // the swaps use their own stream, behind the streams of the replicas
+        let mut rng = master.stream(n as u64).rng();
+        // inserted replicas use the streams behind the one of the swaps
+        let mut next_stream = n as u64 + 1;
+
+        trace_span!(_span = info_span!("parallel_tempering", replicas = n, iterations = self.config.iterations));
+        let mut report = Report::default();
+        let mut cancelled = false;
+        self.ladder_changes.clear();
+        for round in 0..self.config.adapt_rounds {
+            let sweeps = self.config.adapt_sweeps;
+            cancelled = self.threaded(&mut rngs, &mut report, |run, commands, answers| {
+                run.exchange_only(commands, answers, sweeps, &mut rng)
+            })?;
+            if cancelled || !self.adapt_ladder(round, &mut rngs, master, &mut next_stream) {
+                break
+            }
+        }
+        if !cancelled && self.config.feedback_rounds > 0 {
+            cancelled = self.threaded(&mut rngs, &mut report, |run, commands, answers| {
+                run.feedback(commands, answers, &mut rng)
+            })?;
+        }
+
+        // the temperatures are written after they are adapted and optimized
+        if self.config.adapt_rounds > 0 {
+            let inserted = self.ladder_changes.iter().filter(|c| matches!(c, LadderChange::Inserted { .. })).count();
+            let removed = self.ladder_changes.len() - inserted;
+            writeln!(file, "# adapted temperatures: {} inserted, {} removed", inserted, removed)?;
+        }
+        let temperatures: Vec<String> = self.config.temperatures.iter().map(|t| t.to_string()).collect();
+        writeln!(file, "# temperatures = {}", temperatures.join(" "))?;
+        report.streams = (0..next_stream).map(|k| master.stream(k)).collect();
+        seed::write_streams(&mut file, &report.streams)?;
+        writeln!(file, "# temperature index, followed by")?;
+        writeln!(file, "{}", header)?;
+
+        if !cancelled {
+            cancelled = self.threaded(&mut rngs, &mut report, |run, commands, answers| {
+                run.coordinate(commands, answers, &mut rng, &mut file)
+            })?;
+        }
+        report.stopped = cancelled;
+        report.seed = seed;
+        report.ladder = self.ladder_changes.clone();
+        trace_event!(info!(tries = report.tries, acceptance = report.acceptance(), stopped = report.stopped, "finished"));
+
+        file.flush()?;
+
+        Ok(report)
+    }
+
+    /// Move every replica with its random number generator from `rngs` to its own
+    /// thread, call `coordinate` to drive them and return the replicas to the sampler
+    /// afterwards. Their proposals are added to `report`.
+    fn threaded<T>(
+        &mut self,
+        rngs: &mut Vec<DefaultRng>,
+        report: &mut Report,
+        coordinate: impl FnOnce(&mut Self, &[mpsc::Sender<Command>], &mpsc::Receiver<Answer>) -> Result<T>,
+    ) -> Result<T> {
+        let n = self.models.len();
+        let sweep = self.config.sweep;
+
+        thread::scope(|s| {
+            let (answer_tx, answers) = mpsc::channel();
+
+            let mut commands = Vec::with_capacity(n);
+            let mut workers = Vec::with_capacity(n);
+            // the replicas are moved to their threads and returned at the end
+            let replicas = std::mem::take(&mut self.models).into_iter().zip(std::mem::take(rngs));
+            for (replica, (mut model, mut rng)) in replicas.enumerate() {
+                let (tx, rx) = mpsc::channel();
+                commands.push(tx);
+                let answer_tx = answer_tx.clone();
+                workers.push(s.spawn(move || {
+                    trace_span!(_span = debug_span!("replica", replica));
+                    let mut uniforms = Uniforms::new(sweep);
+                    let mut report = Report::default();
+                    let mut energy = model.value();
+                    let mut record = String::new();
+                    while let Ok(Command::Sweep { beta, measure }) = rx.recv() {
+                        let acceptance = Acceptance::new(beta, 0);
+                        metropolis::sweep(&mut model, &mut rng, &mut uniforms, sweep, &acceptance, &mut energy, &mut report);
+                        let record = if measure {
+                            record.clear();
+                            Some(model.write_record(&mut record).map(|_| record.clone()).map_err(Into::into))
+                        } else {
+                            None
+                        };
+                        if answer_tx.send(Answer { replica, energy, record }).is_err() {
+                            break
+                        }
+                    }
+                    (model, rng, report)
+                }));
+            }
+            drop(answer_tx);
+
+            let result = coordinate(self, &commands, &answers);
+
+            for tx in &commands {
+                // a replica, which already stopped, does not need the command
+                tx.send(Command::Stop).ok();
+            }
+            for worker in workers {
+                let (model, rng, r) = worker.join().expect("a replica panicked");
+                self.models.push(model);
+                rngs.push(rng);
+                report.tries += r.tries;
+                report.rejects += r.rejects;
+                report.invalid += r.invalid;
+            }
+            result
+        })
+    }
+
+    /// let all replicas perform one sweep at their current temperatures and collect
+    /// their energies and, if they are measured, their records
+    fn sweep_all(
+        &self,
+        commands: &[mpsc::Sender<Command>],
+        answers: &mpsc::Receiver<Answer>,
+        betas: &[f64],
+        measure: bool,
+        energies: &mut [f64],
+        records: &mut [String],
+    ) -> Result<()> {
+        for (replica, tx) in commands.iter().enumerate() {
+            let beta = betas[self.temperature_of[replica]];
+            tx.send(Command::Sweep { beta, measure })
+                .map_err(|_| LargedevError::InvalidParameter("a replica stopped unexpectedly".into()))?;
+        }
+        for _ in 0..commands.len() {
+            let answer = answers.recv()
+                .map_err(|_| LargedevError::InvalidParameter("a replica stopped unexpectedly".into()))?;
+            energies[answer.replica] = answer.energy;
+            if let Some(record) = answer.record {
+                records[self.temperature_of[answer.replica]] = record?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Perform `sweeps` sweeps with swaps, but without measurements, and collect the
+    /// statistics of the swaps. Returns whether the simulation was cancelled.
+    fn exchange_only(
+        &mut self,
+        commands: &[mpsc::Sender<Command>],
+        answers: &mpsc::Receiver<Answer>,
+        sweeps: usize,
+        rng: &mut impl Rng,
+    ) -> Result<bool> {
+        let n = commands.len();
+        let betas: Vec<f64> = self.config.temperatures.iter().map(|t| 1. / t).collect();
+        let mut energies = vec![0.; n];
+        let mut records = vec![String::new(); n];
+        self.stats = vec![MoveStats::default(); n.saturating_sub(1)];
+
+        for i in 0..sweeps {
+            if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                return Ok(true)
+            }
+            self.sweep_all(commands, answers, &betas, false, &mut energies, &mut records)?;
+            if (i + 1) % self.config.exchange == 0 {
+                let round = (i + 1) / self.config.exchange;
+                self.swap(round, &energies, &betas, rng);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Insert and remove temperatures according to the statistics of the swaps of the
+    /// adaptation round `round`, see `ParallelTempering::adapt`. New replicas draw
+    /// their random numbers from the stream `next_stream` of `master` and the following
+    /// ones. Returns whether the temperatures changed.
+    fn adapt_ladder(&mut self, round: usize, rngs: &mut Vec<DefaultRng>, master: Seed, next_stream: &mut u64) -> bool {
+        let n = self.models.len();
+        let (low, high) = self.config.acceptance_band;
+        let acceptance: Vec<Option<f64>> = self.stats.iter().map(MoveStats::acceptance).collect();
+
+        // order the replicas by their temperatures
+        let mut replicas: Vec<Option<(MC, DefaultRng)>> = std::mem::take(&mut self.models)
+            .into_iter()
+            .zip(std::mem::take(rngs))
+            .map(Some)
+            .collect();
+        let mut ordered = Vec::with_capacity(n);
+        for k in 0..n {
+            let replica = self.temperature_of.iter().position(|&t| t == k).unwrap();
+            ordered.push(replicas[replica].take().unwrap());
+        }
+
+        let old = std::mem::take(&mut self.config.temperatures);
+        let mut removed_previous = false;
+        for (k, (model, rng)) in ordered.into_iter().enumerate() {
+            // never remove two neighbors at once, and estimate the acceptance between
+            // the remaining neighbors by the product to avoid a reinsertion
+            // pairs without a proposal in this round are kept as they are
+            let remove = k > 0 && k + 1 < n && !removed_previous && match (acceptance[k - 1], acceptance[k]) {
+                (Some(a), Some(b)) => a > high && b > high && a * b > (low + high) / 2.,
+                _ => false,
+            };
+            if remove {
+                self.ladder_changes.push(LadderChange::Removed { round, temperature: old[k] });
+                removed_previous = true;
+                continue
+            }
+            removed_previous = false;
+
+            let insert = k + 1 < n && acceptance[k].is_some_and(|a| a < low);
+            let copy = if insert { Some(model.clone()) } else { None };
+            self.config.temperatures.push(old[k]);
+            self.models.push(model);
+            rngs.push(rng);
+            if let Some(copy) = copy {
+                // the temperatures have the same sign
+                let temperature = old[k].signum() * (old[k] * old[k + 1]).sqrt();
+                self.ladder_changes.push(LadderChange::Inserted { round, temperature });
+                self.config.temperatures.push(temperature);
+                self.models.push(copy);
+                rngs.push(master.stream(*next_stream).rng());
+                *next_stream += 1;
+            }
+        }
+
+        let n = self.models.len();
+        self.temperature_of = (0..n).collect();
+        self.stats = vec![MoveStats::default(); n - 1];
+        trace_event!(debug!(round, temperatures = ?self.config.temperatures, acceptance = ?acceptance, "adapted the temperatures"));
+        n != old.len() || self.config.temperatures != old
+    }
+
+    /// Perform the rounds of the feedback optimization of the temperatures, see
+    /// `ParallelTempering::feedback`. Returns whether the simulation was cancelled.
+    fn feedback(
+        &mut self,
+        commands: &[mpsc::Sender<Command>],
+        answers: &mpsc::Receiver<Answer>,
+        rng: &mut impl Rng,
+    ) -> Result<bool> {
+        let n = commands.len();
+        let mut energies = vec![0.; n];
+        let mut records = vec![String::new(); n];
+
+        for _round in 0..self.config.feedback_rounds {
+            let betas: Vec<f64> = self.config.temperatures.iter().map(|t| 1. / t).collect();
+            // whether every replica visited the first temperature more recently than the
+            // last one, `None` if it visited neither yet
+            let mut from_first: Vec<Option<bool>> = vec![None; n];
+            let mut first = vec![0; n];
+            let mut visits = vec![0; n];
+
+            for i in 0..self.config.feedback_sweeps {
+                if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                    return Ok(true)
+                }
+                self.sweep_all(commands, answers, &betas, false, &mut energies, &mut records)?;
+                if (i + 1) % self.config.exchange == 0 {
+                    let round = (i + 1) / self.config.exchange;
+                    self.swap(round, &energies, &betas, rng);
+                }
+
+                for (replica, &k) in self.temperature_of.iter().enumerate() {
+                    if k == 0 {
+                        from_first[replica] = Some(true);
+                    } else if k == n - 1 {
+                        from_first[replica] = Some(false);
+                    }
+                    if let Some(label) = from_first[replica] {
+                        visits[k] += 1;
+                        if label {
+                            first[k] += 1;
+                        }
+                    }
+                }
+            }
+
+            self.flow = first.iter().zip(&visits).map(|(&f, &v)| f as f64 / v as f64).collect();
+            if let Some(temperatures) = feedback_temperatures(&self.config.temperatures, &self.flow) {
+                self.config.temperatures = temperatures;
+            }
+            // the statistics of the swaps belong to the final temperatures
+            self.stats = vec![MoveStats::default(); n - 1];
+            trace_event!(debug!(round = _round + 1, temperatures = ?self.config.temperatures, flow = ?self.flow, "optimized the temperatures"));
+        }
+
+        Ok(false)
+    }
+
+    /// Drive the replicas through all sweeps, write their measurements and propose the
+    /// swaps. Returns whether the simulation was cancelled.
+    fn coordinate(
+        &mut self,
+        commands: &[mpsc::Sender<Command>],
+        answers: &mpsc::Receiver<Answer>,
+        rng: &mut impl Rng,
+        file: &mut impl Write,
+    ) -> Result<bool> {
+        let n = commands.len();
+        let betas: Vec<f64> = self.config.temperatures.iter().map(|t| 1. / t).collect();
+        let mut energies = vec![0.; n];
+        let mut records = vec![String::new(); n];
+
+        for i in 0..self.config.t_eq + self.config.iterations {
+            if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                return Ok(true)
+            }
+
+            let measure = i >= self.config.t_eq;
+            self.sweep_all(commands, answers, &betas, measure, &mut energies, &mut records)?;
+
+            if measure {
+                for (k, record) in records.iter().enumerate() {
+                    writeln!(file, "{} {}", k, record)?;
+                }
+            }
+
+            if (i + 1) % self.config.exchange == 0 {
+                // alternate between the even and the odd pairs of neighbors
+                let round = (i + 1) / self.config.exchange;
+                self.swap(round, &energies, &betas, rng);
+                trace_event!(debug!(t = i + 1, acceptance = ?self.stats.iter().map(MoveStats::acceptance).collect::<Vec<_>>(), "swaps"));
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// propose swaps of the temperatures in the `round`-th round of swaps according to
+    /// the `SwapScheme` of the configuration
+    fn swap(&mut self, round: usize, energies: &[f64], betas: &[f64], rng: &mut impl Rng) {
+        let n = betas.len();
+        let mut replica_at = vec![0; n];
+        for (replica, &k) in self.temperature_of.iter().enumerate() {
+            replica_at[k] = replica;
+        }
+
+        match self.config.swap_scheme {
+            SwapScheme::EvenOdd => {
+                for k in (round % 2..n.saturating_sub(1)).step_by(2) {
+                    self.propose_swap(k, k + 1, &mut replica_at, energies, betas, rng);
+                }
+            }
+            SwapScheme::RandomNeighbors => {
+                for _ in 1..n {
+                    let k = rng.gen_range(0..n - 1);
+                    self.propose_swap(k, k + 1, &mut replica_at, energies, betas, rng);
+                }
+            }
+            SwapScheme::RandomPairs => {
+                for _ in 1..n {
+                    let i = rng.gen_range(0..n);
+                    let j = rng.gen_range(0..n - 1);
+                    // skip `i` to draw `j` uniformly from the others
+                    let j = if j >= i { j + 1 } else { j };
+                    self.propose_swap(i.min(j), i.max(j), &mut replica_at, energies, betas, rng);
+                }
+            }
+            SwapScheme::AllPairs => {
+                let mut pairs: Vec<(usize, usize)> = (0..n)
+                    .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+                    .collect();
+                pairs.shuffle(rng);
+                for (i, j) in pairs {
+                    self.propose_swap(i, j, &mut replica_at, energies, betas, rng);
+                }
+            }
+            SwapScheme::SuwaTodo => {
+                for k in (round % 3..n.saturating_sub(2)).step_by(3) {
+                    self.permute_window(k, &mut replica_at, energies, betas, rng);
+                }
+            }
+        }
+    }
+
+    /// propose to swap the temperatures `i < j` with the Metropolis criterion
+    fn propose_swap(&mut self, i: usize, j: usize, replica_at: &mut [usize], energies: &[f64], betas: &[f64], rng: &mut impl Rng) {
+        let (a, b) = (replica_at[i], replica_at[j]);
+        let ln_weight = ln_boltzmann(betas[i] - betas[j], energies[b] - energies[a]);
+        let neighbors = j == i + 1;
+        if neighbors {
+            self.stats[i].tries += 1;
+        }
+        if !MetropolisRule.accept(ln_weight, rng.gen_range(0.0..1.0)) {
+            if neighbors {
+                self.stats[i].rejects += 1;
+            }
+        } else {
+            self.temperature_of.swap(a, b);
+            replica_at.swap(i, j);
+        }
+    }
+
+    /// assign the replicas at the temperatures `k`, `k+1` and `k+2` to one of the
+    /// permutations of their temperatures, see `SwapScheme::SuwaTodo`
+    fn permute_window(&mut self, k: usize, replica_at: &mut [usize], energies: &[f64], betas: &[f64], rng: &mut impl Rng) {
+        const PERMUTATIONS: [[usize; 3]; 6] = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+        // the order of the assignments must not depend on the current one, so they are
+        // labeled by the sorted indices of the replicas
+        let mut replicas = [replica_at[k], replica_at[k + 1], replica_at[k + 2]];
+        replicas.sort_unstable();
+        let current = PERMUTATIONS.iter()
+            .position(|p| (0..3).all(|l| replicas[p[l]] == replica_at[k + l]))
+            .unwrap();
+        let ln_w: Vec<f64> = PERMUTATIONS.iter()
+            .map(|p| -(0..3).map(|l| betas[k + l] * energies[replicas[p[l]]]).sum::<f64>())
+            .collect();
+        let max = ln_w.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let w: Vec<f64> = ln_w.iter().map(|x| (x - max).exp()).collect();
+
+        let chosen = suwa_todo(&w, current, rng.gen_range(0.0..1.0));
+        self.stats[k].tries += 1;
+        if chosen == current {
+            self.stats[k].rejects += 1;
+            return
+        }
+        for (l, &r) in PERMUTATIONS[chosen].iter().enumerate() {
+            self.temperature_of[replicas[r]] = k + l;
+            replica_at[k + l] = replicas[r];
+        }
+    }
+}
+
+/// Choose the next state, starting from the state `current`, among states with the
+/// weights `w` by the geometric allocation of Suwa and Todo, where `u` is uniform in
+/// `[0, 1)`. The weights are allocated like boxes stacked in a circle, starting with the
+/// largest weight, which keeps the weights stationary and avoids rejections, whenever
+/// the largest weight is at most half of the total weight.
+fn suwa_todo(w: &[f64], current: usize, u: f64) -> usize {
+    let n = w.len();
+    let first = (0..n).fold(0, |m, i| if w[i] > w[m] { i } else { m });
+    let order: Vec<usize> = (0..n).map(|i| (first + i) % n).collect();
+    let weights: Vec<f64> = order.iter().map(|&i| w[i]).collect();
+    let cumulative: Vec<f64> = weights.iter()
+        .scan(0., |s, &x| {
+            *s += x;
+            Some(*s)
+        })
+        .collect();
+
+    let i = (current + n - first) % n;
+    let mut p = 0.;
+    for j in 0..n {
+        let previous = if j == 0 { cumulative[n - 1] } else { cumulative[j - 1] };
+        let delta = cumulative[i] - previous + weights[0];
+        let flow = delta.min(weights[i] + weights[j] - delta).min(weights[i]).min(weights[j]).max(0.);
+        p += flow / weights[i];
+        if u < p {
+            return order[j]
+        }
+    }
+    // rounding errors of the probabilities, which sum up to one
+    current
+}
+
+/// Place new temperatures between the first and the last of `temperatures`, such that
+/// the density of the temperatures is proportional to `sqrt(|df/dT| / dT)`, where `flow`
+/// is the measured fraction `f` of the replicas coming from the first temperature.
+/// Every interval gets a share `sqrt(|df|)` of the new temperatures, which are then
+/// spaced linearly within the interval. Returns `None`, if the flow is not defined at
+/// every temperature, or does not decrease at all.
+fn feedback_temperatures(temperatures: &[f64], flow: &[f64]) -> Option<Vec<f64>> {
+    if flow.iter().any(|f| f.is_nan()) {
+        return None
+    }
+    let n = temperatures.len();
+    // the statistical noise can let the flow increase, which gets no temperatures
+    let weights: Vec<f64> = flow.windows(2).map(|f| (f[0] - f[1]).max(0.).sqrt()).collect();
+    let total: f64 = weights.iter().sum();
+    if total <= 0. {
+        return None
+    }
+
+    let mut new = Vec::with_capacity(n);
+    new.push(temperatures[0]);
+    let mut k = 0;
+    let mut cumulated = 0.;
+    for j in 1..n - 1 {
+        let target = j as f64 * total / (n - 1) as f64;
+        while cumulated + weights[k] < target {
+            cumulated += weights[k];
+            k += 1;
+        }
+        let x = (target - cumulated) / weights[k];
+        new.push(temperatures[k] + x * (temperatures[k + 1] - temperatures[k]));
+    }
+    new.push(temperatures[n - 1]);
+    Some(new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Model;
+
+    /// the probabilities `P(i -> j)` of `suwa_todo`, integrated over `u` on a fine grid
+    fn transitions(w: &[f64]) -> Vec<Vec<f64>> {
+        let samples = 100_000;
+        let n = w.len();
+        let mut p = vec![vec![0.; n]; n];
+        for i in 0..n {
+            for m in 0..samples {
+                let u = (m as f64 + 0.5) / samples as f64;
+                p[i][suwa_todo(w, i, u)] += 1. / samples as f64;
+            }
+        }
+        p
+    }
+
+    #[test]
+    fn suwa_todo_is_stationary() {
+        for w in [[3., 1., 2., 2., 1., 1.], [1., 6., 1., 1., 1., 1.], [0.5, 0.1, 4., 0.2, 0.1, 0.1]] {
+            let p = transitions(&w);
+            for j in 0..w.len() {
+                let inflow: f64 = (0..w.len()).map(|i| w[i] * p[i][j]).sum();
+                assert!((inflow - w[j]).abs() < 1e-3 * w[j], "weight {} of {} in {:?}", inflow, j, w);
+            }
+        }
+    }
+
+    #[test]
+    fn suwa_todo_without_rejections() {
+        // the largest weight is at most half of the total weight
+        let w = [3., 1., 2., 2., 1., 1.];
+        let p = transitions(&w);
+        for (i, row) in p.iter().enumerate() {
+            assert_eq!(row[i], 0., "state {} is rejected", i);
+        }
+        // otherwise, only the largest weight is rejected with the excess probability
+        let w = [1., 6., 1., 1., 1., 1.];
+        let p = transitions(&w);
+        assert!((p[1][1] - 1. / 6.).abs() < 1e-3);
+        for (i, row) in p.iter().enumerate().filter(|&(i, _)| i != 1) {
+            assert_eq!(row[i], 0., "state {} is rejected", i);
+        }
+    }
+
+    #[test]
+    fn feedback_keeps_the_endpoints() {
+        let flow = [1., 0.9, 0.5, 0.6, 0.1, 0.];
+        let increasing = [1., 2., 3., 4., 5., 6.];
+        let new = feedback_temperatures(&increasing, &flow).unwrap();
+        assert_eq!(new.len(), 6);
+        assert_eq!((new[0], new[5]), (1., 6.));
+        assert!(new.windows(2).all(|t| t[0] < t[1]), "{:?}", new);
+        // the temperatures concentrate where the flow drops
+        assert!(new[2] - new[1] < 1.);
+
+        let decreasing: Vec<f64> = increasing.iter().rev().cloned().collect();
+        let new = feedback_temperatures(&decreasing, &flow).unwrap();
+        assert_eq!((new[0], new[5]), (6., 1.));
+        assert!(new.windows(2).all(|t| t[0] > t[1]), "{:?}", new);
+
+        assert_eq!(feedback_temperatures(&increasing, &[1., 0.5, f64::NAN, 0.2, 0.1, 0.]), None);
+        assert_eq!(feedback_temperatures(&increasing, &[0.5; 6]), None);
+    }
+
+    #[derive(Clone)]
+    struct Constant;
+
+    impl Model for Constant {
+        fn value(&self) -> f64 {
+            0.
+        }
+    }
+
+    impl MarkovChain for Constant {
+        fn change(&mut self, _rng: &mut impl Rng) {}
+
+        fn undo(&mut self) {}
+    }
+
+    fn stats(acceptance: Option<f64>) -> MoveStats {
+        match acceptance {
+            Some(a) => MoveStats { tries: 10, rejects: (10. * (1. - a)).round() as usize, invalid: 0 },
+            None => MoveStats::default(),
+        }
+    }
+
+    fn adapt(acceptance: &[Option<f64>]) -> (ParallelTemperingRun<Constant>, bool, u64) {
+        let temperatures = vec![1., 2., 4., 8., 16.];
+        let n = temperatures.len();
+        let mut run = ParallelTempering::new(Constant).temperatures(temperatures).build().unwrap();
+        run.stats = acceptance.iter().cloned().map(stats).collect();
+        let master = Seed::new(1);
+        let mut rngs = (0..n).map(|k| master.stream(k as u64).rng()).collect();
+        let mut next_stream = n as u64 + 1;
+        let changed = run.adapt_ladder(3, &mut rngs, master, &mut next_stream);
+        assert_eq!(rngs.len(), run.models.len());
+        (run, changed, next_stream)
+    }
+
+    #[test]
+    fn adapt_ladder_inserts_and_removes() {
+        // the acceptance band is (0.2, 0.4)
+        let (run, changed, next_stream) = adapt(&[Some(0.1), Some(0.9), Some(0.9), Some(0.3)]);
+        assert!(changed);
+        let sqrt2 = 2f64.sqrt();
+        assert_eq!(run.config.temperatures, [1., sqrt2, 2., 8., 16.]);
+        assert_eq!(run.ladder_changes(), [
+            LadderChange::Inserted { round: 3, temperature: sqrt2 },
+            LadderChange::Removed { round: 3, temperature: 4. },
+        ]);
+        assert_eq!(run.models.len(), 5);
+        assert_eq!(run.temperature_of, [0, 1, 2, 3, 4]);
+        assert_eq!(run.swap_stats().len(), 4);
+        // the inserted replica draws from a new stream
+        assert_eq!(next_stream, 7);
+
+        // two neighbors are never removed at once
+        let (run, changed, _) = adapt(&[Some(0.9), Some(0.9), Some(0.9), Some(0.9)]);
+        assert!(changed);
+        assert_eq!(run.config.temperatures, [1., 4., 16.]);
+
+        // a removal needs the product of the acceptances within the band
+        let (run, changed, _) = adapt(&[Some(0.5), Some(0.5), Some(0.3), Some(0.3)]);
+        assert!(!changed);
+        assert_eq!(run.config.temperatures, [1., 2., 4., 8., 16.]);
+        assert!(run.ladder_changes().is_empty());
+
+        // pairs without proposals are kept
+        let (run, changed, _) = adapt(&[None, None, None, None]);
+        assert!(!changed);
+        assert_eq!(run.config.temperatures, [1., 2., 4., 8., 16.]);
+    }
+
+    #[test]
+    fn report_lists_the_ladder_changes() {
+        // all swaps of a constant energy are accepted, so inner temperatures are removed
+        let mut run = ParallelTempering::new(Constant)
+            .temperatures(vec![1., 2., 4., 8., 16.])
+            .adapt(1, 10)
+            .iterations(10)
+            .build()
+            .unwrap();
+        let mut output = Vec::new();
+        let report = run.run(&mut Seed::new(158).rng(), &mut output).unwrap();
+        assert!(!report.ladder.is_empty());
+        assert_eq!(report.ladder, run.ladder_changes());
+        assert!(report.ladder.iter().all(|c| matches!(c, LadderChange::Removed { round: 0, .. })));
+    }
+
+    #[test]
+    fn negative_temperatures() {
+        let valid = |temperatures: Vec<f64>| ParallelTempering::new(Constant).temperatures(temperatures).build().is_ok();
+        assert!(valid(vec![-1., -2., 4.]));
+        assert!(!valid(vec![1., 0.]));
+        assert!(!valid(vec![1., f64::INFINITY]));
+        assert!(!valid(vec![f64::NAN, 1.]));
+        let adapted = ParallelTempering::new(Constant).temperatures(vec![-1., 2.]).adapt(1, 10).build();
+        assert!(adapted.is_err());
+
+        // the geometric mean keeps the sign
+        let mut run = ParallelTempering::new(Constant).temperatures(vec![-1., -4.]).build().unwrap();
+        run.stats = vec![stats(Some(0.1))];
+        let master = Seed::new(1);
+        let mut rngs = (0..2).map(|k| master.stream(k as u64).rng()).collect();
+        assert!(run.adapt_ladder(0, &mut rngs, master, &mut 3));
+        assert_eq!(run.config.temperatures, [-1., -2., -4.]);
+    }
+}
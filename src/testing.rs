@@ -0,0 +1,442 @@
+//! Helpers to test samplers and code built on top of them deterministically.
+//! They are only compiled with the `testing` feature.
+
+use std::cell::Cell;
+use std::fmt;
+
+use rand::Rng;
+
+use crate::Model;
+use crate::markovchain::{MarkovChain, Proposal, Restorable};
+use crate::seed::{DefaultRng, Seed};
+use crate::simple::DirectSamplable;
+use crate::validate::record_of;
+
+/// A call of a `MockChain` method, which changes its state, in the order of the calls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Call {
+    /// `change` or `try_change`, with the proposal returned to the sampler
+    Change(Proposal),
+    Undo,
+    Reconstruct,
+    Restore,
+}
+
+/// A Markov chain without physics, whose states are a scripted sequence of values.
+/// Every valid `change` moves to the next value of the script, which is repeated
+/// cyclically, and `undo` returns to the previous one. Optionally, the outcomes of
+/// `try_change` are scripted as well, such that invalid and unchanged proposals can
+/// be tested. All calls are recorded, such that the behavior of a sampler can be
+/// asserted exactly without a random model, e.g.:
+///
+/// ```ignore
+/// let model = MockChain::new(vec![0., 100.]);
+/// let mut run = Metropolis::new(model).temperature(1.).sweep(2).build()?;
+/// let report = run.run(&mut rng, outfile)?;
+/// // every increase of the value is rejected and undone
+/// assert_eq!(run.model().undos(), report.rejects);
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MockChain {
+    values: Vec<f64>,
+    proposals: Vec<Proposal>,
+    /// the position in `values` of the current state
+    position: usize,
+    /// the position before the last valid `change`
+    previous: Option<usize>,
+    /// the number of proposals drawn from the script
+    proposed: usize,
+    calls: Vec<Call>,
+    value_calls: Cell<usize>,
+}
+
+impl MockChain {
+    /// a chain through the scripted `values`, which starts at the first one
+    pub fn new(values: Vec<f64>) -> Self {
+        assert!(!values.is_empty(), "the script needs at least one value");
+        MockChain {
+            values,
+            proposals: vec![Proposal::Valid],
+            position: 0,
+            previous: None,
+            proposed: 0,
+            calls: Vec::new(),
+            value_calls: Cell::new(0),
+        }
+    }
+
+    /// the outcomes of `try_change` in order, which are repeated cyclically,
+    /// by default all proposals are valid
+    pub fn with_proposals(mut self, proposals: Vec<Proposal>) -> Self {
+        assert!(!proposals.is_empty(), "the script needs at least one proposal");
+        self.proposals = proposals;
+        self
+    }
+
+    /// the position of the current state in the script of values, without wrapping
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// all recorded calls, which changed the state or proposed a change
+    pub fn calls(&self) -> &[Call] {
+        &self.calls
+    }
+
+    /// number of calls of `change` and `try_change`
+    pub fn changes(&self) -> usize {
+        self.calls.iter().filter(|c| matches!(c, Call::Change(_))).count()
+    }
+
+    /// number of calls of `undo`
+    pub fn undos(&self) -> usize {
+        self.calls.iter().filter(|&&c| c == Call::Undo).count()
+    }
+
+    /// number of calls of `value`
+    pub fn value_calls(&self) -> usize {
+        self.value_calls.get()
+    }
+
+    /// forget all recorded calls
+    pub fn clear_calls(&mut self) {
+        self.calls.clear();
+        self.value_calls.set(0);
+    }
+}
+
+impl Model for MockChain {
+    fn value(&self) -> f64 {
+        self.value_calls.set(self.value_calls.get() + 1);
+        self.values[self.position % self.values.len()]
+    }
+}
+
+impl MarkovChain for MockChain {
+    fn change(&mut self, rng: &mut impl Rng) {
+        self.try_change(rng);
+    }
+
+    /// the next scripted proposal, the random number generator is not used
+    fn try_change(&mut self, _rng: &mut impl Rng) -> Proposal {
+        let proposal = self.proposals[self.proposed % self.proposals.len()];
+        self.proposed += 1;
+        self.calls.push(Call::Change(proposal));
+        if proposal == Proposal::Valid {
+            self.previous = Some(self.position);
+            self.position += 1;
+        } else {
+            self.previous = None;
+        }
+        proposal
+    }
+
+    fn undo(&mut self) {
+        self.calls.push(Call::Undo);
+        if let Some(position) = self.previous.take() {
+            self.position = position;
+        }
+    }
+}
+
+impl DirectSamplable for MockChain {
+    /// moves to the next scripted value like a valid `change`
+    fn reconstruct(&mut self, _rng: &mut impl Rng) {
+        self.calls.push(Call::Reconstruct);
+        self.position += 1;
+        self.previous = None;
+    }
+}
+
+impl Restorable for MockChain {
+    type Snapshot = usize;
+
+    fn snapshot(&self) -> usize {
+        self.position
+    }
+
+    fn restore(&mut self, snapshot: &usize) {
+        self.calls.push(Call::Restore);
+        self.position = *snapshot;
+        self.previous = None;
+    }
+}
+
+/// An operation of `ChainLaws` on a model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Op {
+    /// `try_change`, which is kept like an accepted proposal
+    Accept,
+    /// `try_change` followed by `undo` for a valid proposal, like a rejected proposal
+    Reject,
+    /// `value` and `write_record` without a change
+    Value,
+}
+
+/// A law of `MarkovChain`, which is checked by `ChainLaws`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Law {
+    /// `undo` restores the value
+    Undo,
+    /// `undo` restores the record
+    Record,
+    /// invalid and unchanged proposals do not change the value or the record
+    Unchanged,
+    /// `value` and `write_record` are functions of the state without side effects
+    Value,
+    /// the logarithmic proposal ratio of a valid proposal is finite
+    ProposalRatio,
+    /// two models built from the same seed follow the same trajectory for the same
+    /// random numbers
+    Deterministic,
+}
+
+/// A sequence of operations, which violates a law, found by `ChainLaws::check`.
+/// It is shrunk to the shortest found sequence and is reproduced by the model built
+/// from `seed.rng()`, to which the operations are applied with random numbers from
+/// `seed.stream(0).rng()`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LawViolation {
+    pub law: Law,
+    /// the index of the failing case
+    pub case: usize,
+    pub seed: Seed,
+    /// the operations, the last one violated the law
+    pub ops: Vec<Op>,
+    /// the expected and the observed behavior
+    pub message: String,
+}
+
+impl fmt::Display for LawViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "law {:?} violated in case {} (seed {}) after {} operations {:?}: {}",
+            self.law, self.case, self.seed.value(), self.ops.len(), self.ops, self.message
+        )
+    }
+}
+
+impl std::error::Error for LawViolation {}
+
+/// maximal number of replays, to shrink a violating sequence of operations
+const MAX_SHRINKS: usize = 1000;
+
+/// A property test of a `MarkovChain` implementation in the style of `proptest` or
+/// `quickcheck`. Every case builds a fresh model from a seeded random number generator
+/// with `factory` and applies a random sequence of operations, see `Op`, after each of
+/// which the laws of the trait are checked, see `Law`. A failing sequence is shrunk by
+/// removing operations, which are not needed to reproduce the failure, e.g.:
+///
+/// ```ignore
+/// #[test]
+/// fn ising_laws() {
+///     ChainLaws::new(|rng| Ising2D::random(8, rng).unwrap()).cases(256).assert();
+/// }
+/// ```
+///
+/// Since a twin of every model is built from the same seed and follows the same
+/// operations, the factory needs to be deterministic as well. Compared to the
+/// statistical `validate::Validator`, this tests many short trajectories from
+/// random initial states and does not test the balance of the proposals.
+pub struct ChainLaws<F> {
+    factory: F,
+    cases: usize,
+    steps: usize,
+    seed: Seed,
+    tolerance: f64,
+}
+
+/// the first violation of a law in a sequence of operations, with its index
+type Failure = (usize, Law, String);
+
+impl<MC, F> ChainLaws<F>
+where
+    MC: MarkovChain,
+    F: FnMut(&mut DefaultRng) -> MC,
+{
+    pub fn new(factory: F) -> Self {
+        ChainLaws {
+            factory,
+            cases: 64,
+            steps: 100,
+            seed: Seed::new(0),
+            tolerance: 0.,
+        }
+    }
+
+    /// number of tested initial states, `64` by default
+    pub fn cases(mut self, cases: usize) -> Self {
+        self.cases = cases;
+        self
+    }
+
+    /// number of operations per case, `100` by default
+    pub fn steps(mut self, steps: usize) -> Self {
+        self.steps = steps;
+        self
+    }
+
+    /// the master seed of the cases, `0` by default, such that the test is
+    /// reproducible, the case `k` uses `seed.stream(k)`
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Self {
+        self.seed = seed.into();
+        self
+    }
+
+    /// largest accepted absolute deviation of the value after `undo`, `0` by default
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Run all cases and return the first, shrunk violation.
+    pub fn check(&mut self) -> std::result::Result<(), LawViolation> {
+        for case in 0..self.cases {
+            let seed = self.seed.stream(case as u64);
+            let mut choice = seed.stream(1).rng();
+            let ops: Vec<Op> = (0..self.steps)
+                .map(|_| match choice.gen_range(0..5) {
+                    0 | 1 => Op::Accept,
+                    2 | 3 => Op::Reject,
+                    _ => Op::Value,
+                })
+                .collect();
+            if let Some(failure) = self.apply(seed, &ops) {
+                let (ops, (_, law, message)) = self.shrink(seed, ops, failure);
+                return Err(LawViolation { law, case, seed, ops, message })
+            }
+        }
+        Ok(())
+    }
+
+    /// Run all cases and panic with the first violation, e.g., in a unit test.
+    pub fn assert(&mut self) {
+        if let Err(violation) = self.check() {
+            panic!("{}", violation);
+        }
+    }
+
+    /// remove operations from a failing sequence, as long as it still fails
+    fn shrink(&mut self, seed: Seed, ops: Vec<Op>, failure: Failure) -> (Vec<Op>, Failure) {
+        let mut ops = ops;
+        let mut failure = failure;
+        ops.truncate(failure.0 + 1);
+        let mut replays = 0;
+        let mut i = 0;
+        while i < ops.len() && replays < MAX_SHRINKS {
+            let mut shorter = ops.clone();
+            shorter.remove(i);
+            replays += 1;
+            match self.apply(seed, &shorter) {
+                Some(f) => {
+                    shorter.truncate(f.0 + 1);
+                    ops = shorter;
+                    failure = f;
+                }
+                None => i += 1,
+            }
+        }
+        (ops, failure)
+    }
+
+    /// apply `ops` to a model and its twin and return the first violation
+    fn apply(&mut self, seed: Seed, ops: &[Op]) -> Option<Failure> {
+        let mut model = (self.factory)(&mut seed.rng());
+        let mut twin = (self.factory)(&mut seed.rng());
+        let mut rng = seed.stream(0).rng();
+        let mut twin_rng = seed.stream(0).rng();
+
+        let mut value = model.value();
+        let mut record = match record_of(&model) {
+            Ok(record) => record,
+            Err(_) => return Some((0, Law::Value, "write_record failed".into())),
+        };
+        for (step, &op) in ops.iter().enumerate() {
+            let fail = |law, message: String| Some((step, law, message));
+            let current = match op {
+                Op::Value => {
+                    let (first, second) = (model.value(), model.value());
+                    let now = record_of(&model).ok();
+                    if first.to_bits() != value.to_bits() || second.to_bits() != value.to_bits() {
+                        return fail(Law::Value, format!("value {} changed to {} and {}", value, first, second))
+                    }
+                    if now.as_ref() != Some(&record) {
+                        return fail(Law::Value, format!("record {:?} changed to {:?}", record, now))
+                    }
+                    continue
+                }
+                Op::Accept | Op::Reject => {
+                    let kind = model.try_change(&mut rng);
+                    let twin_kind = twin.try_change(&mut twin_rng);
+                    if kind != twin_kind {
+                        return fail(Law::Deterministic, format!("proposal {:?}, but {:?} for the twin", kind, twin_kind))
+                    }
+                    kind
+                }
+            };
+
+            let new = model.value();
+            let new_record = match record_of(&model) {
+                Ok(record) => record,
+                Err(_) => return fail(Law::Value, "write_record failed".into()),
+            };
+            if current != Proposal::Valid {
+                if new.to_bits() != value.to_bits() || new_record != record {
+                    let message = format!("{:?} proposal changed {} ({:?}) to {} ({:?})", current, value, record, new, new_record);
+                    return fail(Law::Unchanged, message)
+                }
+                continue
+            }
+            let ln_ratio = model.ln_proposal_ratio();
+            if !ln_ratio.is_finite() {
+                return fail(Law::ProposalRatio, format!("ln_proposal_ratio is {}", ln_ratio))
+            }
+            let twin_value = twin.value();
+            if twin_value.to_bits() != new.to_bits() || twin.ln_proposal_ratio().to_bits() != ln_ratio.to_bits() {
+                return fail(Law::Deterministic, format!("value {}, but {} for the twin", new, twin_value))
+            }
+
+            if op == Op::Accept {
+                value = new;
+                record = new_record;
+                continue
+            }
+            model.undo();
+            twin.undo();
+            let restored = model.value();
+            if restored.to_bits() != value.to_bits() && (restored - value).abs() > self.tolerance {
+                return fail(Law::Undo, format!("undo restored {} instead of {}", restored, value))
+            }
+            // a record with a rounded value differs within the tolerance as well
+            let restored_record = record_of(&model).ok();
+            if restored.to_bits() == value.to_bits() && restored_record.as_ref() != Some(&record) {
+                return fail(Law::Record, format!("undo restored {:?} instead of {:?}", restored_record, record))
+            }
+            // continue from the exact restored state, which may differ by rounding
+            value = restored;
+            if let Some(restored_record) = restored_record {
+                record = restored_record;
+            }
+        }
+        None
+    }
+}
+
+/// Check the laws of `MarkovChain` for models built by `factory` with the default
+/// settings of `ChainLaws` and panic on a violation, e.g., in a unit test:
+///
+/// ```ignore
+/// #[test]
+/// fn laws() {
+///     largedev::testing::assert_chain_laws(|rng| MyModel::random(rng));
+/// }
+/// ```
+pub fn assert_chain_laws<MC: MarkovChain>(factory: impl FnMut(&mut DefaultRng) -> MC) {
+    ChainLaws::new(factory).assert();
+}
@@ -0,0 +1,30 @@
+//! Instrumentation of the samplers with `tracing`, e.g., for structured logging and
+//! timing analysis, when the crate is embedded in a larger application. The spans and
+//! events are only emitted with the `tracing` feature, without it the macros expand to
+//! nothing, such that the fields are not even evaluated.
+
+/// emit an event with one of the event macros of `tracing`, e.g.,
+/// `trace_event!(debug!(lnf, t, "halved ln f"));`
+macro_rules! trace_event {
+    ($level:ident!($($arg:tt)*)) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)*);
+    };
+}
+
+/// enter a span created by one of the span macros of `tracing` until `guard` is
+/// dropped, e.g., `trace_span!(_span = info_span!("metropolis", temperature));`
+macro_rules! trace_span {
+    ($guard:ident = $level:ident!($($arg:tt)*)) => {
+        #[cfg(feature = "tracing")]
+        let $guard = tracing::$level!($($arg)*).entered();
+    };
+}
+
+/// leave a span entered by `trace_span!` before the end of the scope
+macro_rules! trace_exit {
+    ($guard:ident) => {
+        #[cfg(feature = "tracing")]
+        drop($guard);
+    };
+}
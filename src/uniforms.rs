@@ -0,0 +1,36 @@
+use rand::Rng;
+
+/// A buffer of uniform random numbers in `[0, 1)`, which are drawn in batches to
+/// amortize the overhead of the calls to the generator, e.g., one batch per sweep.
+pub(crate) struct Uniforms {
+    buffer: Vec<u64>,
+    next: usize,
+}
+
+impl Uniforms {
+    /// a buffer which draws `batch` numbers at once
+    pub(crate) fn new(batch: usize) -> Self {
+        let batch = batch.max(1);
+        Uniforms {
+            buffer: vec![0; batch],
+            next: batch,
+        }
+    }
+
+    /// discard the remaining numbers, such that the next batch is drawn from a new generator
+    pub(crate) fn reset(&mut self) {
+        self.next = self.buffer.len();
+    }
+
+    /// the next uniform random number, the buffer is refilled from `rng` if it is exhausted
+    pub(crate) fn next(&mut self, rng: &mut (impl Rng + ?Sized)) -> f64 {
+        if self.next == self.buffer.len() {
+            rng.fill(&mut self.buffer[..]);
+            self.next = 0;
+        }
+        let x = self.buffer[self.next];
+        self.next += 1;
+        // use the upper 53 bits as the mantissa, like the `Standard` distribution
+        (x >> 11) as f64 * (1. / (1u64 << 53) as f64)
+    }
+}
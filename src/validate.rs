@@ -0,0 +1,305 @@
+//! Checks of user implementations of `MarkovChain`, which catch the typical bugs
+//! before they silently bias the results of a sampler, e.g., an `undo` that does not
+//! restore an incrementally updated energy. See `Validator`.
+
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::error::{LargedevError, Result};
+use crate::histogram::Histogram;
+use crate::markovchain::{MarkovChain, Proposal};
+use crate::Model;
+
+/// maximal number of violations, which are kept in the report, all are counted
+const MAX_VIOLATIONS: usize = 100;
+
+/// minimal number of transitions between two values, for which the balance of the
+/// flux is tested
+const MIN_TRANSITIONS: usize = 20;
+
+/// A violation of the contract of `MarkovChain`, found by `Validator::check`. The
+/// `proposal` is the index of the offending proposal.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Violation {
+    /// `undo` did not restore the value before the change
+    Undo { proposal: usize, before: f64, after: f64 },
+    /// `undo` restored the value, but not the record, i.e., another observable
+    Record { proposal: usize, before: String, after: String },
+    /// an invalid or unchanged proposal changed the value or the record, although
+    /// `undo` is not called for it
+    Unchanged { proposal: usize, kind: Proposal, before: f64, after: f64 },
+    /// two calls of `value` without a change in between returned different values
+    Value { proposal: usize, first: f64, second: f64 },
+    /// the logarithmic proposal ratio is not finite, i.e., the reverse proposal is
+    /// impossible or the ratio is undefined
+    ProposalRatio { proposal: usize, ln_ratio: f64 },
+    /// In a random walk, which is reversible for correct proposals, the transitions
+    /// from the key `from` to `to` and back are out of balance by `z` standard
+    /// deviations. The keys are the bits of the values, e.g., `f64::from_bits(from)`,
+    /// the indices of their bins or the keys of the states, see `Validator::key`.
+    Flux { from: u64, to: u64, forward: usize, backward: usize, z: f64 },
+}
+
+/// The result of `Validator::check`.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ValidationReport {
+    /// number of proposed changes
+    pub proposals: usize,
+    /// number of invalid proposals
+    pub invalid: usize,
+    /// number of unchanged proposals
+    pub unchanged: usize,
+    /// number of checks of `undo`
+    pub undos: usize,
+    /// number of pairs of values, whose flux was tested
+    pub pairs: usize,
+    /// the largest imbalance of the flux between two values in standard deviations
+    pub max_z: f64,
+    /// number of all found violations
+    pub count: usize,
+    /// the first found violations, at most 100
+    pub violations: Vec<Violation>,
+}
+
+impl ValidationReport {
+    /// whether no violation was found
+    pub fn is_ok(&self) -> bool {
+        self.count == 0
+    }
+
+    fn push(&mut self, violation: Violation) {
+        self.count += 1;
+        if self.violations.len() < MAX_VIOLATIONS {
+            self.violations.push(violation);
+        }
+    }
+}
+
+/// A checker, which exercises a `MarkovChain` implementation with many proposals and
+/// verifies that
+///
+/// * `undo` restores the value and the record exactly,
+/// * invalid and unchanged proposals do not change the model,
+/// * `value` is a function of the state and the logarithmic proposal ratio is finite,
+/// * the transitions in a random walk at infinite temperature, which accepts with the
+///   Metropolis-Hastings ratio, are balanced in both directions, as they are for
+///   correct, reversible proposals, e.g.:
+///
+/// ```ignore
+/// let report = Validator::new(model)
+///    .proposals(1_000_000)
+///    .check(&mut rng)?;
+/// assert!(report.is_ok(), "{:?}", report.violations);
+/// ```
+///
+/// The walk alternates between checks of `undo` and accepted changes, such that many
+/// states are visited. The balance is tested for every pair of keys with at least 20
+/// transitions between them. By default, the keys are the values, models with
+/// continuous values need `bins`. The flux between values is balanced by construction,
+/// if the value changes only by single steps, e.g., by one flipped spin, such that only
+/// the keys of the complete states, see `key`, reveal unbalanced proposals of small
+/// systems. Since the test is statistical, its threshold `z` should grow with the
+/// number of tested pairs.
+pub struct Validator<MC> {
+    model: MC,
+    proposals: usize,
+    tolerance: f64,
+    z: f64,
+    bins: Option<(f64, f64, usize)>,
+    key: Option<StateKey<MC>>,
+}
+
+/// the key of the state of a model, see `Validator::key`
+type StateKey<MC> = Box<dyn Fn(&MC) -> u64>;
+
+impl<MC: MarkovChain> Validator<MC> {
+    pub fn new(model: MC) -> Self {
+        Validator {
+            model,
+            proposals: 100_000,
+            tolerance: 0.,
+            z: 5.,
+            bins: None,
+            key: None,
+        }
+    }
+
+    /// number of proposals, `100000` by default
+    pub fn proposals(mut self, proposals: usize) -> Self {
+        self.proposals = proposals;
+        self
+    }
+
+    /// largest accepted absolute deviation of the value after `undo`, `0` by default,
+    /// i.e., the value has to be restored exactly, a small tolerance allows for
+    /// rounding errors of incremental updates
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// threshold of the imbalance of the flux in standard deviations, `5` by default
+    pub fn z(mut self, z: f64) -> Self {
+        self.z = z;
+        self
+    }
+
+    /// count the transitions between `bins` bins of the values in `[low, high)`
+    /// instead of between the exact values, e.g., for continuous values
+    pub fn bins(mut self, low: f64, high: f64, bins: usize) -> Self {
+        self.bins = Some((low, high, bins));
+        self
+    }
+
+    /// Count the transitions between the states with the keys `key(model)` instead of
+    /// between their values, e.g., the bits of the spins of a small lattice or a hash
+    /// of the configuration. This tests the detailed balance of the proposals
+    /// themselves, if the states are visited often enough.
+    pub fn key(mut self, key: impl Fn(&MC) -> u64 + 'static) -> Self {
+        self.key = Some(Box::new(key));
+        self
+    }
+
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// Exercise the model with random proposals from `rng` and report all violations.
+    pub fn check(&mut self, mut rng: &mut (impl Rng + ?Sized)) -> Result<ValidationReport> {
+        if self.proposals == 0 {
+            return Err(LargedevError::InvalidParameter("proposals must be positive".into()))
+        }
+        if self.tolerance.is_nan() || self.tolerance < 0. {
+            return Err(LargedevError::InvalidParameter(format!("tolerance must be non-negative, not {}", self.tolerance)))
+        }
+        let histogram = match self.bins {
+            Some((low, high, bins)) => Some(Histogram::new(low, high, bins)?),
+            None => None,
+        };
+        // the key of the state for the flux, `None` outside of the bins
+        let custom = &self.key;
+        let key = |model: &MC, value: f64| match (custom, &histogram) {
+            (Some(key), _) => Some(key(model)),
+            (None, Some(histogram)) => histogram.index_of(value).map(|i| i as u64),
+            (None, None) => Some(value.to_bits()),
+        };
+
+        let mut report = ValidationReport { proposals: self.proposals, ..ValidationReport::default() };
+        // the transitions of the walk between the keys of the values
+        let mut flux: HashMap<(u64, u64), usize> = HashMap::new();
+        // the first transitions relax from the initial state and are not counted
+        let burn_in = self.proposals / 10;
+
+        let mut value = self.model.value();
+        let mut current = key(&self.model, value);
+        let mut record = record_of(&self.model)?;
+        let mut after = String::new();
+        for proposal in 0..self.proposals {
+            let kind = self.model.try_change(&mut rng);
+            if kind != Proposal::Valid {
+                if kind == Proposal::Invalid {
+                    report.invalid += 1;
+                } else {
+                    report.unchanged += 1;
+                }
+                let new = self.model.value();
+                after.clear();
+                self.model.write_record(&mut after)?;
+                if !self.same(value, new) || after != record {
+                    report.push(Violation::Unchanged { proposal, kind, before: value, after: new });
+                    value = new;
+                    current = key(&self.model, value);
+                    record.clone_from(&after);
+                }
+                continue
+            }
+
+            let new = self.model.value();
+            let second = self.model.value();
+            if new.to_bits() != second.to_bits() {
+                report.push(Violation::Value { proposal, first: new, second });
+            }
+            let ln_ratio = self.model.ln_proposal_ratio();
+            if !ln_ratio.is_finite() {
+                report.push(Violation::ProposalRatio { proposal, ln_ratio });
+            }
+
+            // every other change is checked, the others are accepted by the walk
+            let accept = rng.gen::<bool>() && ln_ratio.is_finite() && rng.gen::<f64>() < ln_ratio.exp();
+            if accept {
+                let next = key(&self.model, new);
+                if let (true, Some(from), Some(to)) = (proposal >= burn_in, current, next) {
+                    if from != to {
+                        *flux.entry((from, to)).or_insert(0) += 1;
+                    }
+                }
+                value = new;
+                current = next;
+                record.clear();
+                self.model.write_record(&mut record)?;
+            } else {
+                self.model.undo();
+                report.undos += 1;
+                let restored = self.model.value();
+                if !self.same(value, restored) {
+                    report.push(Violation::Undo { proposal, before: value, after: restored });
+                    // continue from the corrupted state to find further violations
+                    value = restored;
+                    current = key(&self.model, value);
+                    record = record_of(&self.model)?;
+                    continue
+                }
+                after.clear();
+                self.model.write_record(&mut after)?;
+                if after != record {
+                    report.push(Violation::Record { proposal, before: record.clone(), after: after.clone() });
+                    record.clone_from(&after);
+                }
+            }
+        }
+
+        // the transitions in both directions between every pair of keys
+        let mut balance: HashMap<(u64, u64), (usize, usize)> = HashMap::new();
+        for (&(from, to), &n) in &flux {
+            if from < to {
+                balance.entry((from, to)).or_insert((0, 0)).0 += n;
+            } else {
+                balance.entry((to, from)).or_insert((0, 0)).1 += n;
+            }
+        }
+        let mut pairs: Vec<_> = balance.into_iter()
+            .filter(|&(_, (forward, backward))| forward + backward >= MIN_TRANSITIONS)
+            .collect();
+        // report the imbalances in a reproducible order
+        pairs.sort_unstable_by_key(|&(pair, _)| pair);
+        report.pairs = pairs.len();
+        for ((from, to), (forward, backward)) in pairs {
+            let z = (forward as f64 - backward as f64).abs() / ((forward + backward) as f64).sqrt();
+            report.max_z = report.max_z.max(z);
+            if z > self.z {
+                report.push(Violation::Flux { from, to, forward, backward, z });
+            }
+        }
+        trace_event!(info!(count = report.count, pairs = report.pairs, max_z = report.max_z, "validated"));
+
+        Ok(report)
+    }
+
+    fn same(&self, a: f64, b: f64) -> bool {
+        a.to_bits() == b.to_bits() || (a - b).abs() <= self.tolerance
+    }
+}
+
+/// the record of the current state of `model`, also used by `testing::ChainLaws`
+pub(crate) fn record_of<M: Model>(model: &M) -> Result<String> {
+    let mut record = String::new();
+    model.write_record(&mut record)?;
+    Ok(record)
+}
@@ -1,208 +1,708 @@
-use std::io::{self, Write};
-use std::fs::File;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 use crate::histogram::Histogram;
-use crate::markovchain::MarkovChain;
+#[cfg(feature = "tracing")]
+use crate::histogram::{ASCII_HEIGHT, ASCII_WIDTH};
+use crate::acceptance::{AcceptanceRule, MetropolisRule};
+use crate::markovchain::{MarkovChain, Proposal};
+use crate::error::{LargedevError, Result};
+use crate::report::{PhaseReport, Report};
+use crate::seed::{self, Seed};
+use crate::hooks::{CancelToken, SweepHook, SweepState, WangLandauEvent, WangLandauMonitor, WangLandauState};
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::uniforms::Uniforms;
 
 use rand::Rng;
 
 
-/// A struct used to perform Wang-Landau sampling on some model, which implements the
-/// `MarkovChain` trait. This follows the builder pattern to specify all parameters.
-/// The `run` method executes the sampling, e.g.:
+/// Parameters of a Wang-Landau simulation, see `WangLandau` for the builder.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WangLandauConfig {
+    /// lower and upper bound for the energy of the sampled window
+    pub range: Option<(f64, f64)>,
+    /// number of bins of the density of states
+    pub bins: usize,
+    /// how many change attempts per sweep
+    pub sweep: usize,
+    /// final refinement parameter (logarithmic)
+    pub lnf_final: f64,
+    /// exponent of the decrease `lnf = t^-alpha` in the second phase, see
+    /// `WangLandau::alpha`
+    pub alpha: f64,
+    /// when the auxiliary histogram completes a stage of the first phase
+    pub criterion: HistogramCriterion,
+    /// seed for `run_seeded`
+    pub seed: Option<Seed>,
+    /// whether to emit charts of the density of states and the histogram of the
+    /// visits as events of the `tracing` feature, see `WangLandau::verbose`
+    pub verbose: bool,
+    /// number of sweeps between the periodic calls of the monitor, see
+    /// `WangLandau::monitor`
+    pub monitor_interval: usize,
+    /// the prefix of the files and the number of sweeps between the snapshots of the
+    /// current estimate, see `WangLandau::snapshots`
+    pub snapshots: Option<(PathBuf, usize)>,
+    /// the ranges of the values of the masked bins, see `WangLandau::mask`
+    pub masked: Vec<(f64, f64)>,
+}
+
+impl Default for WangLandauConfig {
+    fn default() -> Self {
+        WangLandauConfig {
+            range: None,
+            bins: 100,
+            sweep: 1,
+            lnf_final: 1e-5,
+            alpha: 1.,
+            criterion: HistogramCriterion::default(),
+            seed: None,
+            verbose: false,
+            monitor_interval: 1000,
+            snapshots: None,
+            masked: Vec::new(),
+        }
+    }
+}
+
+impl WangLandauConfig {
+    pub fn validate(&self) -> Result<()> {
+        let (low, high) = match self.range {
+            Some(range) => range,
+            None => return Err(LargedevError::InvalidParameter("the energy range must be specified".into()))
+        };
+        // the histograms of the simulation need to be constructable
+        let g = Histogram::new(low, high, self.bins)?;
+        for &(a, b) in &self.masked {
+            if !a.is_finite() || !b.is_finite() || a > b {
+                return Err(LargedevError::InvalidParameter(format!("invalid range of masked bins [{}, {}]", a, b)))
+            }
+        }
+        if self.mask(&g).iter().all(|&m| m) {
+            return Err(LargedevError::InvalidParameter("all bins are masked".into()))
+        }
+        if self.sweep == 0 {
+            return Err(LargedevError::InvalidParameter("sweep must be positive".into()))
+        }
+        if self.monitor_interval == 0 {
+            return Err(LargedevError::InvalidParameter("the interval of the monitor must be positive".into()))
+        }
+        if matches!(self.snapshots, Some((_, 0))) {
+            return Err(LargedevError::InvalidParameter("the interval of the snapshots must be positive".into()))
+        }
+        if self.lnf_final.is_nan() || self.lnf_final <= 0. {
+            return Err(LargedevError::InvalidParameter(format!("lnf_final must be positive, not {}", self.lnf_final)))
+        }
+        if self.alpha.is_nan() || self.alpha <= 0. || self.alpha > 1. {
+            return Err(LargedevError::InvalidParameter(format!("alpha must be in (0, 1], not {}", self.alpha)))
+        }
+        match self.criterion {
+            HistogramCriterion::Flat(x) if x.is_nan() || x <= 0. || x > 1. => {
+                return Err(LargedevError::InvalidParameter(format!("the flatness must be in (0, 1], not {}", x)))
+            }
+            HistogramCriterion::EqualHit(0) => {
+                return Err(LargedevError::InvalidParameter("the equal-hit criterion needs at least one hit".into()))
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// the refinement parameter `t^-alpha` of the second phase after `t` sweeps
+    pub(crate) fn schedule(&self, t: usize) -> f64 {
+        if self.alpha == 1. {
+            1. / t as f64
+        } else {
+            (t as f64).powf(-self.alpha)
+        }
+    }
+
+    /// whether each bin of `g` is masked, i.e., contains any value of the ranges of
+    /// `masked`
+    pub(crate) fn mask(&self, g: &Histogram) -> Vec<bool> {
+        let mut mask = vec![false; g.bins()];
+        let (low, high) = g.bounds();
+        for &(a, b) in &self.masked {
+            if b < low || a >= high {
+                continue
+            }
+            let first = g.index_of(a).unwrap_or(0);
+            let last = g.index_of(b).unwrap_or(g.bins() - 1);
+            for m in &mut mask[first..=last] {
+                *m = true;
+            }
+        }
+        mask
+    }
+
+    /// the number of sweeps at the end of the second phase, i.e., when the schedule
+    /// reaches `lnf_final`
+    pub(crate) fn final_sweep(&self) -> usize {
+        self.lnf_final.powf(-1. / self.alpha).ceil() as usize
+    }
+}
+
+/// The criterion, when the auxiliary histogram `H` completes a stage of the first
+/// phase of Wang-Landau sampling, after which `ln f` is halved and `H` is reset.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HistogramCriterion {
+    /// every bin was visited at least once, as proposed for the `1/t` algorithm
+    #[default]
+    Visited,
+    /// every bin was visited at least `x` times the mean number of visits, e.g.,
+    /// `x = 0.8`, as in the original algorithm
+    Flat(f64),
+    /// Every bin received at least the given number of accepted moves, i.e., only
+    /// the moves, which are accepted, are counted in `H`. This ignores the
+    /// rejections, which pile up visits in the bins next to steep parts of the density
+    /// of states without exploring them, and is often faster than `Flat` with large
+    /// numbers of bins.
+    EqualHit(usize),
+}
+
+impl HistogramCriterion {
+    /// whether the auxiliary histogram with the minimal entry `min` and the mean
+    /// `mean` completes a stage
+    pub(crate) fn is_met(&self, min: f64, mean: f64) -> bool {
+        match *self {
+            HistogramCriterion::Visited => min > 0.,
+            HistogramCriterion::Flat(x) => min > 0. && min >= x * mean,
+            HistogramCriterion::EqualHit(hits) => min >= hits as f64,
+        }
+    }
+
+    /// whether the auxiliary histogram `h` without the `mask`ed bins completes a stage
+    pub(crate) fn is_met_unmasked(&self, h: &Histogram, mask: &[bool]) -> bool {
+        let (min, mean) = unmasked_min_mean(h, mask);
+        self.is_met(min, mean)
+    }
+
+    /// whether the auxiliary histogram counts the rejected proposals, too
+    pub(crate) fn counts_rejections(&self) -> bool {
+        !matches!(self, HistogramCriterion::EqualHit(_))
+    }
+}
+
+/// A builder used to set up Wang-Landau sampling on some model, which implements the
+/// `MarkovChain` trait. After all parameters are specified, `build` validates them
+/// and returns a `WangLandauRun`, whose `run` method executes the sampling, e.g.:
 ///
-/// ```
-/// let (tries, rejects) = WangLandau::new(model)
-///    .bins(WangLandau::uniform_bins(low, high, num))
+/// ```ignore
+/// let report = WangLandau::new(model)
+///    .range(low, high)
+///    .bins(100)
 ///    .sweep(100)
 ///    .lnf_final(1e-5)
+///    .build()?
 ///    .run(&mut rng, outfile)?;
 /// ```
+///
+/// The estimate of `ln g` is always accumulated in `f64`. In the last phases `ln f`
+/// drops far below `1e-6`, while `ln g` grows to the order of the entropy of the
+/// model, such that an `f32` with its 24 bits of mantissa would round most of the
+/// updates away and the run would stall without notice. A finished estimate can be
+/// stored with half the memory, see `Histogram::convert`.
 pub struct WangLandau<MC> {
-    /// file handle of the output file
     model: MC,
-    /// lower bound for the energy of the sampled window
-    low: f64,
-    /// upper bound for the energy of the sampled window
-    high: f64,
-    /// estimate of the density of states
-    g: Histogram,
-    /// auxiliary histogram for flatness criterion
-    h: Histogram,
-    /// how many change attempts per sweep
-    sweep: usize,
-    /// final refinement parameter (logarithmic)
-    lnf_final: f64,
+    config: WangLandauConfig,
+    hook: Option<SweepHook<MC>>,
+    monitor: Option<WangLandauMonitor>,
+    cancel: Option<CancelToken>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
 }
 
 impl<MC: MarkovChain> WangLandau<MC> {
-    pub fn new(model: MC, low: f64, high: f64) -> Self {
+    pub fn new(model: MC) -> Self {
+        WangLandau::with_config(model, WangLandauConfig::default())
+    }
+
+    pub fn with_config(model: MC, config: WangLandauConfig) -> Self {
         WangLandau::<MC> {
             model,
-            low,
-            high,
-            g: Histogram::new(low, high, 100),
-            h: Histogram::new(low, high, 100),
-            sweep: 1,
-            lnf_final: 1e-5,
+            config,
+            hook: None,
+            monitor: None,
+            cancel: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
         }
     }
 
-    pub fn sweep(&mut self, sweep: usize) -> &mut Self {
-        assert!(sweep > 0);
-        self.sweep = sweep;
+    /// the energy window to sample, `low` is included, `high` is excluded
+    pub fn range(mut self, low: f64, high: f64) -> Self {
+        self.config.range = Some((low, high));
         self
     }
 
-    pub fn lnf_final(&mut self, lnf_final: f64) -> &mut Self {
-        assert!(lnf_final > 0.);
-        self.lnf_final = lnf_final;
+    pub fn sweep(mut self, sweep: usize) -> Self {
+        self.config.sweep = sweep;
         self
     }
 
-    pub fn bins(&mut self, bins: usize) -> &mut Self {
-        self.g = Histogram::new(self.low, self.high, bins);
-        self.h = Histogram::new(self.low, self.high, bins);
+    pub fn lnf_final(mut self, lnf_final: f64) -> Self {
+        self.config.lnf_final = lnf_final;
         self
     }
 
-    /// Create a starrting walk with lb < S < ub by a simple downhill strategy.
-    fn find_start(&mut self, mut rng: impl Rng) {
-        loop {
-            let old_e = self.model.value();
-            self.model.change(&mut rng);
-            let new_e = self.model.value();
+    pub fn bins(mut self, bins: usize) -> Self {
+        self.config.bins = bins;
+        self
+    }
 
-            if (new_e < self.low && old_e > new_e) || (new_e > self.high && old_e < new_e) {
-                self.model.undo();
-            }
+    /// The exponent `alpha` of the decrease `lnf = t^-alpha` of the refinement
+    /// parameter in the second phase, `1` by default. The `1/t` schedule converges
+    /// fastest, but on rough landscapes it can freeze in a biased estimate, if the
+    /// walker did not explore the whole range, before `lnf` is too small to correct
+    /// it. A smaller `alpha`, e.g., `0.8`, keeps `lnf` larger for longer, which fixes
+    /// the bias at the cost of `lnf_final^(-1/alpha)` instead of `1/lnf_final` sweeps
+    /// in the second phase and a larger statistical error for the same `lnf_final`.
+    pub fn alpha(mut self, alpha: f64) -> Self {
+        self.config.alpha = alpha;
+        self
+    }
 
-            if new_e > self.low && new_e < self.high {
-                break;
-            }
+    /// when a stage of the first phase is complete, `HistogramCriterion::Visited` by
+    /// default, see `HistogramCriterion`
+    pub fn criterion(mut self, criterion: HistogramCriterion) -> Self {
+        self.config.criterion = criterion;
+        self
+    }
+
+    /// the seed used by `run_seeded`, if not set, a seed is drawn from entropy
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Self {
+        self.config.seed = Some(seed.into());
+        self
+    }
+
+    /// Emit charts of the density of states and of the histogram of the visits after
+    /// every stage of the first phase and at the end as `info` events of the `tracing`
+    /// feature, see `Histogram::to_ascii`, e.g., to check the progress of a run on a
+    /// remote machine in its log. Without the feature, the charts can be drawn by a
+    /// `monitor` from its `WangLandauState`.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.config.verbose = verbose;
+        self
+    }
+
+    /// a hook called after every sweep, see `SweepHook`
+    pub fn on_sweep(mut self, hook: impl FnMut(&MC, &SweepState) -> ControlFlow<()> + 'static) -> Self {
+        self.hook = Some(Box::new(hook));
+        self
+    }
+
+    /// A callback with the state of the convergence, which is called every `interval`
+    /// sweeps and at the milestones of the simulation, see `WangLandauMonitor`. It is
+    /// only called by `WangLandau` itself, not by, e.g., `SharedWangLandau`.
+    pub fn monitor(mut self, interval: usize, monitor: impl FnMut(&WangLandauState) + 'static) -> Self {
+        self.config.monitor_interval = interval;
+        self.monitor = Some(Box::new(monitor));
+        self
+    }
+
+    /// Write the current estimate of `ln g` and the histogram of the visits `H` to a
+    /// numbered file every `interval` sweeps, e.g., `dos_0001.dat`, `dos_0002.dat`, ...
+    /// for the `prefix` `dos`, to assess the convergence afterwards and to keep the
+    /// partial results of a simulation, which crashes or is killed. The files have the
+    /// format of the output, followed by a line of `H`, and start with the phase, `t`
+    /// and `ln f` as comments. They are written to a temporary file first and renamed,
+    /// such that a crash leaves no truncated snapshot.
+    pub fn snapshots(mut self, prefix: impl Into<PathBuf>, interval: usize) -> Self {
+        self.config.snapshots = Some((prefix.into(), interval));
+        self
+    }
+
+    /// Mask the bins, which contain any value in `[low, high]`, e.g., `mask(e, e)` for
+    /// the bin of a single value `e`. Masked bins are unreachable, e.g., the energies
+    /// between the ground state and the first excitation of the Ising model. Proposals
+    /// into them are rejected and they are ignored by the `HistogramCriterion`, such that
+    /// the walker does not wait forever for them to be visited. Their estimate of
+    /// `ln g` is `-inf`. Every call masks one more range. The masks are supported by
+    /// `WangLandau`, its windows and its production run, but not by `SharedWangLandau`
+    /// and `KernelWangLandau`.
+    pub fn mask(mut self, low: f64, high: f64) -> Self {
+        self.config.masked.push((low, high));
+        self
+    }
+
+    /// a token to cancel the simulation, see `CancelToken`
+    pub fn cancel_token(mut self, token: impl Into<CancelToken>) -> Self {
+        self.cancel = Some(token.into());
+        self
+    }
+
+    /// metrics, which are updated after every sweep, see `Metrics`
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    pub(crate) fn into_parts(self) -> (MC, WangLandauConfig, Option<SweepHook<MC>>, Option<CancelToken>) {
+        (self.model, self.config, self.hook, self.cancel)
+    }
+
+    /// the inverse of `into_parts`
+    pub(crate) fn from_parts(model: MC, config: WangLandauConfig, hook: Option<SweepHook<MC>>, cancel: Option<CancelToken>) -> Self {
+        WangLandau {
+            hook,
+            cancel,
+            ..WangLandau::with_config(model, config)
+        }
+    }
+
+    /// validate the parameters and return the configured sampler
+    pub fn build(self) -> Result<WangLandauRun<MC>> {
+        self.config.validate()?;
+        // `validate` ensures that the range is set
+        let (low, high) = self.config.range.unwrap();
+        let mask = self.config.mask(&Histogram::new(low, high, self.config.bins)?);
+        Ok(WangLandauRun {
+            model: self.model,
+            uniforms: Uniforms::new(self.config.sweep),
+            low,
+            high,
+            mask,
+            visits: None,
+            config: self.config,
+            hook: self.hook,
+            monitor: self.monitor,
+            cancel: self.cancel,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics,
+        })
+    }
+}
+
+/// A configured Wang-Landau sampler, see `WangLandau` for the builder.
+pub struct WangLandauRun<MC> {
+    model: MC,
+    /// random numbers for the acceptance tests of one sweep
+    uniforms: Uniforms,
+    /// lower bound for the energy of the sampled window
+    low: f64,
+    /// upper bound for the energy of the sampled window
+    high: f64,
+    /// whether each bin of the current histograms is masked, see `WangLandau::mask`
+    mask: Vec<bool>,
+    /// the visits of the entropic sampling of the last run
+    visits: Option<Histogram>,
+    config: WangLandauConfig,
+    hook: Option<SweepHook<MC>>,
+    monitor: Option<WangLandauMonitor>,
+    cancel: Option<CancelToken>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+}
+
+impl<MC: MarkovChain> WangLandauRun<MC> {
+    pub fn config(&self) -> &WangLandauConfig {
+        &self.config
+    }
+
+    /// the current state of the model
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    /// consume the sampler and return the model in its current state
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// The visits of every bin during the entropic sampling of the last run, which
+    /// corrects the estimate of `ln g` by `ln(H / mean(H))`. It is `None` before the
+    /// first run and for a run, which was stopped before the entropic sampling.
+    pub fn visits(&self) -> Option<&Histogram> {
+        self.visits.as_ref()
+    }
+
+    /// report the `phase` of the simulation and the expected `total` number of sweeps
+    /// to the metrics, if any
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    fn enter_phase(&self, phase: usize, total: Option<usize>) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.set_phase(phase, total);
         }
     }
 
-    fn accept(&mut self, old_e: f64, rng: &mut impl Rng) -> f64 {
+    /// call the hook, if any, and return whether the simulation should stop,
+    /// either because the hook requests it, or because the run was cancelled
+    pub(crate) fn call_hook(&mut self, state: &SweepState) -> bool {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.update(state);
+        }
+        if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return true
+        }
+        match &mut self.hook {
+            Some(hook) => hook(&self.model, state).is_break(),
+            None => false,
+        }
+    }
+
+    /// call the monitor, if any
+    fn notify(&mut self, state: &WangLandauState) {
+        if let Some(monitor) = &mut self.monitor {
+            monitor(state);
+        }
+    }
+
+    /// Propose a change and accept it according to the current estimate `g` of the
+    /// density of states. The proposal is counted in `report`, returns the new energy.
+    pub(crate) fn step(&mut self, g: &Histogram, old_e: f64, mut rng: &mut impl Rng, report: &mut Report) -> f64 {
+        report.tries += 1;
+        match self.model.try_change(&mut rng) {
+            Proposal::Valid => (),
+            Proposal::Invalid => {
+                report.invalid += 1;
+                return old_e
+            }
+            Proposal::Unchanged => return old_e,
+        }
         let mut new_e = self.model.value();
 
-        let p_acc = match (self.g.at(old_e), self.g.at(new_e)) {
-            (Some(old), Some(new)) => (old - new).exp(),
-            // if one of the values is outside of the histogram range,
-            // reject the proposal (-> p_acc = 0)
-            _ => 0.,
+        let ln_weight = match (g.at(old_e), g.index_of(new_e)) {
+            (Some(old), Some(new)) if !self.mask[new] => old - g.data()[new] + self.model.ln_proposal_ratio(),
+            // if one of the values is outside of the histogram range or in a masked
+            // bin, reject the proposal (-> p_acc = 0)
+            _ => f64::NEG_INFINITY,
         };
 
-        // println!("{} ({:?}) -> {} ({:?}) ({})", old_e, self.g.at(old_e), new_e, self.g.at(new_e), p_acc);
-        // println!("{:?}", self.g);
-
-        if p_acc < rng.gen::<f64>() {
+        if !MetropolisRule.accept(ln_weight, self.uniforms.next(rng)) {
             self.model.undo();
+            report.rejects += 1;
             new_e = old_e;
-            // println!("reject!");
         }
 
         new_e
     }
 
     /** Implementation of the "Fast" 1/t Wang Landau algorithm extended by Entropic Sampling.
+     * The exponent of the 1/t schedule can be configured, see `WangLandau::alpha`.
      *
      * Larger values of the final refinement parameter are ok, since
      * the simulation will be "corrected" by an entropic sampling
      * simulation after the Wang Landau estimation of g.
      *
+     * The output are the bin borders and the estimate of ln g, each on one
+     * line, followed by the visits of the entropic sampling and the errors of
+     * ln g, which they imply, see `visits`.
+     *
      * Literature used:
      *   * 10.1103/PhysRevE.75.046701 (original paper)
      *   * 10.1063/1.2803061 (analytical)
      *   * http://arxiv.org/pdf/cond-mat/0701672.pdf ("fast")
      *   * http://arxiv.org/pdf/1107.2951v1.pdf (entropic sampling)
      */
-    #[allow(clippy::float_cmp)]
-    pub fn run(&mut self, mut rng: &mut impl Rng, file: &mut File) -> io::Result<(usize, usize)> {
-        let mut tries = 0;
-        let mut rejects = 0;
+    pub fn run(&mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<Report> {
+        self.simulate(&mut rng, file, None)
+    }
+
+    /// like `run`, but with a random number generator initialized from the configured
+    /// `seed` (or from entropy), which is written to the output and the report
+    pub fn run_seeded(&mut self, file: &mut impl Write) -> Result<Report> {
+        let seed = self.config.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate(&mut seed.rng(), file, Some(seed))
+    }
+
+    fn simulate(&mut self, rng: &mut impl Rng, file: &mut impl Write, seed: Option<Seed>) -> Result<Report> {
+        // buffer the output, since it is written bin by bin
+        let mut file = BufWriter::new(file);
+
+        let mut report = Report {
+            seed,
+            ..Report::default()
+        };
+        let g = self.estimate(rng, &mut report)?;
+
+        write_dos(&mut file, &g, seed, &report.streams)?;
+        if let Some(visits) = &self.visits {
+            write_visits(&mut file, visits)?;
+        }
+        file.flush()?;
+
+        Ok(report)
+    }
+
+    /// Perform the simulation and return the estimate of the logarithm of the density
+    /// of states. The proposals are counted in `report`.
+    pub(crate) fn estimate(&mut self, mut rng: &mut impl Rng, report: &mut Report) -> Result<Histogram> {
+        // estimate of the density of states
+        let mut g = Histogram::new(self.low, self.high, self.config.bins)?;
+        // auxiliary histogram for flatness criterion
+        let mut h = g.clone();
+        // the numbers left over from the last run stem from another generator
+        self.uniforms.reset();
+
         let initial_num_iterations = 1000;
 
-        self.find_start(&mut rng);
+        trace_span!(_span = info_span!("wang_landau", low = self.low, high = self.high, bins = self.config.bins));
+
+        // the time to find a start is part of the first phase
+        let clock = PhaseClock::start(1, report);
+        find_start(&mut self.model, self.low, self.high, &mut rng);
 
         let mut t = 0;
         let mut lnf = 1.;
+        let mut energy = self.model.value();
+        let mut entropic = false;
+        // the round trips are only tracked for the monitor
+        let monitored = self.monitor.is_some();
+        let interval = self.config.monitor_interval;
+        self.mask = self.config.mask(&g);
+        let mut convergence = Convergence { phase: 1, ..Convergence::default() };
+        convergence.set_mask(&self.mask);
+        let mut snapshots = self.config.snapshots.clone().map(|(prefix, interval)| Snapshots { prefix, interval, written: 0 });
 
         // start first phase
-        while t < 10 || lnf > 1./t as f64 {
-            // TODO: good logging system
-            println!("ln f = {}, t = {}", lnf, t);
-            while self.h.min() == 0. {
+        trace_span!(phase = info_span!("phase", number = 1));
+        self.enter_phase(1, None);
+        self.notify(&convergence.state(WangLandauEvent::Phase, t, lnf, report, &g, &h));
+        'phase1: while t < 10 || lnf > self.config.schedule(t) {
+            trace_event!(debug!(lnf, t, acceptance = report.acceptance(), "ln f"));
+            let criterion = self.config.criterion;
+            let mut trimmed = false;
+            while !criterion.is_met_unmasked(&h, &self.mask) {
                 for _ in 0..initial_num_iterations {
-                    for _ in 0..self.sweep {
-                        let old_e = self.model.value();
-                        self.model.change(&mut rng);
-                        let new_e = self.accept(old_e, &mut rng);
-
-                        tries += 1;
-                        rejects += if new_e == old_e {1} else {0};
-
-                        self.g.add(new_e, lnf);
-                        self.h.count(new_e);
+                    for _ in 0..self.config.sweep {
+                        let failed = report.rejects + report.invalid;
+                        let new_e = self.step(&g, energy, &mut rng, report);
+
+                        g.add(new_e, lnf);
+                        if criterion.counts_rejections() || report.rejects + report.invalid == failed {
+                            h.count(new_e);
+                        }
+                        energy = new_e;
+                        if monitored {
+                            convergence.visit(&g, energy);
+                        }
                     }
                     t += 1;
+                    trace_event!(trace!(t, energy, "sweep"));
+
+                    let state = SweepState { sweep: t, energy, tries: report.tries, rejects: report.rejects, invalid: report.invalid, lnf: Some(lnf), g: Some(&g), h: Some(&h) };
+                    if self.call_hook(&state) {
+                        report.stopped = true;
+                        break 'phase1;
+                    }
+                    if monitored && t % interval == 0 {
+                        self.notify(&convergence.state(WangLandauEvent::Periodic, t, lnf, report, &g, &h));
+                    }
+                    if let Some(snapshots) = snapshots.as_mut().filter(|s| t % s.interval == 0) {
+                        snapshots.write(&convergence.state(WangLandauEvent::Periodic, t, lnf, report, &g, &h), report)?;
+                    }
                 }
 
                 // emergency abort: if too much of the time is spend in this stage,
                 // panic, trim the histogram and proceed
                 // this might lead to inaccurate results
-                if lnf > 0.9 && self.lnf_final > 0.2 / t as f64 {
-                    println!("Spend 20% time in phase 1 at lnf=1: panic, trim and reset the histograms and restart");
-                    println!("The results of this simulation may be inaccurate");
-                    println!("You should restart with a different range or smaller lnf");
-                    println!("before");
-                    println!("g = {:?}", self.g);
-                    println!("h = {:?}", self.h);
-                    self.g.trim();
-                    self.h.trim();
-                    println!("after");
-                    println!("g = {:?}", self.g);
-                    println!("h = {:?}", self.h);
-                    self.g.reset();
-                    self.h.reset();
-                    println!("continue with");
-                    println!("g = {:?}", self.g);
-                    println!("h = {:?}", self.h);
-                    assert_eq!(self.g.bounds(), self.h.bounds());
+                if lnf > 0.9 && self.config.lnf_final > 0.2 / t as f64 {
+                    trace_event!(warn!(t, "too much time spent in phase 1 at ln f = 1, trimming the histograms"));
+                    // `h` may count fewer bins than `g`, so both are cut to the range of `g`
+                    let (first, end) = g.occupied()
+                        .ok_or_else(|| LargedevError::HistogramRange("can not trim a histogram without entries".into()))?;
+                    g.crop(first, end);
+                    h.crop(first, end);
+                    g.reset();
+                    h.reset();
+                    self.mask = self.config.mask(&g);
+                    convergence.set_mask(&self.mask);
+                    trace_event!(warn!(low = g.bounds().0, high = g.bounds().1, bins = g.bins(), "trimmed the histograms"));
+                    self.notify(&convergence.state(WangLandauEvent::Trimmed, t, lnf, report, &g, &h));
                     lnf = 2.;
                     t = 0;
+                    trimmed = true;
                     break;
                 }
             }
-            // run until we have one entry in each bin
-            self.h.reset();
+            if !trimmed {
+                self.notify(&convergence.state(WangLandauEvent::Stage, t, lnf, report, &g, &h));
+            }
+            #[cfg(feature = "tracing")]
+            if self.config.verbose && !report.stopped {
+                trace_event!(info!(lnf, t, "ln g after the stage\n{}", g.to_ascii(ASCII_WIDTH, ASCII_HEIGHT)));
+                trace_event!(info!(lnf, t, "H after the stage\n{}", h.to_ascii(ASCII_WIDTH, ASCII_HEIGHT)));
+            }
+            // run until the histogram meets the criterion
+            h.reset();
             lnf /= 2.;
         }
 
-        if lnf <= self.lnf_final {
-            println!("phase 1 took too long, phase 2 will not be performed");
-            println!("The results of this simulation may be inaccurate");
-            println!("You should restart with a different range, smaller windows or smaller lnf");
+        if !report.stopped && lnf <= self.config.lnf_final {
+            trace_event!(warn!(lnf, t, "phase 1 took too long, phase 2 will not be performed"));
+            self.notify(&convergence.state(WangLandauEvent::Skipped, t, lnf, report, &g, &h));
         }
+        report.phases.push(clock.stop(self.config.sweep, report));
+        trace_exit!(phase);
 
         //start second phase
         // let status = 1./t as f64;
-        println!("begin phase 2 (power-law decrease) at t = {}", t);
-        while lnf > self.lnf_final {
-            lnf = 1./t as f64;
-
-            for _ in 0..self.sweep {
-                let old_e = self.model.value();
-                self.model.change(&mut rng);
-                let new_e = self.accept(old_e, &mut rng);
+        if !report.stopped {
+            trace_event!(info!(t, acceptance = report.acceptance(), "begin phase 2"));
+            convergence.phase = 2;
+            self.notify(&convergence.state(WangLandauEvent::Phase, t, lnf, report, &g, &h));
+        }
+        trace_span!(phase = info_span!("phase", number = 2));
+        let clock = if report.stopped {
+            None
+        } else {
+            // phase 3 takes twice as long as the first two phases
+            self.enter_phase(2, Some(3 * self.config.final_sweep()));
+            Some(PhaseClock::start(2, report))
+        };
+        while !report.stopped && lnf > self.config.lnf_final {
+            lnf = self.config.schedule(t);
 
-                tries += 1;
-                rejects += if new_e == old_e {1} else {0};
+            for _ in 0..self.config.sweep {
+                let new_e = self.step(&g, energy, &mut rng, report);
 
-                self.g.add(new_e, lnf);
+                g.add(new_e, lnf);
+                energy = new_e;
+                if monitored {
+                    convergence.visit(&g, energy);
+                }
             }
             t += 1;
+            trace_event!(trace!(lnf, t, energy, "sweep"));
+
+            let state = SweepState { sweep: t, energy, tries: report.tries, rejects: report.rejects, invalid: report.invalid, lnf: Some(lnf), g: Some(&g), h: None };
+            report.stopped = self.call_hook(&state);
+            if monitored && !report.stopped && t % interval == 0 {
+                self.notify(&convergence.state(WangLandauEvent::Periodic, t, lnf, report, &g, &h));
+            }
+            if let Some(snapshots) = snapshots.as_mut().filter(|s| t % s.interval == 0) {
+                snapshots.write(&convergence.state(WangLandauEvent::Periodic, t, lnf, report, &g, &h), report)?;
+            }
+        }
+        if let Some(clock) = clock {
+            report.phases.push(clock.stop(self.config.sweep, report));
         }
+        trace_exit!(phase);
 
         // perform entropic sampling with the bias g
         // this way the errors caused by too large f_final
@@ -210,50 +710,287 @@ impl<MC: MarkovChain> WangLandau<MC> {
 
         // the entropic sampling phase should be twice as long as
         // the previous phase
-        println!("begin phase 3 (entropic sampling) at t = {} until t = {}", t, 3*t);
-        let t_limit = 2*t;
-        for _ in 0..t_limit {
-            for _ in 0..self.sweep {
-                let old_e = self.model.value();
-                self.model.change(&mut rng);
-                let new_e = self.accept(old_e, &mut rng);
-
-                tries += 1;
-                rejects += if new_e == old_e {1} else {0};
-
-                self.h.count(new_e);
+        if !report.stopped {
+            entropic = true;
+            trace_event!(info!(t, acceptance = report.acceptance(), "begin phase 3"));
+            convergence.phase = 3;
+            self.notify(&convergence.state(WangLandauEvent::Phase, t, lnf, report, &g, &h));
+            trace_span!(_phase = info_span!("phase", number = 3));
+            self.enter_phase(3, Some(3 * t));
+            let clock = PhaseClock::start(3, report);
+            let t_limit = 2*t;
+            for i in 0..t_limit {
+                for _ in 0..self.config.sweep {
+                    let new_e = self.step(&g, energy, &mut rng, report);
+
+                    h.count(new_e);
+                    energy = new_e;
+                    if monitored {
+                        convergence.visit(&g, energy);
+                    }
+                }
+                // write out samples for correlation
+                // TODO
+                trace_event!(trace!(t = t + i + 1, energy, "sweep"));
+
+                let state = SweepState { sweep: t + i + 1, energy, tries: report.tries, rejects: report.rejects, invalid: report.invalid, lnf: Some(lnf), g: Some(&g), h: Some(&h) };
+                if self.call_hook(&state) {
+                    report.stopped = true;
+                    break;
+                }
+                if monitored && (t + i + 1) % interval == 0 {
+                    self.notify(&convergence.state(WangLandauEvent::Periodic, t + i + 1, lnf, report, &g, &h));
+                }
+                if let Some(snapshots) = snapshots.as_mut().filter(|s| (t + i + 1) % s.interval == 0) {
+                    snapshots.write(&convergence.state(WangLandauEvent::Periodic, t + i + 1, lnf, report, &g, &h), report)?;
+                }
             }
-            // write out samples for correlation
-            // TODO
+            report.phases.push(clock.stop(self.config.sweep, report));
         }
 
         // remove the bias
-        for j in 0..self.g.bins() {
-            *self.g.idx(j) += *self.h.idx(j)/self.h.mean();
+        // if the simulation was stopped before the entropic sampling phase
+        // this writes the current estimate of g
+        if entropic {
+            let mean = h.mean();
+            for j in 0..g.bins() {
+                // the histogram of the entropic sampling is proportional to the ratio of
+                // the true and the estimated density of states, bins, which were not
+                // visited, keep the estimate
+                let visits = *h.idx(j);
+                if visits > 0. {
+                    *g.idx(j) += (visits / mean).ln();
+                }
+            }
         }
+        // there are no states in the masked bins
+        for (j, &masked) in self.mask.iter().enumerate() {
+            if masked {
+                *g.idx(j) = f64::NEG_INFINITY;
+            }
+        }
+        trace_event!(info!(lnf, t, acceptance = report.acceptance(), stopped = report.stopped, "finished"));
+        let sweeps = report.tries / self.config.sweep;
+        self.notify(&convergence.state(WangLandauEvent::Finished, sweeps, lnf, report, &g, &h));
+        #[cfg(feature = "tracing")]
+        if self.config.verbose {
+            trace_event!(info!(t, "final ln g\n{}", g.to_ascii(ASCII_WIDTH, ASCII_HEIGHT)));
+        }
+        self.visits = if entropic { Some(h) } else { None };
 
-        // let centers = self.g.centers();
-        let borders = self.g.borders();
-        let data = self.g.data();
+        Ok(g)
+    }
 
-        for b in borders.iter() {
-            write!(file, "{} ", b)?;
+    pub fn exec(mut self, mut rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<MC> {
+        self.run(&mut rng, file)?;
+        Ok(self.model)
+    }
+}
+
+/// The minimum and the mean of the bins of `h`, which are not masked.
+fn unmasked_min_mean(h: &Histogram, mask: &[bool]) -> (f64, f64) {
+    let (min, sum, n) = h.data().iter()
+        .zip(mask)
+        .filter(|(_, &masked)| !masked)
+        .fold((f64::INFINITY, 0., 0), |(min, sum, n), (&x, _)| (min.min(x), sum + x, n + 1));
+    (min, sum / n as f64)
+}
+
+/// The phase and the round trips of the walker for the `WangLandauState`.
+#[derive(Default)]
+struct Convergence {
+    phase: usize,
+    /// the masked bins, see `WangLandau::mask`
+    mask: Vec<bool>,
+    /// the lowest and the highest bin, which are not masked
+    ends: (usize, usize),
+    /// number of walks from one end of the range to the other
+    half_trips: usize,
+    /// the last end of the range, which the walker visited, `true` for the upper one
+    end: Option<bool>,
+}
+
+impl Convergence {
+    fn set_mask(&mut self, mask: &[bool]) {
+        self.mask = mask.to_vec();
+        // `WangLandauConfig::validate` ensures that not all bins are masked
+        self.ends = (
+            mask.iter().position(|&m| !m).unwrap_or(0),
+            mask.iter().rposition(|&m| !m).unwrap_or(0),
+        );
+    }
+
+    /// count the walks between the lowest and the highest bin of `g`, which are not
+    /// masked
+    fn visit(&mut self, g: &Histogram, energy: f64) {
+        let end = match g.index_of(energy) {
+            Some(i) if i == self.ends.0 => false,
+            Some(i) if i == self.ends.1 => true,
+            _ => return,
+        };
+        if self.end == Some(!end) {
+            self.half_trips += 1;
         }
-        writeln!(file)?;
-        for d in data.iter() {
-            write!(file, "{} ", d)?;
+        self.end = Some(end);
+    }
+
+    fn state<'a>(&self, event: WangLandauEvent, sweep: usize, lnf: f64, report: &Report, g: &'a Histogram, h: &'a Histogram) -> WangLandauState<'a> {
+        // the histogram of the visits is not accumulated in the second phase
+        let (min, mean) = unmasked_min_mean(h, &self.mask);
+        let flatness = match self.phase {
+            2 => None,
+            _ if mean > 0. => Some(min / mean),
+            _ => Some(0.),
+        };
+        WangLandauState {
+            event,
+            phase: self.phase,
+            sweep,
+            lnf,
+            flatness,
+            round_trips: self.half_trips / 2,
+            acceptance: report.acceptance(),
+            g,
+            h,
         }
-        writeln!(file)?;
+    }
+}
 
-        // for (c, d) in centers.iter().zip(data) {
-        //     writeln!(file, "{} {}", c, d)?;
-        // }
+/// The numbered files of `WangLandau::snapshots`.
+struct Snapshots {
+    prefix: PathBuf,
+    interval: usize,
+    /// number of snapshots so far
+    written: usize,
+}
 
-        Ok((tries, rejects))
+impl Snapshots {
+    /// write the next snapshot of the `state`
+    fn write(&mut self, state: &WangLandauState, report: &Report) -> Result<()> {
+        self.written += 1;
+        let mut name = self.prefix.clone().into_os_string();
+        name.push(format!("_{:04}.dat", self.written));
+        let path = PathBuf::from(name);
+        let partial = path.with_extension("dat.tmp");
+        write_snapshot(&partial, state, report)?;
+        fs::rename(&partial, &path)?;
+        Ok(())
     }
+}
 
-    pub fn exec(mut self, mut rng: &mut impl Rng, mut file: &mut File) -> io::Result<MC> {
-        self.run(&mut rng, &mut file)?;
-        Ok(self.model)
+/// Write `ln g` and `H` of the `state` to the file at `path`.
+fn write_snapshot(path: &Path, state: &WangLandauState, report: &Report) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "# phase = {}, t = {}, ln f = {}", state.phase, state.sweep, state.lnf)?;
+    write_dos(&mut file, state.g, report.seed, &report.streams)?;
+    for x in state.h.data() {
+        write!(file, "{} ", x)?;
     }
+    writeln!(file)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// The wall time and the counts of the proposals at the start of a phase.
+pub(crate) struct PhaseClock {
+    phase: usize,
+    /// `None` on `wasm32-unknown-unknown`, where `Instant::now` panics
+    start: Option<Instant>,
+    tries: usize,
+    rejects: usize,
+    invalid: usize,
+}
+
+impl PhaseClock {
+    pub(crate) fn start(phase: usize, report: &Report) -> Self {
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        let start = Some(Instant::now());
+        #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+        let start = None;
+        PhaseClock {
+            phase,
+            start,
+            tries: report.tries,
+            rejects: report.rejects,
+            invalid: report.invalid,
+        }
+    }
+
+    /// the statistics of the phase with sweeps of `sweep` proposals until now
+    pub(crate) fn stop(self, sweep: usize, report: &Report) -> PhaseReport {
+        let tries = report.tries - self.tries;
+        PhaseReport {
+            phase: self.phase,
+            seconds: self.start.map_or(f64::NAN, |s| s.elapsed().as_secs_f64()),
+            sweeps: tries / sweep,
+            tries,
+            rejects: report.rejects - self.rejects,
+            invalid: report.invalid - self.invalid,
+        }
+    }
+}
+
+/// Create a starrting walk with lb < S < ub by a simple downhill strategy.
+pub(crate) fn find_start<MC: MarkovChain>(model: &mut MC, low: f64, high: f64, mut rng: impl Rng) {
+    let mut old_e = model.value();
+    loop {
+        if model.try_change(&mut rng) != Proposal::Valid {
+            continue;
+        }
+        let new_e = model.value();
+
+        if (new_e < low && old_e > new_e) || (new_e > high && old_e < new_e) {
+            model.undo();
+        } else {
+            old_e = new_e;
+        }
+
+        if new_e > low && new_e < high {
+            break;
+        }
+    }
+}
+
+/// Write the `visits` of the entropic sampling and the errors `1 / sqrt(H)` of `ln g`,
+/// which they imply for uncorrelated visits, as two lines after a comment. Correlated
+/// visits have larger errors, e.g., from the spread of independent runs, such that
+/// these are lower bounds. Bins without visits have an infinite error.
+fn write_visits(file: &mut impl Write, visits: &Histogram) -> Result<()> {
+    writeln!(file, "# visits of the entropic sampling and the errors of ln g")?;
+    for v in visits.data() {
+        write!(file, "{} ", v)?;
+    }
+    writeln!(file)?;
+    for v in visits.data() {
+        write!(file, "{} ", 1. / v.sqrt())?;
+    }
+    writeln!(file)?;
+    Ok(())
+}
+
+/// Write the density of states `g` as two lines, the bin borders and the values.
+pub(crate) fn write_dos(file: &mut impl Write, g: &Histogram, seed: Option<Seed>, streams: &[Seed]) -> Result<()> {
+    // let centers = g.centers();
+    let borders = g.borders();
+    let data = g.data();
+
+    if let Some(seed) = seed {
+        writeln!(file, "# seed = {}", seed)?;
+    }
+    seed::write_streams(file, streams)?;
+
+    for b in borders.iter() {
+        write!(file, "{} ", b)?;
+    }
+    writeln!(file)?;
+    for d in data.iter() {
+        write!(file, "{} ", d)?;
+    }
+    writeln!(file)?;
+
+    // for (c, d) in centers.iter().zip(data) {
+    //     writeln!(file, "{} {}", c, d)?;
+    // }
+
+    Ok(())
 }
@@ -0,0 +1,583 @@
+use std::cell::Cell;
+use std::io::{BufWriter, Write};
+use std::ops::ControlFlow;
+use std::rc::Rc;
+
+use rand::Rng;
+use rayon::prelude::*;
+
+use crate::histogram::Histogram;
+use crate::markovchain::MarkovChain;
+use crate::error::{LargedevError, Result};
+use crate::report::Report;
+use crate::seed::{DefaultRng, Seed};
+use crate::hooks::CancelToken;
+use crate::wanglandau::{self, HistogramCriterion, WangLandau, WangLandauConfig};
+
+/// Parameters of a windowed Wang-Landau simulation, see `WindowedWangLandau` for the builder.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WindowedWangLandauConfig {
+    /// parameters of the Wang-Landau simulation of every window,
+    /// `range` and `bins` refer to the full range, which is split into the windows
+    pub wang_landau: WangLandauConfig,
+    /// number of windows
+    pub windows: usize,
+    /// fraction of each window, which overlaps with its neighbor
+    pub overlap: f64,
+    /// number of threads to simulate the windows, `0` uses one thread per core
+    pub threads: usize,
+    /// number of pre-runs to adapt the boundaries of the windows, see
+    /// `WindowedWangLandau::adapt`
+    pub adapt_rounds: usize,
+    /// sweeps of every window per pre-run
+    pub adapt_sweeps: usize,
+    /// the first and one after the last bin of every window, equal widths if not set,
+    /// the adapted boundaries after a run with `adapt_rounds > 0`
+    pub boundaries: Option<Vec<(usize, usize)>>,
+}
+
+impl Default for WindowedWangLandauConfig {
+    fn default() -> Self {
+        WindowedWangLandauConfig {
+            wang_landau: WangLandauConfig::default(),
+            windows: 1,
+            overlap: 0.5,
+            threads: 0,
+            adapt_rounds: 0,
+            adapt_sweeps: 0,
+            boundaries: None,
+        }
+    }
+}
+
+impl WindowedWangLandauConfig {
+    pub fn validate(&self) -> Result<()> {
+        self.wang_landau.validate()?;
+        if self.windows == 0 {
+            return Err(LargedevError::InvalidParameter("at least one window is needed".into()))
+        }
+        if self.overlap.is_nan() || self.overlap <= 0. || self.overlap >= 1. {
+            return Err(LargedevError::InvalidParameter(format!("overlap must be in (0, 1), not {}", self.overlap)))
+        }
+        if self.adapt_rounds > 0 && self.adapt_sweeps == 0 {
+            return Err(LargedevError::InvalidParameter("the pre-runs need at least one sweep".into()))
+        }
+        if let Some(boundaries) = &self.boundaries {
+            if boundaries.len() != self.windows {
+                return Err(LargedevError::InvalidParameter(format!("{} boundaries for {} windows", boundaries.len(), self.windows)))
+            }
+            let bins = self.wang_landau.bins;
+            if boundaries.first().is_some_and(|w| w.0 != 0) || boundaries.last().is_some_and(|w| w.1 != bins) {
+                return Err(LargedevError::InvalidParameter("the windows need to cover all bins".into()))
+            }
+        }
+        let windows = self.window_bins();
+        if windows.iter().any(|&(a, b)| b < a + 2) {
+            return Err(LargedevError::InvalidParameter("too few bins for this number of windows".into()))
+        }
+        if !valid_overlaps(&windows) {
+            return Err(LargedevError::InvalidParameter("neighboring windows need to overlap by at least two bins".into()))
+        }
+        Ok(())
+    }
+
+    /// the first and one after the last bin of every window
+    pub(crate) fn window_bins(&self) -> Vec<(usize, usize)> {
+        if let Some(boundaries) = &self.boundaries {
+            return boundaries.clone()
+        }
+        let bins = self.wang_landau.bins;
+        // every bin has the same cost, i.e., all windows have the same width
+        place_windows(&vec![1.; bins], self.windows, self.overlap)
+    }
+}
+
+/// whether every window overlaps with the next one by at least two bins, but does
+/// not reach beyond it
+fn valid_overlaps(windows: &[(usize, usize)]) -> bool {
+    windows.windows(2).all(|w| w[0].1 > w[1].0 + 1 && w[0].0 < w[1].0 && w[0].1 < w[1].1)
+}
+
+/// Place `n` windows, such that the sum of the `cost` of the bins is the same in every
+/// window and the neighbors share the fraction `overlap` of it. Returns the first and
+/// one after the last bin of every window.
+fn place_windows(cost: &[f64], n: usize, overlap: f64) -> Vec<(usize, usize)> {
+    let bins = cost.len();
+    // the cumulative cost before every bin
+    let mut cumulative = vec![0.; bins + 1];
+    for (i, c) in cost.iter().enumerate() {
+        cumulative[i + 1] = cumulative[i] + c;
+    }
+    let total = cumulative[bins];
+    let len = total / (1. + (n as f64 - 1.) * (1. - overlap));
+    let stride = len * (1. - overlap);
+    // the border of the bins, which is closest to the cumulative cost `x`
+    let border = |x: f64| -> usize {
+        let i = cumulative.partition_point(|&c| c < x).min(bins);
+        if i > 0 && x - cumulative[i - 1] < cumulative[i] - x { i - 1 } else { i }
+    };
+
+    (0..n).map(|k| {
+        let a = border(k as f64 * stride);
+        let b = if k + 1 == n {
+            bins
+        } else {
+            border(k as f64 * stride + len)
+        };
+        (a, b)
+    }).collect()
+}
+
+/// A builder used to set up Wang-Landau sampling, where the range is split into
+/// overlapping windows, which are simulated independently on a thread pool. Afterwards
+/// the densities of states of the windows are merged. Every window uses an independent
+/// random number stream, derived from a master seed. The model needs to be `Clone` and
+/// `Send`, since every window simulates its own copy, e.g.:
+///
+/// ```ignore
+/// let report = WindowedWangLandau::new(model)
+///    .range(low, high)
+///    .bins(1000)
+///    .windows(16)
+///    .overlap(0.5)
+///    .threads(8)
+///    .build()?
+///    .run(&mut rng, outfile)?;
+/// ```
+///
+/// Like `WangLandau`, every window accumulates its estimate in `f64`, only the merged
+/// result can be converted to `f32`.
+pub struct WindowedWangLandau<MC> {
+    model: MC,
+    config: WindowedWangLandauConfig,
+    cancel: Option<CancelToken>,
+}
+
+impl<MC: MarkovChain> WindowedWangLandau<MC> {
+    pub fn new(model: MC) -> Self {
+        WindowedWangLandau::with_config(model, WindowedWangLandauConfig::default())
+    }
+
+    pub fn with_config(model: MC, config: WindowedWangLandauConfig) -> Self {
+        WindowedWangLandau::<MC> {
+            model,
+            config,
+            cancel: None,
+        }
+    }
+
+    /// the full energy range to sample, `low` is included, `high` is excluded
+    pub fn range(mut self, low: f64, high: f64) -> Self {
+        self.config.wang_landau.range = Some((low, high));
+        self
+    }
+
+    /// number of bins of the full range
+    pub fn bins(mut self, bins: usize) -> Self {
+        self.config.wang_landau.bins = bins;
+        self
+    }
+
+    pub fn sweep(mut self, sweep: usize) -> Self {
+        self.config.wang_landau.sweep = sweep;
+        self
+    }
+
+    pub fn lnf_final(mut self, lnf_final: f64) -> Self {
+        self.config.wang_landau.lnf_final = lnf_final;
+        self
+    }
+
+    /// the exponent of the schedule `t^-alpha` in the second phase, see `WangLandau::alpha`
+    pub fn alpha(mut self, alpha: f64) -> Self {
+        self.config.wang_landau.alpha = alpha;
+        self
+    }
+
+    /// when a stage of the first phase is complete, see `HistogramCriterion`
+    pub fn criterion(mut self, criterion: HistogramCriterion) -> Self {
+        self.config.wang_landau.criterion = criterion;
+        self
+    }
+
+    /// the master seed used by `run_seeded`, if not set, a seed is drawn from entropy
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Self {
+        self.config.wang_landau.seed = Some(seed.into());
+        self
+    }
+
+    pub fn windows(mut self, windows: usize) -> Self {
+        self.config.windows = windows;
+        self
+    }
+
+    pub fn overlap(mut self, overlap: f64) -> Self {
+        self.config.overlap = overlap;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.config.threads = threads;
+        self
+    }
+
+    /// Adapt the boundaries of the windows by `rounds` pre-runs of `sweeps` sweeps of
+    /// Wang-Landau sampling in every window, such that all windows have a comparable
+    /// tunneling time, i.e., the time a walker needs to traverse its window, instead
+    /// of equal widths. The tunneling time of a diffusive walker grows with the square
+    /// of the width of its window, so in every pre-run the square root of the measured
+    /// tunneling time of every window is distributed over its bins and the windows are
+    /// placed, such that they have the same sum. Windows without any traversal count
+    /// as if they needed twice the sweeps of the pre-run. The adapted boundaries are
+    /// written to the output and are available from `config` after the run.
+    pub fn adapt(mut self, rounds: usize, sweeps: usize) -> Self {
+        self.config.adapt_rounds = rounds;
+        self.config.adapt_sweeps = sweeps;
+        self
+    }
+
+    /// a token to cancel the simulation of all windows, see `CancelToken`
+    pub fn cancel_token(mut self, token: impl Into<CancelToken>) -> Self {
+        self.cancel = Some(token.into());
+        self
+    }
+
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// validate the parameters and return the configured sampler
+    pub fn build(self) -> Result<WindowedWangLandauRun<MC>> {
+        self.config.validate()?;
+        Ok(WindowedWangLandauRun {
+            model: self.model,
+            config: self.config,
+            cancel: self.cancel,
+        })
+    }
+}
+
+/// A configured windowed Wang-Landau sampler, see `WindowedWangLandau` for the builder.
+pub struct WindowedWangLandauRun<MC> {
+    /// the initial state, which is copied to every window
+    model: MC,
+    config: WindowedWangLandauConfig,
+    cancel: Option<CancelToken>,
+}
+
+impl<MC: MarkovChain + Clone + Send> WindowedWangLandauRun<MC> {
+    pub fn config(&self) -> &WindowedWangLandauConfig {
+        &self.config
+    }
+
+    /// the initial state of the model, which is copied to every window
+    pub fn model(&self) -> &MC {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut MC {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> MC {
+        self.model
+    }
+
+    /// The master seed of the windows is drawn from `rng`, the merged density of states
+    /// is written like the one of `WangLandauRun::run`. The report accumulates the
+    /// reports of all windows. It needs threads, which are not available on,
+    /// e.g., `wasm32-unknown-unknown`.
+    pub fn run(&mut self, rng: &mut (impl Rng + ?Sized), file: &mut impl Write) -> Result<Report> {
+        let master = Seed::from_rng(rng);
+        self.simulate(master, file, None)
+    }
+
+    /// like `run`, but with the master seed from the configuration (or from entropy),
+    /// which is written to the output and the report
+    pub fn run_seeded(&mut self, file: &mut impl Write) -> Result<Report> {
+        let seed = self.config.wang_landau.seed.unwrap_or_else(Seed::from_entropy);
+        self.simulate(seed, file, Some(seed))
+    }
+
+    fn simulate(&mut self, master: Seed, file: &mut impl Write, seed: Option<Seed>) -> Result<Report> {
+        let mut file = BufWriter::new(file);
+
+        let mut pre_report = Report::default();
+        for round in 0..self.config.adapt_rounds {
+            if !self.adapt_windows(master, round, &mut pre_report)? {
+                break
+            }
+        }
+
+        let (g, mut report) = self.estimate(master)?;
+        report.seed = seed;
+        report.streams = (0..self.config.window_bins().len()).map(|k| master.stream(k as u64)).collect();
+        report.tries += pre_report.tries;
+        report.rejects += pre_report.rejects;
+        report.invalid += pre_report.invalid;
+        report.stopped |= pre_report.stopped;
+
+        if self.config.adapt_rounds > 0 {
+            let (low, high) = self.config.wang_landau.range.unwrap();
+            let width = (high - low) / self.config.wang_landau.bins as f64;
+            write!(file, "# adapted windows =")?;
+            for (a, b) in self.config.window_bins() {
+                write!(file, " [{}, {})", low + a as f64 * width, low + b as f64 * width)?;
+            }
+            writeln!(file)?;
+        }
+        wanglandau::write_dos(&mut file, &g, seed, &report.streams)?;
+        file.flush()?;
+
+        Ok(report)
+    }
+
+    /// simulate all windows in parallel and merge their densities of states
+    fn estimate(&self, master: Seed) -> Result<(Histogram, Report)> {
+        let windows = self.simulate_windows(master, |_| true)?;
+        self.merge_windows(windows)
+    }
+
+    /// simulate the windows with an index `k`, for which `select(k)` is true, in parallel
+    /// and return their densities of states and reports ordered by their index
+    pub(crate) fn simulate_windows(&self, master: Seed, select: impl Fn(usize) -> bool) -> Result<Vec<(Histogram, Report)>> {
+        self.run_windows(master, select, |builder, _, rng| {
+            let mut report = Report::default();
+            let g = builder.build()?.estimate(rng, &mut report)?;
+            Ok((g, report))
+        })
+    }
+
+    /// Run `job` for every window with an index `k`, for which `select(k)` is true, in
+    /// parallel, with a builder for the window, its configuration and a random number
+    /// generator from the `k`-th stream of `master`. Returns the results ordered by the
+    /// index.
+    fn run_windows<T: Send>(
+        &self,
+        master: Seed,
+        select: impl Fn(usize) -> bool,
+        job: impl Fn(WangLandau<MC>, &WangLandauConfig, &mut DefaultRng) -> Result<T> + Sync,
+    ) -> Result<Vec<T>> {
+        let (low, high) = self.config.wang_landau.range.unwrap();
+        let bins = self.config.wang_landau.bins;
+        let width = (high - low) / bins as f64;
+
+        let jobs: Vec<_> = self.config.window_bins()
+            .into_iter()
+            .enumerate()
+            .filter(|(k, _)| select(*k))
+            .map(|(k, (a, b))| {
+                let mut config = self.config.wang_landau.clone();
+                config.range = Some((low + a as f64 * width, low + b as f64 * width));
+                config.bins = b - a;
+                config.seed = Some(master.stream(k as u64));
+                (self.model.clone(), config, self.cancel.clone())
+            })
+            .collect();
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.config.threads)
+            .build()
+            .map_err(|e| LargedevError::InvalidParameter(format!("can not create the thread pool: {}", e)))?;
+
+        pool.install(|| {
+            jobs.into_par_iter()
+                .map(|(model, config, cancel)| {
+                    let mut rng = config.seed.unwrap().rng();
+                    let mut builder = WangLandau::with_config(model, config.clone());
+                    if let Some(cancel) = cancel {
+                        builder = builder.cancel_token(cancel);
+                    }
+                    job(builder, &config, &mut rng)
+                })
+                .collect()
+        })
+    }
+
+    /// Measure the tunneling times of all windows in the pre-run `round` and place new
+    /// windows, see `WindowedWangLandau::adapt`. The proposals are counted in
+    /// `report`. Returns whether the adaptation should continue.
+    fn adapt_windows(&mut self, master: Seed, round: usize, report: &mut Report) -> Result<bool> {
+        let sweeps = self.config.adapt_sweeps;
+        // the pre-runs must not reuse the streams of the windows
+        let seed = master.stream(u64::MAX - round as u64);
+        let measured = self.run_windows(seed, |_| true, |builder, config, rng| {
+            let (low, high) = config.range.unwrap();
+            let width = (high - low) / config.bins as f64;
+            // the end of the window, which the walker visited last, and the number of
+            // traversals between the ends
+            let traversals = Rc::new(Cell::new((None, 0)));
+            let counter = traversals.clone();
+            let builder = builder.on_sweep(move |_, state| {
+                let end = if state.energy < low + width {
+                    Some(false)
+                } else if state.energy >= high - width {
+                    Some(true)
+                } else {
+                    None
+                };
+                let (last, n) = counter.get();
+                if end.is_some() && end != last {
+                    counter.set((end, if last.is_some() { n + 1 } else { n }));
+                }
+                if state.sweep >= sweeps {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            });
+            let mut report = Report::default();
+            builder.build()?.estimate(rng, &mut report)?;
+            let n = traversals.get().1;
+            let tunneling = if n > 0 { sweeps as f64 / n as f64 } else { 2. * sweeps as f64 };
+            Ok((tunneling, report))
+        })?;
+
+        let windows = self.config.window_bins();
+        let mut cost = vec![0.; self.config.wang_landau.bins];
+        let mut covered = vec![0; self.config.wang_landau.bins];
+        for (&(a, b), (tunneling, r)) in windows.iter().zip(&measured) {
+            report.tries += r.tries;
+            report.rejects += r.rejects;
+            report.invalid += r.invalid;
+            // cancelled pre-runs are not representative
+            if r.stopped && self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                report.stopped = true;
+                return Ok(false)
+            }
+            for i in a..b {
+                cost[i] += tunneling.sqrt() / (b - a) as f64;
+                covered[i] += 1;
+            }
+        }
+        for (c, &n) in cost.iter_mut().zip(&covered) {
+            *c /= n as f64;
+        }
+        trace_event!(debug!(round, tunneling = ?measured.iter().map(|m| m.0).collect::<Vec<_>>(), "measured the tunneling times"));
+
+        let placed = place_windows(&cost, self.config.windows, self.config.overlap);
+        if placed.iter().any(|&(a, b)| b < a + 2) || !valid_overlaps(&placed) {
+            trace_event!(warn!(round, "the adapted windows are too narrow, keeping the previous ones"));
+            return Ok(false)
+        }
+        self.config.boundaries = Some(placed);
+        Ok(true)
+    }
+
+    /// merge the densities of states of all windows, ordered by their index, and
+    /// accumulate their reports
+    pub(crate) fn merge_windows(&self, windows: Vec<(Histogram, Report)>) -> Result<(Histogram, Report)> {
+        let (low, high) = self.config.wang_landau.range.unwrap();
+        let bins = self.config.wang_landau.bins;
+        let width = (high - low) / bins as f64;
+
+        trace_event!(info!(windows = windows.len(), "merging the windows"));
+        let mut report = Report::default();
+        let mut dos = Vec::new();
+        for (g, r) in windows {
+            report.tries += r.tries;
+            report.rejects += r.rejects;
+            report.invalid += r.invalid;
+            report.stopped |= r.stopped;
+            dos.push(g);
+        }
+
+        Ok((merge(&dos, low, width, bins)?, report))
+    }
+}
+
+/// Merge the densities of states `dos` of neighboring windows on a common grid of `bins`
+/// bins of size `width` starting at `low`. The logarithmic densities of states are only
+/// defined up to an additive constant, so every window is shifted to agree on average
+/// with the previous windows in their overlap. In the overlap, the first half is taken
+/// from the previous windows, the second half from the new window. Masked bins, whose
+/// estimate is `-inf`, do not contribute to the shift and are never joined at.
+fn merge(dos: &[Histogram], low: f64, width: f64, bins: usize) -> Result<Histogram> {
+    let mut merged: Vec<Option<f64>> = vec![None; bins];
+
+    for g in dos {
+        // values of this window on the common grid
+        let mut window: Vec<Option<f64>> = vec![None; bins];
+        for (c, &d) in g.centers().iter().zip(g.data()) {
+            let idx = ((c - low) / width).floor() as usize;
+            if idx < bins {
+                window[idx] = Some(d);
+            }
+        }
+
+        let finite = |v: Option<f64>| v.is_some_and(f64::is_finite);
+        let overlap: Vec<usize> = (0..bins)
+            .filter(|&i| finite(merged[i]) && finite(window[i]))
+            .collect();
+
+        // the first window defines the reference
+        let shift = if merged.iter().all(Option::is_none) {
+            0.
+        } else if overlap.is_empty() {
+            return Err(LargedevError::HistogramRange("neighboring windows do not overlap".into()))
+        } else {
+            overlap.iter()
+                .map(|&i| merged[i].unwrap() - window[i].unwrap())
+                .sum::<f64>() / overlap.len() as f64
+        };
+
+        let join = overlap.get(overlap.len() / 2).cloned().unwrap_or(0);
+        for i in join..bins {
+            if let Some(v) = window[i] {
+                merged[i] = Some(v + shift);
+            }
+        }
+    }
+
+    // windows at the border of the range may be trimmed, if some values are not reachable
+    let first = merged.iter().position(Option::is_some);
+    let last = merged.iter().rposition(Option::is_some);
+    let (first, last) = match (first, last) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Err(LargedevError::HistogramRange("no window has entries".into()))
+    };
+
+    let mut g = Histogram::new(low + first as f64 * width, low + (last + 1) as f64 * width, last + 1 - first)?;
+    for (n, m) in merged[first..=last].iter().enumerate() {
+        *g.idx(n) = m.ok_or_else(|| LargedevError::HistogramRange("neighboring windows do not overlap".into()))?;
+    }
+
+    Ok(g)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the cost of the bins of every window
+    fn work(cost: &[f64], windows: &[(usize, usize)]) -> Vec<f64> {
+        windows.iter().map(|&(a, b)| cost[a..b].iter().sum()).collect()
+    }
+
+    #[test]
+    fn windows_share_the_work() {
+        // the first quarter of the bins is four times as expensive
+        let cost: Vec<f64> = (0..64).map(|i| if i < 16 { 4. } else { 1. }).collect();
+        let windows = place_windows(&cost, 4, 0.5);
+        assert_eq!(windows.first().unwrap().0, 0);
+        assert_eq!(windows.last().unwrap().1, 64);
+        assert!(valid_overlaps(&windows));
+
+        let equal = place_windows(&[1.; 64], 4, 0.5);
+        assert_eq!(equal, vec![(0, 26), (13, 38), (26, 51), (38, 64)]);
+        // the expensive windows are narrower than with equal costs
+        assert!(windows[0].1 < equal[0].1);
+        let work = work(&cost, &windows);
+        let mean = work.iter().sum::<f64>() / work.len() as f64;
+        assert!(work.iter().all(|w| (w - mean).abs() < 0.1 * mean), "{:?}", work);
+    }
+}
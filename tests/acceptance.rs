@@ -0,0 +1,40 @@
+use largedev::{ln_boltzmann, AcceptanceRule, GlauberRule, MetropolisRule};
+
+#[test]
+fn metropolis_probabilities() {
+    assert_eq!(MetropolisRule.probability(0.), 1.);
+    assert_eq!(MetropolisRule.probability(3.), 1.);
+    assert!((MetropolisRule.probability(-1.) - (-1f64).exp()).abs() < 1e-15);
+    assert_eq!(MetropolisRule.probability(f64::NEG_INFINITY), 0.);
+    assert_eq!(MetropolisRule.probability(f64::NAN), 0.);
+}
+
+#[test]
+fn glauber_probabilities() {
+    assert_eq!(GlauberRule.probability(0.), 0.5);
+    assert_eq!(GlauberRule.probability(f64::INFINITY), 1.);
+    assert_eq!(GlauberRule.probability(f64::NEG_INFINITY), 0.);
+    assert_eq!(GlauberRule.probability(-1e308), 0.);
+    assert_eq!(GlauberRule.probability(f64::NAN), 0.);
+}
+
+#[test]
+fn extreme_products() {
+    // the limits of zero and infinite temperature
+    assert_eq!(ln_boltzmann(0., f64::INFINITY), 0.);
+    assert_eq!(ln_boltzmann(f64::INFINITY, 0.), 0.);
+    assert_eq!(ln_boltzmann(f64::INFINITY, 1e-300), f64::NEG_INFINITY);
+    assert_eq!(ln_boltzmann(f64::INFINITY, -1e-300), f64::INFINITY);
+    // an overflowing product rejects or accepts, but is never `NaN`
+    assert_eq!(ln_boltzmann(1e300, 1e300), f64::NEG_INFINITY);
+    assert!(!MetropolisRule.accept(ln_boltzmann(1e300, 1e300), 0.));
+    assert!(MetropolisRule.accept(ln_boltzmann(1e300, -1e300), 0.999));
+}
+
+#[test]
+fn extreme_probabilities() {
+    // a probability of `0` is never and one of `1` always accepted
+    assert!(!MetropolisRule.accept(f64::NEG_INFINITY, 0.));
+    assert!(MetropolisRule.accept(0., 1. - f64::EPSILON));
+    assert!(!MetropolisRule.accept(f64::NAN, 0.));
+}
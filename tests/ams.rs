@@ -0,0 +1,93 @@
+#![cfg(feature = "testing")]
+
+use std::io;
+
+use largedev::testing::MockChain;
+use largedev::{Ams, DirectSamplable, LargedevError, MarkovChain, Model, Proposal, Restorable, Seed};
+use rand::Rng;
+
+/// a random variable with the density `exp(-x)`, whose tail is `P(x > a) = exp(-a)`
+struct Exponential {
+    x: f64,
+    old: f64,
+}
+
+impl Model for Exponential {
+    fn value(&self) -> f64 {
+        self.x
+    }
+}
+
+impl DirectSamplable for Exponential {
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        self.x = -(1. - rng.gen::<f64>()).ln();
+    }
+}
+
+impl MarkovChain for Exponential {
+    fn change(&mut self, rng: &mut impl Rng) {
+        self.old = self.x;
+        self.x += rng.gen_range(-1. ..1.);
+    }
+
+    fn try_change(&mut self, rng: &mut impl Rng) -> Proposal {
+        self.change(rng);
+        if self.x < 0. {
+            self.undo();
+            return Proposal::Invalid
+        }
+        Proposal::Valid
+    }
+
+    fn undo(&mut self) {
+        self.x = self.old;
+    }
+
+    /// the ratio of the densities, such that the symmetric random walk leaves the
+    /// exponential distribution invariant
+    fn ln_proposal_ratio(&self) -> f64 {
+        self.old - self.x
+    }
+}
+
+impl Restorable for Exponential {
+    type Snapshot = f64;
+
+    fn snapshot(&self) -> f64 {
+        self.x
+    }
+
+    fn restore(&mut self, snapshot: &f64) {
+        self.x = *snapshot;
+    }
+}
+
+#[test]
+fn exponential_tail() {
+    let particles = 400;
+    let report = Ams::new(Exponential { x: 0., old: 0. })
+        .threshold(10.)
+        .particles(particles)
+        .sweep(20)
+        .build()
+        .unwrap()
+        .run(&mut Seed::new(169).rng(), &mut io::sink())
+        .unwrap();
+    assert!(!report.stopped);
+    // the relative error of the estimate is about `sqrt(a / particles)`
+    let sigma = (10. / particles as f64).sqrt();
+    assert!((report.ln_probability + 10.).abs() < 3. * sigma, "ln P = {}", report.ln_probability);
+    assert!(report.lower < report.probability && report.probability < report.upper);
+}
+
+#[test]
+fn nan_values() {
+    let mut run = Ams::new(MockChain::new(vec![0., f64::NAN, 1.]))
+        .threshold(10.)
+        .particles(10)
+        .kill(1)
+        .build()
+        .unwrap();
+    let result = run.run(&mut Seed::new(1).rng(), &mut io::sink());
+    assert!(matches!(result, Err(LargedevError::NanValue)));
+}
@@ -0,0 +1,161 @@
+use largedev::analysis::{autocorrelation, blocking, cross_correlation, integrated_time, reweight, StreamingAutocorrelation};
+use largedev::Seed;
+use rand::Rng;
+
+/// `spins` independent two-level systems with the energies 0 and 1 at the inverse
+/// temperature `beta`, the energy is also the observable
+fn two_level(spins: usize, beta: f64, samples: usize) -> Vec<f64> {
+    let mut rng = Seed::new(198).rng();
+    let p = excited(beta);
+    (0..samples)
+        .map(|_| (0..spins).filter(|_| rng.gen::<f64>() < p).count() as f64)
+        .collect()
+}
+
+/// the probability of the excited level
+fn excited(beta: f64) -> f64 {
+    (-beta).exp() / (1. + (-beta).exp())
+}
+
+#[test]
+fn reweight_two_level_system() {
+    let energies = two_level(100, 1., 10000);
+    let points = reweight(&energies, &energies, 1., &[1., 1.05, 3.], 20).unwrap();
+
+    let sampled = &points[0];
+    assert_eq!(sampled.overlap, 1.);
+    assert!(sampled.reliable);
+
+    let near = &points[1];
+    let exact = 100. * excited(1.05);
+    assert!(near.reliable, "{:?}", near);
+    assert!(near.overlap > 0.5, "{:?}", near);
+    assert!((near.mean - exact).abs() < 4. * near.error, "{} != {} +- {}", near.mean, exact, near.error);
+
+    // the typical energies at `beta = 3` are never sampled at `beta = 1`
+    let far = &points[2];
+    assert!(!far.reliable, "{:?}", far);
+    assert!(far.overlap < 0.1);
+}
+
+#[test]
+fn reweight_invalid_input() {
+    let energies = [0., 1., 2., 3.];
+    assert!(reweight(&energies, &energies[1..], 1., &[1.], 2).is_err());
+    assert!(reweight(&energies, &energies, 1., &[1.], 1).is_err());
+    assert!(reweight(&energies, &energies, 1., &[1.], 5).is_err());
+    assert!(reweight(&energies, &energies, 1., &[f64::NAN], 2).is_err());
+}
+
+/// the autoregressive process `x_{t+1} = a x_t + u_t` with uniform noise, whose
+/// integrated autocorrelation time is `(1 + a) / (2 (1 - a))`
+fn ar1(a: f64, n: usize, seed: u64) -> Vec<f64> {
+    let mut rng = Seed::new(seed).rng();
+    let mut x = 0.;
+    (0..n)
+        .map(|_| {
+            x = a * x + rng.gen::<f64>() - 0.5;
+            x
+        })
+        .collect()
+}
+
+#[test]
+fn streaming_autocorrelation_matches_the_batch() {
+    let series = ar1(0.8, 1000, 199);
+    let mut streaming = StreamingAutocorrelation::new(30);
+    assert!(streaming.is_empty());
+    for (i, &x) in series.iter().enumerate() {
+        if i == 30 {
+            assert!(streaming.correlation().is_err());
+        }
+        streaming.push(x);
+    }
+    assert_eq!(streaming.len(), 1000);
+    let batch = autocorrelation(&series, 30).unwrap();
+    for (a, b) in streaming.correlation().unwrap().iter().zip(&batch) {
+        assert!((a - b).abs() < 1e-10 * batch[0], "{} != {}", a, b);
+    }
+}
+
+#[test]
+fn autocorrelation_matches_the_definition() {
+    // long enough for the FFT, if the `fft` feature is enabled
+    let series = ar1(0.8, 5000, 1199);
+    let n = series.len();
+    let mean = series.iter().sum::<f64>() / n as f64;
+    let correlation = autocorrelation(&series, 50).unwrap();
+    assert_eq!(correlation.len(), 51);
+    for (t, c) in correlation.iter().enumerate() {
+        let direct = (0..n - t).map(|i| (series[i] - mean) * (series[i + t] - mean)).sum::<f64>() / (n - t) as f64;
+        assert!((c - direct).abs() < 1e-10 * correlation[0], "{}: {} != {}", t, c, direct);
+    }
+}
+
+#[test]
+fn integrated_time_of_ar1() {
+    let a = 0.8;
+    let exact = (1. + a) / (2. * (1. - a));
+    let series = ar1(a, 100000, 2199);
+    let tau = integrated_time(&autocorrelation(&series, 100).unwrap()).unwrap();
+    assert!((tau - exact).abs() < 0.1 * exact, "{} != {}", tau, exact);
+
+    // the window needs at least `5 tau` lags
+    assert_eq!(integrated_time(&autocorrelation(&series, 10).unwrap()), None);
+    assert_eq!(integrated_time(&[]), None);
+    assert_eq!(integrated_time(&[0., 0.]), None);
+}
+
+#[test]
+fn cross_correlation_of_a_delayed_copy() {
+    // `y` follows `x` with a delay of 3 steps
+    let delay = 3;
+    let noise = ar1(0., 2000 + delay, 200);
+    let x = &noise[delay..];
+    let y = &noise[..2000];
+    let max_lag = 10;
+    let correlation = cross_correlation(x, y, max_lag).unwrap();
+    assert_eq!(correlation.len(), 2 * max_lag + 1);
+    let peak = correlation.iter()
+        .enumerate()
+        .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+        .unwrap()
+        .0;
+    assert_eq!(peak, max_lag + delay);
+
+    // the cross-correlation with itself is the autocorrelation at both signs
+    let auto = autocorrelation(x, max_lag).unwrap();
+    let both = cross_correlation(x, x, max_lag).unwrap();
+    for t in 0..=max_lag {
+        assert!((both[max_lag + t] - auto[t]).abs() < 1e-12);
+        assert!((both[max_lag - t] - auto[t]).abs() < 1e-12);
+    }
+    assert!(cross_correlation(x, &y[1..], max_lag).is_err());
+}
+
+#[test]
+fn blocking_of_ar1() {
+    let a = 0.8;
+    let tau = (1. + a) / (2. * (1. - a));
+    let variance = 1. / 12. / (1. - a * a);
+    let n = 1 << 17;
+    let series = ar1(a, n, 201);
+    let result = blocking(&series).unwrap();
+    let exact = (2. * tau * variance / n as f64).sqrt();
+    assert!(result.reliable);
+    assert!((result.error - exact).abs() < 0.15 * exact, "{} != {}", result.error, exact);
+    // the naive error of the uncorrelated values is too small
+    assert!(result.levels[0].error < 0.5 * exact);
+    assert_eq!(result.levels[0].blocks, n);
+    assert_eq!(result.levels[3].size, 8);
+}
+
+#[test]
+fn blocking_of_a_short_series() {
+    // far shorter than its autocorrelation time of about 200
+    let series = ar1(0.995, 256, 1201);
+    let result = blocking(&series).unwrap();
+    assert!(!result.reliable);
+    assert_eq!(result.plateau, None);
+    assert!(blocking(&series[..15]).is_err());
+}
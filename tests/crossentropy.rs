@@ -0,0 +1,71 @@
+use std::io;
+
+use largedev::{CrossEntropy, DirectSamplable, LargedevError, Model, Parametric, Seed};
+use rand::Rng;
+
+/// an exponential random variable with the tunable mean `mean`, whose tail at the
+/// nominal mean `1` is `P(x >= a) = exp(-a)`
+struct Exponential {
+    x: f64,
+    mean: f64,
+}
+
+impl Model for Exponential {
+    fn value(&self) -> f64 {
+        self.x
+    }
+}
+
+impl DirectSamplable for Exponential {
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        self.x = -self.mean * (1. - rng.gen::<f64>()).ln();
+    }
+}
+
+impl Parametric for Exponential {
+    fn parameters(&self) -> Vec<f64> {
+        vec![self.mean]
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.mean = parameters[0];
+    }
+
+    fn ln_density(&self, parameters: &[f64]) -> f64 {
+        -parameters[0].ln() - self.x / parameters[0]
+    }
+
+    fn statistics(&self) -> Vec<f64> {
+        vec![self.x]
+    }
+
+    fn fit(&self, mean: &[f64]) -> Vec<f64> {
+        mean.to_vec()
+    }
+}
+
+#[test]
+fn exponential_tail() {
+    let report = CrossEntropy::new(Exponential { x: 0., mean: 1. })
+        .threshold(20.)
+        .build()
+        .unwrap()
+        .run(&mut Seed::new(170).rng(), &mut io::sink())
+        .unwrap();
+    assert!(!report.stopped);
+    let exact = (-20f64).exp();
+    assert!(report.error < 0.1 * exact, "{} +- {}", report.probability, report.error);
+    assert!((report.probability - exact).abs() < 4. * report.error, "{} +- {} != {}", report.probability, report.error, exact);
+    // the optimal tilt of an exponential tail has about the mean `a + 1`
+    assert!((report.parameters[0] - 21.).abs() < 3., "{:?}", report.parameters);
+}
+
+#[test]
+fn nan_values() {
+    let result = CrossEntropy::new(Exponential { x: 0., mean: f64::NAN })
+        .threshold(20.)
+        .build()
+        .unwrap()
+        .run(&mut Seed::new(1).rng(), &mut io::sink());
+    assert!(matches!(result, Err(LargedevError::NanValue)));
+}
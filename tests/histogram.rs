@@ -0,0 +1,186 @@
+use largedev::{Axis, Histogram, HistogramNd, LargedevError, WeightedHistogram};
+
+#[test]
+fn convert_nd_to_f32() {
+    let mut g: HistogramNd = HistogramNd::new(vec![Axis::new(0., 4., 4).unwrap(), Axis::new(-1., 1., 2).unwrap()]).unwrap();
+    g.add(&[2.5, 0.5], 1.);
+    // an increment, which `f32` can not resolve
+    g.add(&[2.5, 0.5], 1e-8);
+    let h = g.convert::<f32>();
+    assert_eq!(h.shape(), g.shape());
+    assert_eq!(h.data().iter().filter(|&&x| x != 0.).count(), 1);
+    assert_eq!(g.at(&[2.5, 0.5]), Some(1. + 1e-8));
+    assert_eq!(h.at(&[2.5, 0.5]), Some(1.));
+}
+
+#[test]
+fn min_ignores_nan() {
+    let mut h = Histogram::new(0., 3., 3).unwrap();
+    h.add(0.5, 2.);
+    h.add(1.5, f64::NAN);
+    h.add(2.5, 1.);
+    assert_eq!(h.min(), 1.);
+
+    let mut h = Histogram::new(0., 1., 1).unwrap();
+    h.add(0.5, f64::NAN);
+    assert_eq!(h.min(), f64::INFINITY);
+}
+
+fn grid() -> HistogramNd {
+    // 3 bins of width 1 along the first axis, 2 bins of width 0.5 along the second
+    HistogramNd::new(vec![Axis::new(0., 3., 3).unwrap(), Axis::new(-1., 0., 2).unwrap()]).unwrap()
+}
+
+#[test]
+fn nd_indexing() {
+    let g = grid();
+    assert_eq!(g.dimensions(), 2);
+    assert_eq!(g.shape(), vec![3, 2]);
+    assert_eq!(g.bins(), 6);
+
+    // the last axis is contiguous
+    assert_eq!(g.index_of(&[0.5, -0.75]), Some(0));
+    assert_eq!(g.index_of(&[0.5, -0.25]), Some(1));
+    assert_eq!(g.index_of(&[2.5, -0.25]), Some(5));
+    assert_eq!(g.flat_index(&[1, 1]), Some(3));
+    for index in 0..g.bins() {
+        assert_eq!(g.flat_index(&g.indices(index)), Some(index));
+        assert_eq!(g.index_of(&g.center(index)), Some(index));
+    }
+    assert_eq!(g.center(3), vec![1.5, -0.25]);
+
+    // low is included, high is excluded on every axis
+    assert_eq!(g.index_of(&[0., -1.]), Some(0));
+    assert_eq!(g.index_of(&[3., -0.5]), None);
+    assert_eq!(g.index_of(&[1., 0.]), None);
+    assert_eq!(g.index_of(&[f64::NAN, -0.5]), None);
+    assert_eq!(g.index_of(&[1.]), None);
+    assert_eq!(g.flat_index(&[3, 0]), None);
+    assert_eq!(g.flat_index(&[0, 0, 0]), None);
+}
+
+#[test]
+fn nd_marginals() {
+    let mut g = grid();
+    g.add(&[0.5, -0.75], 1.);
+    g.add(&[0.5, -0.25], 2.);
+    g.add(&[2.5, -0.25], 4.);
+    // outside, it is not counted
+    g.add(&[3.5, -0.25], 8.);
+
+    let first = g.marginal(0);
+    assert_eq!([first.at(0.5), first.at(1.5), first.at(2.5)], [Some(3.), Some(0.), Some(4.)]);
+    let second = g.marginal(1);
+    assert_eq!(second.at(-0.75), Some(1.));
+    assert_eq!(second.at(-0.25), Some(6.));
+    assert_eq!(g.min(), 0.);
+    assert_eq!(g.mean(), 7. / 6.);
+}
+
+#[test]
+fn nd_invalid_axes() {
+    assert!(HistogramNd::<f64>::new(vec![]).is_err());
+    assert!(Axis::new(1., 0., 3).is_err());
+    assert!(Axis::new(0., 1., 0).is_err());
+    assert!(HistogramNd::<f64>::new(vec![Axis::new(0., 1., 1 << 22).unwrap(); 3]).is_err());
+}
+
+#[test]
+fn nd_min_ignores_nan() {
+    let mut g = grid();
+    g.add(&[0.5, -0.75], f64::NAN);
+    g.add(&[0.5, -0.25], 1.);
+    g.add(&[1.5, -0.75], 1.);
+    assert_eq!(g.min(), 0.);
+}
+
+#[test]
+fn ratio_with_poisson_errors() {
+    let mut a = Histogram::new(0., 3., 3).unwrap();
+    let mut b = Histogram::new(0., 3., 3).unwrap();
+    for _ in 0..4 {
+        a.count(0.5);
+    }
+    for _ in 0..9 {
+        a.count(2.5);
+    }
+    for _ in 0..16 {
+        b.count(0.5);
+    }
+    for _ in 0..2 {
+        b.count(1.5);
+    }
+    let ratio = a.ratio(&b).unwrap();
+    // `r = a / b` with the error `sqrt(a + r^2 b) / b`
+    assert_eq!(ratio[0].0, 0.25);
+    assert!((ratio[0].1 - 5_f64.sqrt() / 16.).abs() < 1e-15);
+    assert_eq!(ratio[1], (0., 0.));
+    assert!(ratio[2].0.is_nan() && ratio[2].1.is_nan());
+
+    let other = Histogram::new(0., 3., 4).unwrap();
+    assert!(matches!(a.ratio(&other), Err(LargedevError::HistogramRange(_))));
+}
+
+#[test]
+fn weighted_ratio() {
+    let mut a = WeightedHistogram::new(0., 2., 2).unwrap();
+    let mut b = WeightedHistogram::new(0., 2., 2).unwrap();
+    a.add(0.5, 1.);
+    a.add(0.5, 2.);
+    for _ in 0..3 {
+        b.add(0.5, 2.);
+    }
+    a.add(1.5, 1.);
+    let ratio = a.ratio(&b).unwrap();
+    // the sums of the weights `3` and `6` with the variances `5` and `12`
+    assert_eq!(ratio[0].0, 0.5);
+    assert!((ratio[0].1 - 8_f64.sqrt() / 6.).abs() < 1e-15);
+    assert!(ratio[1].0.is_nan());
+
+    let other = WeightedHistogram::new(0., 1., 2).unwrap();
+    assert!(matches!(a.ratio(&other), Err(LargedevError::HistogramRange(_))));
+}
+
+#[test]
+fn effective_sample_size() {
+    let mut h = WeightedHistogram::new(0., 3., 3).unwrap();
+    for _ in 0..4 {
+        h.add(0.5, 1.);
+    }
+    h.add(1.5, 1.);
+    h.add(1.5, 3.);
+    // outside of the range
+    h.add(5., 100.);
+    // `(sum w)^2 / sum w^2` is `16 / 4`, `16 / 10` and `0` for the empty bin
+    assert_eq!(h.ess(), [4., 1.6, 0.]);
+    assert_eq!(h.errors(), [2., 10_f64.sqrt(), 0.]);
+    assert_eq!(h.total_ess(), 64. / 14.);
+    assert_eq!(WeightedHistogram::new(0., 1., 1).unwrap().total_ess(), 0.);
+}
+
+#[test]
+fn moments_of_the_binned_values() {
+    let mut h = Histogram::new(0., 3., 3).unwrap();
+    for x in [0.1, 0.5, 0.9, 2.5] {
+        h.count(x);
+    }
+    // the values at the centers `0.5, 0.5, 0.5, 2.5`
+    assert_eq!(h.mean_value(), 1.);
+    assert_eq!(h.variance_value(), 0.75);
+    assert_eq!(h.central_moment(3), 0.75);
+    assert_eq!(h.central_moment(4), (3. * 0.0625 + 5.0625) / 4.);
+    // unlike the mean height of the bins
+    assert_eq!(h.mean(), 4. / 3.);
+
+    let mut symmetric = Histogram::new(0., 3., 3).unwrap();
+    for x in [0.5, 1.5, 1.5, 2.5] {
+        symmetric.count(x);
+    }
+    assert_eq!(symmetric.mean_value(), 1.5);
+    assert_eq!(symmetric.variance_value(), 0.5);
+    assert_eq!(symmetric.central_moment(3), 0.);
+
+    let empty = Histogram::new(0., 3., 3).unwrap();
+    assert!(empty.mean_value().is_nan());
+    assert!(empty.variance_value().is_nan());
+}
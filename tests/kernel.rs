@@ -0,0 +1,92 @@
+use largedev::{KernelWangLandau, MarkovChain, Model, Proposal, Seed};
+use rand::Rng;
+
+/// the sum of two uniform random numbers in `[0, 1)`, whose density of states is
+/// `g(E) = min(E, 2 - E)`
+struct Sum {
+    x: [f64; 2],
+    old: (usize, f64),
+}
+
+impl Model for Sum {
+    fn value(&self) -> f64 {
+        self.x[0] + self.x[1]
+    }
+}
+
+impl MarkovChain for Sum {
+    fn change(&mut self, rng: &mut impl Rng) {
+        let i = rng.gen_range(0..2);
+        self.old = (i, self.x[i]);
+        self.x[i] += rng.gen_range(-0.1..0.1);
+    }
+
+    fn try_change(&mut self, rng: &mut impl Rng) -> Proposal {
+        self.change(rng);
+        let i = self.old.0;
+        if !(0. ..1.).contains(&self.x[i]) {
+            self.undo();
+            return Proposal::Invalid
+        }
+        Proposal::Valid
+    }
+
+    fn undo(&mut self) {
+        self.x[self.old.0] = self.old.1;
+    }
+}
+
+#[test]
+fn exact_density_of_states() {
+    let mut output = Vec::new();
+    let report = KernelWangLandau::new(Sum { x: [0.5, 0.5], old: (0, 0.5) })
+        .range(0.2, 1.8)
+        .bins(16)
+        .nodes(16)
+        .sweep(10)
+        .lnf_final(1e-4)
+        .build()
+        .unwrap()
+        .run(&mut Seed::new(162).rng(), &mut output)
+        .unwrap();
+    assert!(!report.stopped);
+    assert_eq!(report.phases.iter().map(|p| p.phase).collect::<Vec<_>>(), [1, 2, 3]);
+    assert_eq!(report.phases.iter().map(|p| p.tries).sum::<usize>(), report.tries);
+
+    let output = String::from_utf8(output).unwrap();
+    let mut lines = output.lines().filter(|line| !line.starts_with('#'));
+    let borders: Vec<f64> = lines.next().unwrap().split_whitespace().map(|d| d.parse().unwrap()).collect();
+    let g: Vec<f64> = lines.next().unwrap().split_whitespace().map(|d| d.parse().unwrap()).collect();
+    assert_eq!(g.len(), 16);
+
+    // the kernels smooth the kink at `E = 1` and the reflection at the ends of the range
+    // flattens the estimate there, so only the nodes a bandwidth away from them are compared
+    let nodes: Vec<(f64, f64)> = borders.windows(2)
+        .map(|b| (b[0] + b[1]) / 2.)
+        .zip(g)
+        .filter(|(e, _)| (e - 1.).abs() > 0.2 && (e - 1.).abs() < 0.7)
+        .map(|(e, d)| (d, e.min(2. - e).ln()))
+        .collect();
+    let offset = nodes.iter().map(|(d, exact)| d - exact).sum::<f64>() / nodes.len() as f64;
+    for (d, exact) in &nodes {
+        let deviation = d - offset - exact;
+        assert!(deviation.abs() < 0.15, "deviation {} of {:?}", deviation, nodes);
+    }
+}
+
+#[test]
+fn seeded_report() {
+    let mut output = Vec::new();
+    let report = KernelWangLandau::new(Sum { x: [0.5, 0.5], old: (0, 0.5) })
+        .range(0.2, 1.8)
+        .bins(8)
+        .lnf_final(1e-2)
+        .seed(7)
+        .build()
+        .unwrap()
+        .run_seeded(&mut output)
+        .unwrap();
+    assert_eq!(report.seed, Some(Seed::new(7)));
+    assert_eq!(report.phases.len(), 3);
+    assert!(String::from_utf8(output).unwrap().starts_with("# seed = 7"));
+}
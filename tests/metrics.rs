@@ -0,0 +1,21 @@
+#![cfg(feature = "metrics")]
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use largedev::Metrics;
+
+#[test]
+fn serve_requests_without_headers() {
+    let addr = "127.0.0.1:39153";
+    Metrics::new().serve(addr).unwrap();
+
+    // an idle client must not block the scrapes of the others
+    let _idle = TcpStream::connect(addr).unwrap();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /metrics HTTP/1.0\r\n\r\n").unwrap();
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer).unwrap();
+    assert!(answer.starts_with("HTTP/1.1 200 OK"));
+    assert!(answer.contains("largedev_phase"));
+}
@@ -0,0 +1,67 @@
+#![cfg(feature = "testing")]
+
+use largedev::testing::MockChain;
+use largedev::{Metropolis, Model, Proposal, Restorable, Seed};
+
+#[test]
+fn run_seeded_is_reproducible() {
+    // the invalid proposals need no random number for the acceptance test, so the
+    // batch of random numbers of the last sweep is not used up
+    let model = MockChain::new(vec![0., 1., 0.5, 2.])
+        .with_proposals(vec![Proposal::Valid, Proposal::Valid, Proposal::Invalid]);
+    let mut run = Metropolis::new(model)
+        .temperature(1.)
+        .sweep(3)
+        .iterations(10)
+        .seed(7)
+        .build()
+        .unwrap();
+    let start = run.model().snapshot();
+
+    let mut first = Vec::new();
+    run.run_seeded(&mut first).unwrap();
+    run.model_mut().restore(&start);
+    let mut second = Vec::new();
+    run.run_seeded(&mut second).unwrap();
+    assert_eq!(String::from_utf8(first).unwrap(), String::from_utf8(second).unwrap());
+}
+
+#[test]
+fn downhill_and_uphill() {
+    let mut rng = Seed::new(1).rng();
+    // after the first undo, the chain proposes the same increase again and again
+    let mut run = Metropolis::new(MockChain::new(vec![5., 3., 4., 1.])).build().unwrap();
+    let mut value = run.model().value();
+    for _ in 0..10 {
+        let new = run.downhill(&mut rng);
+        assert!(new <= value, "downhill increased the value from {} to {}", value, new);
+        assert_eq!(new, run.model().value());
+        value = new;
+    }
+    assert_eq!(value, 3.);
+    assert_eq!(run.model().undos(), 9);
+
+    let mut run = Metropolis::new(MockChain::new(vec![1., 3., 2., 5.])).build().unwrap();
+    let mut value = run.model().value();
+    for _ in 0..10 {
+        let new = run.uphill(&mut rng);
+        assert!(new >= value, "uphill decreased the value from {} to {}", value, new);
+        assert_eq!(new, run.model().value());
+        value = new;
+    }
+    assert_eq!(value, 3.);
+    assert_eq!(run.model().undos(), 9);
+}
+
+#[test]
+fn samples_are_reproducible() {
+    let model = MockChain::new(vec![0., 1., 0.5, 2.])
+        .with_proposals(vec![Proposal::Valid, Proposal::Valid, Proposal::Invalid]);
+    let mut run = Metropolis::new(model).temperature(1.).sweep(3).iterations(10).build().unwrap();
+    let start = run.model().snapshot();
+
+    let first: Vec<f64> = run.samples(&mut Seed::new(7).rng()).map(|s| s.value).collect();
+    run.model_mut().restore(&start);
+    let second: Vec<f64> = run.samples(&mut Seed::new(7).rng()).map(|s| s.value).collect();
+    assert_eq!(first, second);
+}
@@ -0,0 +1,128 @@
+#![cfg(all(feature = "models", feature = "testing"))]
+
+use largedev::models::*;
+use largedev::testing::assert_chain_laws;
+use largedev::validate::Validator;
+use largedev::{LargedevError, MarkovChain, Seed};
+
+fn validate<MC: MarkovChain>(validator: Validator<MC>) {
+    let mut validator = validator.proposals(20_000);
+    let report = validator.check(&mut Seed::new(7).rng()).unwrap();
+    assert!(report.is_ok(), "{:?}", report.violations);
+}
+
+#[test]
+fn ising() {
+    assert_chain_laws(|rng| Ising2D::random(4, rng).unwrap());
+    validate(Validator::new(Ising2D::random(4, &mut Seed::new(1).rng()).unwrap()));
+}
+
+#[test]
+fn potts() {
+    assert_chain_laws(|rng| Potts2D::random(4, 3, rng).unwrap());
+    validate(Validator::new(Potts2D::random(4, 3, &mut Seed::new(1).rng()).unwrap()));
+}
+
+#[test]
+fn spin_glass() {
+    assert_chain_laws(|rng| SpinGlass2D::random(4, 3, rng).unwrap());
+    validate(Validator::new(SpinGlass2D::random(4, 3, &mut Seed::new(1).rng()).unwrap()).bins(-40., 40., 80));
+}
+
+#[test]
+fn lattice_walk() {
+    assert_chain_laws(|rng| LatticeWalk::random(20, 2, rng).unwrap());
+    validate(Validator::new(LatticeWalk::random(20, 2, &mut Seed::new(1).rng()).unwrap()).bins(0., 20., 40));
+}
+
+#[test]
+fn levy_walk() {
+    assert_chain_laws(|rng| LevyWalk::random(20, 1.5, rng).unwrap());
+    validate(Validator::new(LevyWalk::random(20, 1.5, &mut Seed::new(1).rng()).unwrap()).bins(0., 100., 50));
+}
+
+#[test]
+fn self_avoiding_walk() {
+    assert_chain_laws(|_| SelfAvoidingWalk::new(20).unwrap());
+    validate(Validator::new(SelfAvoidingWalk::new(20).unwrap()).bins(0., 20., 40));
+}
+
+#[test]
+fn convex_hulls() {
+    for &measure in &[HullMeasure::Volume, HullMeasure::Surface] {
+        assert_chain_laws(|rng| ConvexHull::new(LatticeWalk::random(20, 2, rng).unwrap(), measure).unwrap());
+        assert_chain_laws(|rng| ConvexHull::new(LatticeWalk::random(20, 3, rng).unwrap(), measure).unwrap());
+        assert_chain_laws(|rng| ConvexHull::new(LevyWalk::random(20, 1.5, rng).unwrap(), measure).unwrap());
+        assert_chain_laws(|_| ConvexHull::new(SelfAvoidingWalk::new(20).unwrap(), measure).unwrap());
+    }
+    let hull = ConvexHull::new(LatticeWalk::random(20, 2, &mut Seed::new(1).rng()).unwrap(), HullMeasure::Volume).unwrap();
+    validate(Validator::new(hull).bins(0., 100., 50));
+}
+
+#[test]
+fn tsp() {
+    assert_chain_laws(|rng| Tsp::random(8, rng).unwrap());
+    validate(Validator::new(Tsp::random(8, &mut Seed::new(1).rng()).unwrap()).bins(0., 10., 50));
+}
+
+#[test]
+fn erdos_renyi() {
+    for &observable in &[GraphObservable::LargestComponent, GraphObservable::Components] {
+        assert_chain_laws(|rng| ErdosRenyi::random(10, 8, observable, rng).unwrap());
+        validate(Validator::new(ErdosRenyi::random(10, 8, observable, &mut Seed::new(1).rng()).unwrap()));
+    }
+}
+
+#[test]
+fn number_partitioning() {
+    assert_chain_laws(|rng| NumberPartitioning::random(10, 8, rng).unwrap());
+    validate(Validator::new(NumberPartitioning::random(10, 8, &mut Seed::new(1).rng()).unwrap()));
+}
+
+#[test]
+fn lennard_jones() {
+    assert_chain_laws(|rng| LennardJones::random(8, 0.5, rng).unwrap());
+    validate(Validator::new(LennardJones::random(8, 0.5, &mut Seed::new(1).rng()).unwrap()).bins(-50., 50., 100));
+}
+
+#[test]
+fn hard_spheres() {
+    assert_chain_laws(|_| HardSpheres::new(9, 0.3).unwrap());
+    validate(Validator::new(HardSpheres::new(9, 0.3).unwrap()).bins(0., 10., 50));
+}
+
+#[test]
+fn rna() {
+    assert_chain_laws(|_| RnaStructure::new("GGGAAAUCCCAGGGAAAUCC").unwrap());
+    validate(Validator::new(RnaStructure::homopolymer(16).unwrap()));
+}
+
+#[test]
+fn percolation() {
+    for &kind in &[PercolationKind::Site, PercolationKind::Bond] {
+        assert_chain_laws(|rng| Percolation::random(6, kind, 0.5, rng).unwrap());
+        validate(Validator::new(Percolation::random(6, kind, 0.5, &mut Seed::new(1).rng()).unwrap()));
+    }
+}
+
+#[test]
+fn invalid_configurations() {
+    let invalid = |result: Result<(), LargedevError>| matches!(result, Err(LargedevError::InvalidConfig(_)));
+    let mut rng = Seed::new(1).rng();
+    assert!(invalid(Ising2D::new(1).map(drop)));
+    assert!(invalid(Potts2D::new(4, 1).map(drop)));
+    assert!(invalid(SpinGlass2D::new(1, 3).map(drop)));
+    assert!(invalid(LatticeWalk::new(0, 2).map(drop)));
+    assert!(invalid(LevyWalk::random(10, 0., &mut rng).map(drop)));
+    assert!(invalid(SelfAvoidingWalk::new(0).map(drop)));
+    assert!(invalid(ConvexHull::new(LatticeWalk::new(10, 4).unwrap(), HullMeasure::Volume).map(drop)));
+    assert!(invalid(Tsp::new(vec![(0., 0.); 3]).map(drop)));
+    assert!(invalid(ErdosRenyi::random(4, 6, GraphObservable::Components, &mut rng).map(drop)));
+    assert!(invalid(NumberPartitioning::random(10, 33, &mut rng).map(drop)));
+    assert!(invalid(LennardJones::new(8, f64::NAN).map(drop)));
+    assert!(invalid(HardSpheres::new(9, 0.95).map(drop)));
+    assert!(invalid(RnaStructure::new("GAXC").map(drop)));
+    assert!(invalid(RnaStructure::homopolymer(4).map(drop)));
+    assert!(invalid(Percolation::new(4, PercolationKind::Site, 1.5).map(drop)));
+    assert!(invalid(HardSpheres::new(9, 0.3).unwrap().set_step(-1.)));
+}
@@ -0,0 +1,113 @@
+use largedev::{LargedevError, MarkovChain, Model, Move, MoveSet, Proposal, Seed, WithMoves};
+use rand::RngCore;
+
+struct Spin(f64);
+
+impl Model for Spin {
+    fn value(&self) -> f64 {
+        self.0
+    }
+}
+
+struct Flip;
+
+impl Move<Spin> for Flip {
+    fn propose(&mut self, model: &mut Spin, _rng: &mut dyn RngCore) {
+        model.0 = -model.0;
+    }
+
+    fn undo(&mut self, model: &mut Spin) {
+        model.0 = -model.0;
+    }
+}
+
+#[test]
+fn invalid_move_sets() {
+    let invalid = |moves: MoveSet<Spin>| matches!(WithMoves::new(Spin(1.), moves), Err(LargedevError::InvalidParameter(_)));
+    assert!(invalid(MoveSet::new()));
+    for &weight in &[0., -1., f64::NAN, f64::INFINITY] {
+        assert!(invalid(MoveSet::new().with(Flip).with_weight(Flip, weight)));
+    }
+    assert!(invalid(MoveSet::new().with_weight(Flip, f64::MAX).with_weight(Flip, f64::MAX)));
+    assert!(WithMoves::new(Spin(1.), MoveSet::new().with(Flip).with_weight(Flip, 0.1)).is_ok());
+}
+
+/// a move, which always violates a constraint
+struct Blocked;
+
+impl Move<Spin> for Blocked {
+    fn propose(&mut self, _model: &mut Spin, _rng: &mut dyn RngCore) {}
+
+    fn undo(&mut self, _model: &mut Spin) {
+        panic!("an invalid proposal is never undone")
+    }
+
+    fn try_propose(&mut self, _model: &mut Spin, _rng: &mut dyn RngCore) -> Proposal {
+        Proposal::Invalid
+    }
+}
+
+#[test]
+fn frequencies_follow_the_weights() {
+    let mut chain = WithMoves::new(Spin(1.), MoveSet::new().with_weight(Flip, 1.).with_weight(Flip, 3.)).unwrap();
+    let mut rng = Seed::new(109).rng();
+    let n = 40000;
+    for _ in 0..n {
+        chain.try_change(&mut rng);
+    }
+    let stats = chain.moves().stats();
+    assert_eq!(stats[0].tries + stats[1].tries, n);
+    // the binomial standard deviation of the tries of the first move is about 87
+    let expected = n as f64 / 4.;
+    assert!((stats[0].tries as f64 - expected).abs() < 4. * (n as f64 * 3. / 16.).sqrt(), "{} tries of the first move", stats[0].tries);
+}
+
+#[test]
+fn statistics_of_the_moves() {
+    let mut chain = WithMoves::new(Spin(1.), MoveSet::new().with(Flip).with(Blocked)).unwrap();
+    let mut rng = Seed::new(109).rng();
+    let (mut flips, mut blocked) = (0, 0);
+    for i in 0..1000 {
+        match chain.try_change(&mut rng) {
+            Proposal::Valid => {
+                flips += 1;
+                assert_eq!(chain.moves().last(), Some(0));
+                // reject every second flip
+                if i % 2 == 0 {
+                    chain.undo();
+                    assert_eq!(chain.value(), 1.);
+                } else {
+                    chain.model_mut().0 = 1.;
+                }
+            }
+            Proposal::Invalid => {
+                blocked += 1;
+                assert_eq!(chain.moves().last(), None);
+                // undoing an invalid proposal does nothing and is not counted as a reject
+                chain.undo();
+                assert_eq!(chain.value(), 1.);
+            }
+            Proposal::Unchanged => unreachable!(),
+        }
+    }
+    let stats = chain.moves().stats();
+    assert_eq!(stats[0].tries, flips);
+    assert_eq!(stats[0].invalid, 0);
+    assert!(stats[0].rejects > 0 && stats[0].rejects < flips);
+    assert_eq!(stats[1].tries, blocked);
+    assert_eq!(stats[1].invalid, blocked);
+    assert_eq!(stats[1].rejects, 0);
+    assert_eq!(stats[1].acceptance(), Some(0.));
+
+    chain.moves_mut().reset_stats();
+    assert_eq!(chain.moves().stats()[0].acceptance(), None);
+}
+
+#[test]
+fn empty_move_set_is_invalid() {
+    let mut moves = MoveSet::<Spin>::new();
+    let mut spin = Spin(1.);
+    assert_eq!(moves.propose(&mut spin, &mut Seed::new(1).rng()), Proposal::Invalid);
+    moves.undo(&mut spin);
+    assert_eq!(spin.0, 1.);
+}
@@ -0,0 +1,54 @@
+#![cfg(feature = "models")]
+
+use largedev::models::Ising2D;
+use largedev::{Multicanonical, Seed};
+
+/// the number of states of every second energy `-32, -24, -20, ..., 32` of the 4 x 4
+/// Ising model with periodic boundaries
+const DEGENERACIES: [f64; 15] = [
+    2., 32., 64., 424., 1728., 6688., 13568., 20524., 13568., 6688., 1728., 424., 64., 32., 2.,
+];
+
+#[test]
+fn exact_density_of_states() {
+    let mut output = Vec::new();
+    let report = Multicanonical::new(Ising2D::new(4).unwrap())
+        .range(-34., 34.)
+        .bins(17)
+        .sweep(16)
+        .iterations(30)
+        .iteration_sweeps(10000)
+        .walkers(2)
+        .build()
+        .unwrap()
+        .run(&mut Seed::new(156).rng(), &mut output)
+        .unwrap();
+    assert_eq!(report.streams.len(), 2);
+    assert_eq!(report.tries, 30 * 2 * 10000 * 16);
+
+    let output = String::from_utf8(output).unwrap();
+    let g: Vec<f64> = output.lines()
+        .filter(|line| !line.starts_with('#'))
+        .nth(1)
+        .unwrap()
+        .split_whitespace()
+        .map(|d| d.parse().unwrap())
+        .collect();
+    assert_eq!(g.len(), 17);
+
+    // the energies -28 and 28 are not reachable
+    let reachable: Vec<f64> = g.iter()
+        .enumerate()
+        .filter(|&(i, _)| i != 1 && i != 15)
+        .map(|(_, &d)| d)
+        .collect();
+    // the estimate is only determined up to an additive constant
+    let offset = reachable.iter()
+        .zip(&DEGENERACIES)
+        .map(|(d, exact)| d - exact.ln())
+        .sum::<f64>() / reachable.len() as f64;
+    for (d, exact) in reachable.iter().zip(&DEGENERACIES) {
+        let deviation = d - offset - exact.ln();
+        assert!(deviation.abs() < 0.2, "deviation {} of {:?}", deviation, reachable);
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+use std::io;
+
+use largedev::{Growable, Model, Perm, Seed};
+
+/// the number of self-avoiding walks of `0, 1, ..., 10` steps on the square lattice
+const WALKS: [f64; 11] = [1., 4., 12., 36., 100., 284., 780., 2172., 5916., 16268., 44100.];
+
+const STEPS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+/// an athermal self-avoiding walk on the square lattice, whose partition function is
+/// the number of walks
+struct Walk {
+    sites: Vec<(i32, i32)>,
+    occupied: HashSet<(i32, i32)>,
+}
+
+impl Walk {
+    fn new() -> Self {
+        let mut walk = Walk { sites: Vec::new(), occupied: HashSet::new() };
+        walk.reset();
+        walk
+    }
+
+    fn next(&self, candidate: usize) -> (i32, i32) {
+        let (x, y) = *self.sites.last().unwrap();
+        let (dx, dy) = STEPS[candidate];
+        (x + dx, y + dy)
+    }
+}
+
+impl Model for Walk {
+    fn value(&self) -> f64 {
+        let (x, y) = *self.sites.last().unwrap();
+        (x * x + y * y) as f64
+    }
+}
+
+impl Growable for Walk {
+    fn reset(&mut self) {
+        self.sites = vec![(0, 0)];
+        self.occupied = self.sites.iter().cloned().collect();
+    }
+
+    fn candidates(&self, weights: &mut Vec<f64>) {
+        weights.extend((0..4).map(|c| if self.occupied.contains(&self.next(c)) { 0. } else { 1. }));
+    }
+
+    fn grow(&mut self, candidate: usize) {
+        let site = self.next(candidate);
+        self.sites.push(site);
+        self.occupied.insert(site);
+    }
+
+    fn shrink(&mut self) {
+        let site = self.sites.pop().unwrap();
+        self.occupied.remove(&site);
+    }
+}
+
+#[test]
+fn self_avoiding_walks() {
+    let report = Perm::new(Walk::new())
+        .length(10)
+        .tours(20000)
+        .build()
+        .unwrap()
+        .run(&mut Seed::new(167).rng(), &mut io::sink())
+        .unwrap();
+    assert_eq!(report.tours, 20000);
+    assert_eq!(report.ln_z.len(), 11);
+    assert!(report.ln_z[0].abs() < 1e-12);
+    // a single step has no dead ends, its weight is exact
+    assert!((report.ln_z[1] - 4f64.ln()).abs() < 1e-12);
+    for (n, (z, exact)) in report.ln_z.iter().zip(&WALKS).enumerate() {
+        assert!((z - exact.ln()).abs() < 0.02, "ln Z_{} = {} != {}", n, z, exact.ln());
+    }
+}
+
+#[test]
+fn rosenbluth_sampling() {
+    // without enrichment and pruning, it is unbiased, too
+    let report = Perm::new(Walk::new())
+        .length(10)
+        .tours(20000)
+        .thresholds(f64::INFINITY, 0.)
+        .build()
+        .unwrap()
+        .run(&mut Seed::new(1167).rng(), &mut io::sink())
+        .unwrap();
+    // every tour yields at most one walk, the others end in a trap
+    assert!(report.samples < 20000);
+    for (n, (z, exact)) in report.ln_z.iter().zip(&WALKS).enumerate() {
+        assert!((z - exact.ln()).abs() < 0.02, "ln Z_{} = {} != {}", n, z, exact.ln());
+    }
+}
@@ -0,0 +1,69 @@
+#![cfg(feature = "models")]
+
+use largedev::models::Ising2D;
+use largedev::{Seed, WangLandau};
+
+/// the number of states of every second energy `-32, -24, -20, ..., 32` of the 4 x 4
+/// Ising model with periodic boundaries
+const DEGENERACIES: [f64; 15] = [
+    2., 32., 64., 424., 1728., 6688., 13568., 20524., 13568., 6688., 1728., 424., 64., 32., 2.,
+];
+
+#[test]
+fn flat_production_run() {
+    let iterations = 50000;
+    let mut output = Vec::new();
+    let report = WangLandau::new(Ising2D::new(4).unwrap())
+        .range(-34., 34.)
+        .bins(17)
+        .sweep(16)
+        .lnf_final(1e-4)
+        .mask(-28., -28.)
+        .mask(28., 28.)
+        .production(iterations)
+        .t_eq(100)
+        .build()
+        .unwrap()
+        .run(&mut Seed::new(164).rng(), &mut output)
+        .unwrap();
+    assert!(!report.stopped);
+
+    let output = String::from_utf8(output).unwrap();
+    let mut lines = output.lines().filter(|line| !line.starts_with('#'));
+    lines.next().unwrap();
+    let g: Vec<f64> = lines.next().unwrap().split_whitespace().map(|d| d.parse().unwrap()).collect();
+    assert_eq!(g.len(), 17);
+
+    // the visits of the production run and `ln g` as written with every sample
+    let mut visits = [0.; 17];
+    let mut ln_g = [f64::NEG_INFINITY; 17];
+    let mut samples = 0;
+    for line in lines {
+        let mut columns = line.split_whitespace().map(|d| d.parse::<f64>().unwrap());
+        let (e, l) = (columns.next().unwrap(), columns.next().unwrap());
+        let j = ((e + 34.) / 4.) as usize;
+        visits[j] += 1.;
+        ln_g[j] = l;
+        samples += 1;
+    }
+    assert_eq!(samples, iterations);
+    for (j, &l) in ln_g.iter().enumerate() {
+        assert!(l == g[j] || visits[j] == 0., "ln g {} of bin {} differs from {}", l, j, g[j]);
+    }
+
+    // the weights `exp(-ln g)` sample all reachable energies about equally often
+    let visited: Vec<usize> = (0..17).filter(|&j| visits[j] > 0.).collect();
+    assert_eq!(visited.len(), 15);
+    let mean = iterations as f64 / 15.;
+    for &j in &visited {
+        assert!((visits[j] / mean - 1.).abs() < 0.3, "{} visits of bin {}, expected {}", visits[j], j, mean);
+    }
+
+    // reweighting the visits with `g` gives the exact density of states
+    let reweighted: Vec<f64> = visited.iter().map(|&j| visits[j].ln() + g[j]).collect();
+    let offset = reweighted.iter().zip(&DEGENERACIES).map(|(d, exact)| d - exact.ln()).sum::<f64>() / 15.;
+    for (d, exact) in reweighted.iter().zip(&DEGENERACIES) {
+        let deviation = d - offset - exact.ln();
+        assert!(deviation.abs() < 0.15, "deviation {} of {:?}", deviation, reweighted);
+    }
+}
@@ -0,0 +1,75 @@
+use largedev::QuasiRng;
+use rand::{Rng, RngCore};
+
+fn points(qrng: &mut QuasiRng, n: usize) -> Vec<Vec<f64>> {
+    (0..n).map(|_| (0..qrng.dimensions()).map(|_| qrng.gen::<f64>()).collect()).collect()
+}
+
+#[test]
+fn sobol_reference_points() {
+    // the first points of the Joe-Kuo direction numbers, after the origin
+    let reference = [
+        [0.5, 0.5, 0.5],
+        [0.75, 0.25, 0.25],
+        [0.25, 0.75, 0.75],
+        [0.375, 0.375, 0.625],
+        [0.875, 0.875, 0.125],
+        [0.625, 0.125, 0.875],
+        [0.125, 0.625, 0.375],
+    ];
+    let mut qrng = QuasiRng::sobol(3).unwrap();
+    let p = points(&mut qrng, 8);
+    assert_eq!(p[0], vec![0.; 3]);
+    for (x, r) in p[1..].iter().zip(reference.iter()) {
+        assert_eq!(x.as_slice(), r);
+    }
+    assert_eq!(qrng.index(), 8);
+}
+
+#[test]
+fn halton_radical_inverses() {
+    let mut qrng = QuasiRng::halton(2).unwrap();
+    let p = points(&mut qrng, 5);
+    let reference = [
+        [0., 0.],
+        [1. / 2., 1. / 3.],
+        [1. / 4., 2. / 3.],
+        [3. / 4., 1. / 9.],
+        [1. / 8., 4. / 9.],
+    ];
+    for (x, r) in p.iter().zip(reference.iter()) {
+        for (a, b) in x.iter().zip(r.iter()) {
+            assert!((a - b).abs() < 1e-12, "{} != {}", a, b);
+        }
+    }
+}
+
+#[test]
+fn next_point_skips_the_remaining_coordinates() {
+    let mut qrng = QuasiRng::sobol(3).unwrap();
+    qrng.next_u64();
+    qrng.next_point();
+    assert_eq!(qrng.index(), 1);
+    assert_eq!(qrng.gen::<f64>(), 0.5);
+}
+
+#[test]
+fn qmc_integration() {
+    // the integral of x^2 y exp(z) over the unit cube
+    let exact = (1f64.exp() - 1.) / 6.;
+    for mut qrng in [QuasiRng::sobol(3).unwrap(), QuasiRng::halton(3).unwrap()] {
+        let n = 1 << 12;
+        let mean = points(&mut qrng, n).iter()
+            .map(|x| x[0] * x[0] * x[1] * x[2].exp())
+            .sum::<f64>() / n as f64;
+        // plain Monte Carlo would err by about 4e-3
+        assert!((mean - exact).abs() < 1e-3, "{} != {}", mean, exact);
+    }
+}
+
+#[test]
+fn invalid_dimensions() {
+    assert!(QuasiRng::sobol(0).is_err());
+    assert!(QuasiRng::sobol(17).is_err());
+    assert!(QuasiRng::halton(0).is_err());
+}
@@ -0,0 +1,45 @@
+use std::io;
+
+use largedev::{HistogramCriterion, Seed, WangLandauConfig};
+
+fn config() -> WangLandauConfig {
+    WangLandauConfig {
+        sweep: 16,
+        lnf_final: 1e-5,
+        ..WangLandauConfig::default()
+    }
+}
+
+#[test]
+fn exact_density_of_states() {
+    let report = config().self_test(&mut Seed::new(42).rng(), &mut io::sink()).unwrap();
+    assert!(!report.report.stopped);
+    assert_eq!(report.energies.len(), 15);
+    let total: f64 = report.exact.iter().map(|g| g.exp()).sum();
+    assert!((total - 65536.).abs() < 1e-6);
+    assert!(report.max_deviation < 0.1, "max deviation {}", report.max_deviation);
+}
+
+#[test]
+fn flat_criterion() {
+    let config = WangLandauConfig {
+        criterion: HistogramCriterion::Flat(0.8),
+        ..config()
+    };
+    let report = config.self_test(&mut Seed::new(7).rng(), &mut io::sink()).unwrap();
+    assert!(report.max_deviation < 0.1, "max deviation {}", report.max_deviation);
+}
+
+#[test]
+fn seeded_is_reproducible() {
+    let config = WangLandauConfig {
+        seed: Some(Seed::new(3)),
+        ..config()
+    };
+    let mut output = Vec::new();
+    let a = config.self_test_seeded(&mut output).unwrap();
+    let b = config.self_test_seeded(&mut io::sink()).unwrap();
+    assert_eq!(a.estimate, b.estimate);
+    assert_eq!(a.report.seed, Some(Seed::new(3)));
+    assert!(String::from_utf8(output).unwrap().starts_with("# seed = "));
+}
@@ -0,0 +1,87 @@
+use largedev::{MarkovChain, Model, Restorable, SEnsemble, Seed};
+use rand::Rng;
+
+/// A spin, whose energy is `0` or `1`, and whose Metropolis dynamics with flips is the
+/// Markov chain with the transition probabilities `p(0 -> 1) = exp(-beta)` and
+/// `p(1 -> 0) = 1`. Its scaled cumulant generating function is the logarithm of the
+/// largest eigenvalue of the tilted matrix `p(x -> y) exp(-s y)`, the exact generating
+/// function of a finite trajectory is a product of these matrices.
+#[derive(Clone)]
+struct Spin {
+    up: bool,
+}
+
+impl Model for Spin {
+    fn value(&self) -> f64 {
+        if self.up { 1. } else { 0. }
+    }
+}
+
+impl MarkovChain for Spin {
+    fn change(&mut self, _rng: &mut impl Rng) {
+        self.up = !self.up;
+    }
+
+    fn undo(&mut self) {
+        self.up = !self.up;
+    }
+}
+
+impl Restorable for Spin {
+    type Snapshot = bool;
+
+    fn snapshot(&self) -> bool {
+        self.up
+    }
+
+    fn restore(&mut self, snapshot: &bool) {
+        self.up = *snapshot;
+    }
+}
+
+/// `ln Z(s)` of the trajectories of `length` slices of `Spin` started in equilibrium
+fn ln_z(s: f64, beta: f64, length: usize) -> f64 {
+    let p = [[1. - (-beta).exp(), (-beta).exp()], [1., 0.]];
+    let norm = 1. + (-beta).exp();
+    let mut v = [1. / norm, (-beta).exp() / norm];
+    for _ in 0..length {
+        v = [
+            v[0] * p[0][0] + v[1] * p[1][0],
+            (v[0] * p[0][1] + v[1] * p[1][1]) * (-s).exp(),
+        ];
+    }
+    (v[0] + v[1]).ln()
+}
+
+#[test]
+fn scaled_cumulant_generating_function() {
+    let (beta, length, iterations) = (1., 20, 4000);
+    for s in [-0.5, 0.5] {
+        let mut output = Vec::new();
+        SEnsemble::new(Spin { up: false })
+            .s(s)
+            .temperature(1. / beta)
+            .length(length)
+            .proposals(20)
+            .t_eq(100)
+            .iterations(iterations)
+            .build()
+            .unwrap()
+            .run(&mut Seed::new(165).rng(), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let a: Vec<f64> = output.lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| line.split_whitespace().next().unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(a.len(), iterations);
+        let mean = a.iter().sum::<f64>() / iterations as f64;
+
+        // `<A>_s = -d ln Z / ds` and `Var(A)_s = d^2 ln Z / ds^2`
+        let h = 1e-3;
+        let exact = -(ln_z(s + h, beta, length) - ln_z(s - h, beta, length)) / (2. * h);
+        let variance = (ln_z(s + h, beta, length) - 2. * ln_z(s, beta, length) + ln_z(s - h, beta, length)) / (h * h);
+        let sigma = (variance / iterations as f64).sqrt();
+        assert!((mean - exact).abs() < 4. * sigma, "s = {}: <A> = {}, exact {} +- {}", s, mean, exact, sigma);
+    }
+}
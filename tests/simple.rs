@@ -0,0 +1,100 @@
+use std::io;
+use std::ops::ControlFlow;
+
+use largedev::{DirectSamplable, ImportanceWeighted, Model, Seed, Simple, UnitCube};
+use rand::Rng;
+
+/// the samples `0, 1, 2, ...` in order, all with the same weight
+struct Counter(f64);
+
+impl Model for Counter {
+    fn value(&self) -> f64 {
+        self.0
+    }
+}
+
+impl DirectSamplable for Counter {
+    fn reconstruct(&mut self, _rng: &mut impl Rng) {
+        self.0 += 1.;
+    }
+}
+
+impl ImportanceWeighted for Counter {
+    fn ln_weight(&self) -> f64 {
+        0.
+    }
+}
+
+#[test]
+fn resample_stopped_early() {
+    let mut run = Simple::new(Counter(-1.))
+        .iterations(10)
+        .resample(true)
+        .on_sample(|_, value| if value == 3. { ControlFlow::Break(()) } else { ControlFlow::Continue(()) })
+        .build()
+        .unwrap();
+    let mut output = Vec::new();
+    let report = run.run_weighted(&mut Seed::new(1).rng(), &mut output).unwrap();
+    assert!(report.stopped);
+
+    // with equal weights, every sample up to the stopping one occurs exactly once
+    let output = String::from_utf8(output).unwrap();
+    let records: Vec<&str> = output.lines().filter(|line| !line.starts_with('#')).collect();
+    assert_eq!(records, ["0", "1", "2", "3"]);
+}
+
+/// the sum of two uniform random numbers in `[0, 1)`
+struct Sum([f64; 2]);
+
+impl Model for Sum {
+    fn value(&self) -> f64 {
+        self.0[0] + self.0[1]
+    }
+}
+
+impl DirectSamplable for Sum {
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        self.0 = [rng.gen(), rng.gen()];
+    }
+}
+
+impl UnitCube for Sum {
+    fn dimensions(&self) -> usize {
+        2
+    }
+
+    fn set_point(&mut self, u: &[f64]) {
+        self.0 = [u[0], u[1]];
+    }
+}
+
+#[test]
+fn latin_hypercube_error_is_nan() {
+    let n = 100;
+    let mut run = Simple::new(Sum([0.; 2])).iterations(n).records(false).build().unwrap();
+    let means: Vec<f64> = (0..20)
+        .map(|seed| {
+            let report = run.run_latin_hypercube(&mut Seed::new(seed).rng(), &mut io::sink()).unwrap();
+            assert!(report.error.is_nan());
+            report.mean
+        })
+        .collect();
+    // the stratification of every coordinate leaves an error of the mean of order
+    // `1 / n^1.5` instead of the one of independent points `sqrt(1 / 6n) = 0.04`
+    let spread = means.iter().map(|m| (m - 1.).powi(2)).sum::<f64>() / means.len() as f64;
+    assert!(spread.sqrt() < 0.004, "spread {} of {:?}", spread.sqrt(), means);
+}
+
+#[test]
+fn effective_sample_size_of_equal_weights() {
+    let report = Simple::new(Counter(-1.))
+        .iterations(10)
+        .histogram(0., 10., 2)
+        .build()
+        .unwrap()
+        .run_weighted(&mut Seed::new(1).rng(), &mut io::sink())
+        .unwrap();
+    // equal weights are as good as unweighted samples
+    assert_eq!(report.ess, 10.);
+    assert_eq!(report.histogram_ess, Some(vec![5., 5.]));
+}
@@ -0,0 +1,76 @@
+use std::io;
+
+use largedev::{DirectSamplable, MarkovChain, Model, Proposal, Seed, TailPipeline};
+use rand::Rng;
+
+/// a random variable with the density `exp(-x)`, whose tail is `P(x > a) = exp(-a)`
+#[derive(Clone)]
+struct Exponential {
+    x: f64,
+    old: f64,
+}
+
+impl Model for Exponential {
+    fn value(&self) -> f64 {
+        self.x
+    }
+}
+
+impl DirectSamplable for Exponential {
+    fn reconstruct(&mut self, rng: &mut impl Rng) {
+        self.x = -(1. - rng.gen::<f64>()).ln();
+    }
+}
+
+impl MarkovChain for Exponential {
+    fn change(&mut self, rng: &mut impl Rng) {
+        self.old = self.x;
+        self.x += rng.gen_range(-1. ..1.);
+    }
+
+    fn try_change(&mut self, rng: &mut impl Rng) -> Proposal {
+        self.change(rng);
+        if self.x < 0. {
+            self.undo();
+            return Proposal::Invalid
+        }
+        Proposal::Valid
+    }
+
+    fn undo(&mut self) {
+        self.x = self.old;
+    }
+
+    /// the ratio of the densities, such that the unbiased random walk samples the
+    /// exponential distribution like `reconstruct`
+    fn ln_proposal_ratio(&self) -> f64 {
+        self.old - self.x
+    }
+}
+
+#[test]
+fn exponential_tail() {
+    let report = TailPipeline::new(Exponential { x: 0., old: 0. })
+        .range(0., 20.)
+        .bins(20)
+        .samples(100000)
+        .rare(4., 20.)
+        .sweep(10)
+        .lnf_final(1e-4)
+        .runs(4)
+        .build()
+        .unwrap()
+        .run(&mut Seed::new(171).rng(), &mut io::sink())
+        .unwrap();
+    assert!(!report.report.stopped);
+    // simple sampling has at least 100 samples per bin up to `x = 6`
+    assert!(report.overlap >= 2, "overlap of {} bins", report.overlap);
+
+    // the exact mean density in the bin `[a, a + 1)` is `exp(-a) - exp(-a - 1)`, the
+    // normalization on `[0, 20)` differs from one by less than `exp(-20)`
+    for (j, (&x, &ln_p)) in report.centers.iter().zip(&report.ln_p).enumerate() {
+        let a = x - 0.5;
+        let exact = ((-a).exp() - (-a - 1.).exp()).ln();
+        assert!((ln_p - exact).abs() < 0.2, "bin {}: ln P = {} +- {}, exact {}", j, ln_p, report.error[j], exact);
+    }
+}
@@ -0,0 +1,57 @@
+#![cfg(feature = "testing")]
+
+use rand::Rng;
+
+use largedev::testing::{ChainLaws, Law, MockChain};
+use largedev::{MarkovChain, Model};
+
+/// a scripted chain, whose every second `undo` is off by a rounding error
+struct Rounding {
+    chain: MockChain,
+    drift: f64,
+    undos: usize,
+}
+
+impl Model for Rounding {
+    fn value(&self) -> f64 {
+        self.chain.value() + self.drift
+    }
+}
+
+impl MarkovChain for Rounding {
+    fn change(&mut self, rng: &mut impl Rng) {
+        self.chain.change(rng);
+    }
+
+    fn undo(&mut self) {
+        self.chain.undo();
+        self.undos += 1;
+        if self.undos % 2 == 1 {
+            self.drift += 1e-12;
+        }
+    }
+}
+
+fn rounding(_rng: &mut impl Rng) -> Rounding {
+    Rounding {
+        chain: MockChain::new(vec![0., 1., 2., 3.]),
+        drift: 0.,
+        undos: 0,
+    }
+}
+
+#[test]
+fn rounded_undo_within_tolerance() {
+    ChainLaws::new(rounding).tolerance(1e-9).assert();
+}
+
+#[test]
+fn rounded_undo_without_tolerance() {
+    let violation = ChainLaws::new(rounding).check().unwrap_err();
+    assert_eq!(violation.law, Law::Undo);
+}
+
+#[test]
+fn exact_mock_chain() {
+    ChainLaws::new(|_| MockChain::new(vec![0., 1., 2.])).assert();
+}
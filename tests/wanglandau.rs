@@ -0,0 +1,105 @@
+#![cfg(feature = "models")]
+
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+use largedev::models::{Ising2D, RnaStructure};
+use largedev::{Restorable, Seed, WangLandau, WangLandauEvent};
+
+#[test]
+fn entropic_correction() {
+    // the estimate of `ln g`, which biases the entropic sampling, and the final one
+    let bias = Rc::new(RefCell::new(Vec::new()));
+    let result = Rc::new(RefCell::new((Vec::new(), Vec::new())));
+    let (b, r) = (bias.clone(), result.clone());
+    let mut run = WangLandau::new(Ising2D::new(4).unwrap())
+        .range(-34., 34.)
+        .bins(17)
+        .sweep(16)
+        .lnf_final(1e-4)
+        .mask(-28., -28.)
+        .mask(28., 28.)
+        .monitor(usize::MAX, move |state| match state.event {
+            WangLandauEvent::Phase if state.phase == 3 => *b.borrow_mut() = state.g.data().to_vec(),
+            WangLandauEvent::Finished => *r.borrow_mut() = (state.g.data().to_vec(), state.h.data().to_vec()),
+            _ => (),
+        })
+        .build()
+        .unwrap();
+    run.run(&mut Seed::new(5).rng(), &mut io::sink()).unwrap();
+
+    // the visits are proportional to the ratio of the true and the biasing density of
+    // states, so the correction is their logarithm, not their linearization
+    let bias = bias.borrow();
+    let (g, h) = &*result.borrow();
+    let mean = h.iter().sum::<f64>() / h.len() as f64;
+    let visited: Vec<usize> = (0..h.len()).filter(|&j| h[j] > 0.).collect();
+    assert_eq!(visited.len(), 15);
+    let min = visited.iter().map(|&j| h[j]).fold(f64::INFINITY, f64::min);
+    let max = visited.iter().map(|&j| h[j]).fold(0., f64::max);
+    assert!(min < 0.99 * max, "the visits are too flat to test the correction");
+    for &j in &visited {
+        let correction = g[j] - bias[j];
+        assert!((correction - (h[j] / mean).ln()).abs() < 1e-9, "correction {} of bin {}", correction, j);
+    }
+}
+
+#[test]
+fn run_seeded_is_reproducible() {
+    // most proposals of the structure are invalid and need no random number for the
+    // acceptance test, so the batch of random numbers of the last sweep is not used up
+    let mut run = WangLandau::new(RnaStructure::homopolymer(12).unwrap())
+        .range(0., 3.)
+        .bins(3)
+        .sweep(7)
+        .lnf_final(1e-4)
+        .seed(7)
+        .build()
+        .unwrap();
+    let start = run.model().snapshot();
+
+    let mut first = Vec::new();
+    run.run_seeded(&mut first).unwrap();
+    run.model_mut().restore(&start);
+    let mut second = Vec::new();
+    run.run_seeded(&mut second).unwrap();
+    assert_eq!(String::from_utf8(first).unwrap(), String::from_utf8(second).unwrap());
+}
+
+#[test]
+fn shared_exact_density_of_states() {
+    // the number of states of every second energy `-24, -20, ..., 24` of the 4 x 4
+    // Ising model, all of them are reachable
+    let degeneracies = [32., 64., 424., 1728., 6688., 13568., 20524., 13568., 6688., 1728., 424., 64., 32.];
+    let mut output = Vec::new();
+    let report = WangLandau::new(Ising2D::new(4).unwrap())
+        .range(-26., 26.)
+        .bins(13)
+        .sweep(16)
+        .lnf_final(1e-4)
+        .shared(4)
+        .build()
+        .unwrap()
+        .run(&mut Seed::new(129).rng(), &mut output)
+        .unwrap();
+    assert_eq!(report.streams.len(), 4);
+
+    let output = String::from_utf8(output).unwrap();
+    let g: Vec<f64> = output.lines()
+        .filter(|line| !line.starts_with('#'))
+        .nth(1)
+        .unwrap()
+        .split_whitespace()
+        .map(|d| d.parse().unwrap())
+        .collect();
+    assert_eq!(g.len(), 13);
+    let offset = g.iter()
+        .zip(&degeneracies)
+        .map(|(d, exact): (&f64, &f64)| d - exact.ln())
+        .sum::<f64>() / g.len() as f64;
+    for (d, exact) in g.iter().zip(&degeneracies) {
+        let deviation = d - offset - exact.ln();
+        assert!(deviation.abs() < 0.2, "deviation {} of {:?}", deviation, g);
+    }
+}
@@ -0,0 +1,97 @@
+#![cfg(feature = "models")]
+
+use largedev::models::Ising2D;
+use largedev::{Seed, WangLandauConfig, WindowedWangLandau, WindowedWangLandauConfig};
+
+/// the number of states of every second energy `-32, -24, -20, ..., 32` of the 4 x 4
+/// Ising model with periodic boundaries
+const DEGENERACIES: [f64; 15] = [
+    2., 32., 64., 424., 1728., 6688., 13568., 20524., 13568., 6688., 1728., 424., 64., 32., 2.,
+];
+
+#[test]
+fn masked_bin_in_overlap() {
+    let config = WindowedWangLandauConfig {
+        wang_landau: WangLandauConfig {
+            range: Some((-34., 34.)),
+            bins: 17,
+            sweep: 16,
+            lnf_final: 1e-4,
+            masked: vec![(-28., -28.), (28., 28.)],
+            ..WangLandauConfig::default()
+        },
+        windows: 2,
+        // the unreachable energy `-28` lies in the overlap of both windows
+        boundaries: Some(vec![(0, 10), (1, 17)]),
+        ..WindowedWangLandauConfig::default()
+    };
+    let mut output = Vec::new();
+    WindowedWangLandau::with_config(Ising2D::new(4).unwrap(), config)
+        .build()
+        .unwrap()
+        .run(&mut Seed::new(3).rng(), &mut output)
+        .unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    let g: Vec<f64> = output.lines()
+        .filter(|line| !line.starts_with('#'))
+        .nth(1)
+        .unwrap()
+        .split_whitespace()
+        .map(|d| d.parse().unwrap())
+        .collect();
+    assert_eq!(g.len(), 17);
+    assert_eq!(g[1], f64::NEG_INFINITY);
+    assert_eq!(g[15], f64::NEG_INFINITY);
+
+    let reachable: Vec<f64> = g.iter().cloned().filter(|d| d.is_finite()).collect();
+    assert_eq!(reachable.len(), 15);
+    for (d, exact) in reachable.iter().zip(&DEGENERACIES) {
+        let deviation = (d - reachable[0]) - (exact / 2.).ln();
+        assert!(deviation.abs() < 0.2, "deviation {} of {:?}", deviation, reachable);
+    }
+}
+
+#[test]
+fn adapted_windows() {
+    let config = WindowedWangLandauConfig {
+        wang_landau: WangLandauConfig {
+            range: Some((-34., 34.)),
+            bins: 17,
+            sweep: 16,
+            lnf_final: 1e-4,
+            masked: vec![(-28., -28.), (28., 28.)],
+            ..WangLandauConfig::default()
+        },
+        windows: 3,
+        adapt_rounds: 2,
+        adapt_sweeps: 2000,
+        ..WindowedWangLandauConfig::default()
+    };
+    let mut output = Vec::new();
+    let mut run = WindowedWangLandau::with_config(Ising2D::new(4).unwrap(), config)
+        .build()
+        .unwrap();
+    run.run(&mut Seed::new(163).rng(), &mut output).unwrap();
+
+    // the adapted windows still cover the range with valid overlaps
+    let boundaries = run.config().boundaries.clone().unwrap();
+    assert_eq!(boundaries.len(), 3);
+    run.config().validate().unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.lines().any(|line| line.starts_with("# adapted windows")));
+    let g: Vec<f64> = output.lines()
+        .filter(|line| !line.starts_with('#'))
+        .nth(1)
+        .unwrap()
+        .split_whitespace()
+        .map(|d| d.parse().unwrap())
+        .collect();
+    let reachable: Vec<f64> = g.iter().cloned().filter(|d| d.is_finite()).collect();
+    assert_eq!(reachable.len(), 15);
+    for (d, exact) in reachable.iter().zip(&DEGENERACIES) {
+        let deviation = (d - reachable[0]) - (exact / 2.).ln();
+        assert!(deviation.abs() < 0.2, "deviation {} of {:?}", deviation, reachable);
+    }
+}